@@ -0,0 +1,334 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::settings::Settings;
+use crate::visualizer::{self, SpectrogramColors, VisualizerMode};
+use crate::{CollectionClickAction, SortOrder};
+
+/// Name of the config file, kept in `config_dir`.
+pub const CONFIG_FILE: &str = "config.toml";
+
+/// App state that survives restarts, stored as TOML. Anything missing from
+/// the file takes the default documented on its field.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Output device name. Unset: the system default device.
+    pub output_device: Option<String>,
+    /// Collections folder. Unset: `my_collections` in the per-user data folder,
+    /// or in the working directory when an older version left one there.
+    pub collections_dir: Option<PathBuf>,
+    /// Filter typed into the collections search box. Default: empty.
+    pub collections_search: String,
+    /// Show only favourites in the collections list. Default: off.
+    pub favorites_only: bool,
+    /// What clicking a collection item does. Default: play now.
+    pub collection_click: CollectionClickAction,
+    /// Order of the collections list. Default: by title.
+    pub collections_sort: SortOrder,
+    /// Draw the waveform outline on the progress bar. Default: on.
+    pub waveform_overview: bool,
+    /// Scroll the queue to the playing track on track changes. Default: on.
+    pub queue_auto_follow: bool,
+    /// Count the time label down to the end of the track. Default: off.
+    pub show_remaining_time: bool,
+    /// What the visualizer draws. Default: spectrum.
+    pub visualizer_mode: VisualizerMode,
+    /// FFT window in samples; a power of two. Default: 4096.
+    pub visualizer_fft_size: usize,
+    /// Spectrum bars. Default: 64.
+    pub visualizer_bands: usize,
+    /// Split the spectrum into left and right. Default: off.
+    pub visualizer_stereo: bool,
+    /// FFT frames shown across the spectrogram. Default: 256.
+    pub visualizer_history: usize,
+    /// Colour map of the spectrogram. Default: heat.
+    pub visualizer_colors: SpectrogramColors,
+    /// Run the equalizer. Default: on.
+    pub eq_enabled: bool,
+    /// Stereo balance from -1.0 (left) to 1.0 (right). Default: 0.0.
+    pub balance: f32,
+    /// Run the output limiter. Default: on.
+    pub limiter_enabled: bool,
+    /// Limiter attack in milliseconds. Default: 5.
+    pub limiter_attack_ms: f32,
+    /// Limiter release in milliseconds. Default: 200.
+    pub limiter_release_ms: f32,
+    /// Blend some of each channel into the other for headphones. Default: off.
+    pub crossfeed_enabled: bool,
+    /// Crossfeed amount from 0.0 to 1.0. Default: 0.5.
+    pub crossfeed_intensity: f32,
+    /// Listen for media keys while unfocused. Unset: on where supported.
+    pub global_keys: Option<bool>,
+    /// Overlap between tracks in seconds. Default: 0, no crossfade.
+    pub crossfade_secs: f32,
+    /// Fade in on play and resume, in seconds. Default: 0.3.
+    pub fade_in_secs: f32,
+    /// Fade out on pause and stop, in seconds. Default: 0.3.
+    pub fade_out_secs: f32,
+    /// Keep playing from the collections when the queue ends. Default: off.
+    pub auto_continue: bool,
+    /// Show a desktop notification on track changes. Default: on.
+    pub notifications: bool,
+    /// Start in the compact window layout. Default: off.
+    pub compact_mode: bool,
+    /// Remember an EQ per track. Default: off.
+    pub per_track_eq: bool,
+    /// Keep the pitch when changing speed. Default: on.
+    pub preserve_pitch: bool,
+    /// Play every track at the same loudness. Default: off.
+    pub normalize_volume: bool,
+    /// Scrobble to Last.fm. Default: off.
+    pub lastfm_enabled: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            output_device: None,
+            collections_dir: None,
+            collections_search: String::new(),
+            favorites_only: false,
+            collection_click: CollectionClickAction::PlayNow,
+            collections_sort: SortOrder::Title,
+            waveform_overview: true,
+            queue_auto_follow: true,
+            show_remaining_time: false,
+            visualizer_mode: VisualizerMode::Spectrum,
+            visualizer_fft_size: visualizer::DEFAULT_FFT_SIZE,
+            visualizer_bands: visualizer::DEFAULT_BANDS,
+            visualizer_stereo: false,
+            visualizer_history: visualizer::DEFAULT_SPECTROGRAM_HISTORY,
+            visualizer_colors: SpectrogramColors::Heat,
+            eq_enabled: true,
+            balance: 0.0,
+            limiter_enabled: true,
+            limiter_attack_ms: 5.0,
+            limiter_release_ms: 200.0,
+            crossfeed_enabled: false,
+            crossfeed_intensity: 0.5,
+            global_keys: None,
+            crossfade_secs: 0.0,
+            fade_in_secs: 0.3,
+            fade_out_secs: 0.3,
+            auto_continue: false,
+            notifications: true,
+            compact_mode: false,
+            per_track_eq: false,
+            preserve_pitch: true,
+            normalize_volume: false,
+            lastfm_enabled: false,
+        }
+    }
+}
+
+impl Config {
+    /// Load the config from `path`. A missing file yields the defaults. The
+    /// file is decoded lossily, so stray non-UTF-8 bytes only affect the
+    /// values they're in. A damaged file is copied to `<path>.bak` and
+    /// rewritten with whatever entries could be read, so one bad line
+    /// doesn't lose the rest.
+    pub fn load(path: &Path) -> Self {
+        let bytes = match fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                if e.kind() != io::ErrorKind::NotFound {
+                    eprintln!("Cannot read {}: {}; using defaults", path.display(), e);
+                }
+                return Self::default();
+            }
+        };
+        let (config, damaged) = Self::parse(&String::from_utf8_lossy(&bytes));
+        if damaged {
+            eprintln!("Repairing malformed {}", path.display());
+            let mut backup = path.as_os_str().to_owned();
+            backup.push(".bak");
+            if let Err(e) = fs::copy(path, &backup).and_then(|_| config.save(path)) {
+                eprintln!("Failed to repair {}: {}", path.display(), e);
+            }
+        }
+        config
+    }
+
+    /// Carry over the `key = value` settings file older versions wrote.
+    pub fn from_legacy(settings: &Settings) -> Self {
+        let mut accepted = toml::Table::new();
+        for (key, value) in settings.entries() {
+            // Values were untyped; try the TOML reading first, then as a string
+            let typed = format!("value = {}", value)
+                .parse::<toml::Table>()
+                .ok()
+                .and_then(|mut table| table.remove("value"));
+            for value in typed.into_iter().chain([toml::Value::String(value.to_string())]) {
+                if accept(&mut accepted, key, value) {
+                    break;
+                }
+            }
+        }
+        toml::Value::Table(accepted).try_into().unwrap_or_default()
+    }
+
+    /// Parse `text`, keeping each entry that is valid on its own when the
+    /// whole isn't. Returns the config and whether anything was dropped.
+    fn parse(text: &str) -> (Self, bool) {
+        if let Ok(config) = toml::from_str(text) {
+            return (config, false);
+        }
+        let mut accepted = toml::Table::new();
+        for line in text.lines() {
+            let Ok(entry) = line.parse::<toml::Table>() else {
+                continue;
+            };
+            for (key, value) in entry {
+                accept(&mut accepted, &key, value);
+            }
+        }
+        (toml::Value::Table(accepted).try_into().unwrap_or_default(), true)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let text = toml::to_string(self).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        fs::write(path, text)
+    }
+}
+
+/// Per-user folder for the config and theme files, or the working directory
+/// where the platform has none.
+pub fn config_dir() -> PathBuf {
+    directories::ProjectDirs::from("", "", "Rust Audio Player")
+        .map(|dirs| dirs.config_dir().to_path_buf())
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+/// Per-user folder for data the app keeps outside the collections folder,
+/// or the working directory where the platform has none.
+pub fn data_dir() -> PathBuf {
+    directories::ProjectDirs::from("", "", "Rust Audio Player")
+        .map(|dirs| dirs.data_dir().to_path_buf())
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+/// Path of `name` in `dir`, creating `dir` if needed. A copy older versions
+/// kept in `legacy_dir` is moved over first, unless `dir` already has one.
+pub fn user_file(dir: &Path, legacy_dir: &Path, name: &str) -> PathBuf {
+    if let Err(e) = fs::create_dir_all(dir) {
+        eprintln!("Cannot create {}: {}", dir.display(), e);
+    }
+    let path = dir.join(name);
+    let legacy = legacy_dir.join(name);
+    if !path.exists() && legacy.is_file() {
+        match fs::copy(&legacy, &path) {
+            Ok(_) => {
+                let _ = fs::remove_file(&legacy);
+            }
+            Err(e) => eprintln!("Failed to move {} to {}: {}", legacy.display(), path.display(), e),
+        }
+    }
+    path
+}
+
+/// Add `key = value` to `table` if the result still reads as a `Config`.
+fn accept(table: &mut toml::Table, key: &str, value: toml::Value) -> bool {
+    let mut candidate = table.clone();
+    candidate.insert(key.to_string(), value);
+    let candidate = toml::Value::Table(candidate);
+    if candidate.clone().try_into::<Config>().is_err() {
+        return false;
+    }
+    if let toml::Value::Table(candidate) = candidate {
+        *table = candidate;
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_dir;
+
+    #[test]
+    fn missing_keys_take_their_defaults() {
+        let (config, damaged) = Config::parse("auto_continue = true\n");
+        assert!(!damaged);
+        assert!(config.auto_continue);
+        assert_eq!(Config { auto_continue: false, ..config }, Config::default());
+    }
+
+    #[test]
+    fn saved_config_reads_back() {
+        let dir = temp_dir("config", "round_trip");
+        let path = dir.join(CONFIG_FILE);
+        let config = Config {
+            output_device: Some("USB DAC".to_string()),
+            collections_sort: SortOrder::DateAdded,
+            visualizer_mode: VisualizerMode::VuMeter,
+            balance: -0.25,
+            ..Config::default()
+        };
+        config.save(&path).unwrap();
+        assert_eq!(Config::load(&path), config);
+        assert!(!dir.join("config.toml.bak").exists());
+    }
+
+    #[test]
+    fn malformed_lines_are_dropped_and_the_rest_kept() {
+        let dir = temp_dir("config", "repair");
+        let path = dir.join(CONFIG_FILE);
+        let damaged = "fade_in_secs = 1.5\nthis is not toml\nbalance = \"left\"\ncollections_sort = \"artist\"\n";
+        fs::write(&path, damaged).unwrap();
+
+        let config = Config::load(&path);
+        assert_eq!(config.fade_in_secs, 1.5);
+        assert_eq!(config.balance, 0.0);
+        assert_eq!(config.collections_sort, SortOrder::Artist);
+        // The original is kept and the file now reads cleanly
+        assert_eq!(fs::read_to_string(dir.join("config.toml.bak")).unwrap(), damaged);
+        assert!(!Config::parse(&fs::read_to_string(&path).unwrap()).1);
+    }
+
+    #[test]
+    fn non_utf8_bytes_only_affect_their_value() {
+        let dir = temp_dir("config", "latin1");
+        let path = dir.join(CONFIG_FILE);
+        fs::write(&path, b"collections_search = \"Bj\xf6rk\"\nauto_continue = true\n").unwrap();
+
+        let config = Config::load(&path);
+        assert_eq!(config.collections_search, "Bj\u{fffd}rk");
+        assert!(config.auto_continue);
+        assert!(!dir.join("config.toml.bak").exists());
+    }
+
+    #[test]
+    fn user_files_move_out_of_the_legacy_dir_once() {
+        let legacy_dir = temp_dir("config", "user_file_legacy");
+        let dir = temp_dir("config", "user_file").join("nested");
+        fs::write(legacy_dir.join(CONFIG_FILE), "auto_continue = true\n").unwrap();
+
+        let path = user_file(&dir, &legacy_dir, CONFIG_FILE);
+        assert_eq!(path, dir.join(CONFIG_FILE));
+        assert!(Config::load(&path).auto_continue);
+        assert!(!legacy_dir.join(CONFIG_FILE).exists());
+
+        // A copy left behind later doesn't replace the one already moved
+        fs::write(legacy_dir.join(CONFIG_FILE), "auto_continue = false\n").unwrap();
+        assert!(Config::load(&user_file(&dir, &legacy_dir, CONFIG_FILE)).auto_continue);
+    }
+
+    #[test]
+    fn legacy_settings_are_carried_over() {
+        let dir = temp_dir("config", "legacy");
+        let path = dir.join("audio_player.conf");
+        let legacy = "crossfade_secs = 2\noutput_device = 123\ncollections_dir = /music/2024\ncollection_click = play_next\nvisualizer_colors = nonsense\n";
+        fs::write(&path, legacy).unwrap();
+
+        let config = Config::from_legacy(&Settings::load(path));
+        assert_eq!(config.crossfade_secs, 2.0);
+        assert_eq!(config.output_device.as_deref(), Some("123"));
+        assert_eq!(config.collections_dir, Some(PathBuf::from("/music/2024")));
+        assert_eq!(config.collection_click, CollectionClickAction::PlayNext);
+        assert_eq!(config.visualizer_colors, SpectrogramColors::Heat);
+    }
+}
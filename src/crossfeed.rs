@@ -0,0 +1,137 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use biquad::{Biquad, DirectForm1, Q_BUTTERWORTH_F32};
+use rodio::Source;
+
+use crate::biquad_coefficients;
+
+/// Cutoff of the low-pass applied to the signal fed to the opposite ear.
+pub const CROSSFEED_CUTOFF_HZ: f32 = 700.0;
+/// Interaural delay applied to the crossfed signal, in seconds.
+pub const CROSSFEED_DELAY_SECS: f32 = 0.0003;
+/// Crossfeed level at full intensity (about -6 dB).
+pub const CROSSFEED_MAX_LEVEL: f32 = 0.5;
+
+/// User-facing crossfeed controls, shared with the audio thread.
+#[derive(Clone)]
+pub struct CrossfeedSettings {
+    pub enabled: bool,
+    pub intensity: f32, // 0.0..=1.0
+}
+
+impl Default for CrossfeedSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            intensity: 0.5,
+        }
+    }
+}
+
+/// One channel's path into the opposite ear: low-pass then a short delay.
+struct CrossfeedPath {
+    lowpass: DirectForm1<f32>,
+    delay: VecDeque<f32>,
+}
+
+impl CrossfeedPath {
+    fn new(sample_rate: f32) -> Self {
+        let coef = biquad_coefficients(biquad::Type::LowPass, sample_rate, CROSSFEED_CUTOFF_HZ, Q_BUTTERWORTH_F32);
+        let delay_len = (sample_rate * CROSSFEED_DELAY_SECS).round().max(1.0) as usize;
+        Self {
+            lowpass: DirectForm1::<f32>::new(coef),
+            delay: VecDeque::from(vec![0.0; delay_len]),
+        }
+    }
+
+    fn run(&mut self, sample: f32) -> f32 {
+        self.delay.push_back(self.lowpass.run(sample));
+        self.delay.pop_front().unwrap_or(0.0)
+    }
+}
+
+/// Stereo-aware source that blends a filtered, delayed copy of each channel
+/// into the other (Bauer-style crossfeed). Non-stereo sources pass through.
+pub struct CrossfeedSource<S>
+where
+    S: Source<Item = f32>,
+{
+    inner: S,
+    settings: Arc<Mutex<CrossfeedSettings>>,
+    left: CrossfeedPath,
+    right: CrossfeedPath,
+    // Right sample of the current frame, returned on the following call
+    pending: Option<f32>,
+}
+
+impl<S> CrossfeedSource<S>
+where
+    S: Source<Item = f32>,
+{
+    pub fn new(inner: S, settings: Arc<Mutex<CrossfeedSettings>>) -> Self {
+        let sample_rate = inner.sample_rate() as f32;
+        Self {
+            inner,
+            settings,
+            left: CrossfeedPath::new(sample_rate),
+            right: CrossfeedPath::new(sample_rate),
+            pending: None,
+        }
+    }
+}
+
+impl<S> Iterator for CrossfeedSource<S>
+where
+    S: Source<Item = f32>,
+{
+    type Item = f32;
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(sample) = self.pending.take() {
+            return Some(sample);
+        }
+        if self.inner.channels() != 2 {
+            return self.inner.next();
+        }
+        let l = self.inner.next()?;
+        let Some(r) = self.inner.next() else {
+            return Some(l);
+        };
+
+        // Keep the filters running while disabled so toggling doesn't click
+        let to_right = self.left.run(l);
+        let to_left = self.right.run(r);
+
+        let settings = self.settings.lock().unwrap().clone();
+        if !settings.enabled {
+            self.pending = Some(r);
+            return Some(l);
+        }
+        let level = settings.intensity.clamp(0.0, 1.0) * CROSSFEED_MAX_LEVEL;
+        let norm = 1.0 / (1.0 + level);
+        self.pending = Some((r + to_right * level) * norm);
+        Some((l + to_left * level) * norm)
+    }
+}
+
+impl<S> Source for CrossfeedSource<S>
+where
+    S: Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        // A buffered right sample still belongs to the current frame
+        self.inner
+            .current_frame_len()
+            .map(|len| len + self.pending.is_some() as usize)
+    }
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+}
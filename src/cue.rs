@@ -0,0 +1,258 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// CD frames per second, the unit of the last field of cue timestamps.
+const FRAMES_PER_SECOND: f32 = 75.0;
+
+/// One track of a cue sheet, as a span of the underlying file.
+pub struct CueTrack {
+    pub number: u32,
+    pub title: Option<String>,
+    pub performer: Option<String>,
+    pub start: f32,       // seconds into the file
+    pub end: Option<f32>, // None for the last track, which runs to the end
+}
+
+/// The album a cue sheet describes and its tracks, in order.
+pub struct CueSheet {
+    pub title: Option<String>,
+    pub performer: Option<String>,
+    pub tracks: Vec<CueTrack>,
+}
+
+/// Cue sheet describing `audio`: `<stem>.cue` next to it, or any cue sheet
+/// in the same folder whose FILE line names it. None if there is no sheet or
+/// it lists no tracks for this file.
+pub fn for_file(audio: &Path) -> Option<CueSheet> {
+    FolderCues::read(audio.parent()?).sheet_for(audio)
+}
+
+/// The text of every cue sheet in one folder, read once so each audio file
+/// in it can be matched without listing and rereading the folder.
+pub struct FolderCues {
+    sheets: Vec<(PathBuf, String)>, // sorted by path
+}
+
+impl FolderCues {
+    pub fn read(dir: &Path) -> Self {
+        let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+            .map(|entries| {
+                entries
+                    .flatten()
+                    .map(|entry| entry.path())
+                    .filter(|path| path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("cue")))
+                    .collect()
+            })
+            .unwrap_or_default();
+        paths.sort();
+        let sheets = paths.into_iter().filter_map(|path| read_text(&path).map(|text| (path, text))).collect();
+        Self { sheets }
+    }
+
+    /// Sheet describing `audio`, which must be in this folder: its own
+    /// `<stem>.cue` first, then the others in name order.
+    pub fn sheet_for(&self, audio: &Path) -> Option<CueSheet> {
+        let file_name = audio.file_name()?.to_str()?;
+        let own_path = audio.with_extension("cue");
+        let own = self.sheets.iter().filter(|(path, _)| *path == own_path).map(|(_, text)| parse(text, file_name, true));
+        let others = self.sheets.iter().filter(|(path, _)| *path != own_path).map(|(_, text)| parse(text, file_name, false));
+        own.chain(others).find(|sheet| !sheet.tracks.is_empty())
+    }
+}
+
+/// Cue sheets are often Latin-1 rather than UTF-8.
+fn read_text(path: &Path) -> Option<String> {
+    let bytes = fs::read(path).ok()?;
+    Some(match String::from_utf8(bytes) {
+        Ok(text) => text,
+        Err(e) => e.into_bytes().iter().map(|&b| b as char).collect(),
+    })
+}
+
+struct ParsedTrack {
+    number: u32,
+    file: Option<usize>,
+    title: Option<String>,
+    performer: Option<String>,
+    start: Option<f32>,
+}
+
+/// Parse cue sheet `text`, keeping the tracks that belong to the audio file
+/// named `audio_name`. When no FILE line matches, for example because the
+/// rip was renamed after the sheet was written, a sheet named after the
+/// file (`own_sheet`) with a single FILE is assumed to describe it anyway.
+pub fn parse(text: &str, audio_name: &str, own_sheet: bool) -> CueSheet {
+    let mut title = None;
+    let mut performer = None;
+    let mut files: Vec<String> = Vec::new();
+    let mut tracks: Vec<ParsedTrack> = Vec::new();
+    for line in text.trim_start_matches('\u{feff}').lines() {
+        let line = line.trim();
+        let (command, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let rest = rest.trim();
+        match command.to_ascii_uppercase().as_str() {
+            "FILE" => files.push(file_name_arg(rest)),
+            "TRACK" => tracks.push(ParsedTrack {
+                number: rest.split_whitespace().next().and_then(|n| n.parse().ok()).unwrap_or(tracks.len() as u32 + 1),
+                file: files.len().checked_sub(1),
+                title: None,
+                performer: None,
+                start: None,
+            }),
+            // Before the first TRACK these describe the whole album
+            "TITLE" => match tracks.last_mut() {
+                Some(track) => track.title = Some(unquote(rest)),
+                None => title = Some(unquote(rest)),
+            },
+            "PERFORMER" => match tracks.last_mut() {
+                Some(track) => track.performer = Some(unquote(rest)),
+                None => performer = Some(unquote(rest)),
+            },
+            "INDEX" => {
+                let mut fields = rest.split_whitespace();
+                let number = fields.next().and_then(|n| n.parse::<u32>().ok());
+                let start = fields.next().and_then(parse_timestamp);
+                // INDEX 00 is the pregap; the track proper starts at 01
+                if let (Some(1), Some(track), Some(start)) = (number, tracks.last_mut(), start) {
+                    track.start = Some(start);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let matching_file = files.iter().position(|file| same_file(file, audio_name));
+    let wanted = match matching_file {
+        Some(index) => Some(index),
+        None if own_sheet && files.len() <= 1 => files.len().checked_sub(1),
+        None => return CueSheet { title, performer, tracks: Vec::new() },
+    };
+    let starts: Vec<(ParsedTrack, f32)> = tracks
+        .into_iter()
+        .filter(|track| track.file == wanted)
+        .filter_map(|track| track.start.map(|start| (track, start)))
+        .collect();
+    let ends: Vec<Option<f32>> = starts.iter().skip(1).map(|(_, start)| Some(*start)).chain([None]).collect();
+    let tracks = starts
+        .into_iter()
+        .zip(ends)
+        .map(|((track, start), end)| CueTrack {
+            number: track.number,
+            title: track.title,
+            performer: track.performer,
+            start,
+            end,
+        })
+        .collect();
+    CueSheet { title, performer, tracks }
+}
+
+/// `"Album Name.flac" WAVE` or `Album.flac WAVE` -> the file name.
+fn file_name_arg(rest: &str) -> String {
+    if rest.starts_with('"') {
+        return unquote(rest);
+    }
+    // Unquoted names may contain spaces; the file type is the last word
+    match rest.rsplit_once(char::is_whitespace) {
+        Some((name, _file_type)) => name.trim().to_string(),
+        None => rest.to_string(),
+    }
+}
+
+/// Text between the first pair of double quotes, or the whole value when it
+/// isn't quoted. A missing closing quote runs to the end of the line.
+fn unquote(value: &str) -> String {
+    match value.strip_prefix('"') {
+        Some(inner) => inner.split('"').next().unwrap_or_default().to_string(),
+        None => value.to_string(),
+    }
+}
+
+/// Whether a FILE entry names `audio_name`, ignoring case and, since rips
+/// are often converted after the sheet was written, the extension.
+fn same_file(entry: &str, audio_name: &str) -> bool {
+    // Entries may carry a relative path
+    let entry = entry.rsplit(['/', '\\']).next().unwrap_or(entry);
+    let stem = |name: &str| Path::new(name).file_stem().map(|s| s.to_string_lossy().to_lowercase());
+    entry.eq_ignore_ascii_case(audio_name) || stem(entry) == stem(audio_name)
+}
+
+/// `mm:ss:ff` in seconds, where ff counts 1/75 s frames. Minutes may exceed
+/// 59, and a missing frames field is read as `mm:ss`.
+fn parse_timestamp(text: &str) -> Option<f32> {
+    let fields: Vec<f32> = text.split(':').map(|field| field.trim().parse().ok()).collect::<Option<_>>()?;
+    match fields[..] {
+        [minutes, seconds, frames] => Some(minutes * 60.0 + seconds + frames / FRAMES_PER_SECOND),
+        [minutes, seconds] => Some(minutes * 60.0 + seconds),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_dir;
+
+    const SHEET: &str = "\u{feff}REM GENRE Rock
+PERFORMER \"The Band\"
+TITLE \"Live At Home\"
+FILE \"Live At Home.flac\" WAVE
+  TRACK 01 AUDIO
+    TITLE \"Opening, Part 1\"
+    INDEX 01 00:00:00
+  TRACK 02 AUDIO
+    TITLE Encore
+    PERFORMER \"Guest Singer\"
+    INDEX 00 03:59:70
+    INDEX 01 04:00:30
+  TRACK 03 AUDIO
+    INDEX 01 61:02:74
+";
+
+    #[test]
+    fn titles_and_performers_are_read() {
+        let sheet = parse(SHEET, "Live At Home.flac", false);
+        assert_eq!(sheet.performer.as_deref(), Some("The Band"));
+        assert_eq!(sheet.title.as_deref(), Some("Live At Home"));
+        let titles: Vec<Option<&str>> = sheet.tracks.iter().map(|track| track.title.as_deref()).collect();
+        assert_eq!(titles, [Some("Opening, Part 1"), Some("Encore"), None]);
+        let performers: Vec<Option<&str>> = sheet.tracks.iter().map(|track| track.performer.as_deref()).collect();
+        assert_eq!(performers, [None, Some("Guest Singer"), None]);
+    }
+
+    #[test]
+    fn timestamps_count_75_frames_a_second() {
+        let sheet = parse(SHEET, "live at home.FLAC", false);
+        let numbers: Vec<u32> = sheet.tracks.iter().map(|track| track.number).collect();
+        assert_eq!(numbers, [1, 2, 3]);
+        // Track 2 starts at INDEX 01, not at its pregap
+        let close = |a: f32, b: f32| (a - b).abs() < 1e-3;
+        assert!(close(sheet.tracks[1].start, 240.4));
+        assert!(close(sheet.tracks[2].start, 3662.9867));
+        assert!(sheet.tracks[0].end.is_some_and(|end| close(end, 240.4)));
+        assert!(sheet.tracks[2].end.is_none());
+        assert_eq!(parse_timestamp("01:30"), Some(90.0));
+        assert_eq!(parse_timestamp("1:xx:00"), None);
+    }
+
+    #[test]
+    fn unmatched_single_file_sheet_counts_only_when_named_after_the_audio() {
+        assert_eq!(parse(SHEET, "Renamed.flac", true).tracks.len(), 3);
+        assert!(parse(SHEET, "Renamed.flac", false).tracks.is_empty());
+        // A re-encoded rip still matches by stem
+        assert_eq!(parse(SHEET, "Live At Home.mp3", false).tracks.len(), 3);
+    }
+
+    #[test]
+    fn latin1_sheets_are_decoded() {
+        let dir = temp_dir("cue", "latin1");
+        fs::write(dir.join("Album.cue"), b"FILE \"Album.flac\" WAVE\nTRACK 01 AUDIO\nTITLE \"Bj\xf6rk\"\nINDEX 01 00:00:00\n").unwrap();
+        fs::write(dir.join("Other.cue"), "FILE \"Other.flac\" WAVE\nTRACK 01 AUDIO\nINDEX 01 00:00:00\n").unwrap();
+
+        let cues = FolderCues::read(&dir);
+        let sheet = cues.sheet_for(&dir.join("Album.flac")).unwrap();
+        assert_eq!(sheet.tracks[0].title.as_deref(), Some("Björk"));
+        // Another file's sheet with one FILE line doesn't claim this one
+        assert!(cues.sheet_for(&dir.join("Unrelated.flac")).is_none());
+    }
+}
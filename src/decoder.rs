@@ -0,0 +1,244 @@
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use rodio::Source;
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::{Decoder, DecoderOptions, CODEC_TYPE_NULL};
+use symphonia::core::errors::Error;
+use symphonia::core::formats::{FormatOptions, FormatReader, SeekMode, SeekTo};
+use symphonia::core::io::{MediaSource, MediaSourceStream};
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+use symphonia::core::units::TimeBase;
+
+/// Decodes the first audio track of a file or stream with symphonia. Unlike
+/// rodio's `Decoder` it can start partway in by seeking the container,
+/// rather than decoding and throwing away everything before that point.
+pub struct TrackDecoder {
+    format: Box<dyn FormatReader>,
+    decoder: Box<dyn Decoder>,
+    track_id: u32,
+    time_base: Option<TimeBase>,
+    total_duration: Option<Duration>,
+    buffer: Option<SampleBuffer<f32>>,
+    pos: usize, // next sample in `buffer`
+    channels: u16,
+    sample_rate: u32,
+    // Frames still to drop after a seek landed before the requested time
+    skip_frames: u64,
+}
+
+impl TrackDecoder {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let file = fs::File::open(path).map_err(|e| e.to_string())?;
+        let extension = path.extension().and_then(|e| e.to_str());
+        Self::new(Box::new(file), extension)
+    }
+
+    /// Probe `source` and decode its first packet, so the channel count and
+    /// sample rate are known and undecodable files fail here.
+    pub fn new(source: Box<dyn MediaSource>, extension: Option<&str>) -> Result<Self, String> {
+        let mut hint = Hint::new();
+        if let Some(extension) = extension {
+            hint.with_extension(extension);
+        }
+        let stream = MediaSourceStream::new(source, Default::default());
+        let options = FormatOptions { enable_gapless: true, ..Default::default() };
+        let probed = symphonia::default::get_probe()
+            .format(&hint, stream, &options, &MetadataOptions::default())
+            .map_err(|e| e.to_string())?;
+        let format = probed.format;
+        let track = format
+            .tracks()
+            .iter()
+            .find(|track| track.codec_params.codec != CODEC_TYPE_NULL)
+            .ok_or("no audio track")?;
+        let params = &track.codec_params;
+        let decoder = symphonia::default::get_codecs()
+            .make(params, &DecoderOptions::default())
+            .map_err(|e| e.to_string())?;
+        let total_duration = params.time_base.zip(params.n_frames).map(|(time_base, frames)| {
+            let time = time_base.calc_time(frames);
+            Duration::from_secs(time.seconds) + Duration::from_secs_f64(time.frac)
+        });
+        let mut this = Self {
+            track_id: track.id,
+            time_base: params.time_base,
+            total_duration,
+            channels: params.channels.map_or(2, |channels| channels.count() as u16),
+            sample_rate: params.sample_rate.unwrap_or(44100),
+            format,
+            decoder,
+            buffer: None,
+            pos: 0,
+            skip_frames: 0,
+        };
+        if !this.refill() {
+            return Err("no audio could be decoded".to_string());
+        }
+        Ok(this)
+    }
+
+    /// Continue from `secs` into the track. The container is seeked to the
+    /// packet before that time and the frames up to it are dropped, so
+    /// playback lands exactly on the requested time.
+    pub fn seek(&mut self, secs: f32) -> Result<(), String> {
+        let to = SeekTo::Time { time: (secs.max(0.0) as f64).into(), track_id: Some(self.track_id) };
+        let seeked = self.format.seek(SeekMode::Accurate, to).map_err(|e| e.to_string())?;
+        self.decoder.reset();
+        let early = seeked.required_ts.saturating_sub(seeked.actual_ts);
+        // Timestamps count in the track's time base, frames at its sample rate
+        self.skip_frames = match self.time_base {
+            Some(time_base) => early * time_base.numer as u64 * self.sample_rate as u64 / time_base.denom as u64,
+            None => early,
+        };
+        self.buffer = None;
+        self.pos = 0;
+        if !self.refill() {
+            return Err("nothing to play after the seek position".to_string());
+        }
+        Ok(())
+    }
+
+    /// Samples left in the decoded packet being played.
+    pub fn remaining_in_packet(&self) -> usize {
+        self.buffer.as_ref().map_or(0, |buffer| buffer.len() - self.pos)
+    }
+
+    /// Decode the next packet of our track into `buffer`. False at the end
+    /// of the stream or on an error decoding can't continue past.
+    fn refill(&mut self) -> bool {
+        loop {
+            let packet = match self.format.next_packet() {
+                Ok(packet) => packet,
+                Err(_) => return false,
+            };
+            if packet.track_id() != self.track_id {
+                continue;
+            }
+            let decoded = match self.decoder.decode(&packet) {
+                Ok(decoded) => decoded,
+                // A damaged packet is skipped rather than ending the track
+                Err(Error::DecodeError(_)) => continue,
+                Err(_) => return false,
+            };
+            let spec = *decoded.spec();
+            let channels = spec.channels.count();
+            let frames = decoded.frames() as u64;
+            // The buffer is reused while the packets keep the same layout
+            let fits = self.channels as usize == channels
+                && self.buffer.as_ref().is_some_and(|buffer| buffer.capacity() >= decoded.capacity() * channels);
+            if !fits {
+                self.buffer = Some(SampleBuffer::new(decoded.capacity() as u64, spec));
+            }
+            let buffer = self.buffer.as_mut().expect("buffer was just made");
+            buffer.copy_interleaved_ref(decoded);
+            self.channels = channels as u16;
+            self.sample_rate = spec.rate;
+            let skipped = self.skip_frames.min(frames);
+            self.skip_frames -= skipped;
+            self.pos = skipped as usize * self.channels as usize;
+            if self.pos < buffer.len() {
+                return true;
+            }
+        }
+    }
+}
+
+impl Iterator for TrackDecoder {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let buffer = self.buffer.as_ref()?;
+        let sample = buffer.samples()[self.pos];
+        self.pos += 1;
+        // Decode ahead so the frame length and format below describe what comes next
+        if self.pos >= buffer.len() && !self.refill() {
+            self.buffer = None;
+        }
+        Some(sample)
+    }
+}
+
+impl Source for TrackDecoder {
+    fn current_frame_len(&self) -> Option<usize> {
+        Some(self.remaining_in_packet())
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.total_duration
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const RATE: u32 = 8000;
+
+    /// A mono 16-bit WAV file whose nth sample is n, wrapping at 16 bits.
+    fn ramp_wav(frames: u32) -> Vec<u8> {
+        let data_len = frames * 2;
+        let mut wav = Vec::new();
+        wav.extend_from_slice(b"RIFF");
+        wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+        wav.extend_from_slice(b"WAVEfmt ");
+        wav.extend_from_slice(&16u32.to_le_bytes());
+        wav.extend_from_slice(&1u16.to_le_bytes()); // PCM
+        wav.extend_from_slice(&1u16.to_le_bytes()); // mono
+        wav.extend_from_slice(&RATE.to_le_bytes());
+        wav.extend_from_slice(&(RATE * 2).to_le_bytes());
+        wav.extend_from_slice(&2u16.to_le_bytes());
+        wav.extend_from_slice(&16u16.to_le_bytes());
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&data_len.to_le_bytes());
+        for n in 0..frames {
+            wav.extend_from_slice(&(n as i16).to_le_bytes());
+        }
+        wav
+    }
+
+    /// The ramp value a decoded sample stands for.
+    fn frame_index(sample: f32) -> u32 {
+        (sample * 32768.0).round() as u32
+    }
+
+    fn decoder(frames: u32) -> TrackDecoder {
+        TrackDecoder::new(Box::new(Cursor::new(ramp_wav(frames))), Some("wav")).unwrap()
+    }
+
+    #[test]
+    fn decodes_every_frame_in_order() {
+        let decoder = decoder(RATE);
+        assert_eq!((decoder.channels(), decoder.sample_rate()), (1, RATE));
+        assert_eq!(decoder.total_duration(), Some(Duration::from_secs(1)));
+        let samples: Vec<u32> = decoder.map(frame_index).collect();
+        assert_eq!(samples, (0..RATE).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn seek_lands_on_the_exact_frame() {
+        let mut decoder = decoder(RATE);
+        decoder.seek(0.3).unwrap();
+        assert_eq!(decoder.next().map(frame_index), Some(2400));
+        assert_eq!(decoder.count(), (RATE - 2401) as usize);
+    }
+
+    #[test]
+    fn frame_length_counts_down_the_packet() {
+        let mut decoder = decoder(RATE);
+        let packet = decoder.current_frame_len().unwrap();
+        decoder.next();
+        assert_eq!(decoder.current_frame_len(), Some(packet - 1));
+    }
+}
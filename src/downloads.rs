@@ -0,0 +1,407 @@
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+
+use crate::tasks::{TaskHandle, TaskManager};
+use crate::{clean_title, metadata, DownloadEvent, MediaItem, YT_DLP_MISSING};
+
+/// yt-dlp processes allowed to run at once.
+pub const MAX_CONCURRENT_DOWNLOADS: usize = 2;
+/// Tries per job before it is left as failed.
+const MAX_ATTEMPTS: u32 = 3;
+/// How often a running process is checked for exit or cancellation.
+const EXIT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Where a job is in its lifecycle.
+#[derive(Clone, Copy, PartialEq)]
+pub enum JobState {
+    Queued,
+    Active,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+/// What a worker thread reports when its yt-dlp run ends.
+struct JobDone {
+    id: u64,
+    result: Result<String, String>,
+    cancelled: bool,
+    // Playlist entries that failed, as their own URLs, so only those are retried
+    failed_entries: Vec<String>,
+}
+
+struct DownloadJob {
+    id: u64,
+    url: String,
+    output_dir: PathBuf,
+    state: JobState,
+    status: String,
+    attempts: u32,
+    // The job's row in the task bar while it is queued; moved into the worker once it starts
+    task: Option<TaskHandle>,
+}
+
+/// Runs yt-dlp downloads on a bounded number of worker threads. Queued and
+/// running downloads show in the task bar, where they can be cancelled.
+/// Finished tracks are delivered as `DownloadEvent::Finished` on `items`.
+pub struct DownloadManager {
+    jobs: Vec<DownloadJob>,
+    next_id: u64,
+    items: Sender<DownloadEvent>,
+    sender: Sender<JobDone>,
+    receiver: Receiver<JobDone>,
+}
+
+impl DownloadManager {
+    pub fn new(items: Sender<DownloadEvent>) -> Self {
+        let (sender, receiver) = channel();
+        Self {
+            jobs: Vec::new(),
+            next_id: 0,
+            items,
+            sender,
+            receiver,
+        }
+    }
+
+    /// Queue `url` for download into `output_dir`. It starts on the next `poll`
+    /// once a worker slot is free.
+    pub fn enqueue(&mut self, url: String, output_dir: &Path) {
+        self.push_job(url, output_dir.to_path_buf(), 0);
+    }
+
+    fn push_job(&mut self, url: String, output_dir: PathBuf, attempts: u32) {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.jobs.push(DownloadJob {
+            id,
+            url,
+            output_dir,
+            state: JobState::Queued,
+            status: "Queued".to_string(),
+            attempts,
+            task: None,
+        });
+    }
+
+    fn count(&self, state: JobState) -> usize {
+        self.jobs.iter().filter(|job| job.state == state).count()
+    }
+
+    /// Whether any download has finished, so there is something for `draw` to show.
+    pub fn has_finished(&self) -> bool {
+        self.jobs.iter().any(|job| {
+            matches!(job.state, JobState::Completed | JobState::Failed | JobState::Cancelled)
+        })
+    }
+
+    /// Aggregate status such as "2 downloading, 3 queued".
+    pub fn summary(&self) -> String {
+        let parts: Vec<String> = [
+            (JobState::Active, "downloading"),
+            (JobState::Queued, "queued"),
+            (JobState::Completed, "done"),
+            (JobState::Failed, "failed"),
+        ]
+        .iter()
+        .map(|&(state, label)| (self.count(state), label))
+        .filter(|&(count, _)| count > 0)
+        .map(|(count, label)| format!("{} {}", count, label))
+        .collect();
+        if parts.is_empty() {
+            "No downloads".to_string()
+        } else {
+            parts.join(", ")
+        }
+    }
+
+    /// Apply finished runs, retry failures and start queued jobs while slots
+    /// are free. Queued jobs get their task bar row from `tasks`.
+    pub fn poll(&mut self, tasks: &mut TaskManager) {
+        while let Ok(done) = self.receiver.try_recv() {
+            let Some(job) = self.jobs.iter_mut().find(|job| job.id == done.id) else {
+                continue;
+            };
+            let (attempts, output_dir) = (job.attempts, job.output_dir.clone());
+            match done.result {
+                _ if done.cancelled => {
+                    job.state = JobState::Cancelled;
+                    job.status = "Cancelled".to_string();
+                }
+                Ok(message) => {
+                    job.state = JobState::Completed;
+                    job.status = message;
+                }
+                // A missing binary won't fix itself on retry
+                Err(error) if attempts < MAX_ATTEMPTS && error != YT_DLP_MISSING && done.failed_entries.is_empty() => {
+                    job.state = JobState::Queued;
+                    job.status = format!("Retrying after error: {}", error);
+                }
+                Err(error) => {
+                    job.state = JobState::Failed;
+                    job.status = error;
+                }
+            }
+            if done.cancelled {
+                continue;
+            }
+            // Each failed playlist entry becomes its own job, so a retry
+            // doesn't download the rest of the playlist again
+            for entry in done.failed_entries {
+                self.push_job(entry, output_dir.clone(), attempts);
+                let job = self.jobs.last_mut().unwrap();
+                if attempts >= MAX_ATTEMPTS {
+                    job.state = JobState::Failed;
+                    job.status = "Playlist entry failed".to_string();
+                }
+            }
+        }
+
+        for job in self.jobs.iter_mut().filter(|job| job.state == JobState::Queued) {
+            match &job.task {
+                Some(task) if task.is_cancelled() => {
+                    job.task = None;
+                    job.state = JobState::Cancelled;
+                    job.status = "Cancelled".to_string();
+                }
+                Some(_) => {}
+                None => job.task = Some(tasks.start(format!("Download {}", job.url))),
+            }
+        }
+
+        let mut free = MAX_CONCURRENT_DOWNLOADS.saturating_sub(self.count(JobState::Active));
+        for job in self.jobs.iter_mut().filter(|job| job.state == JobState::Queued) {
+            if free == 0 {
+                break;
+            }
+            let Some(task) = job.task.take() else {
+                continue;
+            };
+            free -= 1;
+            job.state = JobState::Active;
+            job.attempts += 1;
+            job.status = "Downloading".to_string();
+            let id = job.id;
+            let url = job.url.clone();
+            let output_template = format!("{}/%(title)s.%(ext)s", job.output_dir.display());
+            let updates = self.sender.clone();
+            let items = self.items.clone();
+            thread::spawn(move || {
+                let (result, failed_entries) = run_download(&url, &output_template, &task, &items);
+                let cancelled = task.is_cancelled();
+                let _ = updates.send(JobDone { id, result, cancelled, failed_entries });
+            });
+        }
+    }
+
+    /// One row per finished job with its outcome, and a retry button for
+    /// failed or cancelled ones. Progress is shown in the task bar.
+    pub fn draw(&mut self, ui: &mut egui::Ui) {
+        for job in &mut self.jobs {
+            if matches!(job.state, JobState::Queued | JobState::Active) {
+                continue;
+            }
+            ui.horizontal(|ui| {
+                ui.label(&job.url);
+                if job.state == JobState::Failed {
+                    ui.colored_label(egui::Color32::LIGHT_RED, "Failed");
+                }
+                ui.label(egui::RichText::new(&job.status).small());
+                if matches!(job.state, JobState::Failed | JobState::Cancelled) && ui.button("Retry").clicked() {
+                    job.attempts = 0;
+                    job.state = JobState::Queued;
+                    job.status = "Queued".to_string();
+                }
+            });
+        }
+        if ui.button("Clear finished").clicked() {
+            self.jobs.retain(|job| matches!(job.state, JobState::Queued | JobState::Active));
+        }
+    }
+}
+
+/// Whether `url` points at a YouTube playlist rather than a single video.
+fn is_playlist_url(url: &str) -> bool {
+    url.contains("list=") || url.contains("/playlist")
+}
+
+/// Fraction complete from a yt-dlp `[download]  42.3% of ...` line.
+fn parse_download_progress(line: &str) -> Option<f32> {
+    let rest = line.strip_prefix("[download]")?;
+    let percent = rest.split_whitespace().next()?.strip_suffix('%')?;
+    percent.parse::<f32>().ok().map(|p| p / 100.0)
+}
+
+/// Position from a yt-dlp `[download] Downloading item 3 of 12` line.
+/// Older yt-dlp versions say "video" instead of "item".
+fn parse_playlist_item(line: &str) -> Option<(usize, usize)> {
+    let rest = line.strip_prefix("[download]")?.trim_start();
+    let rest = rest
+        .strip_prefix("Downloading item ")
+        .or_else(|| rest.strip_prefix("Downloading video "))?;
+    let (index, total) = rest.split_once(" of ")?;
+    Some((index.trim().parse().ok()?, total.trim().parse().ok()?))
+}
+
+/// Item for a yt-dlp `<path>\t<channel>\t<title>` line, or `None` if the file
+/// isn't there. The title is cleaned up and written back into the file's tags
+/// so it survives the tag read that follows.
+fn downloaded_item(line: &str) -> Option<MediaItem> {
+    let mut fields = line.split('\t').map(str::trim);
+    let path = PathBuf::from(fields.next()?);
+    if !path.exists() {
+        return None;
+    }
+    let known = |field: &&str| !field.is_empty() && *field != "NA";
+    let channel = fields.next().filter(known);
+    let title = fields.next().filter(known);
+    let mut item = MediaItem::from_path(path);
+    if let Some(title) = title {
+        item.display_name = clean_title(title);
+        if let Err(e) = metadata::write_title(&item.file_path, &item.display_name) {
+            eprintln!("Could not tag {}: {}", item.file_path.display(), e);
+        }
+    }
+    item.artist = channel.map(str::to_string);
+    Some(item)
+}
+
+/// Video URL of a playlist entry yt-dlp reported as `ERROR: [youtube] <id>: <reason>`.
+fn failed_entry_url(line: &str) -> Option<String> {
+    let rest = line.strip_prefix("ERROR: [youtube] ")?;
+    let (id, _) = rest.split_once(':')?;
+    let id = id.trim();
+    let valid = !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    valid.then(|| format!("https://www.youtube.com/watch?v={}", id))
+}
+
+/// Wait for `child` to exit, killing it once the task is cancelled.
+fn wait_for_exit(child: &mut Child, task: &TaskHandle) -> io::Result<ExitStatus> {
+    loop {
+        if task.is_cancelled() {
+            let _ = child.kill();
+        }
+        if let Some(status) = child.try_wait()? {
+            return Ok(status);
+        }
+        thread::sleep(EXIT_POLL_INTERVAL);
+    }
+}
+
+/// Run one yt-dlp download to completion, reporting progress on `task` and
+/// sending each finished track on `items`. Returns the final status line or
+/// the error to show, plus the playlist entries that failed.
+fn run_download(
+    url: &str,
+    output_template: &str,
+    task: &TaskHandle,
+    items: &Sender<DownloadEvent>,
+) -> (Result<String, String>, Vec<String>) {
+    let playlist = is_playlist_url(url);
+
+    let mut command = Command::new("yt-dlp");
+    // One tab-separated line per finished entry: path, channel and original title
+    command.args(["--newline", "--progress", "--print", "after_move:%(filepath)s\t%(channel,uploader|)s\t%(title|)s"]);
+    // Tag the file and keep the thumbnail both embedded and as a sidecar image
+    command.args(["--embed-metadata", "--embed-thumbnail", "--write-thumbnail", "--convert-thumbnails", "jpg"]);
+    if playlist {
+        // Skip unavailable entries instead of aborting the whole playlist
+        command.args(["--yes-playlist", "--ignore-errors"]);
+    } else {
+        command.arg("--no-playlist");
+    }
+    let child = command
+        .args(["--extract-audio", "--audio-format", "mp3", "-o", output_template, url])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn();
+    let mut child = match child {
+        Ok(child) => child,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            let _ = items.send(DownloadEvent::Failed(YT_DLP_MISSING.to_string()));
+            return (Err(YT_DLP_MISSING.to_string()), Vec::new());
+        }
+        Err(e) => return (Err(format!("Failed to start yt-dlp: {}", e)), Vec::new()),
+    };
+    let stdout = child.stdout.take();
+    let stderr = child.stderr.take();
+
+    let (status, stderr_text, (added, total)) = thread::scope(|scope| {
+        // Drain stderr on its own thread so a chatty yt-dlp can't block on a full pipe
+        let stderr_reader = scope.spawn(move || {
+            let mut text = String::new();
+            if let Some(mut stderr) = stderr {
+                let _ = stderr.read_to_string(&mut text);
+            }
+            text
+        });
+        let stdout_reader = scope.spawn(move || {
+            // Playlist position as (item, total); single videos stay at 1/1
+            let mut item_position = (1, 1);
+            let mut added = 0;
+            if let Some(stdout) = stdout {
+                for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                    let line = line.trim();
+                    let (index, total) = item_position;
+                    if let Some(position) = parse_playlist_item(line) {
+                        item_position = position;
+                        task.progress(position.0.saturating_sub(1) as f32 / position.1.max(1) as f32);
+                    } else if let Some(fraction) = parse_download_progress(line) {
+                        task.progress((index as f32 - 1.0 + fraction) / total as f32);
+                    } else if !line.is_empty() && !line.starts_with('[') {
+                        // The only non-bracketed output is the printed entry line
+                        if let Some(item) = downloaded_item(line) {
+                            added += 1;
+                            let _ = items.send(DownloadEvent::Finished(item, url.to_string()));
+                        }
+                    }
+                }
+            }
+            (added, item_position.1)
+        });
+        let status = wait_for_exit(&mut child, task);
+        (status, stderr_reader.join().unwrap_or_default(), stdout_reader.join().unwrap_or((0, 1)))
+    });
+    if task.is_cancelled() {
+        return (Err("Cancelled".to_string()), Vec::new());
+    }
+    let failed_entries: Vec<String> = if playlist {
+        stderr_text.lines().filter_map(failed_entry_url).collect()
+    } else {
+        Vec::new()
+    };
+    let reason = stderr_text
+        .lines()
+        .rev()
+        .find(|line| !line.trim().is_empty())
+        .unwrap_or("no error output")
+        .trim()
+        .to_string();
+
+    let result = match status {
+        Ok(_) if playlist && (added > 0 || !failed_entries.is_empty()) => {
+            let skipped = total.saturating_sub(added);
+            if !failed_entries.is_empty() {
+                Ok(format!("Added {} playlist tracks ({} failed, queued on their own)", added, failed_entries.len()))
+            } else if skipped > 0 {
+                Ok(format!("Added {} playlist tracks ({} unavailable)", added, skipped))
+            } else {
+                Ok(format!("Added {} playlist tracks", added))
+            }
+        }
+        Ok(status) if status.success() => {
+            if added == 0 {
+                Err("yt-dlp finished but no audio file was produced".to_string())
+            } else {
+                Ok("Added to collection".to_string())
+            }
+        }
+        Ok(status) => Err(format!("yt-dlp failed ({}): {}", status, reason)),
+        Err(e) => Err(format!("yt-dlp failed: {}", e)),
+    };
+    (result, failed_entries)
+}
@@ -0,0 +1,63 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+/// A user-saved equalizer curve.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SavedPreset {
+    pub name: String,
+    pub bands: Vec<f32>,
+    #[serde(default)]
+    pub preamp_db: f32,
+    #[serde(default)]
+    pub bass_db: f32,
+    #[serde(default)]
+    pub treble_db: f32,
+}
+
+/// Directory holding saved presets, one JSON file each.
+pub fn presets_dir(collections_path: &Path) -> PathBuf {
+    collections_path.join("eq_presets")
+}
+
+/// `name` with characters that aren't safe in file names replaced, so any
+/// display name can be saved to disk.
+pub fn safe_file_stem(name: &str) -> String {
+    let file_stem: String = name
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' || c == ' ' { c } else { '_' })
+        .collect();
+    file_stem.trim().to_string()
+}
+
+/// File a preset named `name` is stored in.
+fn preset_path(dir: &Path, name: &str) -> PathBuf {
+    dir.join(format!("{}.json", safe_file_stem(name)))
+}
+
+/// Load every readable preset in `dir`, sorted by name.
+pub fn load_all(dir: &Path) -> Vec<SavedPreset> {
+    let mut presets: Vec<SavedPreset> = fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|entry| entry.path())
+                .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+                .filter_map(|path| fs::read_to_string(path).ok())
+                .filter_map(|text| serde_json::from_str(&text).ok())
+                .collect()
+        })
+        .unwrap_or_default();
+    presets.sort_by_key(|preset: &SavedPreset| preset.name.to_lowercase());
+    presets
+}
+
+/// Write `preset` to `dir`, replacing any preset with the same name.
+pub fn save(dir: &Path, preset: &SavedPreset) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    let json = serde_json::to_string_pretty(preset)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    fs::write(preset_path(dir, &preset.name), json)
+}
@@ -0,0 +1,54 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// File the favorites are stored in, inside the collections folder.
+pub const FAVORITES_FILE: &str = "favorites.json";
+
+/// Starred tracks, keyed by file path.
+#[derive(Default)]
+pub struct Favorites {
+    paths: BTreeSet<PathBuf>,
+}
+
+impl Favorites {
+    /// Load saved favorites; a missing or unreadable file starts empty.
+    pub fn load(collections_path: &Path) -> Self {
+        let paths = fs::read_to_string(collections_path.join(FAVORITES_FILE))
+            .ok()
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .unwrap_or_default();
+        Self { paths }
+    }
+
+    pub fn save(&self, collections_path: &Path) -> io::Result<()> {
+        let json = serde_json::to_string_pretty(&self.paths)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        fs::write(collections_path.join(FAVORITES_FILE), json)
+    }
+
+    pub fn contains(&self, path: &Path) -> bool {
+        self.paths.contains(path)
+    }
+
+    pub fn toggle(&mut self, path: &Path) {
+        if !self.paths.remove(path) {
+            self.paths.insert(path.to_path_buf());
+        }
+    }
+
+    /// Move the star to a renamed file. Returns whether `from` was starred.
+    pub fn rename(&mut self, from: &Path, to: &Path) -> bool {
+        let starred = self.paths.remove(from);
+        if starred {
+            self.paths.insert(to.to_path_buf());
+        }
+        starred
+    }
+
+    /// Forget a deleted file. Returns whether it was starred.
+    pub fn remove(&mut self, path: &Path) -> bool {
+        self.paths.remove(path)
+    }
+}
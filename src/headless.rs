@@ -0,0 +1,152 @@
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use rand::seq::SliceRandom;
+use rodio::{OutputStream, Sink};
+
+use crate::crossfeed::CrossfeedSettings;
+use crate::loudness::LoudnessCache;
+use crate::player::Player;
+use crate::position::PlaybackPosition;
+use crate::widgets::format_time;
+use crate::{
+    config, configured_collections_path, cue, eq_presets, folder_audio_files, is_audio_file, metadata,
+    EqualizerPreset, EqualizerSettings, LaunchArgs, MediaItem, RepeatMode, SourceChain,
+};
+
+/// How often the progress line is redrawn and Ctrl+C is checked for.
+const TICK: Duration = Duration::from_millis(200);
+
+/// Play `args.paths` to the end on the default output device, printing each
+/// track and its progress, until they run out or Ctrl+C is pressed.
+pub fn run(args: LaunchArgs, config: &config::Config) -> Result<(), String> {
+    let mut queue = Vec::new();
+    for path in &args.paths {
+        let files = if path.is_dir() {
+            folder_audio_files(path)
+        } else if path.is_file() && is_audio_file(path) {
+            vec![path.clone()]
+        } else {
+            eprintln!("Warning: skipping {}: not an audio file or folder", path.display());
+            continue;
+        };
+        let folder = if path.is_dir() { path.as_path() } else { path.parent().unwrap_or(path) };
+        let cues = cue::FolderCues::read(folder);
+        for file in files {
+            let sheet = cues.sheet_for(&file);
+            queue.extend(MediaItem::from_path(file).split_by_sheet(sheet));
+        }
+    }
+    if queue.is_empty() {
+        return Err("nothing to play".to_string());
+    }
+    if args.shuffle {
+        queue.shuffle(&mut rand::thread_rng());
+    }
+
+    let collections_path = configured_collections_path(config);
+    let mut equalizer = EqualizerSettings::new();
+    if let Some(name) = &args.eq_preset {
+        let presets_dir = eq_presets::presets_dir(&collections_path);
+        let saved = eq_presets::load_all(&presets_dir)
+            .into_iter()
+            .find(|p| p.name.eq_ignore_ascii_case(name));
+        if let Some(saved) = saved {
+            equalizer.apply_saved(&saved);
+        } else if let Some(preset) = EqualizerPreset::from_name(name) {
+            equalizer.preset = preset;
+            equalizer.apply_preset();
+        } else {
+            eprintln!("Warning: unknown equalizer preset \"{}\"", name);
+        }
+    }
+    let crossfeed = CrossfeedSettings {
+        enabled: config.crossfeed_enabled,
+        intensity: config.crossfeed_intensity,
+    };
+    // Only gains measured by the player are used; nothing is analyzed here
+    let loudness = config.normalize_volume.then(|| LoudnessCache::load(&collections_path));
+
+    let output = OutputStream::try_default().map_err(|e| format!("no audio output: {}", e))?;
+    let mut player = Player::new(Some(output), SourceChain::new(equalizer, crossfeed));
+    player.set_volume(args.volume.unwrap_or(1.0));
+    let interrupted = Arc::new(AtomicBool::new(false));
+    let flag = interrupted.clone();
+    ctrlc::set_handler(move || flag.store(true, Ordering::Relaxed))
+        .map_err(|e| format!("cannot handle Ctrl+C: {}", e))?;
+
+    let count = queue.len();
+    // The queue is already in play order, so it simply runs to the end
+    player.queue = queue;
+    player.current_index = Some(0);
+    player.repeat_mode = RepeatMode::Off;
+    // The following track, appended behind the current one so there is no
+    // gap between them, with its length and position
+    let mut queued: Option<(usize, Option<f32>, PlaybackPosition)> = None;
+    while let Some(i) = player.current_index {
+        let gain = |player: &Player, index: usize| {
+            loudness.as_ref().map_or(1.0, |loudness| player.normalization_gain(&player.queue[index], loudness))
+        };
+        let total = match queued.take() {
+            Some((index, total, position)) if index == i => {
+                player.playback_position = Some(position);
+                total
+            }
+            _ => match player.play_track(i, 0.0, gain(&player, i)) {
+                Ok(total) => total,
+                Err(e) => {
+                    eprintln!("Warning: cannot decode {}: {}", player.queue[i].file_path.display(), e);
+                    advance(&mut player);
+                    continue;
+                }
+            },
+        };
+        // Tags are read as each track starts rather than all up front
+        if let Some(tags) = metadata::read_tags(&player.queue[i].file_path) {
+            player.queue[i].apply_tags(&tags);
+        }
+        println!("[{}/{}] {}", i + 1, count, player.queue[i].label());
+
+        let mut prebuffered = false;
+        // The current track has ended once only the queued one is left
+        while player.sink.as_ref().map_or(0, Sink::len) > usize::from(queued.is_some()) {
+            if interrupted.load(Ordering::Relaxed) {
+                player.stop();
+                println!("\nStopped");
+                return Ok(());
+            }
+            if !prebuffered {
+                prebuffered = true;
+                // A track that fails here is reported when its turn comes
+                if let Some(next) = player.auto_advance_index()
+                    && let Ok(track) = player.decode(next, 0.0, gain(&player, next))
+                    && player.append_source(track.source)
+                {
+                    queued = Some((next, track.duration, track.position));
+                }
+            }
+            let status = player.status();
+            let progress = match total {
+                Some(total) => format!("{} / {}", format_time(status.position.min(total)), format_time(total)),
+                None => format_time(status.position),
+            };
+            print!("\r  {}", progress);
+            let _ = io::stdout().flush();
+            thread::sleep(TICK);
+        }
+        println!();
+        advance(&mut player);
+    }
+    Ok(())
+}
+
+/// Move to the next track, or clear the current one at the end of the queue.
+fn advance(player: &mut Player) {
+    match player.auto_advance_index() {
+        Some(next) => player.play_index(next),
+        None => player.current_index = None,
+    }
+}
@@ -0,0 +1,86 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+/// File the play history is stored in, inside the collections folder.
+pub const HISTORY_FILE: &str = "play_history.json";
+
+/// Most plays kept in the log; older ones are pruned. Play counts are kept
+/// separately and survive pruning.
+const MAX_PLAYS: usize = 1000;
+
+/// One completed play.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Play {
+    pub path: PathBuf,
+    pub timestamp: u64, // seconds since the Unix epoch
+}
+
+/// Completed plays, oldest first, and how often each track was played.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct History {
+    plays: Vec<Play>,
+    counts: HashMap<PathBuf, u32>,
+}
+
+impl History {
+    /// Load the saved history; a missing or unreadable file starts a new one.
+    pub fn load(collections_path: &Path) -> Self {
+        fs::read_to_string(collections_path.join(HISTORY_FILE))
+            .ok()
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, collections_path: &Path) -> io::Result<()> {
+        let json = serde_json::to_string(self)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        fs::write(collections_path.join(HISTORY_FILE), json)
+    }
+
+    pub fn record(&mut self, path: PathBuf, timestamp: u64) {
+        *self.counts.entry(path.clone()).or_insert(0) += 1;
+        self.plays.push(Play { path, timestamp });
+        if self.plays.len() > MAX_PLAYS {
+            let excess = self.plays.len() - MAX_PLAYS;
+            self.plays.drain(..excess);
+        }
+    }
+
+    /// Move plays and the play count to a moved file. Returns whether `from` had any.
+    pub fn rename(&mut self, from: &Path, to: &Path) -> bool {
+        let mut found = false;
+        for play in self.plays.iter_mut().filter(|play| play.path == from) {
+            play.path = to.to_path_buf();
+            found = true;
+        }
+        if let Some(count) = self.counts.remove(from) {
+            *self.counts.entry(to.to_path_buf()).or_insert(0) += count;
+            found = true;
+        }
+        found
+    }
+
+    /// Up to `limit` distinct tracks, most recently played first.
+    pub fn recent(&self, limit: usize) -> Vec<&Play> {
+        let mut seen = HashSet::new();
+        self.plays
+            .iter()
+            .rev()
+            .filter(|play| seen.insert(&play.path))
+            .take(limit)
+            .collect()
+    }
+
+    /// Up to `limit` tracks with their play counts, most played first.
+    pub fn most_played(&self, limit: usize) -> Vec<(&PathBuf, u32)> {
+        let mut counts: Vec<(&PathBuf, u32)> = self.counts.iter().map(|(path, &count)| (path, count)).collect();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        counts.truncate(limit);
+        counts
+    }
+}
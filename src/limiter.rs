@@ -0,0 +1,178 @@
+use std::collections::VecDeque;
+
+/// Level the limiter holds peaks under, in dBFS.
+pub const LIMITER_THRESHOLD_DB: f32 = -1.0;
+pub const DEFAULT_ATTACK_MS: f32 = 5.0;
+pub const DEFAULT_RELEASE_MS: f32 = 200.0;
+
+/// User-facing limiter controls, carried in the equalizer settings.
+#[derive(Clone)]
+pub struct LimiterSettings {
+    pub enabled: bool,
+    pub attack_ms: f32, // also the look-ahead
+    pub release_ms: f32,
+}
+
+impl Default for LimiterSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            attack_ms: DEFAULT_ATTACK_MS,
+            release_ms: DEFAULT_RELEASE_MS,
+        }
+    }
+}
+
+/// Look-ahead peak limiter over interleaved samples. Audio is delayed by the
+/// attack time so gain reduction is already in place when a peak arrives.
+/// One gain is shared by all channels of a frame, which keeps the stereo
+/// image from shifting while limiting.
+pub struct Limiter {
+    channels: usize,
+    threshold: f32,
+    attack_coef: f32,
+    release_coef: f32,
+    lookahead: u64, // frames
+    delay: VecDeque<f32>,
+    frame_peak: f32,
+    channel_pos: usize,
+    frame_index: u64,
+    // Sliding minimum of the per-frame target gains over the look-ahead window
+    minima: VecDeque<(u64, f32)>,
+    gain: f32,
+}
+
+impl Limiter {
+    pub fn new(settings: &LimiterSettings, sample_rate: f32, channels: u16) -> Self {
+        let mut limiter = Self {
+            channels: channels.max(1) as usize,
+            threshold: 10.0_f32.powf(LIMITER_THRESHOLD_DB / 20.0),
+            attack_coef: 0.0,
+            release_coef: 0.0,
+            lookahead: 0,
+            delay: VecDeque::new(),
+            frame_peak: 0.0,
+            channel_pos: 0,
+            frame_index: 0,
+            minima: VecDeque::new(),
+            gain: 1.0,
+        };
+        limiter.set_params(settings, sample_rate);
+        limiter
+    }
+
+    /// Apply new attack and release times while keeping the current gain and
+    /// the audio already in the look-ahead, so retuning doesn't click. A
+    /// longer look-ahead pads the delay with silence; a shorter one drops the
+    /// oldest delayed frames.
+    pub fn set_params(&mut self, settings: &LimiterSettings, sample_rate: f32) {
+        let lookahead = (settings.attack_ms / 1000.0 * sample_rate).round().max(1.0) as usize;
+        // One-pole smoothing coefficient for a time constant, per frame
+        let coef = |ms: f32| (-1.0 / (ms.max(0.1) / 1000.0 * sample_rate)).exp();
+        // Attack settles well within the look-ahead
+        self.attack_coef = coef(settings.attack_ms / 3.0);
+        self.release_coef = coef(settings.release_ms);
+        self.lookahead = lookahead as u64;
+        let len = lookahead * self.channels;
+        while self.delay.len() < len {
+            self.delay.push_front(0.0);
+        }
+        while self.delay.len() > len {
+            self.delay.pop_front();
+        }
+    }
+
+    pub fn process(&mut self, sample: f32) -> f32 {
+        self.delay.push_back(sample);
+        self.frame_peak = self.frame_peak.max(sample.abs());
+        self.channel_pos += 1;
+        if self.channel_pos == self.channels {
+            self.channel_pos = 0;
+            let target = if self.frame_peak > self.threshold {
+                self.threshold / self.frame_peak
+            } else {
+                1.0
+            };
+            self.frame_peak = 0.0;
+
+            while self.minima.back().is_some_and(|&(_, gain)| gain >= target) {
+                self.minima.pop_back();
+            }
+            self.minima.push_back((self.frame_index, target));
+            while self.minima.front().is_some_and(|&(index, _)| index + self.lookahead < self.frame_index) {
+                self.minima.pop_front();
+            }
+            self.frame_index += 1;
+
+            let target = self.minima.front().map_or(1.0, |&(_, gain)| gain);
+            let coef = if target < self.gain { self.attack_coef } else { self.release_coef };
+            self.gain = target + coef * (self.gain - target);
+        }
+        self.delay.pop_front().unwrap_or(0.0) * self.gain
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: f32 = 48000.0;
+
+    fn lookahead_frames(settings: &LimiterSettings) -> usize {
+        (settings.attack_ms / 1000.0 * SAMPLE_RATE).round() as usize
+    }
+
+    #[test]
+    fn quiet_audio_passes_unchanged_after_the_lookahead() {
+        let settings = LimiterSettings::default();
+        let mut limiter = Limiter::new(&settings, SAMPLE_RATE, 1);
+        let delay = lookahead_frames(&settings);
+        let output: Vec<f32> = (0..delay * 3).map(|_| limiter.process(0.5)).collect();
+        assert!(output[..delay].iter().all(|&s| s == 0.0));
+        assert!(output[delay..].iter().all(|&s| (s - 0.5).abs() < 1e-6));
+    }
+
+    #[test]
+    fn sustained_peaks_settle_at_the_threshold() {
+        let settings = LimiterSettings::default();
+        let mut limiter = Limiter::new(&settings, SAMPLE_RATE, 1);
+        let threshold = 10.0_f32.powf(LIMITER_THRESHOLD_DB / 20.0);
+        let delay = lookahead_frames(&settings);
+        let output: Vec<f32> = (0..delay * 10).map(|_| limiter.process(2.0)).collect();
+        // The gain is mostly down by the time the first peak leaves the delay
+        assert!(output[delay] < 1.0);
+        assert!(output[delay * 4..].iter().all(|&s| (s - threshold).abs() < 1e-3));
+    }
+
+    #[test]
+    fn channels_share_one_gain() {
+        let settings = LimiterSettings::default();
+        let mut limiter = Limiter::new(&settings, SAMPLE_RATE, 2);
+        let mut last = (0.0, 0.0);
+        for _ in 0..lookahead_frames(&settings) * 10 {
+            last = (limiter.process(2.0), limiter.process(0.5));
+        }
+        // The quiet channel is turned down by the loud one's gain
+        assert!((last.0 / 2.0 - last.1 / 0.5).abs() < 1e-6);
+        assert!(last.1 < 0.5);
+    }
+
+    #[test]
+    fn retuning_keeps_the_gain() {
+        let settings = LimiterSettings::default();
+        let mut limiter = Limiter::new(&settings, SAMPLE_RATE, 1);
+        for _ in 0..lookahead_frames(&settings) * 10 {
+            limiter.process(2.0);
+        }
+        let gain = limiter.gain;
+        limiter.set_params(&LimiterSettings { release_ms: 500.0, ..settings.clone() }, SAMPLE_RATE);
+        assert_eq!(limiter.gain, gain);
+        // Still limiting on the next sample instead of starting over at unity
+        assert!(limiter.process(2.0) < 1.0);
+
+        let longer = LimiterSettings { attack_ms: 10.0, ..settings };
+        limiter.set_params(&longer, SAMPLE_RATE);
+        assert_eq!(limiter.delay.len(), lookahead_frames(&longer));
+        assert_eq!(limiter.gain, gain);
+    }
+}
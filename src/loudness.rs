@@ -0,0 +1,114 @@
+use std::fs;
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use ebur128::{EbuR128, Mode};
+use lofty::prelude::*;
+use rodio::{Decoder, Source};
+use serde::{Deserialize, Serialize};
+
+use crate::path_store::PathStore;
+
+/// Cache of measured gains, inside the collections folder.
+pub const LOUDNESS_CACHE_FILE: &str = "loudness_cache.json";
+/// Target loudness, matching the ReplayGain 2.0 reference level.
+pub const REFERENCE_LUFS: f64 = -18.0;
+/// Frames handed to the analyzer at a time.
+const ANALYSIS_BLOCK_FRAMES: usize = 4096;
+
+/// Gain for one file, valid while the file's modification time is unchanged.
+#[derive(Serialize, Deserialize)]
+struct CacheEntry {
+    mtime: u64,
+    gain_db: f32,
+}
+
+/// Normalization gains keyed by file path, persisted in `LOUDNESS_CACHE_FILE`.
+pub struct LoudnessCache(PathStore<CacheEntry>);
+
+fn modified_secs(path: &Path) -> Option<u64> {
+    let modified = fs::metadata(path).and_then(|m| m.modified()).ok()?;
+    modified.duration_since(UNIX_EPOCH).ok().map(|d| d.as_secs())
+}
+
+impl LoudnessCache {
+    /// Load the cache from `collections_path`; a missing or corrupt file yields an empty cache.
+    pub fn load(collections_path: &Path) -> Self {
+        Self(PathStore::load(collections_path, LOUDNESS_CACHE_FILE))
+    }
+
+    pub fn save(&self, collections_path: &Path) -> io::Result<()> {
+        self.0.save(collections_path)
+    }
+
+    /// Cached gain for `path`, or `None` if it was never measured or the file changed since.
+    pub fn get(&self, path: &Path) -> Option<f32> {
+        let entry = self.0.get(path)?;
+        (modified_secs(path)? == entry.mtime).then_some(entry.gain_db)
+    }
+
+    pub fn insert(&mut self, path: PathBuf, gain_db: f32) {
+        if let Some(mtime) = modified_secs(&path) {
+            self.0.set(path, CacheEntry { mtime, gain_db });
+        }
+    }
+
+    /// Move the gain to a moved file. Returns whether `from` had one.
+    pub fn rename(&mut self, from: &Path, to: &Path) -> bool {
+        self.0.rename(from, to)
+    }
+}
+
+/// Parse a ReplayGain value such as "-6.54 dB".
+fn parse_gain(value: &str) -> Option<f32> {
+    let value = value.trim();
+    let number = value
+        .strip_suffix("dB")
+        .or_else(|| value.strip_suffix("db"))
+        .unwrap_or(value);
+    number.trim().parse().ok()
+}
+
+/// ReplayGain from the file's tags, preferring track gain over album gain.
+pub fn read_replaygain(path: &Path) -> Option<f32> {
+    let tagged_file = lofty::read_from_path(path).ok()?;
+    let tag = tagged_file
+        .primary_tag()
+        .or_else(|| tagged_file.first_tag())?;
+    [ItemKey::ReplayGainTrackGain, ItemKey::ReplayGainAlbumGain]
+        .iter()
+        .find_map(|key| tag.get_string(key).and_then(parse_gain))
+}
+
+/// Decode the whole file and measure its integrated loudness (EBU R128),
+/// returning the gain in dB that brings it to `REFERENCE_LUFS`.
+pub fn measure_gain(path: &Path) -> Option<f32> {
+    let file = fs::File::open(path).ok()?;
+    let decoder = Decoder::new(BufReader::new(file)).ok()?;
+    let channels = decoder.channels() as usize;
+    let mut meter = EbuR128::new(channels as u32, decoder.sample_rate(), Mode::I).ok()?;
+    let mut samples = decoder.convert_samples::<f32>();
+    let mut block = Vec::with_capacity(ANALYSIS_BLOCK_FRAMES * channels);
+    loop {
+        block.clear();
+        block.extend(samples.by_ref().take(ANALYSIS_BLOCK_FRAMES * channels));
+        // Only whole frames can be analyzed
+        block.truncate(block.len() - block.len() % channels);
+        if block.is_empty() {
+            break;
+        }
+        meter.add_frames_f32(&block).ok()?;
+    }
+    let loudness = meter.loudness_global().ok()?;
+    // Digital silence measures as -inf; leave it alone
+    loudness
+        .is_finite()
+        .then_some((REFERENCE_LUFS - loudness) as f32)
+}
+
+/// Normalization gain for `path`: its ReplayGain tag if present, otherwise a measurement.
+/// This decodes the whole file, so call it off the UI thread.
+pub fn analyze(path: &Path) -> Option<f32> {
+    read_replaygain(path).or_else(|| measure_gain(path))
+}
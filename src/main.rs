@@ -1,29 +1,140 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
-use std::io::BufReader;
-use std::path::PathBuf;
-use std::time::Duration;
-use std::process::Command;
-use std::sync::mpsc::{channel, Receiver, Sender};
+use std::hash::{Hash, Hasher};
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
 use std::thread;
 use std::sync::{Arc, Mutex}; // Add these imports for thread-safe shared state
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
 use eframe::egui;
 use egui::RichText;
 use egui::ViewportBuilder;
-use rand::seq::SliceRandom;
+use fuzzy_matcher::FuzzyMatcher;
+use fuzzy_matcher::skim::SkimMatcherV2;
+use notify::Watcher;
+use rodio::cpal::traits::{DeviceTrait, HostTrait};
 use rodio::{Decoder, OutputStream, OutputStreamHandle, Sink, Source};
 use rfd::FileDialog;
 
 use rdev::{listen, Event, EventType, Key};
+use serde::{Deserialize, Serialize};
 
 // Add this to your Cargo.toml:
 // biquad = "0.3"
-use biquad::{Biquad, Coefficients, DirectForm1, Hertz}; // Add Hertz here
+use biquad::{Biquad, Coefficients, DirectForm1, Q_BUTTERWORTH_F32};
 
+use crossfeed::{CrossfeedSettings, CrossfeedSource};
+use decoder::TrackDecoder;
+use limiter::{Limiter, LimiterSettings};
+use metadata::TrackTags;
+use timestretch::TimeStretchSource;
+use visualizer::{AudioVisualizer, SpectrogramColors, VisualizerMode, VisualizerTap};
+
+mod config;
+mod crossfeed;
+mod cue;
+mod decoder;
+mod downloads;
+mod eq_presets;
+mod favorites;
+mod headless;
+mod history;
+mod limiter;
+mod loudness;
+mod metadata;
+#[cfg(target_os = "linux")]
+mod mpris;
+#[cfg(any(target_os = "windows", target_os = "linux"))]
+mod notifications;
+mod path_store;
+mod player;
+mod playlists;
+mod position;
+mod scrobbler;
+mod session;
+mod settings;
+mod stream;
+mod tasks;
+#[cfg(test)]
+mod test_util;
 mod theme;
+mod timestretch;
+mod track_eq;
+mod track_gains;
+#[cfg(any(target_os = "windows", target_os = "linux"))]
+mod tray;
 mod visualizer;
+mod waveform;
 mod widgets;
 
+/// How long queue auto-follow stays paused after the user scrolls the queue.
+const QUEUE_FOLLOW_PAUSE: Duration = Duration::from_secs(3);
+
+/// How often the collections folder is checked for external changes when
+/// no file-system watcher could be started.
+const COLLECTIONS_POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// Quiet period after the last watcher event before the folder is re-scanned,
+/// so a burst of changes (e.g. a playlist download) causes one scan.
+const COLLECTIONS_DEBOUNCE: Duration = Duration::from_millis(750);
+
+/// Weakest fuzzy match still shown in the collections search.
+const FUZZY_MIN_SCORE: i64 = 25;
+
+/// How long before the end of a track the next one is appended for gapless playback.
+const GAPLESS_PREBUFFER_SECS: f32 = 5.0;
+
+/// Tracks appended from the collection each time auto-continue runs out of queue.
+const AUTO_CONTINUE_BATCH: usize = 10;
+
+/// How long user volume changes take to reach the sink, so they don't click.
+const VOLUME_RAMP: Duration = Duration::from_millis(50);
+
+/// How long the sleep timer takes to fade playback out.
+const SLEEP_FADE: Duration = Duration::from_secs(5);
+/// Sleep timer presets, in minutes.
+const SLEEP_PRESETS_MIN: [u64; 4] = [15, 30, 45, 60];
+
+/// Seconds the Left/Right arrow shortcuts seek by.
+const SEEK_STEP_SECS: f32 = 5.0;
+/// Volume change per Up/Down arrow press or global volume hotkey.
+const VOLUME_STEP: f32 = 0.05;
+
+/// How often a chosen output device is checked for disconnection.
+const DEVICE_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Entries shown in the Recently Played and Most Played lists.
+const HISTORY_LIST_LEN: usize = 50;
+
+/// Keys listed on the Settings tab, matching `handle_shortcuts` and the
+/// global hotkey listener.
+const SHORTCUT_REFERENCE: [(&str, &str); 10] = [
+    ("Space", "Play / pause"),
+    ("Left / Right", "Seek 5 seconds"),
+    ("Up / Down", "Volume"),
+    ("N", "Next track"),
+    ("P", "Previous track"),
+    ("S", "Toggle shuffle"),
+    ("M", "Mute"),
+    ("Ctrl+P", "Play / pause (global)"),
+    ("Ctrl+U", "Volume up (global)"),
+    ("Ctrl+D", "Volume down (global)"),
+];
+
+/// Repaint cadence while nothing is animating, so channel polling and
+/// track-finished checks still run without rendering at full frame rate.
+const IDLE_REPAINT_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How long a toast message stays on screen.
+const TOAST_DURATION: Duration = Duration::from_secs(4);
+
+/// Default size of the full window.
+const FULL_WINDOW_SIZE: [f32; 2] = [1200.0, 600.0];
+/// Window size of the mini player.
+const COMPACT_WINDOW_SIZE: [f32; 2] = [400.0, 120.0];
 
 /// A helper function to remove extra tags or info from a raw title.
 fn clean_title(raw_title: &str) -> String {
@@ -42,11 +153,390 @@ fn clean_title(raw_title: &str) -> String {
     cleaned.split_whitespace().take(6).collect::<Vec<_>>().join(" ")
 }
 
-/// Commands sent by the global key listener.
+/// Parse an EQ curve made of "Freq Gain" pairs, one per line.
+/// Accepts whitespace or comma separated values and the AutoEQ
+/// `GraphicEQ: f g; f g; ...` form. Headers and comments are skipped.
+fn parse_eq_curve(text: &str) -> Vec<(f32, f32)> {
+    let text = text.replace("GraphicEQ:", "").replace(';', "\n");
+    let mut curve: Vec<(f32, f32)> = text
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line
+                .split(|c: char| c.is_whitespace() || c == ',')
+                .filter(|f| !f.is_empty());
+            let freq = fields.next()?.parse::<f32>().ok()?;
+            let gain = fields.next()?.parse::<f32>().ok()?;
+            (freq > 0.0 && gain.is_finite()).then_some((freq, gain))
+        })
+        .collect();
+    curve.sort_by(|a, b| a.0.total_cmp(&b.0));
+    curve
+}
+
+/// Gain of a sorted curve at `freq`, interpolated on a log-frequency axis.
+fn interpolate_gain(curve: &[(f32, f32)], freq: f32) -> f32 {
+    let Some(&(first_freq, first_gain)) = curve.first() else {
+        return 0.0;
+    };
+    if freq <= first_freq {
+        return first_gain;
+    }
+    for pair in curve.windows(2) {
+        let (f0, g0) = pair[0];
+        let (f1, g1) = pair[1];
+        if freq <= f1 {
+            let t = (freq / f0).ln() / (f1 / f0).ln();
+            return g0 + (g1 - g0) * t;
+        }
+    }
+    curve[curve.len() - 1].1
+}
+
+/// Measure a track's length by decoding it fully. Used when the decoder
+/// can't report a duration up front; call from a worker thread.
+fn probe_duration(path: &Path) -> Option<f32> {
+    let file = fs::File::open(path).ok()?;
+    let decoder = Decoder::new(BufReader::new(file)).ok()?;
+    let channels = decoder.channels().max(1) as f32;
+    let sample_rate = decoder.sample_rate() as f32;
+    let samples = decoder.count() as f32;
+    (sample_rate > 0.0).then(|| samples / channels / sample_rate)
+}
+
+/// Length of a queue item: its cue span, or the whole file. None when the
+/// decoder can't tell and the span runs to the end of the file.
+fn item_duration(cue: Option<CueSpan>, decoder: &TrackDecoder) -> Option<f32> {
+    let file_secs = decoder.total_duration().map(|d| d.as_secs_f32());
+    match cue {
+        Some(cue) => cue.length(file_secs),
+        None => file_secs,
+    }
+}
+
+/// Samples of a queue item from `position` seconds into it, stopping at the
+/// end of its cue span. The decoder seeks to the start, so a cue track late
+/// in a long file doesn't wait for everything before it to be decoded.
+fn item_samples(mut decoder: TrackDecoder, cue: Option<CueSpan>, position: f32) -> Box<dyn Source<Item = f32> + Send> {
+    let position = position.max(0.0);
+    let start = cue.map_or(0.0, |cue| cue.start) + position;
+    let samples: Box<dyn Source<Item = f32> + Send> = if start > 0.0 && decoder.seek(start).is_err() {
+        // Containers that can't seek are decoded up to the start instead
+        Box::new(decoder.skip_duration(Duration::from_secs_f32(start)))
+    } else {
+        Box::new(decoder)
+    };
+    match cue.and_then(|cue| cue.end.map(|end| end - cue.start)) {
+        Some(length) => Box::new(samples.take_duration(Duration::from_secs_f32((length - position).max(0.0)))),
+        None => samples,
+    }
+}
+
+/// The shared controls every track is played through: time-stretch,
+/// equalizer and crossfeed. The window and headless playback build their
+/// sources from one of these, so both sound the same.
+struct SourceChain {
+    // Speed applied by the time-stretcher; 1.0 unless preserving pitch
+    stretch_ratio: Arc<Mutex<f32>>,
+    equalizer: Arc<Mutex<EqualizerSettings>>,
+    equalizer_generation: Arc<AtomicUsize>,
+    crossfeed: Arc<Mutex<CrossfeedSettings>>,
+}
+
+impl SourceChain {
+    fn new(equalizer: EqualizerSettings, crossfeed: CrossfeedSettings) -> Self {
+        Self {
+            stretch_ratio: Arc::new(Mutex::new(1.0)),
+            equalizer: Arc::new(Mutex::new(equalizer)),
+            equalizer_generation: Arc::new(AtomicUsize::new(0)),
+            crossfeed: Arc::new(Mutex::new(crossfeed)),
+        }
+    }
+
+    /// `samples` time-stretched, equalized and crossfed.
+    fn build<S>(&self, samples: S) -> CrossfeedSource<EqualizedSource<TimeStretchSource<S>>>
+    where
+        S: Source<Item = f32>,
+    {
+        let stretched = TimeStretchSource::new(samples, self.stretch_ratio.clone());
+        let equalized = EqualizedSource::new(stretched, self.equalizer.clone(), self.equalizer_generation.clone());
+        CrossfeedSource::new(equalized, self.crossfeed.clone())
+    }
+}
+
+/// Convert a gain in decibels to a linear amplitude factor.
+fn db_to_gain(db: f32) -> f32 {
+    10.0_f32.powf(db / 20.0)
+}
+
+/// File extensions the player will enqueue. Whether a given file decodes
+/// depends on its codec; failures are logged when the track is played.
+/// Opus is left out because symphonia can't decode it.
+const AUDIO_EXTENSIONS: [&str; 8] = ["mp3", "wav", "flac", "ogg", "oga", "m4a", "aac", "webm"];
+
+/// Folder used when none has been chosen: the one earlier versions created in
+/// the working directory if it exists, otherwise one in the per-user data dir.
+fn default_collections_path() -> PathBuf {
+    let working_dir = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+    let legacy = working_dir.join("my_collections");
+    if legacy.is_dir() {
+        return legacy;
+    }
+    config::data_dir().join("my_collections")
+}
+
+/// Audio files, their cover images, and the app's own data that live in a
+/// collections folder and move with it.
+fn collections_entries(dir: &Path) -> Vec<PathBuf> {
+    let data = [
+        favorites::FAVORITES_FILE,
+        history::HISTORY_FILE,
+        loudness::LOUDNESS_CACHE_FILE,
+        track_eq::TRACK_EQ_FILE,
+    ];
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let paths: Vec<PathBuf> = entries.flatten().map(|entry| entry.path()).collect();
+    let has_track = |art: &Path| paths.iter().any(|path| is_audio_file(path) && path.file_stem() == art.file_stem());
+    paths
+        .iter()
+        .filter(|path| {
+            let name = path.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+            let is_art = path.extension()
+                .and_then(|e| e.to_str())
+                .is_some_and(|ext| ["jpg", "png", "webp"].contains(&ext));
+            is_audio_file(path)
+                || (is_art && has_track(path))
+                || data.contains(&name)
+                || *path == &playlists::playlists_dir(dir)
+                || *path == &eq_presets::presets_dir(dir)
+        })
+        .cloned()
+        .collect()
+}
+
+/// Free path for `file_name` in `dir`, adding " (2)", " (3)", ... to the
+/// stem while the name is taken.
+fn unique_file_path(dir: &Path, file_name: &std::ffi::OsStr) -> PathBuf {
+    let candidate = dir.join(file_name);
+    if !candidate.exists() {
+        return candidate;
+    }
+    let name = Path::new(file_name);
+    let stem = name.file_stem().unwrap_or(file_name).to_string_lossy();
+    let extension = name.extension().map(|ext| format!(".{}", ext.to_string_lossy())).unwrap_or_default();
+    (2..)
+        .map(|n| dir.join(format!("{} ({}){}", stem, n, extension)))
+        .find(|path| !path.exists())
+        .expect("some counter is free")
+}
+
+/// Rename, falling back to copy and delete for files on another drive.
+fn move_path(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::rename(from, to) {
+        Ok(()) => Ok(()),
+        Err(_) if from.is_file() => {
+            fs::copy(from, to)?;
+            fs::remove_file(from)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Audio files directly inside `folder`, in name order.
+fn folder_audio_files(folder: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(folder) else {
+        return Vec::new();
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| is_audio_file(path))
+        .collect();
+    paths.sort();
+    paths
+}
+
+/// Collections folder chosen in the config, or the default one.
+fn configured_collections_path(config: &config::Config) -> PathBuf {
+    config.collections_dir.clone().unwrap_or_else(default_collections_path)
+}
+
+/// Whether `path` has one of the supported audio extensions.
+fn is_audio_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|ext| AUDIO_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
+/// Last.fm record of a pending play; None while the artist is unknown,
+/// since Last.fm requires one.
+fn scrobble_track(pending: &PendingScrobble) -> Option<scrobbler::Track> {
+    Some(scrobbler::Track {
+        artist: pending.item.artist.clone()?,
+        title: pending.item.display_name.clone(),
+        album: pending.item.album.clone(),
+        duration: pending.duration,
+        timestamp: pending.started_at,
+    })
+}
+
+/// Coarse age of a play, e.g. "5 min ago", "3 h ago", "2 days ago".
+fn format_ago(secs: u64) -> String {
+    match secs {
+        0..60 => "just now".to_string(),
+        60..3600 => format!("{} min ago", secs / 60),
+        3600..86400 => format!("{} h ago", secs / 3600),
+        86400..172800 => "yesterday".to_string(),
+        _ => format!("{} days ago", secs / 86400),
+    }
+}
+
+/// Options given on the command line.
+#[derive(Default)]
+struct LaunchArgs {
+    paths: Vec<PathBuf>,
+    shuffle: bool,
+    eq_preset: Option<String>,
+    no_gui: bool,
+    volume: Option<f32>, // 0.0 ..= 1.0
+}
+
+/// Parse `[--no-gui] [--shuffle] [--eq <preset>] [--volume <0-100>] [PATH...]`.
+/// Unknown flags are reported and ignored so a file manager launch never
+/// fails outright.
+fn parse_args(mut args: impl Iterator<Item = String>) -> LaunchArgs {
+    let mut launch = LaunchArgs::default();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--shuffle" => launch.shuffle = true,
+            "--no-gui" => launch.no_gui = true,
+            "--eq" => match args.next() {
+                Some(name) => launch.eq_preset = Some(name),
+                None => eprintln!("Warning: --eq needs a preset name"),
+            },
+            "--volume" => match args.next().and_then(|value| value.parse::<f32>().ok()) {
+                Some(percent) => launch.volume = Some((percent / 100.0).clamp(0.0, 1.0)),
+                None => eprintln!("Warning: --volume needs a number from 0 to 100"),
+            },
+            flag if flag.starts_with("--") => eprintln!("Warning: unknown option {}", flag),
+            path => launch.paths.push(PathBuf::from(path)),
+        }
+    }
+    launch
+}
+
+/// Names of the output devices the default audio host can see.
+fn list_output_devices() -> Vec<String> {
+    rodio::cpal::default_host()
+        .output_devices()
+        .map(|devices| devices.filter_map(|device| device.name().ok()).collect())
+        .unwrap_or_default()
+}
+
+/// Open an output stream on the device called `name`, or the system default for `None`.
+fn open_output_stream(name: Option<&str>) -> Result<(OutputStream, OutputStreamHandle), String> {
+    let Some(name) = name else {
+        return OutputStream::try_default().map_err(|e| e.to_string());
+    };
+    let device = rodio::cpal::default_host()
+        .output_devices()
+        .map_err(|e| e.to_string())?
+        .find(|device| device.name().is_ok_and(|n| n == name))
+        .ok_or_else(|| format!("Output device \"{}\" not found", name))?;
+    OutputStream::try_from_device(&device).map_err(|e| e.to_string())
+}
+
+/// Shown when the yt-dlp binary can't be found on PATH.
+const YT_DLP_MISSING: &str = "yt-dlp not found — install it and retry";
+
+/// Whether the yt-dlp binary can be launched.
+fn yt_dlp_available() -> bool {
+    Command::new("yt-dlp")
+        .arg("--version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok()
+}
+
+/// Cover image and stream info read for a track, keyed by its path.
+type TrackDetails = (PathBuf, Option<Vec<u8>>, Option<metadata::AudioInfo>);
+
+/// Collection items grouped under an album or artist heading.
+type CollectionGroups = Vec<(String, Vec<MediaItem>)>;
+
+/// Messages from yt-dlp download workers.
+enum DownloadEvent {
+    Finished(MediaItem, String),
+    Failed(String),
+}
+
+/// Commands sent by the global key listener, the tray menu and notification clicks.
 enum KeyCommand {
     IncreaseVolume,
     TogglePause,
     DecreaseVolume,
+    NextTrack,
+    PreviousTrack,
+    ToggleWindow,
+    #[cfg(any(target_os = "windows", target_os = "linux"))]
+    ShowWindow,
+    Quit,
+    ListenerFailed(String),
+}
+
+/// Whether global hotkeys should be on by default on this platform.
+/// macOS needs accessibility permission and Wayland blocks global capture.
+fn global_keys_supported() -> bool {
+    cfg!(any(target_os = "windows", target_os = "linux"))
+        && std::env::var_os("WAYLAND_DISPLAY").is_none()
+}
+
+/// Spawn the global key listener thread. `rdev::listen` cannot be stopped
+/// once running, so events are dropped while `enabled` is false.
+fn spawn_key_listener(key_tx: Sender<KeyCommand>, enabled: Arc<AtomicBool>) {
+    thread::spawn(move || {
+        let error_tx = key_tx.clone();
+        let mut ctrl_pressed = false;
+        if let Err(e) = listen(move |event: Event| {
+            if !enabled.load(Ordering::Relaxed) {
+                ctrl_pressed = false;
+                return;
+            }
+            match event.event_type {
+                EventType::KeyPress(key) => {
+                    if key == Key::ControlLeft || key == Key::ControlRight {
+                        ctrl_pressed = true;
+                    }
+                    if key == Key::KeyU && ctrl_pressed {
+                        let _ = key_tx.send(KeyCommand::IncreaseVolume);
+                    }
+                    if key == Key::KeyD && ctrl_pressed {
+                        let _ = key_tx.send(KeyCommand::DecreaseVolume);
+                    }
+                    if key == Key::KeyP && ctrl_pressed {
+                        let _ = key_tx.send(KeyCommand::TogglePause);
+                    }
+                }
+                EventType::KeyRelease(key) if key == Key::ControlLeft || key == Key::ControlRight => {
+                    ctrl_pressed = false;
+                }
+                _ => {}
+            }
+        }) {
+            let _ = error_tx.send(KeyCommand::ListenerFailed(format!("{:?}", e)));
+        }
+    });
+}
+
+/// The track being listened to, for deciding whether it counts as a Last.fm play.
+struct PendingScrobble {
+    item: MediaItem,
+    duration: Option<f32>,
+    started_at: u64, // seconds since the Unix epoch
+    listened: f32,   // seconds actually played, excluding pauses and skipped-over time
+    announced: bool, // "now playing" sent
 }
 
 /// Struct to represent a media item.
@@ -55,6 +545,297 @@ struct MediaItem {
     file_path: PathBuf,
     display_name: String,
     artist: Option<String>,
+    album: Option<String>,
+    cue: Option<CueSpan>, // one track of a single-file album
+    album_group: Option<u32>, // tracks of one album, played gapless even with crossfade on
+    from_library: bool, // appended by auto-continue when the queue ran out
+}
+
+/// The part of a file a cue sheet track covers.
+#[derive(Clone, Copy, PartialEq)]
+struct CueSpan {
+    track: u32,
+    start: f32,       // seconds into the file
+    end: Option<f32>, // None to play to the end of the file
+}
+
+impl CueSpan {
+    /// Length of the span, given the length of the whole file if known.
+    fn length(self, file_secs: Option<f32>) -> Option<f32> {
+        self.end.or(file_secs).map(|end| (end - self.start).max(0.0))
+    }
+}
+
+impl MediaItem {
+    /// Item for a local file, titled from its cleaned-up file name.
+    fn from_path(file_path: PathBuf) -> Self {
+        let raw_title = file_path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("Unknown")
+            .to_string();
+        Self {
+            display_name: clean_title(&raw_title),
+            file_path,
+            artist: None,
+            album: None,
+            cue: None,
+            album_group: None,
+            from_library: false,
+        }
+    }
+
+    /// One item per track when a cue sheet describes this file, otherwise
+    /// just the item itself.
+    fn split_by_cue(self) -> Vec<MediaItem> {
+        let sheet = cue::for_file(&self.file_path);
+        self.split_by_sheet(sheet)
+    }
+
+    /// One item per track of `sheet`, already looked up for this file.
+    fn split_by_sheet(self, sheet: Option<cue::CueSheet>) -> Vec<MediaItem> {
+        let Some(sheet) = sheet else {
+            return vec![self];
+        };
+        sheet
+            .tracks
+            .into_iter()
+            .map(|track| MediaItem {
+                file_path: self.file_path.clone(),
+                display_name: track.title.unwrap_or_else(|| format!("Track {}", track.number)),
+                artist: track.performer.or_else(|| sheet.performer.clone()),
+                album: sheet.title.clone(),
+                cue: Some(CueSpan { track: track.number, start: track.start, end: track.end }),
+                album_group: self.album_group,
+                from_library: self.from_library,
+            })
+            .collect()
+    }
+
+    /// "Title — Artist" when the artist is known, otherwise just the title.
+    fn label(&self) -> String {
+        match &self.artist {
+            Some(artist) => format!("{} — {}", self.display_name, artist),
+            None => self.display_name.clone(),
+        }
+    }
+
+    /// Overlay embedded tags, keeping the filename-derived title as fallback.
+    /// Cue tracks keep the sheet's titles; the file's tags describe the
+    /// whole album and only fill in what the sheet left out.
+    fn apply_tags(&mut self, tags: &TrackTags) {
+        if self.cue.is_some() {
+            if self.artist.is_none() {
+                self.artist = tags.artist.clone();
+            }
+            if self.album.is_none() {
+                self.album = tags.album.clone();
+            }
+            return;
+        }
+        if let Some(title) = &tags.title {
+            self.display_name = title.clone();
+        }
+        self.artist = tags.artist.clone();
+        self.album = tags.album.clone();
+    }
+}
+
+/// What happens when a track finishes.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+enum RepeatMode {
+    Off,
+    One,
+    All,
+}
+
+impl RepeatMode {
+    fn next(self) -> Self {
+        match self {
+            RepeatMode::Off => RepeatMode::All,
+            RepeatMode::All => RepeatMode::One,
+            RepeatMode::One => RepeatMode::Off,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            RepeatMode::Off => "➡ Repeat Off",
+            RepeatMode::All => "🔁 Repeat All",
+            RepeatMode::One => "🔂 Repeat One",
+        }
+    }
+}
+
+/// Album heading used for tracks without an album tag.
+const UNKNOWN_ALBUM: &str = "Singles";
+
+/// Artist heading used for tracks without an artist tag.
+const UNKNOWN_ARTIST: &str = "Unknown Artist";
+
+/// What clicking a collection item does.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum CollectionClickAction {
+    PlayNow,
+    PlayNext,
+    AddToQueue,
+}
+
+impl CollectionClickAction {
+    const ALL: [CollectionClickAction; 3] = [
+        CollectionClickAction::PlayNow,
+        CollectionClickAction::PlayNext,
+        CollectionClickAction::AddToQueue,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            CollectionClickAction::PlayNow => "Play Now",
+            CollectionClickAction::PlayNext => "Play Next",
+            CollectionClickAction::AddToQueue => "Add to Queue",
+        }
+    }
+}
+
+/// Orders the queue and collections lists can be sorted in.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum SortOrder {
+    DateAdded,
+    Title,
+    Artist,
+    Duration,
+}
+
+impl SortOrder {
+    const ALL: [SortOrder; 4] = [
+        SortOrder::Title,
+        SortOrder::Artist,
+        SortOrder::Duration,
+        SortOrder::DateAdded,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            SortOrder::DateAdded => "Date Added",
+            SortOrder::Title => "Title A–Z",
+            SortOrder::Artist => "Artist",
+            SortOrder::Duration => "Duration",
+        }
+    }
+}
+
+/// Case-insensitive comparison that orders runs of digits by value,
+/// so "Track 2" sorts before "Track 10".
+fn natural_cmp(a: &str, b: &str) -> std::cmp::Ordering {
+    let mut a = a.chars().flat_map(char::to_lowercase).peekable();
+    let mut b = b.chars().flat_map(char::to_lowercase).peekable();
+    loop {
+        match (a.peek().copied(), b.peek().copied()) {
+            (None, None) => return std::cmp::Ordering::Equal,
+            (None, Some(_)) => return std::cmp::Ordering::Less,
+            (Some(_), None) => return std::cmp::Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let take_number = |chars: &mut std::iter::Peekable<_>| {
+                    let mut digits = String::new();
+                    while let Some(c) = chars.next_if(|c: &char| c.is_ascii_digit()) {
+                        digits.push(c);
+                    }
+                    digits
+                };
+                let (x, y) = (take_number(&mut a), take_number(&mut b));
+                let (x, y) = (x.trim_start_matches('0'), y.trim_start_matches('0'));
+                let ordering = x.len().cmp(&y.len()).then_with(|| x.cmp(y));
+                if ordering.is_ne() {
+                    return ordering;
+                }
+            }
+            (Some(x), Some(y)) => {
+                if x != y {
+                    return x.cmp(&y);
+                }
+                a.next();
+                b.next();
+            }
+        }
+    }
+}
+
+/// `text` with the characters at `matched` (char indices) drawn in `highlight`.
+fn highlighted_text(ui: &egui::Ui, text: &str, matched: &[usize], highlight: egui::Color32) -> egui::text::LayoutJob {
+    let font_id = egui::TextStyle::Body.resolve(ui.style());
+    let color = ui.visuals().strong_text_color();
+    let mut job = egui::text::LayoutJob::default();
+    for (i, c) in text.chars().enumerate() {
+        let format = egui::TextFormat {
+            font_id: font_id.clone(),
+            color: if matched.contains(&i) { highlight } else { color },
+            ..Default::default()
+        };
+        job.append(c.encode_utf8(&mut [0; 4]), 0.0, format);
+    }
+    job
+}
+
+/// Whether a slider edit is done: released after a drag, or changed by a
+/// click or the keyboard. Settings are applied live but saved only then.
+fn edit_finished(response: &egui::Response) -> bool {
+    response.drag_stopped() || (response.changed() && !response.dragged())
+}
+
+/// How `item` is written to a playlist file.
+fn playlist_entry(item: &MediaItem) -> playlists::PlaylistEntry {
+    playlists::PlaylistEntry {
+        path: item.file_path.clone(),
+        title: Some(item.display_name.clone()),
+        artist: item.artist.clone(),
+    }
+}
+
+/// Apply a click on `row` to a multi-selection: Ctrl toggles the row, Shift
+/// selects every row in `rows` from the anchor to it. Returns false for a
+/// plain click, which clears the selection and is left to the caller.
+fn select_row<T: Clone + Eq + Hash>(
+    selection: &mut HashSet<T>,
+    anchor: &mut Option<T>,
+    rows: &[T],
+    row: &T,
+    modifiers: egui::Modifiers,
+) -> bool {
+    if modifiers.shift {
+        let position = |target: &T| rows.iter().position(|r| r == target);
+        match (anchor.as_ref().and_then(position), position(row)) {
+            (Some(from), Some(to)) => {
+                selection.clear();
+                selection.extend(rows[from.min(to)..=from.max(to)].iter().cloned());
+            }
+            _ => {
+                selection.insert(row.clone());
+                *anchor = Some(row.clone());
+            }
+        }
+        true
+    } else if modifiers.command {
+        if !selection.remove(row) {
+            selection.insert(row.clone());
+        }
+        *anchor = Some(row.clone());
+        true
+    } else {
+        selection.clear();
+        *anchor = Some(row.clone());
+        false
+    }
+}
+
+/// How the collections panel lays out its items.
+#[derive(PartialEq)]
+enum CollectionsView {
+    List,
+    Albums,
+    ByAlbum,
+    ByArtist,
 }
 
 /// Enum to represent the active UI tab.
@@ -62,10 +843,12 @@ struct MediaItem {
 enum AppTab {
     Player,
     Equalizer,
+    Visualizer,
+    Settings,
 }
 
 /// Enum for Equalizer presets.
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 enum EqualizerPreset {
     Flat,
     Classical,
@@ -77,84 +860,364 @@ enum EqualizerPreset {
     Custom,
 }
 
-/// Struct to hold equalizer settings (assumes a 10-band equalizer).
+impl EqualizerPreset {
+    const BUILT_IN: [EqualizerPreset; 7] = [
+        EqualizerPreset::Flat,
+        EqualizerPreset::Classical,
+        EqualizerPreset::HipHop,
+        EqualizerPreset::Pop,
+        EqualizerPreset::Rock,
+        EqualizerPreset::HeavyMetal,
+        EqualizerPreset::Folk,
+    ];
+
+    /// Built-in preset matching `name`, ignoring case and separators
+    /// so "hip-hop" and "Heavy Metal" are accepted.
+    fn from_name(name: &str) -> Option<Self> {
+        let wanted: String = name.chars().filter(|c| c.is_alphanumeric()).collect::<String>().to_lowercase();
+        Self::BUILT_IN.into_iter().find(|preset| format!("{:?}", preset).to_lowercase() == wanted)
+    }
+}
+
+/// Band counts offered by the equalizer.
+const EQ_BAND_COUNTS: [usize; 4] = [5, 10, 15, 31];
+
+/// Band count presets are defined at and new settings start with.
+const EQ_DEFAULT_BANDS: usize = 10;
+
+/// Center frequencies for `count` bands, spaced logarithmically across
+/// 20 Hz – 20 kHz with each center in the middle of its slice.
+fn eq_center_frequencies(count: usize) -> Vec<f32> {
+    let (low, high) = (20.0_f32, 20000.0_f32);
+    (0..count)
+        .map(|i| low * (high / low).powf((i as f32 + 0.5) / count as f32))
+        .collect()
+}
+
+/// Peaking filter Q giving each band a bandwidth equal to the spacing between centers.
+fn eq_band_q(count: usize) -> f32 {
+    let octaves = (20000.0_f32 / 20.0).log2() / count as f32;
+    let ratio = 2.0_f32.powf(octaves);
+    ratio.sqrt() / (ratio - 1.0)
+}
+
+/// Resample band gains onto a different band count by log-frequency interpolation.
+fn resample_bands(bands: &[f32], count: usize) -> Vec<f32> {
+    if bands.len() == count {
+        return bands.to_vec();
+    }
+    let curve: Vec<(f32, f32)> = eq_center_frequencies(bands.len())
+        .into_iter()
+        .zip(bands.iter().copied())
+        .collect();
+    eq_center_frequencies(count)
+        .into_iter()
+        .map(|freq| interpolate_gain(&curve, freq))
+        .collect()
+}
+
+/// Corner frequencies of the bass and treble shelves.
+const BASS_SHELF_HZ: f32 = 100.0;
+const TREBLE_SHELF_HZ: f32 = 8000.0;
+
+/// Width of the frequency label column in front of the EQ sliders.
+const EQ_LABEL_WIDTH: f32 = 60.0;
+
+/// Gain values marked on the equalizer dB grid.
+const EQ_GRID_DB: [f32; 5] = [-10.0, -5.0, 0.0, 5.0, 10.0];
+
+/// Frequency response graph in the EQ tab: its height, the gain shown at
+/// the top and bottom edges, and how many frequencies are evaluated.
+const EQ_CURVE_HEIGHT: f32 = 140.0;
+const EQ_CURVE_RANGE_DB: f32 = 18.0;
+const EQ_CURVE_POINTS: usize = 200;
+/// Sample rate the response is computed at; the audible range barely
+/// changes between common output rates.
+const EQ_CURVE_SAMPLE_RATE: f32 = 48000.0;
+/// Frequencies marked along the response graph.
+const EQ_CURVE_GRID_HZ: [f32; 9] = [50.0, 100.0, 200.0, 500.0, 1000.0, 2000.0, 5000.0, 10000.0, 20000.0];
+
+/// Format a band center frequency as e.g. "62 Hz", "1.8 kHz" or "14 kHz".
+fn format_frequency(hz: f32) -> String {
+    if hz >= 10000.0 {
+        format!("{} kHz", (hz / 1000.0).round())
+    } else if hz >= 1000.0 {
+        format!("{:.1} kHz", hz / 1000.0)
+    } else {
+        format!("{} Hz", hz.round())
+    }
+}
+
+/// Rect the next slider's rail will occupy at the cursor.
+fn eq_slider_rail(ui: &egui::Ui) -> egui::Rect {
+    egui::Rect::from_min_size(
+        ui.cursor().min,
+        egui::vec2(ui.spacing().slider_width, ui.spacing().interact_size.y),
+    )
+}
+
+/// Paint the dB grid across a slider rail, optionally with value labels.
+fn draw_db_scale(ui: &egui::Ui, rail: egui::Rect, with_labels: bool) {
+    let painter = ui.painter();
+    // egui insets the handle travel by the handle radius
+    let inset = rail.height() / 2.5;
+    let travel = rail.x_range().shrink(inset);
+    for db in EQ_GRID_DB {
+        let x = egui::lerp(travel, (db + 10.0) / 20.0);
+        if with_labels {
+            painter.text(
+                egui::pos2(x, rail.center().y),
+                egui::Align2::CENTER_CENTER,
+                format!("{:+}", db),
+                egui::FontId::proportional(11.0),
+                ui.visuals().weak_text_color(),
+            );
+        } else {
+            let stroke = if db == 0.0 {
+                ui.visuals().widgets.noninteractive.fg_stroke
+            } else {
+                ui.visuals().widgets.noninteractive.bg_stroke
+            };
+            painter.vline(x, rail.y_range(), stroke);
+        }
+    }
+}
+
+/// Struct to hold equalizer settings (band count is `bands.len()`).
 #[derive(Clone)]
 struct EqualizerSettings {
+    enabled: bool, // false bypasses the whole EQ stage, keeping the band values
     preset: EqualizerPreset,
     bands: Vec<f32>, // gain in dB for each band
+    preamp_db: f32,  // gain applied before the filter chain
+    bass_db: f32,    // low-shelf gain, independent of the bands
+    treble_db: f32,  // high-shelf gain, independent of the bands
+    balance: f32,    // -1.0 full left ..= 1.0 full right
+    limiter: LimiterSettings,
 }
 
 impl EqualizerSettings {
     fn new() -> Self {
         Self {
+            enabled: true,
             preset: EqualizerPreset::Flat,
-            bands: vec![0.0; 10],
+            bands: vec![0.0; EQ_DEFAULT_BANDS],
+            preamp_db: 0.0,
+            bass_db: 0.0,
+            treble_db: 0.0,
+            balance: 0.0,
+            limiter: LimiterSettings::default(),
         }
     }
 
-    /// Apply predefined gain values for each preset.
+    /// Left and right gains for the balance setting. The centre is unity;
+    /// moving toward one side only attenuates the other.
+    fn balance_gains(&self) -> [f32; 2] {
+        let balance = self.balance.clamp(-1.0, 1.0);
+        [1.0 - balance.max(0.0), 1.0 + balance.min(0.0)]
+    }
+
+    /// Apply predefined gain values for each preset, interpolated onto the
+    /// current band count.
     fn apply_preset(&mut self) {
-        match self.preset {
-            EqualizerPreset::Flat => self.bands = vec![0.0; 10],
+        let curve = match self.preset {
+            EqualizerPreset::Flat => vec![0.0; EQ_DEFAULT_BANDS],
             EqualizerPreset::Classical => {
-                self.bands = vec![-2.0, -1.0, 0.0, 1.0, 2.0, 2.0, 1.0, 0.0, -1.0, -2.0]
+                vec![-2.0, -1.0, 0.0, 1.0, 2.0, 2.0, 1.0, 0.0, -1.0, -2.0]
             }
             EqualizerPreset::HipHop => {
-                self.bands = vec![3.0, 2.0, 0.0, -1.0, -2.0, -2.0, -1.0, 0.0, 2.0, 3.0]
+                vec![3.0, 2.0, 0.0, -1.0, -2.0, -2.0, -1.0, 0.0, 2.0, 3.0]
             }
             EqualizerPreset::Pop => {
-                self.bands = vec![1.0, 1.5, 2.0, 2.5, 3.0, 3.0, 2.5, 2.0, 1.5, 1.0]
+                vec![1.0, 1.5, 2.0, 2.5, 3.0, 3.0, 2.5, 2.0, 1.5, 1.0]
             }
             EqualizerPreset::Rock => {
-                self.bands = vec![2.0, 1.5, 1.0, 0.0, -1.0, -1.0, 0.0, 1.0, 1.5, 2.0]
+                vec![2.0, 1.5, 1.0, 0.0, -1.0, -1.0, 0.0, 1.0, 1.5, 2.0]
             }
             EqualizerPreset::HeavyMetal => {
-                self.bands = vec![4.0, 3.0, 2.0, 1.0, 0.0, 0.0, 1.0, 2.0, 3.0, 4.0]
+                vec![4.0, 3.0, 2.0, 1.0, 0.0, 0.0, 1.0, 2.0, 3.0, 4.0]
             }
             EqualizerPreset::Folk => {
-                self.bands = vec![0.0, 0.5, 1.0, 1.5, 2.0, 2.0, 1.5, 1.0, 0.5, 0.0]
+                vec![0.0, 0.5, 1.0, 1.5, 2.0, 2.0, 1.5, 1.0, 0.5, 0.0]
             }
             EqualizerPreset::Custom => {
                 // Leave bands unchanged.
+                return;
             }
+        };
+        self.bands = resample_bands(&curve, self.bands.len());
+    }
+
+    /// Switch to `count` bands, carrying the current curve over.
+    fn set_band_count(&mut self, count: usize) {
+        self.bands = resample_bands(&self.bands, count);
+    }
+
+    /// Fit a frequency/gain curve onto the band centers as a Custom preset.
+    fn load_curve(&mut self, curve: &[(f32, f32)]) {
+        self.bands = eq_center_frequencies(self.bands.len())
+            .into_iter()
+            .map(|freq| interpolate_gain(curve, freq).clamp(-10.0, 10.0))
+            .collect();
+        self.preset = EqualizerPreset::Custom;
+    }
+
+    /// Zero every band and the preamp and switch back to the Flat preset.
+    fn reset(&mut self) {
+        self.preset = EqualizerPreset::Flat;
+        self.preamp_db = 0.0;
+        self.bass_db = 0.0;
+        self.treble_db = 0.0;
+        self.apply_preset();
+    }
+
+    /// Take the curve, preamp and shelves of a saved preset, as a Custom curve.
+    fn apply_saved(&mut self, saved: &eq_presets::SavedPreset) {
+        self.bands = saved.bands.clone();
+        self.preamp_db = saved.preamp_db;
+        self.bass_db = saved.bass_db;
+        self.treble_db = saved.treble_db;
+        self.preset = EqualizerPreset::Custom;
+    }
+
+    /// Negate every band gain. The result is kept as a Custom curve.
+    fn invert(&mut self) {
+        for band in self.bands.iter_mut() {
+            *band = -*band;
         }
+        self.preset = EqualizerPreset::Custom;
     }
 }
 
 /// DSP chain using a series of biquad peak filters.
 struct EqualizerDSP {
     filters: Vec<DirectForm1<f32>>,
+    preamp_gain: f32,
+    limiter: Option<Limiter>,
+    bypassed: bool,
+}
+
+/// Biquad coefficients for the shelves and bands, in processing order.
+/// Shared by the DSP chain and the response curve in the EQ tab.
+fn eq_filter_coefficients(equalizer_settings: &EqualizerSettings, sample_rate: f32) -> Vec<Coefficients<f32>> {
+    let band_count = equalizer_settings.bands.len();
+    let center_frequencies = eq_center_frequencies(band_count);
+    let q = eq_band_q(band_count);
+    // Centers above Nyquist can't be realized at low sample rates
+    let max_frequency = sample_rate * 0.45;
+    let mut filters = Vec::new();
+    // Shelves run ahead of the graphic EQ and stack with it
+    let (bass_db, treble_db) = (equalizer_settings.bass_db, equalizer_settings.treble_db);
+    let shelves = [
+        (biquad::Type::LowShelf(bass_db), bass_db, BASS_SHELF_HZ),
+        (biquad::Type::HighShelf(treble_db), treble_db, TREBLE_SHELF_HZ.min(max_frequency)),
+    ];
+    for (shelf, gain_db, frequency) in shelves {
+        if gain_db == 0.0 {
+            continue;
+        }
+        filters.push(biquad_coefficients(shelf, sample_rate, frequency, Q_BUTTERWORTH_F32));
+    }
+    for (i, &gain_db) in equalizer_settings.bands.iter().enumerate() {
+        // Create a peaking EQ filter.
+        // The biquad::Type::PeakingEQ takes the gain value as a parameter.
+        let frequency = center_frequencies[i].min(max_frequency);
+        filters.push(biquad_coefficients(biquad::Type::PeakingEQ(gain_db), sample_rate, frequency, q));
+    }
+    filters
+}
+
+/// Coefficients of a biquad centred on `frequency` at `sample_rate`, which
+/// must be above twice the frequency. biquad's `from_params` normalizes by
+/// twice the sample rate instead of half of it, putting filters two octaves
+/// low, so the normalized form is used.
+fn biquad_coefficients(filter: biquad::Type<f32>, sample_rate: f32, frequency: f32, q: f32) -> Coefficients<f32> {
+    Coefficients::<f32>::from_normalized_params(filter, 2.0 * frequency / sample_rate, q)
+        .expect("filter frequency below Nyquist")
+}
+
+/// Gain in dB of one biquad at `frequency`, from its transfer function
+/// evaluated on the unit circle.
+fn biquad_response_db(coef: &Coefficients<f32>, frequency: f32, sample_rate: f32) -> f32 {
+    let w = std::f32::consts::TAU * frequency / sample_rate;
+    let (cos1, sin1) = (w.cos(), w.sin());
+    let (cos2, sin2) = ((2.0 * w).cos(), (2.0 * w).sin());
+    let num_re = coef.b0 + coef.b1 * cos1 + coef.b2 * cos2;
+    let num_im = -(coef.b1 * sin1 + coef.b2 * sin2);
+    let den_re = 1.0 + coef.a1 * cos1 + coef.a2 * cos2;
+    let den_im = -(coef.a1 * sin1 + coef.a2 * sin2);
+    let power = (num_re * num_re + num_im * num_im) / (den_re * den_re + den_im * den_im);
+    10.0 * power.max(1e-12).log10()
 }
 
 impl EqualizerDSP {
     /// Create a new DSP chain based on the equalizer settings.
-    fn new(equalizer_settings: &EqualizerSettings, sample_rate: f32) -> Self {
-        // Typical 10-band equalizer center frequencies in Hz.
-        let center_frequencies = vec![
-            31.25, 62.5, 125.0, 250.0, 500.0,
-            1000.0, 2000.0, 4000.0, 8000.0, 16000.0,
-        ];
-        let mut filters = Vec::new();
-        for (i, &gain_db) in equalizer_settings.bands.iter().enumerate() {
-            // Create a peaking EQ filter.
-            // The biquad::Type::PeakingEQ takes the gain value as a parameter.
-            let coef = Coefficients::<f32>::from_params(
-                biquad::Type::PeakingEQ(gain_db),
-                Hertz::<f32>::from_hz(sample_rate).unwrap(),          // Use from_hz instead of new
-                Hertz::<f32>::from_hz(center_frequencies[i]).unwrap(), // Use from_hz instead of new
-                1.0, // Q factor (adjust as needed)
-            ).unwrap();
-            // Specify the type to be f32 explicitly.
-            let filter = DirectForm1::<f32>::new(coef);
-            filters.push(filter);
-        }
-        Self { filters }
-    }
-
-    /// Process a single sample through the filter chain.
+    fn new(equalizer_settings: &EqualizerSettings, sample_rate: f32, channels: u16) -> Self {
+        let filters = eq_filter_coefficients(equalizer_settings, sample_rate)
+            .into_iter()
+            .map(DirectForm1::<f32>::new)
+            .collect();
+        let limiter = &equalizer_settings.limiter;
+        Self {
+            filters,
+            preamp_gain: db_to_gain(equalizer_settings.preamp_db),
+            limiter: limiter.enabled.then(|| Limiter::new(limiter, sample_rate, channels)),
+            bypassed: !equalizer_settings.enabled,
+        }
+    }
+
+    /// Retune the chain for changed settings. Filter and limiter state carry
+    /// over, so edits while playing don't drop out or restart the limiter.
+    fn update(&mut self, equalizer_settings: &EqualizerSettings, sample_rate: f32, channels: u16) {
+        let coefficients = eq_filter_coefficients(equalizer_settings, sample_rate);
+        if coefficients.len() == self.filters.len() {
+            for (filter, coef) in self.filters.iter_mut().zip(coefficients) {
+                filter.update_coefficients(coef);
+            }
+        } else {
+            // A shelf was switched on or off, so the chain's layout changed
+            self.filters = coefficients.into_iter().map(DirectForm1::<f32>::new).collect();
+        }
+        self.preamp_gain = db_to_gain(equalizer_settings.preamp_db);
+        let limiter = &equalizer_settings.limiter;
+        if !limiter.enabled {
+            self.limiter = None;
+        } else if let Some(existing) = &mut self.limiter {
+            existing.set_params(limiter, sample_rate);
+        } else {
+            self.limiter = Some(Limiter::new(limiter, sample_rate, channels));
+        }
+        self.bypassed = !equalizer_settings.enabled;
+    }
+
+    /// Process a single sample through the preamp, filter chain and limiter.
+    /// The soft clipper stays last to catch anything the limiter lets through.
+    /// When bypassed, samples pass through untouched.
     fn process_sample(&mut self, sample: f32) -> f32 {
-        self.filters.iter_mut().fold(sample, |s, filter| filter.run(s))
+        if self.bypassed {
+            return sample;
+        }
+        let filtered = self.filters.iter_mut().fold(sample * self.preamp_gain, |s, filter| filter.run(s));
+        let limited = match &mut self.limiter {
+            Some(limiter) => limiter.process(filtered),
+            None => filtered,
+        };
+        soft_clip(limited)
+    }
+}
+
+/// Level above which `soft_clip` starts compressing.
+const SOFT_CLIP_KNEE: f32 = 0.8;
+
+/// Linear below the knee, then a tanh curve approaching full scale so
+/// overdriven samples saturate smoothly instead of hard clipping.
+fn soft_clip(sample: f32) -> f32 {
+    let magnitude = sample.abs();
+    if magnitude <= SOFT_CLIP_KNEE {
+        return sample;
     }
+    let headroom = 1.0 - SOFT_CLIP_KNEE;
+    sample.signum() * (SOFT_CLIP_KNEE + headroom * ((magnitude - SOFT_CLIP_KNEE) / headroom).tanh())
 }
 
 /// Custom rodio source that processes samples with the equalizer DSP chain.
@@ -166,36 +1229,83 @@ where
     dsp: EqualizerDSP,
     // Add shared equalizer settings reference
     equalizer_settings: Arc<Mutex<EqualizerSettings>>,
-    sample_rate: f32,
-    // Track when settings have changed to rebuild the DSP chain
-    last_update: usize,
+    // Rate the DSP chain is tuned for; follows `inner` across frames
+    sample_rate: u32,
+    // Bumped on every settings change; compared to rebuild the DSP chain
+    generation: Arc<AtomicUsize>,
+    last_generation: usize,
+    // Balance only applies to stereo, by position within the frame
+    channels: u16,
+    channel_pos: u16,
+    balance_gains: [f32; 2],
 }
 
-impl<S> Iterator for EqualizedSource<S>
+impl<S> EqualizedSource<S>
 where
     S: Source<Item = f32>,
 {
-    type Item = f32;
-    fn next(&mut self) -> Option<Self::Item> {
-        // Check if equalizer settings have changed
-        let current_update = {
-            let settings = self.equalizer_settings.lock().unwrap();
-            // Just accessing the lock will tell us if settings changed
-            settings.bands.len() // Using length as a simple hash
-        };
-        
-        // If settings changed, rebuild the DSP chain
-        if current_update != self.last_update {
-            let settings = self.equalizer_settings.lock().unwrap().clone();
-            self.dsp = EqualizerDSP::new(&settings, self.sample_rate);
-            self.last_update = current_update;
+    /// Wrap `inner` in the DSP chain for the current shared settings,
+    /// tuned to its own sample rate so filter frequencies hold whatever the
+    /// file's rate; the output converts to the device rate afterwards, once.
+    /// Bumping `generation` makes it pick up changed settings.
+    fn new(inner: S, equalizer_settings: Arc<Mutex<EqualizerSettings>>, generation: Arc<AtomicUsize>) -> Self {
+        let channels = inner.channels();
+        let sample_rate = inner.sample_rate();
+        let settings = equalizer_settings.lock().unwrap().clone();
+        Self {
+            channels,
+            channel_pos: 0,
+            balance_gains: settings.balance_gains(),
+            inner,
+            dsp: EqualizerDSP::new(&settings, sample_rate as f32, channels),
+            equalizer_settings,
+            sample_rate,
+            last_generation: generation.load(Ordering::Acquire),
+            generation,
         }
-        
-        self.inner.next().map(|sample| self.dsp.process_sample(sample))
     }
 }
 
-impl<S> Source for EqualizedSource<S>
+impl<S> Iterator for EqualizedSource<S>
+where
+    S: Source<Item = f32>,
+{
+    type Item = f32;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.channel_pos == 0 {
+            // A new format starts a new chain, as at a track change
+            let (channels, sample_rate) = (self.inner.channels(), self.inner.sample_rate());
+            if (channels, sample_rate) != (self.channels, self.sample_rate) {
+                let settings = self.equalizer_settings.lock().unwrap().clone();
+                self.dsp = EqualizerDSP::new(&settings, sample_rate as f32, channels);
+                (self.channels, self.sample_rate) = (channels, sample_rate);
+            }
+        }
+
+        // Check if equalizer settings have changed
+        let current_generation = self.generation.load(Ordering::Acquire);
+        
+        // If settings changed, retune the DSP chain between frames so both
+        // channels of a frame go through the same settings
+        if current_generation != self.last_generation && self.channel_pos == 0 {
+            let settings = self.equalizer_settings.lock().unwrap().clone();
+            self.dsp.update(&settings, self.sample_rate as f32, self.channels);
+            self.balance_gains = settings.balance_gains();
+            self.last_generation = current_generation;
+        }
+        
+        let sample = self.dsp.process_sample(self.inner.next()?);
+        let channel = self.channel_pos as usize;
+        self.channel_pos = (self.channel_pos + 1) % self.channels.max(1);
+        if self.channels >= 2 && channel < 2 {
+            Some(sample * self.balance_gains[channel])
+        } else {
+            Some(sample)
+        }
+    }
+}
+
+impl<S> Source for EqualizedSource<S>
 where
     S: Source<Item = f32>,
 {
@@ -213,466 +1323,4188 @@ where
     }
 }
 
+/// A track appended to the playing sink ahead of time for gapless playback.
+struct QueuedTrack {
+    index: usize,
+    path: PathBuf, // to find the entry again if the queue is edited
+    cue: Option<CueSpan>,
+    duration: Option<f32>,
+    position: position::PlaybackPosition,
+}
+
+/// Tag values being edited in the Edit Tags window.
+struct TagEditor {
+    path: PathBuf,
+    title: String,
+    artist: String,
+    album: String,
+    error: Option<String>,
+}
+
+/// Outcome of moving the library out of a previous collections folder.
+struct CollectionsMove {
+    from: PathBuf,
+    // (old path, new path) of every audio file that moved
+    moved_tracks: Vec<(PathBuf, PathBuf)>,
+    skipped: usize,
+}
+
+/// A URL playing straight from the network, outside the queue.
+struct ActiveStream {
+    title: String,
+    media_url: String, // resolved address, reopened from an offset to seek
+    status: stream::StreamStatus,
+    // Paused by us while waiting for more data, not by the user
+    stalled: bool,
+}
+
+/// A sink volume change spread over a short time, such as a user volume
+/// change or a fade around pause and resume.
+struct VolumeRamp {
+    from: f32,
+    to: f32,
+    started: Instant,
+    duration: Duration,
+    then_pause: bool, // pause the sink once the volume arrives
+}
+
+/// The outgoing track's sink while a crossfade is in progress.
+struct FadingSink {
+    sink: Sink,
+    volume: f32, // volume when the fade started
+    started: Instant,
+    duration: Duration,
+}
+
+/// A pending sleep: pause at `deadline`, fading out over `SLEEP_FADE`
+/// first, or stop once the current track ends.
+#[derive(Default)]
+struct SleepTimer {
+    deadline: Option<Instant>,
+    at_track_end: bool,
+    fade_started: Option<Instant>,
+}
+
+impl SleepTimer {
+    /// Pause after `minutes`, or push an existing deadline back by that much.
+    /// Returns true when a partial fade needs the volume restored.
+    fn extend(&mut self, minutes: u64) -> bool {
+        let base = self.deadline.unwrap_or_else(Instant::now);
+        self.deadline = Some(base + Duration::from_secs(minutes * 60));
+        self.at_track_end = false;
+        self.fade_started.take().is_some()
+    }
+
+    /// Clear the timer. Returns true when a partial fade needs the volume
+    /// restored.
+    fn cancel(&mut self) -> bool {
+        self.deadline = None;
+        self.at_track_end = false;
+        self.fade_started.take().is_some()
+    }
+
+    fn due(&self) -> bool {
+        self.deadline.is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    /// Fade `sink` down from `volume` once the deadline passes. When the fade
+    /// completes the sink is paused and the timer cleared, and true returned.
+    fn fade(&mut self, sink: &Sink, volume: f32) -> bool {
+        if !self.due() {
+            return false;
+        }
+        let started = *self.fade_started.get_or_insert_with(Instant::now);
+        let t = started.elapsed().as_secs_f32() / SLEEP_FADE.as_secs_f32();
+        if t < 1.0 {
+            sink.set_volume(volume * (1.0 - t));
+            return false;
+        }
+        // Already silent, so pause straight away instead of fading out again
+        sink.pause();
+        *self = SleepTimer::default();
+        true
+    }
+}
+
 /// Main application struct.
 struct AudioPlayerApp {
-    queue: Vec<MediaItem>,
-    current_index: Option<usize>,
-    stream: Option<OutputStream>,
-    stream_handle: Option<OutputStreamHandle>,
-    sink: Option<Sink>,
-    is_paused: bool,
+    player: player::Player,
+    // Chosen output device by name; None follows the system default
+    output_device: Option<String>,
+    output_devices: Vec<String>,
+    device_status: String,
+    device_checked_at: Option<Instant>,
+    // Device list being gathered on a worker thread
+    device_scan: Option<Receiver<Vec<String>>>,
+    fading_out: Option<FadingSink>,
+    // Fade applied to the next source started, set when a crossfade begins
+    fade_in_next: Option<Duration>,
+    crossfade_secs: f32, // 0 disables crossfading
+    fade_in_secs: f32,   // on play and resume; 0 disables
+    fade_out_secs: f32,  // before pausing; 0 disables
+    auto_continue: bool, // keep playing from the collection when the queue ends
+    sleep: SleepTimer,
+    // A-B loop points in seconds; playback jumps back to A on reaching B
+    loop_a: Option<f32>,
+    loop_b: Option<f32>,
+    volume_ramp: Option<VolumeRamp>,
+    queued_next: Option<QueuedTrack>,
+    // Set once the next track has been prebuffered (or failed to) for this track
+    prebuffer_attempted: bool,
     volume: f32,
-    shuffle: bool,
+    // While muted the sink is silent but `volume` keeps the level for display
+    is_muted: bool,
+    pre_mute_volume: f32,
     youtube_url: String,
     download_status: String,
-    youtube_sender: Option<Sender<(MediaItem, String)>>,
-    youtube_receiver: Option<Receiver<(MediaItem, String)>>,
+    yt_dlp_available: bool,
+    downloads: downloads::DownloadManager,
+    youtube_receiver: Option<Receiver<DownloadEvent>>,
+    web_stream: Option<ActiveStream>,
+    stream_sender: Sender<stream::StreamEvent>,
+    stream_receiver: Receiver<stream::StreamEvent>,
+    key_sender: Sender<KeyCommand>,
+    #[cfg(any(target_os = "windows", target_os = "linux"))]
+    tray: Option<tray::Tray>,
+    #[cfg(any(target_os = "windows", target_os = "linux"))]
+    notifications: Sender<notifications::NowPlaying>,
+    #[cfg(any(target_os = "windows", target_os = "linux"))]
+    notify_track_changes: bool,
+    #[cfg(any(target_os = "windows", target_os = "linux"))]
+    notified_track: Option<(PathBuf, Option<CueSpan>)>, // last track announced or seen playing
+    window_visible: bool,
     key_receiver: Receiver<KeyCommand>,
+    global_keys_enabled: Arc<AtomicBool>,
+    key_listener_started: bool,
+    key_listener_error: Option<String>,
+    #[cfg(target_os = "linux")]
+    media_commands: Receiver<mpris::MediaCommand>,
+    #[cfg(target_os = "linux")]
+    media_state: Sender<mpris::MediaState>,
+    // Last state published over MPRIS, so only changes are sent
+    #[cfg(target_os = "linux")]
+    last_media_state: mpris::MediaState,
+    // Cover art URL of the last published track, so the disk is only searched on track changes
+    #[cfg(target_os = "linux")]
+    media_cover_art: Option<(PathBuf, Option<String>)>,
     collections_path: PathBuf,
+    collections: Vec<MediaItem>,
+    collections_mtime: Option<SystemTime>,
+    collections_checked_at: Option<Instant>,
+    // Kept alive to receive events; None falls back to mtime polling
+    collections_watcher: Option<notify::RecommendedWatcher>,
+    // Time of the latest relevant watcher event not yet re-scanned
+    collections_dirty_at: Arc<Mutex<Option<Instant>>>,
     show_collections: bool,
+    // Mini-player layout, and the full window size to restore when leaving it
+    compact: bool,
+    expanded_size: Option<egui::Vec2>,
+    theme_settings: theme::ThemeSettings,
+    // Set while a color picker is being dragged; theme.json is written once it's let go
+    theme_unsaved: bool,
+    // Styling passed to the custom widgets
+    theme: theme::Theme,
     collections_search: String,
+    // Narrows the queue list without changing the queue itself
+    queue_filter: String,
+    favorites: favorites::Favorites,
+    favorites_only: bool,
+    collections_view: CollectionsView,
+    collection_click_action: CollectionClickAction,
+    collections_sort: SortOrder,
+    // Indices into `collections` in `collections_sort` order; None once the
+    // list, its tags or the sort order change
+    collections_order: Option<Vec<usize>>,
+    // Grouped views built from the listed items, keyed by a hash of the view
+    // and those items; cleared along with `collections_order`
+    collection_groups: Option<(u64, CollectionGroups)>,
+    // File modification times from the last scan, for sorting by date added
+    collections_added: HashMap<PathBuf, SystemTime>,
+    expanded_album: Option<String>,
+    // Files awaiting delete confirmation
+    pending_delete: Vec<PathBuf>,
+    // Deletes retried off the UI thread once the audio thread lets go of the file
+    delete_sender: Sender<(PathBuf, io::Result<()>)>,
+    delete_receiver: Receiver<(PathBuf, io::Result<()>)>,
+    // Ctrl/Shift-click selections for batch actions, with the row a Shift-click extends from
+    queue_selection: HashSet<usize>,
+    queue_selection_anchor: Option<usize>,
+    // Fingerprint of the queue the selection was made in
+    queue_selection_fingerprint: u64,
+    collection_selection: HashSet<PathBuf>,
+    collection_selection_anchor: Option<PathBuf>,
+    // Collection rows in the order drawn this frame and the last, for Shift-click ranges
+    collection_rows: Vec<PathBuf>,
+    collection_rows_shown: Vec<PathBuf>,
+    // Why each file that couldn't be played this session failed
+    failed_tracks: HashMap<PathBuf, String>,
+    // Failures in a row, so a queue of unplayable files stops instead of cycling
+    consecutive_failures: usize,
+    skip_failed_track: bool,
+    toast: Option<(String, Instant)>,
+    tag_editor: Option<TagEditor>,
+    // Rename in progress (path, new name)
+    renaming: Option<(PathBuf, String)>,
+    collections_status: String,
+    // Previous collections folder and how many tracks were left in it
+    collections_move_prompt: Option<(PathBuf, usize)>,
+    // Library moves run off the UI thread and report back here
+    collections_move_sender: Sender<CollectionsMove>,
+    collections_move_receiver: Receiver<CollectionsMove>,
+    // (imported, failed) counts from copies into the collections folder
+    import_sender: Sender<(usize, usize)>,
+    import_receiver: Receiver<(usize, usize)>,
     show_youtube_input: bool,
     youtube_search_url: String,
     current_position: f32,
-    total_duration: f32, // dummy value for demonstration
+    total_duration: Option<f32>, // None when the length can't be determined
+    duration_sender: Sender<(PathBuf, f32)>,
+    // Tags read so far; None means the file had no usable tags
+    tag_cache: HashMap<PathBuf, Option<TrackTags>>,
+    tags_pending: HashSet<PathBuf>,
+    tag_sender: Sender<(PathBuf, Option<TrackTags>)>,
+    tag_receiver: Receiver<(PathBuf, Option<TrackTags>)>,
+    // Cover image and stream info of the current track, loaded off the UI thread
+    track_details_path: Option<PathBuf>,
+    album_art: Option<Vec<u8>>,
+    album_art_cache: widgets::AlbumArtCache,
+    track_info: Option<metadata::AudioInfo>,
+    track_details_sender: Sender<TrackDetails>,
+    track_details_receiver: Receiver<TrackDetails>,
+    duration_receiver: Receiver<(PathBuf, f32)>,
     current_tab: AppTab,
     equalizer: EqualizerSettings,
-    // Add shared state for real-time adjustments
-    shared_equalizer: Arc<Mutex<EqualizerSettings>>,
+    eq_status: String,
+    saved_presets: Vec<eq_presets::SavedPreset>,
+    active_saved_preset: Option<String>,
+    per_track_eq: bool,
+    track_eqs: track_eq::TrackEqs,
+    // The global curve, set aside while a track's own EQ is applied
+    global_eq: Option<EqualizerSettings>,
+    preset_name_input: String,
+    pending_overwrite: Option<String>,
+    playlists: Vec<String>,
+    playlist_name_input: String,
+    playlist_status: String,
+    history: history::History,
+    history_most_played: bool, // show Most Played instead of Recently Played
+    // History rows as listed and whether their files still exist; cleared
+    // when the collections folder is re-scanned or tags arrive
+    history_rows: HashMap<PathBuf, (MediaItem, bool)>,
+    speed: f32,
+    preserve_pitch: bool,
+    visualizer: Arc<Mutex<AudioVisualizer>>,
+    visualizer_mode: VisualizerMode,
+    config: config::Config,
+    track_gains: track_gains::TrackGains,
+    tasks: tasks::TaskManager,
+    scrobbler: scrobbler::Scrobbler,
+    scrobbler_events: Receiver<scrobbler::ScrobblerEvent>,
+    scrobbling_enabled: bool,
+    scrobble: Option<PendingScrobble>,
+    // Last.fm login form; the password is cleared once sent
+    lastfm_api_key: String,
+    lastfm_api_secret: String,
+    lastfm_username: String,
+    lastfm_password: String,
+    lastfm_status: String,
+    normalize_volume: bool,
+    loudness_cache: loudness::LoudnessCache,
+    // Paths already checked or queued for analysis this session
+    loudness_checked: HashSet<PathBuf>,
+    loudness_sender: Sender<(PathBuf, Option<f32>)>,
+    loudness_receiver: Receiver<(PathBuf, Option<f32>)>,
+    // Amplitude outlines drawn on the progress bar, keyed by path; empty if
+    // the file couldn't be decoded
+    show_waveform_overview: bool,
+    waveform_overviews: HashMap<PathBuf, waveform::Overview>,
+    waveform_pending: HashSet<PathBuf>,
+    waveform_sender: Sender<(PathBuf, Option<waveform::Overview>)>,
+    waveform_receiver: Receiver<(PathBuf, Option<waveform::Overview>)>,
+    queue_auto_follow: bool,
+    show_remaining_time: bool, // count down to the end instead of up from the start
+    // Index the queue was last scrolled to, so we only follow on track changes
+    queue_followed_index: Option<usize>,
+    queue_user_scrolled_at: Option<Instant>,
+    // Set by "Show in Queue"; scrolls once even with auto-follow off
+    queue_jump_requested: bool,
 }
 
 impl AudioPlayerApp {
-    fn new() -> Self {
-        let (stream, stream_handle) = OutputStream::try_default().unwrap();
-        let (yt_tx, yt_rx) = channel::<(MediaItem, String)>();
-        let (key_tx, key_rx) = channel::<KeyCommand>();
+    /// Build the app from the config `main` loaded.
+    fn new(config: config::Config, theme_settings: theme::ThemeSettings, ctx: &egui::Context) -> Self {
+        let theme = theme_settings.to_theme();
+        theme.apply_to_ctx(ctx);
+        let working_dir = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
 
-        // Global key listener thread.
-        thread::spawn(move || {
-            let mut ctrl_pressed = false;
-            if let Err(e) = listen(move |event: Event| {
-                match event.event_type {
-                    EventType::KeyPress(key) => {
-                        if key == Key::ControlLeft || key == Key::ControlRight {
-                            ctrl_pressed = true;
-                        }
-                        if key == Key::KeyU && ctrl_pressed {
-                            let _ = key_tx.send(KeyCommand::IncreaseVolume);
-                        }
-                        if key == Key::KeyD && ctrl_pressed {
-                            let _ = key_tx.send(KeyCommand::DecreaseVolume);
-                        }
-                        if key == Key::KeyP && ctrl_pressed {
-                            let _ = key_tx.send(KeyCommand::TogglePause);
-                        }
+        let mut output_device = config.output_device.clone();
+        let mut device_status = String::new();
+        // Without any device the app still starts, with playback disabled
+        let output = match open_output_stream(output_device.as_deref()) {
+            Ok(output) => Some(output),
+            Err(e) => {
+                output_device = None;
+                match OutputStream::try_default() {
+                    Ok(output) => {
+                        device_status = format!("{}; using the default device", e);
+                        Some(output)
                     }
-                    EventType::KeyRelease(key) => {
-                        if key == Key::ControlLeft || key == Key::ControlRight {
-                            ctrl_pressed = false;
-                        }
+                    Err(e) => {
+                        eprintln!("No audio output device: {}", e);
+                        None
                     }
-                    _ => {}
                 }
-            }) {
-                eprintln!("Global key listener error: {:?}", e);
             }
-        });
+        };
+        let (yt_tx, yt_rx) = channel::<DownloadEvent>();
+        let (key_tx, key_rx) = channel::<KeyCommand>();
+        let (duration_sender, duration_receiver) = channel::<(PathBuf, f32)>();
+        let (tag_sender, tag_receiver) = channel::<(PathBuf, Option<TrackTags>)>();
+        let (track_details_sender, track_details_receiver) = channel::<TrackDetails>();
+        let (loudness_sender, loudness_receiver) = channel::<(PathBuf, Option<f32>)>();
+        let (waveform_sender, waveform_receiver) = channel::<(PathBuf, Option<waveform::Overview>)>();
+        let (stream_sender, stream_receiver) = channel::<stream::StreamEvent>();
+        let (delete_sender, delete_receiver) = channel::<(PathBuf, io::Result<()>)>();
+        let (collections_move_sender, collections_move_receiver) = channel::<CollectionsMove>();
+        let (import_sender, import_receiver) = channel::<(usize, usize)>();
+        let (scrobbler_tx, scrobbler_events) = channel::<scrobbler::ScrobblerEvent>();
+        #[cfg(target_os = "linux")]
+        let (media_tx, media_commands) = channel::<mpris::MediaCommand>();
+
+        let collections_path = configured_collections_path(&config);
+        if let Err(e) = fs::create_dir_all(&collections_path) {
+            eprintln!("Cannot create collections folder {}: {}", collections_path.display(), e);
+        }
+        let history = history::History::load(&collections_path);
+
+        let saved_presets = eq_presets::load_all(&eq_presets::presets_dir(&collections_path));
+        let track_eqs = track_eq::TrackEqs::load(&collections_path, track_eq::TRACK_EQ_FILE);
+        let mut track_gains = track_gains::TrackGains::load(&collections_path);
+        if track_gains.import_legacy(&working_dir.join(track_gains::LEGACY_TRACK_GAINS_FILE)) {
+            match track_gains.save(&collections_path) {
+                Ok(()) => {
+                    let _ = fs::remove_file(working_dir.join(track_gains::LEGACY_TRACK_GAINS_FILE));
+                }
+                Err(e) => eprintln!("Failed to save track gains: {}", e),
+            }
+        }
+        let playlists = playlists::list(&playlists::playlists_dir(&collections_path));
+        let loudness_cache = loudness::LoudnessCache::load(&collections_path);
+        let favorites = favorites::Favorites::load(&collections_path);
+
+        // A hand-edited, invalid size falls back to the defaults
+        let mut visualizer = AudioVisualizer::with_resolution(44100, config.visualizer_fft_size, config.visualizer_bands)
+            .unwrap_or_else(|_| AudioVisualizer::new(44100));
+        visualizer.stereo = config.visualizer_stereo;
+        visualizer.set_spectrogram_history(config.visualizer_history);
+        visualizer.spectrogram_colors = config.visualizer_colors;
 
-        let collections_path = std::env::current_dir()
-            .unwrap_or_else(|_| PathBuf::from("."))
-            .join("my_collections");
-        fs::create_dir_all(&collections_path).unwrap();
+        let mut equalizer = EqualizerSettings::new();
+        equalizer.enabled = config.eq_enabled;
+        equalizer.balance = config.balance.clamp(-1.0, 1.0);
+        equalizer.limiter.enabled = config.limiter_enabled;
+        equalizer.limiter.attack_ms = config.limiter_attack_ms;
+        equalizer.limiter.release_ms = config.limiter_release_ms;
 
-        let equalizer = EqualizerSettings::new();
-        let shared_equalizer = Arc::new(Mutex::new(equalizer.clone()));
+        let crossfeed = CrossfeedSettings {
+            enabled: config.crossfeed_enabled,
+            intensity: config.crossfeed_intensity,
+        };
         
-        Self {
-            queue: Vec::new(),
-            current_index: None,
-            stream: Some(stream),
-            stream_handle: Some(stream_handle),
-            sink: None,
-            is_paused: false,
+        let global_keys = config.global_keys.unwrap_or_else(global_keys_supported);
+
+        let mut app = Self {
+            player: player::Player::new(output, SourceChain::new(equalizer.clone(), crossfeed)),
+            output_device,
+            output_devices: list_output_devices(),
+            device_status,
+            device_checked_at: None,
+            device_scan: None,
+            fading_out: None,
+            fade_in_next: None,
+            crossfade_secs: config.crossfade_secs,
+            fade_in_secs: config.fade_in_secs,
+            fade_out_secs: config.fade_out_secs,
+            auto_continue: config.auto_continue,
+            queued_next: None,
+            prebuffer_attempted: false,
+            sleep: SleepTimer::default(),
+            loop_a: None,
+            loop_b: None,
+            volume_ramp: None,
             volume: 0.5,
-            shuffle: false,
+            is_muted: false,
+            pre_mute_volume: 0.5,
             youtube_url: String::new(),
             download_status: String::new(),
-            youtube_sender: Some(yt_tx),
+            yt_dlp_available: yt_dlp_available(),
+            downloads: downloads::DownloadManager::new(yt_tx),
             youtube_receiver: Some(yt_rx),
+            web_stream: None,
+            stream_sender,
+            stream_receiver,
+            #[cfg(any(target_os = "windows", target_os = "linux"))]
+            tray: tray::Tray::new(key_tx.clone(), ctx.clone()),
+            #[cfg(any(target_os = "windows", target_os = "linux"))]
+            notifications: notifications::spawn(key_tx.clone(), ctx.clone()),
+            #[cfg(any(target_os = "windows", target_os = "linux"))]
+            notify_track_changes: config.notifications,
+            #[cfg(any(target_os = "windows", target_os = "linux"))]
+            notified_track: None,
+            window_visible: true,
+            key_sender: key_tx,
             key_receiver: key_rx,
+            global_keys_enabled: Arc::new(AtomicBool::new(false)),
+            key_listener_started: false,
+            key_listener_error: None,
+            #[cfg(target_os = "linux")]
+            media_commands,
+            #[cfg(target_os = "linux")]
+            media_state: mpris::spawn(media_tx),
+            #[cfg(target_os = "linux")]
+            last_media_state: mpris::MediaState::default(),
+            #[cfg(target_os = "linux")]
+            media_cover_art: None,
             collections_path,
+            collections: Vec::new(),
+            collections_mtime: None,
+            collections_checked_at: None,
+            collections_watcher: None,
+            collections_dirty_at: Arc::new(Mutex::new(None)),
             show_collections: true,
-            collections_search: String::new(),
+            compact: config.compact_mode,
+            expanded_size: None,
+            theme,
+            theme_settings,
+            theme_unsaved: false,
+            collections_search: config.collections_search.clone(),
+            queue_filter: String::new(),
+            favorites,
+            favorites_only: config.favorites_only,
+            collections_view: CollectionsView::List,
+            collection_click_action: config.collection_click,
+            collections_sort: config.collections_sort,
+            collections_order: None,
+            collection_groups: None,
+            collections_added: HashMap::new(),
+            expanded_album: None,
+            pending_delete: Vec::new(),
+            delete_sender,
+            delete_receiver,
+            queue_selection: HashSet::new(),
+            queue_selection_anchor: None,
+            queue_selection_fingerprint: 0,
+            collection_selection: HashSet::new(),
+            collection_selection_anchor: None,
+            collection_rows: Vec::new(),
+            collection_rows_shown: Vec::new(),
+            failed_tracks: HashMap::new(),
+            consecutive_failures: 0,
+            skip_failed_track: false,
+            toast: None,
+            tag_editor: None,
+            renaming: None,
+            collections_status: String::new(),
+            collections_move_prompt: None,
+            collections_move_sender,
+            collections_move_receiver,
+            import_sender,
+            import_receiver,
             show_youtube_input: false,
             youtube_search_url: String::new(),
             current_position: 0.0,
-            total_duration: 240.0, // Dummy 4-minute duration.
+            total_duration: None,
+            duration_sender,
+            tag_cache: HashMap::new(),
+            tags_pending: HashSet::new(),
+            tag_sender,
+            tag_receiver,
+            track_details_path: None,
+            album_art: None,
+            album_art_cache: widgets::AlbumArtCache::default(),
+            track_info: None,
+            track_details_sender,
+            track_details_receiver,
+            duration_receiver,
             current_tab: AppTab::Player,
             equalizer,
-            shared_equalizer,
+            eq_status: String::new(),
+            saved_presets,
+            active_saved_preset: None,
+            per_track_eq: config.per_track_eq,
+            track_eqs,
+            global_eq: None,
+            preset_name_input: String::new(),
+            pending_overwrite: None,
+            playlists,
+            playlist_name_input: String::new(),
+            playlist_status: String::new(),
+            history,
+            history_most_played: false,
+            history_rows: HashMap::new(),
+            speed: 1.0,
+            preserve_pitch: config.preserve_pitch,
+            visualizer: Arc::new(Mutex::new(visualizer)),
+            visualizer_mode: config.visualizer_mode,
+            normalize_volume: config.normalize_volume,
+            scrobbling_enabled: config.lastfm_enabled,
+            track_gains,
+            tasks: tasks::TaskManager::new(),
+            // Older versions kept the login and queue in the working directory
+            scrobbler: scrobbler::Scrobbler::spawn(
+                config::user_file(&config::config_dir(), &working_dir, scrobbler::LASTFM_FILE),
+                config::user_file(&config::data_dir(), &working_dir, scrobbler::QUEUE_FILE),
+                scrobbler_tx,
+            ),
+            scrobbler_events,
+            scrobble: None,
+            lastfm_api_key: String::new(),
+            lastfm_api_secret: String::new(),
+            lastfm_username: String::new(),
+            lastfm_password: String::new(),
+            lastfm_status: String::new(),
+            loudness_cache,
+            loudness_checked: HashSet::new(),
+            loudness_sender,
+            loudness_receiver,
+            show_waveform_overview: config.waveform_overview,
+            waveform_overviews: HashMap::new(),
+            waveform_pending: HashSet::new(),
+            waveform_sender,
+            waveform_receiver,
+            queue_auto_follow: config.queue_auto_follow,
+            show_remaining_time: config.show_remaining_time,
+            queue_followed_index: None,
+            queue_jump_requested: false,
+            queue_user_scrolled_at: None,
+            config,
+        };
+        app.set_global_keys(global_keys);
+        app.watch_collections();
+        if let Some(saved) = session::load(&app.collections_path) {
+            app.restore_session(saved);
         }
+        app
     }
 
-    /// Load and play the current track.
-    /// Wrap the decoded audio with EqualizedSource to process samples.
-    fn play_current(&mut self) {
-        if let Some(idx) = self.current_index {
-            if idx < self.queue.len() {
-                if let Some(sink) = self.sink.take() {
-                    sink.stop();
-                }
-                let item = &self.queue[idx];
-                if let Ok(file) = fs::File::open(&item.file_path) {
-                    if let Ok(decoder) = Decoder::new(BufReader::new(file)) {
-                        if let Some(ref handle) = self.stream_handle {
-                            self.current_position = 0.0;
-                            let sample_rate = decoder.sample_rate() as f32;
-                            
-                            // Update shared settings before creating the source
-                            {
-                                let mut shared = self.shared_equalizer.lock().unwrap();
-                                *shared = self.equalizer.clone();
-                            }
-                            
-                            let equalized_source = EqualizedSource {
-                                inner: decoder.convert_samples(),
-                                dsp: EqualizerDSP::new(&self.equalizer, sample_rate),
-                                equalizer_settings: self.shared_equalizer.clone(),
-                                sample_rate,
-                                last_update: self.equalizer.bands.len(),
-                            };
-                            
-                            let sink = Sink::try_new(handle).unwrap();
-                            sink.append(equalized_source);
-                            sink.set_volume(self.volume);
-                            self.sink = Some(sink);
-                            self.is_paused = false;
-                        }
-                    }
-                }
-            }
+    /// Snapshot the state that should survive a restart.
+    fn session(&self) -> session::Session {
+        // A track's own EQ isn't the one to come back to
+        let global_eq = self.global_eq.as_ref().unwrap_or(&self.equalizer);
+        session::Session {
+            queue: self.player.queue.iter().map(|item| item.file_path.clone()).collect(),
+            cue_tracks: self.player.queue.iter().map(|item| item.cue.map(|cue| cue.track)).collect(),
+            album_groups: self.player.queue.iter().map(|item| item.album_group).collect(),
+            current_index: self.player.current_index,
+            position: self.current_position,
+            volume: self.volume,
+            shuffle: self.player.shuffle,
+            repeat_mode: self.player.repeat_mode,
+            eq_preset: global_eq.preset.clone(),
+            eq_bands: global_eq.bands.clone(),
+            eq_preamp_db: global_eq.preamp_db,
+            eq_bass_db: global_eq.bass_db,
+            eq_treble_db: global_eq.treble_db,
         }
     }
 
-    fn next_track(&mut self) {
-        if self.queue.is_empty() {
-            return;
+    /// Apply command-line options: queue the given files and folders and play
+    /// the first of them. Paths that can't be opened are skipped with a warning.
+    fn apply_launch_args(&mut self, args: LaunchArgs) {
+        if args.shuffle {
+            self.player.shuffle = true;
         }
-        if self.shuffle {
-            let mut indices: Vec<usize> = (0..self.queue.len()).collect();
-            if let Some(current) = self.current_index {
-                indices.retain(|&i| i != current);
+        if let Some(volume) = args.volume {
+            self.set_volume(volume);
+        }
+        if let Some(name) = args.eq_preset {
+            let saved = self.saved_presets.iter().find(|p| p.name.eq_ignore_ascii_case(&name)).cloned();
+            if let Some(saved) = saved {
+                self.equalizer.apply_saved(&saved);
+                self.active_saved_preset = Some(saved.name);
+                self.update_equalizer_settings();
+            } else if let Some(preset) = EqualizerPreset::from_name(&name) {
+                self.equalizer.preset = preset;
+                self.active_saved_preset = None;
+                self.equalizer.apply_preset();
+                self.update_equalizer_settings();
+            } else {
+                eprintln!("Warning: unknown equalizer preset \"{}\"", name);
             }
-            if let Some(&next) = indices.choose(&mut rand::thread_rng()) {
-                self.current_index = Some(next);
+        }
+
+        let first_new = self.player.queue.len();
+        for path in args.paths {
+            if path.is_dir() {
+                self.add_folder(path);
+            } else if path.is_file() && is_audio_file(&path) {
+                self.add_file(MediaItem::from_path(path));
+            } else if path.is_file() && playlists::is_playlist_file(&path) {
+                self.add_playlist_file(&path);
+            } else {
+                eprintln!("Warning: skipping {}: not an audio file, playlist or folder", path.display());
             }
-        } else {
-            self.current_index = Some(match self.current_index {
-                Some(i) if i + 1 < self.queue.len() => i + 1,
-                _ => 0,
-            });
         }
-        self.play_current();
+        if self.player.queue.len() > first_new && self.player.current_index != Some(first_new) {
+            self.player.play_index(first_new);
+            self.play_current();
+        }
     }
 
-    fn prev_track(&mut self) {
-        if self.queue.is_empty() {
-            return;
-        }
-        if self.shuffle {
-            let mut indices: Vec<usize> = (0..self.queue.len()).collect();
-            if let Some(current) = self.current_index {
-                indices.retain(|&i| i != current);
+    /// Rebuild the queue and settings from a saved session. Files that no
+    /// longer exist are skipped; the last track is loaded paused at its position.
+    fn restore_session(&mut self, saved: session::Session) {
+        let mut current_index = None;
+        for (i, path) in saved.queue.into_iter().enumerate() {
+            if !path.exists() {
+                continue;
             }
-            if let Some(&prev) = indices.choose(&mut rand::thread_rng()) {
-                self.current_index = Some(prev);
+            let mut item = MediaItem::from_path(path);
+            if let Some(track) = saved.cue_tracks.get(i).copied().flatten() {
+                // Skip the track if the cue sheet has since changed or gone
+                let Some(cue_item) = item.split_by_cue().into_iter().find(|cue_item| {
+                    cue_item.cue.is_some_and(|cue| cue.track == track)
+                }) else {
+                    continue;
+                };
+                item = cue_item;
             }
-        } else {
-            self.current_index = Some(match self.current_index {
-                Some(i) if i > 0 => i - 1,
-                _ => self.queue.len() - 1,
-            });
+            if saved.current_index == Some(i) {
+                current_index = Some(self.player.queue.len());
+            }
+            item.album_group = saved.album_groups.get(i).copied().flatten();
+            self.apply_cached_tags(&mut item);
+            self.player.queue.push(item);
         }
-        self.play_current();
-    }
+        let mut paths: Vec<PathBuf> = self.player.queue.iter().map(|item| item.file_path.clone()).collect();
+        paths.dedup();
+        self.request_tags(paths);
 
-    fn pause(&mut self) {
-        if let Some(ref sink) = self.sink {
-            sink.pause();
-            self.is_paused = true;
+        self.volume = saved.volume.clamp(0.0, 1.0);
+        self.player.shuffle = saved.shuffle;
+        self.player.repeat_mode = saved.repeat_mode;
+        if !saved.eq_bands.is_empty() {
+            self.equalizer.preset = saved.eq_preset;
+            self.equalizer.bands = saved.eq_bands;
+            self.equalizer.preamp_db = saved.eq_preamp_db;
+            self.equalizer.bass_db = saved.eq_bass_db;
+            self.equalizer.treble_db = saved.eq_treble_db;
+            self.update_equalizer_settings();
         }
-    }
 
-    fn resume(&mut self) {
-        if let Some(ref sink) = self.sink {
-            sink.play();
-            self.is_paused = false;
+        if current_index.is_some() {
+            self.player.current_index = current_index;
+            self.apply_track_eq();
+            self.total_duration = None;
+            self.player.is_paused = true;
+            self.start_current_at(saved.position);
         }
     }
 
-    fn set_volume(&mut self, vol: f32) {
-        self.volume = vol;
-        if let Some(ref sink) = self.sink {
-            sink.set_volume(vol);
+    /// Turn the global hotkey listener on or off, starting its thread on first use.
+    fn set_global_keys(&mut self, enabled: bool) {
+        self.global_keys_enabled.store(enabled, Ordering::Relaxed);
+        if enabled && !self.key_listener_started {
+            spawn_key_listener(self.key_sender.clone(), self.global_keys_enabled.clone());
+            self.key_listener_started = true;
         }
     }
 
-    fn add_file(&mut self, item: MediaItem) {
-        self.queue.push(item);
-        if self.current_index.is_none() {
-            self.current_index = Some(0);
-            self.play_current();
+    /// Switch playback to the output device called `name` (`None` for the system
+    /// default), falling back to the default if it can't be opened. The current
+    /// track resumes at its position on the new device.
+    fn set_output_device(&mut self, name: Option<String>) {
+        let output = match open_output_stream(name.as_deref()) {
+            Ok(stream) => {
+                self.device_status.clear();
+                self.output_device = name;
+                stream
+            }
+            Err(e) => match OutputStream::try_default() {
+                Ok(stream) => {
+                    self.device_status = format!("{}; using the default device", e);
+                    self.output_device = None;
+                    stream
+                }
+                Err(default_err) => {
+                    self.device_status = format!("No output device available: {}", default_err);
+                    self.output_device = None;
+                    self.player.set_output(None);
+                    return;
+                }
+            },
+        };
+        self.config.output_device = self.output_device.clone();
+        self.save_config();
+
+        let was_playing = self.player.sink.is_some();
+        let paused = self.player.is_paused;
+        self.finish_crossfade();
+        self.player.set_output(Some(output));
+        self.player.is_paused = paused;
+        if was_playing {
+            self.start_current_at(self.current_position);
         }
     }
 
-    fn add_folder(&mut self, folder: PathBuf) {
-        if let Ok(entries) = fs::read_dir(folder) {
-            for entry in entries.flatten() {
-                let path = entry.path();
-                if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
-                    if ["mp3", "wav", "flac", "ogg"].contains(&ext.to_lowercase().as_str()) {
-                        let display_name = path
-                            .file_stem()
-                            .and_then(|s| s.to_str())
-                            .unwrap_or("Unknown")
-                            .to_string();
-                        self.queue.push(MediaItem {
-                            file_path: path,
-                            display_name,
-                            artist: None,
-                        });
-                    }
+    /// Fall back to the default device if the chosen one has been unplugged, and
+    /// pick up a device that appears while the app is running without one.
+    fn check_output_device(&mut self) {
+        if let Some(scan) = &self.device_scan {
+            match scan.try_recv() {
+                Ok(devices) => {
+                    self.device_scan = None;
+                    self.apply_output_devices(devices);
                 }
+                Err(TryRecvError::Empty) => return,
+                Err(TryRecvError::Disconnected) => self.device_scan = None,
             }
         }
-        if self.current_index.is_none() && !self.queue.is_empty() {
-            self.current_index = Some(0);
-            self.play_current();
+        if self.player.stream_handle.is_some() && self.output_device.is_none() {
+            return;
+        }
+        let due = self.device_checked_at
+            .is_none_or(|t| t.elapsed() >= DEVICE_POLL_INTERVAL);
+        if due {
+            self.scan_output_devices();
         }
     }
 
-    fn add_youtube_audio(&mut self, url: String) {
-        if url.is_empty() {
-            self.download_status = "Please enter a valid YouTube URL".to_string();
+    /// List output devices on a worker thread, since enumerating them can
+    /// stall a frame on some hosts. The result is picked up by `check_output_device`.
+    fn scan_output_devices(&mut self) {
+        if self.device_scan.is_some() {
             return;
         }
-        self.download_status = "Downloading...".to_string();
-        let output_template = format!("{}/%(title)s.%(ext)s", self.collections_path.display());
-        let url_clone = url.clone();
-        let tx = self.youtube_sender.clone();
+        self.device_checked_at = Some(Instant::now());
+        let (tx, rx) = channel();
         thread::spawn(move || {
-            let cmd_output = Command::new("yt-dlp")
-                .args(&[
-                    "--print", "after_move:filepath",
-                    "--extract-audio",
-                    "--audio-format", "mp3",
-                    "-o", &output_template,
-                    &url_clone,
-                ])
-                .output();
-            if let Ok(cmd_output) = cmd_output {
-                if cmd_output.status.success() {
-                    let final_path = String::from_utf8_lossy(&cmd_output.stdout)
-                        .trim()
-                        .to_string();
-                    let final_path_buf = PathBuf::from(&final_path);
-                    if final_path_buf.exists() {
-                        let raw_title = final_path_buf
-                            .file_stem()
-                            .and_then(|s| s.to_str())
-                            .unwrap_or("Unknown Title")
-                            .to_string();
-                        let display_name = clean_title(&raw_title);
-                        let item = MediaItem {
-                            file_path: final_path_buf,
-                            display_name,
-                            artist: None,
-                        };
-                        if let Some(tx) = tx {
-                            let _ = tx.send((item, url_clone));
-                        }
-                    }
-                }
-            }
+            let _ = tx.send(list_output_devices());
         });
+        self.device_scan = Some(rx);
     }
 
-    fn process_youtube_result(&mut self) {
-        if let Some(ref rx) = self.youtube_receiver {
-            let mut new_items = Vec::new();
-            while let Ok((item, url)) = rx.try_recv() {
-                self.download_status = format!("Added YouTube audio: {}", url);
-                new_items.push(item);
-            }
-            for item in new_items {
-                self.add_file(item);
+    fn apply_output_devices(&mut self, devices: Vec<String>) {
+        self.output_devices = devices;
+        let Some(name) = self.output_device.clone() else {
+            // No device yet; try the default again now that one may exist
+            if self.player.stream_handle.is_none() && !self.output_devices.is_empty() && let Ok(output) = OutputStream::try_default() {
+                self.player.set_output(Some(output));
+                self.device_status.clear();
             }
+            return;
+        };
+        if !self.output_devices.contains(&name) {
+            self.set_output_device(None);
+            self.device_status = format!("\"{}\" was disconnected; switched to the default device", name);
         }
     }
 
-    fn process_key_commands(&mut self) {
-        while let Ok(cmd) = self.key_receiver.try_recv() {
-            match cmd {
-                KeyCommand::IncreaseVolume => {
-                    self.volume = (self.volume + 0.05).min(1.0);
-                    self.set_volume(self.volume);
-                    println!("Volume increased to {:.2}", self.volume);
+    /// Rebuild the theme from `theme_settings` and restyle the UI. The
+    /// change is saved by `save_theme_settings`.
+    fn apply_theme_settings(&mut self, ctx: &egui::Context) {
+        self.theme = self.theme_settings.to_theme();
+        self.theme.apply_to_ctx(ctx);
+        self.theme_unsaved = true;
+    }
+
+    fn save_theme_settings(&mut self) {
+        self.theme_unsaved = false;
+        if let Err(e) = self.theme_settings.save(&config::config_dir().join(theme::THEME_FILE)) {
+            eprintln!("Failed to save theme: {}", e);
+        }
+    }
+
+    fn draw_visualizer_tab(&mut self, ui: &mut egui::Ui) {
+        ui.add_space(10.0);
+        ui.heading(RichText::new("Visualizer").size(30.0));
+        ui.separator();
+        ui.horizontal(|ui| {
+            let previous_mode = self.visualizer_mode;
+            for mode in VisualizerMode::ALL {
+                ui.selectable_value(&mut self.visualizer_mode, mode, mode.label());
+            }
+            if self.visualizer_mode != previous_mode {
+                self.config.visualizer_mode = self.visualizer_mode;
+                self.save_config();
+            }
+        });
+        ui.horizontal(|ui| {
+            let (mut fft_size, mut band_count) = {
+                let visualizer = self.visualizer.lock().unwrap();
+                (visualizer.fft_size, visualizer.band_count)
+            };
+            let (previous_fft, previous_bands) = (fft_size, band_count);
+            egui::ComboBox::from_label("Bands")
+                .selected_text(band_count.to_string())
+                .show_ui(ui, |ui| {
+                    for count in visualizer::BAND_COUNTS {
+                        ui.selectable_value(&mut band_count, count, count.to_string());
+                    }
+                });
+            egui::ComboBox::from_label("FFT size")
+                .selected_text(fft_size.to_string())
+                .show_ui(ui, |ui| {
+                    for size in visualizer::FFT_SIZES {
+                        ui.selectable_value(&mut fft_size, size, size.to_string());
+                    }
+                })
+                .response
+                .on_hover_text("Larger sizes resolve low frequencies better but react more slowly and use more CPU");
+            if (fft_size, band_count) != (previous_fft, previous_bands)
+                && self.visualizer.lock().unwrap().set_resolution(fft_size, band_count).is_ok() {
+                self.config.visualizer_fft_size = fft_size;
+                self.config.visualizer_bands = band_count;
+                self.save_config();
+            }
+            if self.visualizer_mode == VisualizerMode::Spectrum {
+                let mut stereo = self.visualizer.lock().unwrap().stereo;
+                if ui.checkbox(&mut stereo, "Stereo")
+                    .on_hover_text("Left channel above the center line, right channel below")
+                    .changed() {
+                    self.visualizer.lock().unwrap().stereo = stereo;
+                    self.config.visualizer_stereo = stereo;
+                    self.save_config();
                 }
-                KeyCommand::DecreaseVolume => {
-                    self.volume = (self.volume - 0.05).max(0.0);
-                    self.set_volume(self.volume);
-                    println!("Volume decreased to {:.2}", self.volume);
+            }
+            if self.visualizer_mode == VisualizerMode::Spectrogram {
+                let (mut history, mut colors) = {
+                    let visualizer = self.visualizer.lock().unwrap();
+                    (visualizer.spectrogram_history, visualizer.spectrogram_colors)
+                };
+                let (previous_history, previous_colors) = (history, colors);
+                egui::ComboBox::from_label("History")
+                    .selected_text(history.to_string())
+                    .show_ui(ui, |ui| {
+                        for frames in visualizer::SPECTROGRAM_HISTORIES {
+                            ui.selectable_value(&mut history, frames, frames.to_string());
+                        }
+                    })
+                    .response
+                    .on_hover_text("Number of analysis frames shown across the view");
+                egui::ComboBox::from_label("Colors")
+                    .selected_text(colors.label())
+                    .show_ui(ui, |ui| {
+                        for scheme in SpectrogramColors::ALL {
+                            ui.selectable_value(&mut colors, scheme, scheme.label());
+                        }
+                    });
+                if history != previous_history {
+                    self.visualizer.lock().unwrap().set_spectrogram_history(history);
+                    self.config.visualizer_history = history;
+                    self.save_config();
                 }
-                KeyCommand::TogglePause => {
-                    if self.is_paused {
-                        self.resume();
-                        println!("Playback resumed");
-                    } else {
-                        self.pause();
-                        println!("Playback paused");
-                    }
+                if colors != previous_colors {
+                    self.visualizer.lock().unwrap().spectrogram_colors = colors;
+                    self.config.visualizer_colors = colors;
+                    self.save_config();
                 }
             }
+        });
+        ui.add_space(10.0);
+        let height = match self.visualizer_mode {
+            VisualizerMode::VuMeter => 120.0,
+            _ => ui.available_height().max(120.0),
+        };
+        let (rect, _) = ui.allocate_exact_size(egui::vec2(ui.available_width(), height), egui::Sense::hover());
+        let mut visualizer = self.visualizer.lock().unwrap();
+        visualizer.analyze();
+        match self.visualizer_mode {
+            VisualizerMode::Spectrum => visualizer.draw_spectrum(ui, rect, &self.theme),
+            VisualizerMode::Waveform => visualizer.draw_waveform(ui, rect, &self.theme),
+            VisualizerMode::VuMeter => visualizer.draw_vu_meter(ui, rect, &self.theme),
+            VisualizerMode::Oscilloscope => visualizer.draw_oscilloscope(ui, rect, &self.theme),
+            VisualizerMode::Lissajous => visualizer.draw_lissajous(ui, rect, &self.theme),
+            VisualizerMode::Spectrogram => visualizer.draw_spectrogram(ui, rect, &self.theme),
         }
     }
 
-    fn load_collections(&self) -> Vec<MediaItem> {
-        let mut items = Vec::new();
-        if let Ok(entries) = fs::read_dir(&self.collections_path) {
-            for entry in entries.flatten() {
-                let path = entry.path();
-                if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
-                    if ext.to_lowercase() == "mp3" {
-                        let raw_title = path
-                            .file_stem()
-                            .and_then(|s| s.to_str())
-                            .unwrap_or("Unknown")
-                            .to_string();
-                        let display_name = clean_title(&raw_title);
-                        items.push(MediaItem {
-                            file_path: path,
-                            display_name,
-                            artist: None,
-                        });
+    fn draw_settings_tab(&mut self, ui: &mut egui::Ui) {
+        ui.add_space(10.0);
+        ui.heading(RichText::new("Settings").size(30.0));
+        ui.separator();
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            self.draw_playback_settings(ui);
+            ui.add_space(10.0);
+            self.draw_audio_settings(ui);
+            ui.add_space(10.0);
+            self.draw_appearance_settings(ui);
+            ui.add_space(10.0);
+            self.draw_library_settings(ui);
+            ui.add_space(10.0);
+            self.draw_shortcut_settings(ui);
+        });
+    }
+
+    fn draw_playback_settings(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Playback");
+        if ui.add(egui::Slider::new(&mut self.crossfade_secs, 0.0..=12.0)
+            .step_by(0.5)
+            .suffix(" s")
+            .text("Crossfade"))
+            .on_hover_text("Overlap the end of each track with the start of the next; 0 turns it off")
+            .changed() {
+            self.config.crossfade_secs = self.crossfade_secs;
+            self.save_config();
+        }
+        if ui.add(egui::Slider::new(&mut self.fade_in_secs, 0.0..=2.0)
+            .step_by(0.05)
+            .suffix(" s")
+            .text("Fade in"))
+            .on_hover_text("Fade up when a track starts or playback resumes; 0 turns it off")
+            .changed() {
+            self.config.fade_in_secs = self.fade_in_secs;
+            self.save_config();
+        }
+        if ui.add(egui::Slider::new(&mut self.fade_out_secs, 0.0..=2.0)
+            .step_by(0.05)
+            .suffix(" s")
+            .text("Fade out"))
+            .on_hover_text("Fade down before pausing; 0 turns it off")
+            .changed() {
+            self.config.fade_out_secs = self.fade_out_secs;
+            self.save_config();
+        }
+
+        let mut normalize = self.normalize_volume;
+        if ui.checkbox(&mut normalize, "Normalize volume")
+            .on_hover_text("Play every track at a similar loudness, using ReplayGain tags or a one-time scan")
+            .changed() {
+            self.set_normalize_volume(normalize);
+        }
+
+        if ui.checkbox(&mut self.auto_continue, "Auto-continue from library")
+            .on_hover_text("When the queue runs out, keep going with tracks from the collection (favorites only if that filter is on)")
+            .changed() {
+            self.config.auto_continue = self.auto_continue;
+            self.save_config();
+        }
+
+        #[cfg(any(target_os = "windows", target_os = "linux"))]
+        if ui.checkbox(&mut self.notify_track_changes, "Desktop notifications")
+            .on_hover_text("Announce each new track while the window is in the background")
+            .changed() {
+            self.config.notifications = self.notify_track_changes;
+            self.save_config();
+        }
+    }
+
+    /// Output device, balance and crossfeed.
+    fn draw_audio_settings(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Audio");
+        ui.horizontal(|ui| {
+            let mut selected = self.output_device.clone();
+            egui::ComboBox::from_label("Output device")
+                .selected_text(selected.as_deref().unwrap_or("System default"))
+                .width(260.0)
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut selected, None, "System default");
+                    for device in &self.output_devices {
+                        ui.selectable_value(&mut selected, Some(device.clone()), device);
                     }
-                }
+                });
+            if ui.button("Refresh").on_hover_text("Look for newly connected devices").clicked() {
+                self.scan_output_devices();
+            }
+            if selected != self.output_device {
+                self.set_output_device(selected);
             }
+        });
+        if !self.device_status.is_empty() {
+            ui.label(RichText::new(&self.device_status).color(egui::Color32::YELLOW));
         }
-        items
+
+        ui.horizontal(|ui| {
+            let mut balance = self.equalizer.balance;
+            let response = ui.add(egui::Slider::new(&mut balance, -1.0..=1.0)
+                .text("Balance")
+                .custom_formatter(|value, _| match value {
+                    v if v < 0.0 => format!("L {:.0}%", -v * 100.0),
+                    v if v > 0.0 => format!("R {:.0}%", v * 100.0),
+                    _ => "Center".to_string(),
+                }))
+                .on_hover_text("Shift stereo playback toward one ear; no effect on mono tracks");
+            // Snap to the centre when dragged close to it
+            if balance.abs() < 0.05 {
+                balance = 0.0;
+            }
+            let reset = ui.add_enabled(balance != 0.0, egui::Button::new("Center")).clicked();
+            if reset {
+                balance = 0.0;
+            }
+            if balance != self.equalizer.balance && (response.changed() || reset) {
+                self.equalizer.balance = balance;
+                self.update_equalizer_settings();
+                self.config.balance = balance;
+                self.save_config();
+            }
+        });
+
+        ui.add_space(10.0);
+        self.draw_crossfeed_controls(ui);
     }
 
-    fn check_track_finished(&mut self) {
-        if let Some(ref sink) = self.sink {
-            if !self.is_paused && sink.empty() {
-                self.next_track();
+    fn draw_appearance_settings(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Appearance");
+        let previous = self.theme_settings.clone();
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut self.theme_settings.dark, true, "Dark");
+            ui.selectable_value(&mut self.theme_settings.dark, false, "Light");
+        });
+        ui.horizontal(|ui| {
+            ui.color_edit_button_srgb(&mut self.theme_settings.accent_color);
+            ui.label("Accent color");
+        });
+        ui.horizontal(|ui| {
+            let theme = self.theme_settings.to_theme();
+            let [r, g, b, _] = theme.background_color.to_array();
+            let mut background = self.theme_settings.background_color.unwrap_or([r, g, b]);
+            if ui.color_edit_button_srgb(&mut background).changed() {
+                self.theme_settings.background_color = Some(background);
             }
+            ui.label("Background color");
+            if ui.add_enabled(self.theme_settings.background_color.is_some(), egui::Button::new("Reset"))
+                .on_hover_text("Use the dark or light theme's own background")
+                .clicked() {
+                self.theme_settings.background_color = None;
+            }
+        });
+        if ui.button("Restore default theme").clicked() {
+            self.theme_settings = theme::ThemeSettings::default();
+        }
+        if self.theme_settings != previous {
+            self.apply_theme_settings(ui.ctx());
+        }
+        // Color pickers change on every frame of a drag, so save once it ends
+        if self.theme_unsaved && !ui.input(|i| i.pointer.any_down()) {
+            self.save_theme_settings();
+        }
+        if ui.checkbox(&mut self.show_waveform_overview, "Waveform on the progress bar")
+            .on_hover_text("Outline the whole track's loudness behind the playhead")
+            .changed() {
+            self.config.waveform_overview = self.show_waveform_overview;
+            self.save_config();
         }
     }
 
-    fn seek_to(&mut self, new_time: f32) {
-        if let Some(idx) = self.current_index {
-            if idx < self.queue.len() && self.total_duration > 0.0 {
-                if let Ok(metadata) = fs::metadata(&self.queue[idx].file_path) {
-                    let file_size = metadata.len() as f32;
-                    let offset = ((new_time / self.total_duration) * file_size) as u64;
-                    if let Ok(buffer) = fs::read(&self.queue[idx].file_path) {
-                        use std::io::{Cursor, Seek, SeekFrom};
-                        let mut cursor = Cursor::new(buffer);
-                        if cursor.seek(SeekFrom::Start(offset)).is_ok() {
-                            if let Ok(decoder) = Decoder::new(BufReader::new(cursor)) {
-                                if let Some(ref handle) = self.stream_handle {
-                                    let sample_rate = decoder.sample_rate() as f32;
-                                    let equalized_source = EqualizedSource {
-                                        inner: decoder.convert_samples(),
-                                        dsp: EqualizerDSP::new(&self.equalizer, sample_rate),
-                                        equalizer_settings: self.shared_equalizer.clone(),
-                                        sample_rate,
-                                        last_update: self.equalizer.bands.len(),
-                                    };
-                                    let sink = Sink::try_new(handle).unwrap();
-                                    sink.append(equalized_source);
-                                    sink.set_volume(self.volume);
-                                    self.sink = Some(sink);
-                                    self.current_position = new_time;
-                                }
-                            }
-                        }
+    /// Collections folder and Last.fm scrobbling.
+    fn draw_library_settings(&mut self, ui: &mut egui::Ui) {
+        self.draw_collections_folder_settings(ui);
+        ui.add_space(10.0);
+        self.draw_lastfm_settings(ui);
+    }
+
+    /// Global hotkey toggle and a reference of the in-window keys.
+    fn draw_shortcut_settings(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Shortcuts");
+        let mut global_keys = self.global_keys_enabled.load(Ordering::Relaxed);
+        if ui.checkbox(&mut global_keys, "Global hotkeys")
+            .on_hover_text("Work even when the window is unfocused")
+            .changed() {
+            self.key_listener_error = None;
+            self.set_global_keys(global_keys);
+            self.config.global_keys = Some(global_keys);
+            self.save_config();
+        }
+        if let Some(error) = &self.key_listener_error {
+            ui.colored_label(egui::Color32::LIGHT_RED, error);
+        }
+        egui::Grid::new("shortcut_reference").striped(true).show(ui, |ui| {
+            for (keys, action) in SHORTCUT_REFERENCE {
+                ui.monospace(keys);
+                ui.label(action);
+                ui.end_row();
+            }
+        });
+    }
+
+    /// Wrap decoded samples in the processing chain: time-stretch, equalizer,
+    /// crossfeed, then the visualizer tap so the spectrum reflects what is heard.
+    fn build_source<S>(&self, inner: S) -> VisualizerTap<CrossfeedSource<EqualizedSource<TimeStretchSource<S>>>>
+    where
+        S: Source<Item = f32>,
+    {
+        VisualizerTap::new(self.player.chain.build(inner), self.visualizer.clone())
+    }
+
+    /// Load and play the current track.
+    /// Wrap the decoded audio with EqualizedSource to process samples.
+    fn play_current(&mut self) {
+        self.player.is_paused = false;
+        self.load_current();
+    }
+
+    /// Load the current track from its start, keeping the pause state, so a
+    /// paused player stays paused on the new track.
+    fn load_current(&mut self) {
+        let paused = self.player.is_paused;
+        if self.web_stream.is_some() {
+            self.stop();
+        }
+        self.player.is_paused = paused;
+        self.finish_crossfade();
+        self.clear_ab_loop();
+        self.total_duration = None;
+        self.apply_track_eq();
+        if !paused && self.fade_in_next.is_none() && self.fade_in_secs > 0.0 {
+            self.fade_in_next = Some(Duration::from_secs_f32(self.fade_in_secs));
+        }
+        self.start_current_at(0.0);
+    }
+
+    /// Decode the current track from the start and skip `position` seconds of
+    /// samples, replacing the sink. The new sink honours `is_paused`.
+    fn start_current_at(&mut self, position: f32) {
+        let fade_in = self.fade_in_next.take();
+        // The new sink starts at the right volume, and a pending fade-out
+        // pause belongs to the old one
+        self.volume_ramp = None;
+        self.queued_next = None;
+        self.prebuffer_attempted = false;
+        self.player.mark_shuffle_played();
+        if let Some(idx) = self.player.current_index && idx < self.player.queue.len() {
+            if let Some(sink) = self.player.sink.take() {
+                sink.stop();
+            }
+            // Update shared settings before creating the source
+            *self.player.chain.equalizer.lock().unwrap() = self.equalizer.clone();
+            let item = &self.player.queue[idx];
+            let path = item.file_path.clone();
+            let gain = self.normalization_gain(item);
+            match self.player.decode(idx, position, gain) {
+                Err(e) => self.playback_failed(path, e),
+                Ok(track) => {
+                    self.failed_tracks.remove(&path);
+                    self.consecutive_failures = 0;
+                    if self.player.stream_handle.is_none() {
+                        return;
+                    }
+                    if self.total_duration.is_none() {
+                        self.total_duration = track.duration;
                     }
+                    if self.total_duration.is_none() {
+                        self.spawn_duration_probe(path);
+                    }
+                    let source = VisualizerTap::new(track.source, self.visualizer.clone());
+                    self.player.set_volume(self.effective_volume());
+                    let started = match fade_in {
+                        Some(fade) => self.player.play_source(source.fade_in(fade), track.position),
+                        None => self.player.play_source(source, track.position),
+                    };
+                    if let Err(e) = started {
+                        self.device_status = e;
+                        return;
+                    }
+                    self.apply_speed();
+                    self.current_position = position.max(0.0);
                 }
             }
         }
     }
 
-    /// Update the equalizer settings and apply them in real-time
-    fn update_equalizer_settings(&mut self) {
-        // Update the shared state so audio processing can access the changes
-        let mut shared = self.shared_equalizer.lock().unwrap();
-        *shared = self.equalizer.clone();
+    /// Note a track that couldn't be opened or decoded and, unless every
+    /// track has now failed in a row, skip to the next one on the next frame.
+    fn playback_failed(&mut self, path: PathBuf, error: String) {
+        eprintln!("Warning: cannot decode {}: {}", path.display(), error);
+        let name = path.file_name().map_or_else(|| path.display().to_string(), |name| name.to_string_lossy().into_owned());
+        self.failed_tracks.insert(path, error);
+        self.consecutive_failures += 1;
+        self.player.playback_position = None;
+        self.current_position = 0.0;
+        if self.consecutive_failures >= self.player.queue.len() {
+            self.consecutive_failures = 0;
+            self.show_toast("None of the queued tracks can be played".to_string());
+        } else {
+            self.skip_failed_track = true;
+            self.show_toast(format!("Couldn't play {}, skipping", name));
+        }
     }
 
-    /// Draw the Equalizer tab UI.
-    /// Now updates in real-time without restarting playback.
-    fn draw_equalizer_tab(&mut self, ui: &mut egui::Ui) {
-        ui.heading("Audio Equalizer");
+    fn show_toast(&mut self, message: String) {
+        self.toast = Some((message, Instant::now()));
+    }
 
-        let mut preset_changed = false;
-        
-        egui::ComboBox::from_label("Preset")
-            .selected_text(format!("{:?}", self.equalizer.preset))
-            .show_ui(ui, |ui| {
-                preset_changed |= ui.selectable_value(&mut self.equalizer.preset, EqualizerPreset::Flat, "Flat").clicked();
-                preset_changed |= ui.selectable_value(&mut self.equalizer.preset, EqualizerPreset::Classical, "Classical").clicked();
-                preset_changed |= ui.selectable_value(&mut self.equalizer.preset, EqualizerPreset::HipHop, "Hip Hop").clicked();
-                preset_changed |= ui.selectable_value(&mut self.equalizer.preset, EqualizerPreset::Pop, "Pop").clicked();
-                preset_changed |= ui.selectable_value(&mut self.equalizer.preset, EqualizerPreset::Rock, "Rock").clicked();
-                preset_changed |= ui.selectable_value(&mut self.equalizer.preset, EqualizerPreset::HeavyMetal, "Heavy Metal").clicked();
-                preset_changed |= ui.selectable_value(&mut self.equalizer.preset, EqualizerPreset::Folk, "Folk").clicked();
-                preset_changed |= ui.selectable_value(&mut self.equalizer.preset, EqualizerPreset::Custom, "Custom").clicked();
+    /// Show the latest toast above the bottom of the window until it expires.
+    fn draw_toast(&mut self, ctx: &egui::Context) {
+        if self.toast.as_ref().is_some_and(|(_, shown)| shown.elapsed() > TOAST_DURATION) {
+            self.toast = None;
+        }
+        let Some((message, _)) = &self.toast else {
+            return;
+        };
+        egui::Area::new(egui::Id::new("toast"))
+            .anchor(egui::Align2::CENTER_BOTTOM, egui::vec2(0.0, -40.0))
+            .order(egui::Order::Foreground)
+            .interactable(false)
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    ui.label(message);
+                });
+            });
+    }
+
+    /// Resolve and start streaming `url` in the background; playback begins
+    /// when `process_stream_events` receives the decoder.
+    fn start_stream(&mut self, url: String) {
+        let url = url.trim().to_string();
+        if url.is_empty() {
+            return;
+        }
+        self.download_status = "Connecting to stream...".to_string();
+        stream::open(url, self.stream_sender.clone());
+    }
+
+    fn process_stream_events(&mut self) {
+        while let Ok(event) = self.stream_receiver.try_recv() {
+            match event {
+                stream::StreamEvent::Ready { title, media_url, source, position, status } => {
+                    self.play_stream(ActiveStream { title, media_url, status, stalled: false }, source, position);
+                }
+                stream::StreamEvent::Failed(e) => self.download_status = format!("Stream failed: {}", e),
+            }
+        }
+    }
+
+    /// Replace playback with a network stream, `position` seconds in. The
+    /// queue is left as it is and carries on under the repeat mode once the
+    /// stream ends.
+    fn play_stream(&mut self, stream: ActiveStream, source: stream::StreamSource, position: f32) {
+        // Stopping would pause a stream being reopened to seek
+        let paused = self.player.is_paused && position > 0.0;
+        self.stop();
+        if self.player.stream_handle.is_none() {
+            self.download_status = "No audio output device".to_string();
+            return;
+        }
+        *self.player.chain.equalizer.lock().unwrap() = self.equalizer.clone();
+        let total_duration = source.total_duration().map(|d| d.as_secs_f32());
+        let (samples, playback_position) = position::counted(source, position);
+        self.player.is_paused = paused;
+        self.player.set_volume(self.effective_volume());
+        if let Err(e) = self.player.play_source(self.build_source(samples), playback_position) {
+            self.device_status = e;
+            return;
+        }
+        self.player.current_index = None;
+        self.clear_ab_loop();
+        self.total_duration = total_duration;
+        self.current_position = position;
+        self.apply_speed();
+        self.download_status = format!("Streaming {}", stream.title);
+        self.web_stream = Some(stream);
+    }
+
+    /// Pause a stream whose download has fallen behind, and resume it once
+    /// enough has been buffered, instead of letting the audio run dry.
+    fn check_stream_buffer(&mut self) {
+        let (Some(stream), Some(sink)) = (&mut self.web_stream, &self.player.sink) else {
+            return;
+        };
+        let finished = stream.status.is_finished();
+        let ahead = stream.status.buffered_ahead();
+        if !stream.stalled && !finished && ahead == 0 && !self.player.is_paused {
+            sink.pause();
+            stream.stalled = true;
+            self.download_status = format!("Buffering {}...", stream.title);
+        } else if stream.stalled && (finished || ahead >= stream::RESUME_BYTES) {
+            if !self.player.is_paused {
+                sink.play();
+            }
+            stream.stalled = false;
+            self.download_status = format!("Streaming {}", stream.title);
+        }
+        if let Some(e) = stream.status.error() {
+            self.download_status = format!("Stream interrupted: {}", e);
+        }
+    }
+
+    /// Measure a track's length on a worker thread, for formats like VBR MP3
+    /// that don't report it up front. The result arrives via `duration_receiver`.
+    fn spawn_duration_probe(&self, path: PathBuf) {
+        let tx = self.duration_sender.clone();
+        thread::spawn(move || {
+            if let Some(secs) = probe_duration(&path) {
+                let _ = tx.send((path, secs));
+            }
+        });
+    }
+
+    /// Near the end of the current track, decode the one that follows and
+    /// append it to the same sink so rodio plays them back to back.
+    fn prebuffer_next(&mut self) {
+        if self.prebuffer_attempted || self.player.is_paused || self.sleep.at_track_end {
+            return;
+        }
+        let (Some(total), Some(_)) = (self.total_duration, &self.player.sink) else {
+            return;
+        };
+        if total - self.current_position > GAPLESS_PREBUFFER_SECS * self.speed {
+            return;
+        }
+        self.prebuffer_attempted = true;
+        let Some(index) = self.player.auto_advance_index() else {
+            return;
+        };
+        // Crossfade takes care of the change, except within an album
+        if self.crossfade_secs > 0.0 && !self.player.same_album_group(index) {
+            return;
+        }
+        let item = &self.player.queue[index];
+        let (path, cue) = (item.file_path.clone(), item.cue);
+        let track = match self.player.decode(index, 0.0, self.normalization_gain(item)) {
+            Ok(track) => track,
+            Err(e) => {
+                eprintln!("Warning: cannot decode {}: {}", path.display(), e);
+                return;
+            }
+        };
+        let source = VisualizerTap::new(track.source, self.visualizer.clone());
+        if self.player.append_source(source) {
+            self.queued_next = Some(QueuedTrack { index, path, cue, duration: track.duration, position: track.position });
+        }
+    }
+
+    /// Once the sink has moved on to the prebuffered track, make it current.
+    fn advance_to_queued(&mut self) {
+        let rolled_over = self.player.sink.as_ref().is_some_and(|sink| sink.len() <= 1);
+        if !rolled_over {
+            return;
+        }
+        let Some(queued) = self.queued_next.take() else {
+            return;
+        };
+        // The previous track played through to the appended one
+        self.record_play();
+        // The queue may have been edited since the track was appended
+        let is_queued = |item: &MediaItem| item.file_path == queued.path && item.cue == queued.cue;
+        let index = if self.player.queue.get(queued.index).is_some_and(is_queued) {
+            Some(queued.index)
+        } else {
+            self.player.queue.iter().position(is_queued)
+        };
+        if let Some(index) = index {
+            self.player.play_index(index);
+            self.player.mark_shuffle_played();
+        }
+        self.current_position = 0.0;
+        self.player.playback_position = Some(queued.position);
+        self.total_duration = queued.duration;
+        self.clear_ab_loop();
+        self.apply_track_eq();
+        if self.total_duration.is_none() {
+            self.spawn_duration_probe(queued.path);
+        }
+        self.prebuffer_attempted = false;
+        // Pick up the new track's gain trim
+        self.set_volume(self.volume);
+    }
+
+    fn clear_ab_loop(&mut self) {
+        self.loop_a = None;
+        self.loop_b = None;
+    }
+
+    /// Jump back to A when the playhead reaches B.
+    fn check_ab_loop(&mut self) {
+        if let (Some(a), Some(b)) = (self.loop_a, self.loop_b) && self.player.sink.is_some() && self.current_position >= b {
+            self.seek_to(a);
+        }
+    }
+
+    fn draw_ab_loop_controls(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Loop:");
+            if ui.button("Set A").on_hover_text("Mark the loop start at the current position").clicked() {
+                self.loop_a = Some(self.current_position);
+                if self.loop_b.is_some_and(|b| b <= self.current_position) {
+                    self.loop_b = None;
+                }
+            }
+            let can_set_b = self.loop_a.is_some_and(|a| self.current_position > a);
+            if ui.add_enabled(can_set_b, egui::Button::new("Set B"))
+                .on_hover_text("Mark the loop end at the current position")
+                .on_disabled_hover_text("Set A first, then play past it")
+                .clicked() {
+                self.loop_b = Some(self.current_position);
+            }
+            if ui.add_enabled(self.loop_a.is_some() || self.loop_b.is_some(), egui::Button::new("Clear"))
+                .clicked() {
+                self.clear_ab_loop();
+            }
+            let format_point = |point: Option<f32>| point.map_or("--".to_string(), |p| format!("{:.1}s", p));
+            ui.label(format!("A {}  B {}", format_point(self.loop_a), format_point(self.loop_b)));
+        });
+    }
+
+    fn extend_sleep_timer(&mut self, minutes: u64) {
+        if self.sleep.extend(minutes) {
+            self.set_volume(self.volume);
+        }
+    }
+
+    /// Cancel the sleep timer, undoing a partial fade so playback continues
+    /// at the normal volume.
+    fn cancel_sleep_timer(&mut self) {
+        if self.sleep.cancel() {
+            self.set_volume(self.volume);
+        }
+    }
+
+    /// Once the deadline passes, fade the sink out over `SLEEP_FADE` and pause.
+    fn check_sleep_timer(&mut self) {
+        if !self.sleep.due() {
+            return;
+        }
+        let volume = self.effective_volume();
+        let Some(sink) = self.player.sink.as_ref().filter(|_| !self.player.is_paused) else {
+            self.cancel_sleep_timer();
+            return;
+        };
+        if self.sleep.fade(sink, volume) {
+            self.finish_crossfade();
+            self.player.is_paused = true;
+            self.volume_ramp = None;
+        }
+    }
+
+    fn draw_sleep_timer(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Sleep:");
+            if let Some(deadline) = self.sleep.deadline {
+                let remaining = deadline.saturating_duration_since(Instant::now()).as_secs();
+                ui.label(format!("pausing in {}:{:02}", remaining / 60, remaining % 60));
+                if ui.button("+15 min").clicked() {
+                    self.extend_sleep_timer(15);
+                }
+                if ui.button("Cancel").clicked() {
+                    self.cancel_sleep_timer();
+                }
+            } else if self.sleep.at_track_end {
+                ui.label("stopping after this track");
+                if ui.button("Cancel").clicked() {
+                    self.cancel_sleep_timer();
+                }
+            } else {
+                ui.menu_button("Set timer", |ui| {
+                    for minutes in SLEEP_PRESETS_MIN {
+                        if ui.button(format!("{} min", minutes)).clicked() {
+                            self.extend_sleep_timer(minutes);
+                            ui.close_menu();
+                        }
+                    }
+                    if ui.button("End of current track").clicked() {
+                        self.cancel_sleep_timer();
+                        self.sleep.at_track_end = true;
+                        ui.close_menu();
+                    }
+                });
+            }
+        });
+    }
+
+    fn next_track(&mut self) {
+        if self.player.next().is_some() {
+            self.play_current();
+        }
+    }
+
+    /// Go back to the track that played before this one.
+    fn prev_track(&mut self) {
+        if self.player.prev().is_some() {
+            self.play_current();
+        }
+    }
+
+    /// Pause, fading out first when a fade-out is set. `is_paused` is set
+    /// straight away so the fading track can't be taken as finished and
+    /// advanced past.
+    fn pause(&mut self) {
+        self.finish_crossfade();
+        let Some(ref sink) = self.player.sink else {
+            return;
+        };
+        if self.fade_out_secs > 0.0 && !sink.is_paused() {
+            self.player.is_paused = true;
+            self.ramp_volume(0.0, Duration::from_secs_f32(self.fade_out_secs), true);
+        } else {
+            self.player.pause();
+            self.volume_ramp = None;
+        }
+    }
+
+    /// Halt playback and drop the sink. The current track stays selected
+    /// and restarts from the top when played again.
+    fn stop(&mut self) {
+        self.finish_crossfade();
+        self.player.stop();
+        self.web_stream = None;
+        self.queued_next = None;
+        self.prebuffer_attempted = false;
+        self.volume_ramp = None;
+        self.current_position = 0.0;
+    }
+
+    /// Remove queue entry `index`, keeping `current_index` on the same track.
+    /// Removing the current track moves on to the one after it, paused if
+    /// playback was paused.
+    fn remove_from_queue(&mut self, index: usize) {
+        if self.player.remove(&HashSet::from([index])) {
+            self.current_track_removed();
+        }
+    }
+
+    /// Play on from the track that took the removed current track's place,
+    /// or stop when it was the last one.
+    fn current_track_removed(&mut self) {
+        if self.player.current_index.is_none() {
+            self.stop();
+            self.total_duration = None;
+        } else if self.player.sink.is_some() {
+            self.load_current();
+        }
+    }
+
+    /// Track length from the tag cache, if it has been read.
+    fn cached_duration(&self, path: &Path) -> Option<f32> {
+        self.tag_cache.get(path)?.as_ref()?.duration
+    }
+
+    /// Compare two items for `order`. Unknown artists and durations sort last;
+    /// date added is newest first.
+    fn compare_items(&self, a: &MediaItem, b: &MediaItem, order: SortOrder) -> std::cmp::Ordering {
+        use std::cmp::Ordering;
+        let by_title = || natural_cmp(&a.display_name, &b.display_name);
+        let known_first = |x: Option<Ordering>, a_known: bool, b_known: bool| match (a_known, b_known) {
+            (true, true) => x.unwrap_or(Ordering::Equal),
+            (true, false) => Ordering::Less,
+            (false, true) => Ordering::Greater,
+            (false, false) => Ordering::Equal,
+        };
+        match order {
+            SortOrder::Title => by_title(),
+            SortOrder::Artist => {
+                let artists = a.artist.as_deref().zip(b.artist.as_deref()).map(|(x, y)| natural_cmp(x, y));
+                known_first(artists, a.artist.is_some(), b.artist.is_some()).then_with(by_title)
+            }
+            SortOrder::Duration => {
+                let (x, y) = (self.cached_duration(&a.file_path), self.cached_duration(&b.file_path));
+                known_first(x.zip(y).map(|(x, y)| x.total_cmp(&y)), x.is_some(), y.is_some()).then_with(by_title)
+            }
+            SortOrder::DateAdded => {
+                let added = |item: &MediaItem| self.collections_added.get(&item.file_path).copied();
+                let (x, y) = (added(a), added(b));
+                known_first(x.zip(y).map(|(x, y)| y.cmp(&x)), x.is_some(), y.is_some()).then_with(by_title)
+            }
+        }
+    }
+
+    /// Order tracks within an album: by cue or tag track number, then title.
+    fn album_track_order(&self, a: &MediaItem, b: &MediaItem) -> std::cmp::Ordering {
+        let number = |item: &MediaItem| item.cue.map(|cue| cue.track).or_else(|| {
+            self.tag_cache.get(&item.file_path)?.as_ref()?.track_number
+        });
+        // Untagged tracks go after numbered ones
+        let (x, y) = (number(a).unwrap_or(u32::MAX), number(b).unwrap_or(u32::MAX));
+        x.cmp(&y).then_with(|| natural_cmp(&a.display_name, &b.display_name))
+    }
+
+    /// Sort `collections_order` again if something invalidated it.
+    fn sort_collections_if_needed(&mut self) {
+        if self.collections_order.is_some() {
+            return;
+        }
+        let mut order: Vec<usize> = (0..self.collections.len()).collect();
+        order.sort_by(|&a, &b| self.compare_items(&self.collections[a], &self.collections[b], self.collections_sort));
+        self.collections_order = Some(order);
+    }
+
+    /// Reorder the queue, keeping the playing track current.
+    fn sort_queue(&mut self, order: SortOrder) {
+        if order == SortOrder::DateAdded {
+            // Queued files from outside the collections folder weren't in the last scan
+            for item in &self.player.queue {
+                if !self.collections_added.contains_key(&item.file_path) && let Ok(modified) = fs::metadata(&item.file_path).and_then(|m| m.modified()) {
+                    self.collections_added.insert(item.file_path.clone(), modified);
+                }
+            }
+        }
+        let mut entries: Vec<(usize, MediaItem)> = self.player.queue.drain(..).enumerate().collect();
+        entries.sort_by(|(_, a), (_, b)| self.compare_items(a, b, order));
+        self.player.current_index = self.player.current_index
+            .and_then(|current| entries.iter().position(|(i, _)| *i == current));
+        self.player.queue = entries.into_iter().map(|(_, item)| item).collect();
+        self.queue_selection.clear();
+    }
+
+    fn clear_queue(&mut self) {
+        self.stop();
+        self.player.queue.clear();
+        self.queue_selection.clear();
+        self.player.current_index = None;
+        self.total_duration = None;
+    }
+
+    /// Write the queue to `playlists/<name>.m3u8`.
+    fn save_playlist(&mut self, name: &str) {
+        let entries: Vec<playlists::PlaylistEntry> = self.player.queue.iter().map(playlist_entry).collect();
+        let dir = playlists::playlists_dir(&self.collections_path);
+        match playlists::save(&dir, name, &entries) {
+            Ok(()) => {
+                self.playlist_status = format!("Saved playlist \"{}\" ({} tracks)", name, entries.len());
+                self.playlists = playlists::list(&dir);
+            }
+            Err(e) => self.playlist_status = format!("Failed to save playlist: {}", e),
+        }
+    }
+
+    /// Append `items` to the end of the saved playlist `name`.
+    fn add_to_playlist(&mut self, name: &str, items: &[MediaItem]) {
+        let dir = playlists::playlists_dir(&self.collections_path);
+        let result = playlists::load(&dir, name).and_then(|mut entries| {
+            entries.extend(items.iter().map(playlist_entry));
+            playlists::save(&dir, name, &entries)
+        });
+        match result {
+            Ok(()) => self.show_toast(format!("Added {} tracks to \"{}\"", items.len(), name)),
+            Err(e) => self.show_toast(format!("Failed to update playlist \"{}\": {}", name, e)),
+        }
+    }
+
+    /// A menu of the saved playlists; picking one appends `items` to it.
+    fn add_to_playlist_menu(&mut self, ui: &mut egui::Ui, items: &[MediaItem]) {
+        ui.menu_button("Add to Playlist", |ui| {
+            if self.playlists.is_empty() {
+                ui.label(RichText::new("No saved playlists").color(egui::Color32::GRAY).italics());
+            }
+            for name in self.playlists.clone() {
+                if ui.button(&name).clicked() {
+                    self.add_to_playlist(&name, items);
+                    ui.close_menu();
+                }
+            }
+        });
+    }
+
+    /// Remove every selected queue entry in one pass, reloading the playing
+    /// track at most once if it was among them.
+    fn remove_selected_from_queue(&mut self) {
+        let selected = std::mem::take(&mut self.queue_selection);
+        self.queue_selection_anchor = None;
+        if self.player.remove(&selected) {
+            self.current_track_removed();
+        }
+    }
+
+    /// The selected collection tracks, in collections order.
+    fn selected_collection_items(&self) -> Vec<MediaItem> {
+        self.collections.iter()
+            .filter(|item| self.collection_selection.contains(&item.file_path))
+            .cloned()
+            .collect()
+    }
+
+    /// Count the current track as played. Only called when a track plays
+    /// through to its end, so skipped tracks aren't counted.
+    fn record_play(&mut self) {
+        let Some(item) = self.player.current_index.and_then(|idx| self.player.queue.get(idx)) else {
+            return;
+        };
+        let timestamp = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs());
+        self.history.record(item.file_path.clone(), timestamp);
+        if let Err(e) = self.history.save(&self.collections_path) {
+            eprintln!("Failed to save play history: {}", e);
+        }
+    }
+
+    /// Recently Played and Most Played lists; clicking an entry plays it.
+    fn draw_history(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut self.history_most_played, false, RichText::new("Recently Played").heading());
+            ui.selectable_value(&mut self.history_most_played, true, RichText::new("Most Played").heading());
+        });
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs());
+        let entries: Vec<(PathBuf, String)> = if self.history_most_played {
+            self.history.most_played(HISTORY_LIST_LEN)
+                .into_iter()
+                .map(|(path, count)| (path.clone(), format!("{} play{}", count, if count == 1 { "" } else { "s" })))
+                .collect()
+        } else {
+            self.history.recent(HISTORY_LIST_LEN)
+                .into_iter()
+                .map(|play| (play.path.clone(), format_ago(now.saturating_sub(play.timestamp))))
+                .collect()
+        };
+        if entries.is_empty() {
+            ui.label(RichText::new("Tracks you play to the end show up here").italics().weak());
+            return;
+        }
+        let mut play = None;
+        egui::ScrollArea::vertical()
+            .id_salt("history")
+            .max_height(200.0)
+            .show(ui, |ui| {
+                for (path, detail) in entries {
+                    if !self.history_rows.contains_key(&path) {
+                        let mut item = MediaItem::from_path(path.clone());
+                        self.apply_cached_tags(&mut item);
+                        let exists = path.exists();
+                        self.history_rows.insert(path.clone(), (item, exists));
+                    }
+                    let (item, exists) = &self.history_rows[&path];
+                    let exists = *exists;
+                    ui.horizontal(|ui| {
+                        let label = match &item.artist {
+                            Some(artist) => format!("{} - {}", item.display_name, artist),
+                            None => item.display_name.clone(),
+                        };
+                        if ui.add_enabled(exists, egui::Button::new(label).frame(false))
+                            .on_disabled_hover_text("File no longer exists")
+                            .clicked() {
+                            play = Some(item.clone());
+                        }
+                        ui.weak(detail);
+                    });
+                }
+            });
+        if let Some(item) = play {
+            self.apply_collection_action(item, CollectionClickAction::PlayNow);
+        }
+    }
+
+    /// Replace the queue with a saved playlist and start playing it.
+    fn load_playlist(&mut self, name: &str) {
+        let dir = playlists::playlists_dir(&self.collections_path);
+        let entries = match playlists::load(&dir, name) {
+            Ok(entries) => entries,
+            Err(e) => {
+                self.playlist_status = format!("Failed to load playlist: {}", e);
+                return;
+            }
+        };
+        self.clear_queue();
+        for entry in entries {
+            let mut item = MediaItem::from_path(entry.path);
+            if let Some(title) = entry.title {
+                item.display_name = title;
+            }
+            item.artist = entry.artist;
+            self.apply_cached_tags(&mut item);
+            self.player.queue.push(item);
+        }
+        let paths = self.player.queue.iter().map(|item| item.file_path.clone()).collect();
+        self.request_tags(paths);
+        self.playlist_status = format!("Loaded playlist \"{}\" ({} tracks)", name, self.player.queue.len());
+        if !self.player.queue.is_empty() {
+            self.player.current_index = Some(0);
+            self.play_current();
+        }
+    }
+
+    /// Enqueue the audio files an M3U or PLS playlist from outside the
+    /// playlists folder lists, titled from the playlist where it has titles.
+    fn add_playlist_file(&mut self, path: &Path) {
+        let entries = match playlists::read(path) {
+            Ok(entries) => entries,
+            Err(e) => {
+                self.playlist_status = format!("Failed to open {}: {}", path.display(), e);
+                return;
+            }
+        };
+        let first_new = self.player.queue.len();
+        for entry in entries.into_iter().filter(|entry| is_audio_file(&entry.path)) {
+            let mut item = MediaItem::from_path(entry.path);
+            if let Some(title) = entry.title {
+                item.display_name = title;
+            }
+            item.artist = entry.artist;
+            let items = self.cue_tracks(item);
+            self.player.queue.extend(items);
+        }
+        let added = self.player.queue.len() - first_new;
+        let paths = self.player.queue[first_new..].iter().map(|item| item.file_path.clone()).collect();
+        self.request_tags(paths);
+        let name = path.file_name().map_or_else(|| path.display().to_string(), |name| name.to_string_lossy().into_owned());
+        self.playlist_status = format!("Added {} tracks from {}", added, name);
+        if added > 0 && self.player.current_index.is_none() {
+            self.player.current_index = Some(first_new);
+            self.play_current();
+        }
+    }
+
+    fn draw_playlists(&mut self, ui: &mut egui::Ui) {
+        ui.heading(RichText::new("Playlists").underline());
+        ui.horizontal(|ui| {
+            ui.label("Name:");
+            ui.text_edit_singleline(&mut self.playlist_name_input);
+            let name = self.playlist_name_input.trim().to_string();
+            if ui.add_enabled(!name.is_empty() && !self.player.queue.is_empty(), egui::Button::new("Save Playlist"))
+                .on_hover_text("Save the current queue; an existing playlist with this name is replaced")
+                .clicked() {
+                self.save_playlist(&name);
+            }
+        });
+        let mut to_load = None;
+        egui::ScrollArea::vertical()
+            .id_salt("playlists")
+            .max_height(100.0)
+            .show(ui, |ui| {
+                if self.playlists.is_empty() {
+                    ui.label(RichText::new("No saved playlists").color(egui::Color32::GRAY).italics());
+                }
+                for name in &self.playlists {
+                    ui.horizontal(|ui| {
+                        ui.label(name);
+                        if ui.small_button("Load").clicked() {
+                            to_load = Some(name.clone());
+                        }
+                    });
+                }
+            });
+        if let Some(name) = to_load {
+            self.playlist_name_input = name.clone();
+            self.load_playlist(&name);
+        }
+        if !self.playlist_status.is_empty() {
+            ui.label(&self.playlist_status);
+        }
+    }
+
+    /// Resume, fading back in when a fade-in is set. Resuming partway
+    /// through a fade-out turns it around from the current volume.
+    fn resume(&mut self) {
+        let Some(ref sink) = self.player.sink else {
+            return;
+        };
+        if sink.is_paused() && self.fade_in_secs > 0.0 {
+            sink.set_volume(0.0);
+        }
+        // A buffering stream restarts itself once data arrives
+        if self.web_stream.as_ref().is_some_and(|stream| stream.stalled) {
+            self.player.is_paused = false;
+        } else {
+            self.player.resume();
+        }
+        // Drop any pending fade-out so it can't pause us again
+        self.volume_ramp = None;
+        let target = self.effective_volume();
+        if self.fade_in_secs > 0.0 {
+            self.ramp_volume(target, Duration::from_secs_f32(self.fade_in_secs), false);
+        } else {
+            self.set_volume(self.volume);
+        }
+    }
+
+    /// Apply `speed` either by resampling in the sink (pitch follows) or by
+    /// time-stretching in the source chain (pitch preserved).
+    fn apply_speed(&mut self) {
+        let (sink_speed, stretch) = if self.preserve_pitch {
+            (1.0, self.speed)
+        } else {
+            (self.speed, 1.0)
+        };
+        *self.player.chain.stretch_ratio.lock().unwrap() = stretch;
+        if let Some(ref sink) = self.player.sink {
+            sink.set_speed(sink_speed);
+        }
+    }
+
+    /// Apply `vol` to the sink at once, for track changes and seeks where
+    /// there is no running audio to click.
+    fn set_volume(&mut self, vol: f32) {
+        self.volume = vol;
+        // A pending fade-out must still pause; resuming applies the volume
+        if self.volume_ramp.as_ref().is_some_and(|ramp| ramp.then_pause) {
+            return;
+        }
+        self.volume_ramp = None;
+        self.player.set_volume(self.effective_volume());
+    }
+
+    /// Volume change made by the user; this also ends mute.
+    fn change_volume(&mut self, vol: f32) {
+        self.is_muted = false;
+        self.volume = vol;
+        self.ramp_volume(self.effective_volume(), VOLUME_RAMP, false);
+    }
+
+    fn toggle_mute(&mut self) {
+        if self.is_muted {
+            self.is_muted = false;
+            self.volume = self.pre_mute_volume;
+        } else {
+            self.pre_mute_volume = self.volume;
+            self.is_muted = true;
+        }
+        self.ramp_volume(self.effective_volume(), VOLUME_RAMP, false);
+    }
+
+    /// Move the sink's volume to `target` over `duration`, starting from
+    /// wherever it is now, including partway through an earlier ramp. With
+    /// `then_pause` the sink is paused on arrival.
+    fn ramp_volume(&mut self, target: f32, duration: Duration, then_pause: bool) {
+        let Some(ref sink) = self.player.sink else {
+            return;
+        };
+        // A volume change during a fade-out lands after the resume instead
+        if self.volume_ramp.as_ref().is_some_and(|ramp| ramp.then_pause) && !then_pause {
+            return;
+        }
+        self.volume_ramp = Some(VolumeRamp {
+            from: sink.volume(),
+            to: target,
+            started: Instant::now(),
+            duration,
+            then_pause,
+        });
+    }
+
+    /// Step a running volume ramp; called every frame.
+    fn update_volume_ramp(&mut self) {
+        let Some(ramp) = &self.volume_ramp else {
+            return;
+        };
+        let t = (ramp.started.elapsed().as_secs_f32() / ramp.duration.as_secs_f32()).min(1.0);
+        if let Some(ref sink) = self.player.sink {
+            sink.set_volume(ramp.from + (ramp.to - ramp.from) * t);
+            if t >= 1.0 && ramp.then_pause {
+                sink.pause();
+            }
+        }
+        if t >= 1.0 {
+            self.volume_ramp = None;
+        }
+    }
+
+    /// Sink volume: the user volume adjusted by the current track's gain trim,
+    /// or silence while muted.
+    fn effective_volume(&self) -> f32 {
+        if self.is_muted {
+            return 0.0;
+        }
+        let trim_db = self.player.current_index
+            .and_then(|idx| self.player.queue.get(idx))
+            .map_or(0.0, |item| self.track_trim_db(&item.file_path));
+        self.volume * db_to_gain(trim_db)
+    }
+
+    /// Linear gain bringing `item` to the reference loudness, or 1.0 when
+    /// normalization is off.
+    fn normalization_gain(&self, item: &MediaItem) -> f32 {
+        if !self.normalize_volume {
+            return 1.0;
+        }
+        self.player.normalization_gain(item, &self.loudness_cache)
+    }
+
+    /// Queue loudness analysis for queued tracks that have no cached gain.
+    fn request_missing_loudness(&mut self) {
+        if !self.normalize_volume {
+            return;
+        }
+        let unchecked: HashSet<PathBuf> = self.player.queue
+            .iter()
+            .filter(|item| !self.loudness_checked.contains(&item.file_path))
+            .map(|item| item.file_path.clone())
+            .collect();
+        if unchecked.is_empty() {
+            return;
+        }
+        let paths: Vec<PathBuf> = unchecked
+            .iter()
+            .filter(|path| self.loudness_cache.get(path).is_none())
+            .cloned()
+            .collect();
+        self.loudness_checked.extend(unchecked);
+        if paths.is_empty() {
+            return;
+        }
+        let tx = self.loudness_sender.clone();
+        let task = self.tasks.start(format!("Analyzing loudness of {} tracks", paths.len()));
+        thread::spawn(move || {
+            let count = paths.len();
+            for (i, path) in paths.into_iter().enumerate() {
+                if task.is_cancelled() {
+                    break;
+                }
+                let gain = loudness::analyze(&path);
+                if tx.send((path, gain)).is_err() {
+                    break;
+                }
+                task.progress((i + 1) as f32 / count as f32);
+            }
+        });
+    }
+
+    /// Store finished loudness measurements and persist the cache.
+    fn process_loudness_results(&mut self) {
+        let mut changed = false;
+        while let Ok((path, gain)) = self.loudness_receiver.try_recv() {
+            if let Some(gain) = gain {
+                self.loudness_cache.insert(path, gain);
+                changed = true;
+            }
+        }
+        if changed && let Err(e) = self.loudness_cache.save(&self.collections_path) {
+            eprintln!("Failed to save loudness cache: {}", e);
+        }
+    }
+
+    /// Start computing the current track's overview if it isn't cached yet.
+    fn request_waveform_overview(&mut self) {
+        if !self.show_waveform_overview {
+            return;
+        }
+        let Some(item) = self.player.current_index.and_then(|idx| self.player.queue.get(idx)) else {
+            return;
+        };
+        let path = item.file_path.clone();
+        if self.waveform_overviews.contains_key(&path) || !self.waveform_pending.insert(path.clone()) {
+            return;
+        }
+        let tx = self.waveform_sender.clone();
+        thread::spawn(move || {
+            let overview = waveform::compute(&path);
+            let _ = tx.send((path, overview));
+        });
+    }
+
+    fn process_waveform_results(&mut self) {
+        while let Ok((path, overview)) = self.waveform_receiver.try_recv() {
+            self.waveform_pending.remove(&path);
+            // An empty overview marks an undecodable file so it isn't retried
+            self.waveform_overviews.insert(path, overview.unwrap_or_default());
+        }
+    }
+
+    /// Overview for the progress bar, or None to draw the plain bar.
+    fn current_overview(&self) -> Option<&[(f32, f32)]> {
+        if !self.show_waveform_overview {
+            return None;
+        }
+        let item = self.player.current_index.and_then(|idx| self.player.queue.get(idx))?;
+        // The overview covers the whole file, not one cue track of it
+        if item.cue.is_some() {
+            return None;
+        }
+        self.waveform_overviews.get(&item.file_path).map(Vec::as_slice)
+    }
+
+    fn set_normalize_volume(&mut self, enabled: bool) {
+        self.normalize_volume = enabled;
+        self.config.normalize_volume = enabled;
+        self.save_config();
+        // Restart in place so the change is heard right away
+        if self.player.sink.is_some() {
+            self.seek_to(self.current_position);
+        }
+    }
+
+    /// Manual gain trim in dB stored for a track, 0.0 if none.
+    fn track_trim_db(&self, path: &Path) -> f32 {
+        self.track_gains.get(path)
+    }
+
+    fn set_track_trim(&mut self, path: &Path, db: f32) {
+        self.track_gains.set(path, db);
+        self.set_volume(self.volume);
+    }
+
+    fn save_track_gains(&self) {
+        if let Err(e) = self.track_gains.save(&self.collections_path) {
+            eprintln!("Failed to save track gains: {}", e);
+        }
+    }
+
+    /// Slider for a track's ±12 dB gain trim. The trim is heard while
+    /// dragging and written to disk once the edit is finished.
+    fn track_trim_slider(&mut self, ui: &mut egui::Ui, path: &Path) {
+        let mut trim_db = self.track_trim_db(path);
+        ui.horizontal(|ui| {
+            ui.label("Gain trim:");
+            let response = ui.add(egui::Slider::new(&mut trim_db, -12.0..=12.0).step_by(0.5).suffix(" dB"));
+            if ui.small_button("0").on_hover_text("Reset trim").clicked() {
+                self.set_track_trim(path, 0.0);
+                self.save_track_gains();
+                return;
+            }
+            if response.changed() {
+                self.set_track_trim(path, trim_db);
+            }
+            if edit_finished(&response) {
+                self.save_track_gains();
+            }
+        });
+    }
+
+    /// Read tags for `paths` on a worker thread; results arrive via `process_tag_results`.
+    fn request_tags(&mut self, paths: Vec<PathBuf>) {
+        let paths: Vec<PathBuf> = paths
+            .into_iter()
+            .filter(|path| !self.tag_cache.contains_key(path) && !self.tags_pending.contains(path))
+            .collect();
+        if paths.is_empty() {
+            return;
+        }
+        self.tags_pending.extend(paths.iter().cloned());
+        let tx = self.tag_sender.clone();
+        thread::spawn(move || {
+            for path in paths {
+                let tags = metadata::read_tags(&path);
+                if tx.send((path, tags)).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    fn apply_cached_tags(&self, item: &mut MediaItem) {
+        if let Some(Some(tags)) = self.tag_cache.get(&item.file_path) {
+            item.apply_tags(tags);
+        }
+    }
+
+    /// `item` split into its cue tracks, each with any cached tags applied.
+    fn cue_tracks(&self, item: MediaItem) -> Vec<MediaItem> {
+        let sheet = cue::for_file(&item.file_path);
+        self.sheet_tracks(item, sheet)
+    }
+
+    /// `item` split into the tracks of `sheet`, each with any cached tags applied.
+    fn sheet_tracks(&self, item: MediaItem, sheet: Option<cue::CueSheet>) -> Vec<MediaItem> {
+        let mut items = item.split_by_sheet(sheet);
+        for item in &mut items {
+            self.apply_cached_tags(item);
+        }
+        items
+    }
+
+    /// Update queued items as tag reads complete.
+    fn process_tag_results(&mut self) {
+        let mut received = false;
+        while let Ok((path, tags)) = self.tag_receiver.try_recv() {
+            received = true;
+            self.tags_pending.remove(&path);
+            if let Some(tags) = &tags {
+                for item in self.player.queue.iter_mut().chain(self.collections.iter_mut())
+                    .filter(|item| item.file_path == path) {
+                    item.apply_tags(tags);
+                }
+            }
+            self.tag_cache.insert(path, tags);
+        }
+        if received {
+            self.player.split_mixed_album_groups();
+            self.collections_order = None;
+            self.collection_groups = None;
+            self.history_rows.clear();
+        }
+    }
+
+    /// Load the current track's cover and stream info on a worker thread when the track changes.
+    fn request_track_details(&mut self) {
+        let current = self.player.current_index
+            .and_then(|idx| self.player.queue.get(idx))
+            .map(|item| item.file_path.clone());
+        if current == self.track_details_path {
+            return;
+        }
+        self.album_art = None;
+        // Drop the previous cover's texture rather than holding it until the next one decodes
+        self.album_art_cache = widgets::AlbumArtCache::default();
+        self.track_info = None;
+        self.track_details_path = current.clone();
+        if let Some(path) = current {
+            let tx = self.track_details_sender.clone();
+            thread::spawn(move || {
+                let art = metadata::read_cover_art(&path);
+                let info = metadata::read_audio_info(&path);
+                let _ = tx.send((path, art, info));
+            });
+        }
+    }
+
+    fn process_track_details(&mut self) {
+        while let Ok((path, art, info)) = self.track_details_receiver.try_recv() {
+            // Ignore details for tracks that are no longer current
+            if self.track_details_path.as_ref() == Some(&path) {
+                self.album_art = art;
+                self.track_info = info;
+            }
+        }
+    }
+
+    /// Enqueue a file, or each of its tracks when a cue sheet splits it.
+    fn add_file(&mut self, item: MediaItem) {
+        self.add_files(vec![item]);
+    }
+
+    /// Enqueue `items` together, each split by its cue sheet, as one album
+    /// group when their tags agree.
+    fn add_files(&mut self, items: Vec<MediaItem>) {
+        self.request_tags(items.iter().map(|item| item.file_path.clone()).collect());
+        let first_new = self.player.queue.len();
+        for item in items {
+            let tracks = self.cue_tracks(item);
+            self.player.queue.extend(tracks);
+        }
+        self.player.group_album(first_new..self.player.queue.len());
+        if self.player.current_index.is_none() {
+            self.player.current_index = Some(0);
+            self.play_current();
+        }
+    }
+
+    /// With auto-continue on, append a batch of collection tracks that aren't
+    /// queued yet and return the first one's index. Shuffle picks at random;
+    /// otherwise the batch follows the last track auto-continue added, in the
+    /// collections panel's order.
+    fn continue_from_library(&mut self) -> Option<usize> {
+        use rand::seq::SliceRandom;
+        if !self.auto_continue {
+            return None;
+        }
+        self.sort_collections_if_needed();
+        let mut pool: Vec<&MediaItem> = self.collections_order.iter().flatten()
+            .map(|&i| &self.collections[i])
+            .filter(|item| !self.favorites_only || self.favorites.contains(&item.file_path))
+            .collect();
+        let last_added = self.player.queue.iter().rev().find(|item| item.from_library);
+        let start = last_added
+            .and_then(|last| pool.iter().position(|item| item.file_path == last.file_path))
+            .map_or(0, |i| i + 1)
+            .min(pool.len());
+        pool.rotate_left(start);
+        let queued: HashSet<&Path> = self.player.queue.iter().map(|item| item.file_path.as_path()).collect();
+        let mut fresh: Vec<MediaItem> = pool.iter()
+            .filter(|item| !queued.contains(item.file_path.as_path()))
+            .map(|item| (*item).clone())
+            .collect();
+        // Everything has been queued once already, so start the collection over
+        if fresh.is_empty() {
+            fresh = pool.into_iter().cloned().collect();
+        }
+        if self.player.shuffle {
+            fresh.shuffle(&mut rand::thread_rng());
+        }
+        fresh.truncate(AUTO_CONTINUE_BATCH);
+        if fresh.is_empty() {
+            return None;
+        }
+
+        let first_new = self.player.queue.len();
+        self.request_tags(fresh.iter().map(|item| item.file_path.clone()).collect());
+        for mut item in fresh {
+            item.from_library = true;
+            let tracks = self.cue_tracks(item);
+            let start = self.player.queue.len();
+            self.player.queue.extend(tracks);
+            self.player.group_album(start..self.player.queue.len());
+        }
+        Some(first_new)
+    }
+
+    fn add_folder(&mut self, folder: PathBuf) {
+        let first_new = self.player.queue.len();
+        let cues = cue::FolderCues::read(&folder);
+        for path in folder_audio_files(&folder) {
+            let display_name = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .unwrap_or("Unknown")
+                .to_string();
+            let item = MediaItem {
+                file_path: path,
+                display_name,
+                artist: None,
+                album: None,
+                cue: None,
+                album_group: None,
+                from_library: false,
+            };
+            let sheet = cues.sheet_for(&item.file_path);
+            let items = self.sheet_tracks(item, sheet);
+            self.player.queue.extend(items);
+        }
+        self.player.group_album(first_new..self.player.queue.len());
+        let mut new_paths: Vec<PathBuf> = self.player.queue[first_new..].iter().map(|item| item.file_path.clone()).collect();
+        new_paths.dedup();
+        self.request_tags(new_paths);
+        if self.player.current_index.is_none() && !self.player.queue.is_empty() {
+            self.player.current_index = Some(0);
+            self.play_current();
+        }
+    }
+
+    /// Copy local audio files into the collections folder on a background
+    /// task, leaving the queue and playback alone.
+    fn import_to_collection(&mut self, paths: Vec<PathBuf>) {
+        let collections_path = self.collections_path.clone();
+        let paths: Vec<PathBuf> = paths
+            .into_iter()
+            .filter(|path| is_audio_file(path) && path.parent() != Some(collections_path.as_path()))
+            .collect();
+        if paths.is_empty() {
+            return;
+        }
+        let task = self.tasks.start(format!("Importing {} tracks", paths.len()));
+        let sender = self.import_sender.clone();
+        self.collections_status = "Importing tracks…".to_string();
+        thread::spawn(move || {
+            let (mut imported, mut failed) = (0, 0);
+            let count = paths.len();
+            for (i, path) in paths.iter().enumerate() {
+                if task.is_cancelled() {
+                    break;
+                }
+                let Some(file_name) = path.file_name() else {
+                    continue;
+                };
+                let target = unique_file_path(&collections_path, file_name);
+                match fs::copy(path, &target) {
+                    Ok(_) => imported += 1,
+                    Err(e) => {
+                        eprintln!("Failed to import {}: {}", path.display(), e);
+                        failed += 1;
+                    }
+                }
+                task.progress((i + 1) as f32 / count as f32);
+            }
+            let _ = sender.send((imported, failed));
+        });
+    }
+
+    /// Report finished imports and show the new tracks.
+    fn process_imports(&mut self) {
+        let mut finished = false;
+        while let Ok((imported, failed)) = self.import_receiver.try_recv() {
+            self.collections_status = if failed == 0 {
+                format!("Imported {} tracks", imported)
+            } else {
+                format!("Imported {} tracks; {} could not be copied", imported, failed)
+            };
+            finished = true;
+        }
+        if finished {
+            self.refresh_collections();
+        }
+    }
+
+    /// Enqueue files and folders dropped onto the window. Paths are taken in
+    /// name order, each folder expanding in place, so a mixed drop plays predictably.
+    fn handle_dropped_files(&mut self, ctx: &egui::Context) {
+        let mut paths: Vec<PathBuf> = ctx.input(|i| {
+            i.raw.dropped_files.iter().filter_map(|file| file.path.clone()).collect()
+        });
+        if paths.is_empty() {
+            return;
+        }
+        paths.sort();
+        for path in paths {
+            if path.is_dir() {
+                self.add_folder(path);
+            } else if is_audio_file(&path) {
+                self.add_file(MediaItem::from_path(path));
+            } else if playlists::is_playlist_file(&path) {
+                self.add_playlist_file(&path);
+            }
+        }
+    }
+
+    /// Dim the window and show a hint while files are dragged over it.
+    fn draw_drop_overlay(&self, ctx: &egui::Context) {
+        if ctx.input(|i| i.raw.hovered_files.is_empty()) {
+            return;
+        }
+        let painter = ctx.layer_painter(egui::LayerId::new(
+            egui::Order::Foreground,
+            egui::Id::new("drop_overlay"),
+        ));
+        let screen = ctx.screen_rect();
+        painter.rect_filled(screen, 0.0, egui::Color32::from_black_alpha(180));
+        painter.text(
+            screen.center(),
+            egui::Align2::CENTER_CENTER,
+            "Drop audio files, playlists or folders to add them to the queue",
+            egui::FontId::proportional(24.0),
+            egui::Color32::WHITE,
+        );
+    }
+
+    fn add_youtube_audio(&mut self, url: String) {
+        if !self.yt_dlp_available {
+            self.download_status = YT_DLP_MISSING.to_string();
+            return;
+        }
+        if url.is_empty() {
+            self.download_status = "Please enter a valid YouTube URL".to_string();
+            return;
+        }
+        self.download_status.clear();
+        self.downloads.enqueue(url, &self.collections_path);
+    }
+
+    fn process_youtube_result(&mut self) {
+        if let Some(ref rx) = self.youtube_receiver {
+            let mut new_items = Vec::new();
+            while let Ok(event) = rx.try_recv() {
+                match event {
+                    DownloadEvent::Finished(item, _url) => new_items.push(item),
+                    DownloadEvent::Failed(error) => {
+                        if error == YT_DLP_MISSING {
+                            self.yt_dlp_available = false;
+                        }
+                        self.download_status = error;
+                    }
+                }
+            }
+            if !new_items.is_empty() {
+                self.refresh_collections();
+            }
+            for item in new_items {
+                self.add_file(item);
+            }
+        }
+    }
+
+    fn process_key_commands(&mut self, ctx: &egui::Context) {
+        while let Ok(cmd) = self.key_receiver.try_recv() {
+            match cmd {
+                KeyCommand::IncreaseVolume => {
+                    self.change_volume((self.volume + VOLUME_STEP).min(1.0));
+                    println!("Volume increased to {:.2}", self.volume);
+                }
+                KeyCommand::DecreaseVolume => {
+                    self.change_volume((self.volume - VOLUME_STEP).max(0.0));
+                    println!("Volume decreased to {:.2}", self.volume);
+                }
+                KeyCommand::TogglePause => {
+                    if self.player.is_paused {
+                        self.resume();
+                        println!("Playback resumed");
+                    } else {
+                        self.pause();
+                        println!("Playback paused");
+                    }
+                }
+                KeyCommand::NextTrack => self.next_track(),
+                KeyCommand::PreviousTrack => self.prev_track(),
+                KeyCommand::ToggleWindow => {
+                    self.window_visible = !self.window_visible;
+                    ctx.send_viewport_cmd(egui::ViewportCommand::Visible(self.window_visible));
+                    if self.window_visible {
+                        ctx.send_viewport_cmd(egui::ViewportCommand::Focus);
+                    }
+                }
+                #[cfg(any(target_os = "windows", target_os = "linux"))]
+                KeyCommand::ShowWindow => {
+                    self.window_visible = true;
+                    ctx.send_viewport_cmd(egui::ViewportCommand::Visible(true));
+                    ctx.send_viewport_cmd(egui::ViewportCommand::Focus);
+                }
+                KeyCommand::Quit => ctx.send_viewport_cmd(egui::ViewportCommand::Close),
+                KeyCommand::ListenerFailed(e) => {
+                    self.key_listener_error = Some(format!("Global hotkeys unavailable: {}", e));
+                    self.global_keys_enabled.store(false, Ordering::Relaxed);
+                    self.key_listener_started = false;
+                }
+            }
+        }
+    }
+
+    /// In-window shortcuts. These need no OS permissions and are ignored
+    /// while a text field has keyboard focus.
+    fn handle_shortcuts(&mut self, ctx: &egui::Context) {
+        if ctx.wants_keyboard_input() {
+            return;
+        }
+        // Consume the keys so a focused button doesn't also react to them
+        let pressed = |key| ctx.input_mut(|i| i.consume_key(egui::Modifiers::NONE, key));
+        if pressed(egui::Key::Space) {
+            if self.player.sink.is_some() && !self.player.is_paused {
+                self.pause();
+            } else if self.player.sink.is_some() {
+                self.resume();
+            } else if self.player.current_index.is_some() {
+                self.play_current();
+            }
+        }
+        if self.player.sink.is_some() {
+            let end = self.total_duration.unwrap_or(f32::MAX);
+            if pressed(egui::Key::ArrowLeft) {
+                self.seek_to((self.current_position - SEEK_STEP_SECS).max(0.0));
+            }
+            if pressed(egui::Key::ArrowRight) {
+                self.seek_to((self.current_position + SEEK_STEP_SECS).min(end));
+            }
+        }
+        if pressed(egui::Key::ArrowUp) {
+            self.change_volume((self.volume + VOLUME_STEP).min(1.0));
+        }
+        if pressed(egui::Key::ArrowDown) {
+            self.change_volume((self.volume - VOLUME_STEP).max(0.0));
+        }
+        if pressed(egui::Key::N) {
+            self.next_track();
+        }
+        if pressed(egui::Key::P) {
+            self.prev_track();
+        }
+        if pressed(egui::Key::S) {
+            self.player.toggle_shuffle();
+        }
+        if pressed(egui::Key::M) {
+            self.toggle_mute();
+        }
+    }
+
+    /// Handle transport requests from desktop media controls.
+    #[cfg(target_os = "linux")]
+    fn process_media_commands(&mut self) {
+        while let Ok(cmd) = self.media_commands.try_recv() {
+            match cmd {
+                mpris::MediaCommand::PlayPause if self.player.sink.is_some() && !self.player.is_paused => self.pause(),
+                mpris::MediaCommand::PlayPause | mpris::MediaCommand::Play => {
+                    if self.player.sink.is_some() {
+                        self.resume();
+                    } else if self.player.current_index.is_some() {
+                        self.play_current();
+                    }
+                }
+                mpris::MediaCommand::Pause => self.pause(),
+                mpris::MediaCommand::Stop => self.stop(),
+                mpris::MediaCommand::Next => self.next_track(),
+                mpris::MediaCommand::Previous => self.prev_track(),
+            }
+        }
+    }
+
+    /// Show the current track title on the tray icon.
+    #[cfg(any(target_os = "windows", target_os = "linux"))]
+    fn update_tray_tooltip(&mut self) {
+        let title = self.player.current_index
+            .and_then(|idx| self.player.queue.get(idx))
+            .map(|item| item.display_name.as_str());
+        let tooltip = match title {
+            Some(title) => format!("Rust Audio Player - {}", title),
+            None => "Rust Audio Player".to_string(),
+        };
+        if let Some(tray) = &mut self.tray {
+            tray.set_tooltip(&tooltip);
+        }
+    }
+
+    /// Announce a newly started track with a desktop notification, unless
+    /// the window has focus and the track is already in view.
+    #[cfg(any(target_os = "windows", target_os = "linux"))]
+    fn update_notifications(&mut self, ctx: &egui::Context) {
+        let current = self.player.current_index
+            .and_then(|idx| self.player.queue.get(idx))
+            .filter(|_| self.player.sink.is_some() && !self.player.is_paused);
+        let Some(item) = current else {
+            return;
+        };
+        let track = (item.file_path.clone(), item.cue);
+        if self.notified_track.as_ref() == Some(&track) {
+            return;
+        }
+        self.notified_track = Some(track);
+        if self.notify_track_changes && !ctx.input(|i| i.focused) {
+            let _ = self.notifications.send(notifications::NowPlaying {
+                title: item.display_name.clone(),
+                artist: item.artist.clone(),
+                album: item.album.clone(),
+                path: item.file_path.clone(),
+            });
+        }
+    }
+
+    /// Push the now-playing track and status to the MPRIS server when they change.
+    #[cfg(target_os = "linux")]
+    fn publish_media_state(&mut self) {
+        let current = self.player.current_index.and_then(|idx| self.player.queue.get(idx));
+        let art_url = current.and_then(|item| {
+            match &self.media_cover_art {
+                Some((path, url)) if *path == item.file_path => url.clone(),
+                _ => {
+                    let url = mpris::find_cover_art(&item.file_path);
+                    self.media_cover_art = Some((item.file_path.clone(), url.clone()));
+                    url
+                }
+            }
+        });
+        let state = mpris::MediaState {
+            title: current.map(|item| item.display_name.clone()),
+            artist: current.and_then(|item| item.artist.clone()),
+            art_url,
+            playing: self.player.sink.is_some() && !self.player.is_paused,
+            stopped: self.player.sink.is_none(),
+        };
+        if state != self.last_media_state {
+            let _ = self.media_state.send(state.clone());
+            self.last_media_state = state;
+        }
+    }
+
+    fn load_collections(&mut self) -> Vec<MediaItem> {
+        let mut items = Vec::new();
+        self.collections_added.clear();
+        if let Ok(entries) = fs::read_dir(&self.collections_path) {
+            for entry in entries.flatten() {
+                let path = entry.path();
+                if is_audio_file(&path) {
+                    if let Ok(modified) = entry.metadata().and_then(|m| m.modified()) {
+                        self.collections_added.insert(path.clone(), modified);
+                    }
+                    let raw_title = path
+                        .file_stem()
+                        .and_then(|s| s.to_str())
+                        .unwrap_or("Unknown")
+                        .to_string();
+                    let display_name = clean_title(&raw_title);
+                    let mut item = MediaItem {
+                        file_path: path,
+                        display_name,
+                        artist: None,
+                        album: None,
+                        cue: None,
+                        album_group: None,
+                        from_library: false,
+                    };
+                    self.apply_cached_tags(&mut item);
+                    items.push(item);
+                }
+            }
+        }
+        self.request_tags(items.iter().map(|item| item.file_path.clone()).collect());
+        items
+    }
+
+    /// Re-read the collections folder into the cache.
+    fn refresh_collections(&mut self) {
+        self.collections_mtime = fs::metadata(&self.collections_path)
+            .and_then(|m| m.modified())
+            .ok();
+        self.collections_checked_at = Some(Instant::now());
+        self.collections = self.load_collections();
+        self.collections_order = None;
+        self.collection_groups = None;
+        self.history_rows.clear();
+    }
+
+    /// Point the library at `path`, re-scan it and reload the favorites,
+    /// history, playlists and presets kept there. Downloads go to the new
+    /// folder from now on. If the old folder still has tracks, offer to move them.
+    fn set_collections_path(&mut self, path: PathBuf) {
+        if path == self.collections_path {
+            return;
+        }
+        if let Err(e) = fs::create_dir_all(&path) {
+            self.collections_status = format!("Cannot use {}: {}", path.display(), e);
+            return;
+        }
+        let previous = std::mem::replace(&mut self.collections_path, path);
+        self.config.collections_dir = Some(self.collections_path.clone());
+        self.save_config();
+        self.reload_collections_folder();
+        let left_behind = fs::read_dir(&previous)
+            .map(|entries| entries.flatten().filter(|entry| is_audio_file(&entry.path())).count())
+            .unwrap_or(0);
+        self.collections_move_prompt = (left_behind > 0).then_some((previous, left_behind));
+    }
+
+    fn reload_collections_folder(&mut self) {
+        self.history = history::History::load(&self.collections_path);
+        self.favorites = favorites::Favorites::load(&self.collections_path);
+        self.loudness_cache = loudness::LoudnessCache::load(&self.collections_path);
+        self.track_eqs = track_eq::TrackEqs::load(&self.collections_path, track_eq::TRACK_EQ_FILE);
+        self.apply_track_eq();
+        self.saved_presets = eq_presets::load_all(&eq_presets::presets_dir(&self.collections_path));
+        self.playlists = playlists::list(&playlists::playlists_dir(&self.collections_path));
+        self.refresh_collections();
+        self.watch_collections();
+    }
+
+    /// Move the library out of `from` into the current collections folder on
+    /// a background task. Anything whose name is already taken there, or that
+    /// is loaded for playback, is left in place.
+    fn move_collections_from(&mut self, from: &Path) {
+        let (entries, in_use): (Vec<PathBuf>, Vec<PathBuf>) =
+            collections_entries(from).into_iter().partition(|path| !self.is_loaded_in_sink(path));
+        let task = self.tasks.start(format!("Moving library from {}", from.display()));
+        let sender = self.collections_move_sender.clone();
+        let target_dir = self.collections_path.clone();
+        let from = from.to_path_buf();
+        self.collections_status = "Moving tracks…".to_string();
+        thread::spawn(move || {
+            let mut result = CollectionsMove { from, moved_tracks: Vec::new(), skipped: in_use.len() };
+            let count = entries.len();
+            for (i, source) in entries.into_iter().enumerate() {
+                if task.is_cancelled() {
+                    result.skipped += count - i;
+                    break;
+                }
+                let Some(name) = source.file_name() else {
+                    continue;
+                };
+                let target = target_dir.join(name);
+                if target.exists() || move_path(&source, &target).is_err() {
+                    result.skipped += 1;
+                } else if is_audio_file(&source) {
+                    result.moved_tracks.push((source, target));
+                }
+                task.progress((i + 1) as f32 / count as f32);
+            }
+            let _ = sender.send(result);
+        });
+    }
+
+    /// Pick up a finished library move: reload the data files that came along
+    /// and point everything that refers to a moved track at its new path.
+    fn process_collections_move(&mut self) {
+        let Ok(result) = self.collections_move_receiver.try_recv() else {
+            return;
+        };
+        // Pick up the moved favorites and history before re-keying tracks
+        self.reload_collections_folder();
+        for (source, target) in &result.moved_tracks {
+            for item in self.player.queue.iter_mut().filter(|item| item.file_path == *source) {
+                item.file_path = target.clone();
+            }
+            if let Some(queued) = self.queued_next.as_mut().filter(|queued| queued.path == *source) {
+                queued.path = target.clone();
+            }
+            if self.track_details_path.as_ref() == Some(source) {
+                self.track_details_path = Some(target.clone());
+            }
+            self.favorites.rename(source, target);
+            self.track_gains.rename(source, target);
+            self.history.rename(source, target);
+            self.loudness_cache.rename(source, target);
+            self.track_eqs.rename(source, target);
+            if let Some(tags) = self.tag_cache.remove(source) {
+                self.tag_cache.insert(target.clone(), tags);
+            }
+        }
+        if !result.moved_tracks.is_empty() {
+            self.save_favorites();
+            self.save_track_gains();
+            if let Err(e) = self.history.save(&self.collections_path) {
+                eprintln!("Failed to save play history: {}", e);
+            }
+            if let Err(e) = self.loudness_cache.save(&self.collections_path) {
+                eprintln!("Failed to save loudness cache: {}", e);
+            }
+            self.save_track_eqs();
+            self.refresh_collections();
+        }
+        let moved = result.moved_tracks.len();
+        self.collections_status = if result.skipped == 0 {
+            format!("Moved {} tracks", moved)
+        } else {
+            format!("Moved {} tracks; {} items stayed in {}", moved, result.skipped, result.from.display())
+        };
+    }
+
+    /// Current collections folder, a button to pick another, and the offer
+    /// to bring tracks over from the previous one.
+    fn draw_collections_folder_settings(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Library");
+        ui.horizontal(|ui| {
+            ui.label("Collections folder:");
+            ui.monospace(self.collections_path.display().to_string());
+            if ui.button("Change…").on_hover_text("Downloads are saved here and the library is read from it").clicked() && let Some(folder) = FileDialog::new().set_directory(&self.collections_path).pick_folder() {
+                self.set_collections_path(folder);
+            }
+        });
+        if let Some((previous, count)) = self.collections_move_prompt.clone() {
+            ui.horizontal(|ui| {
+                ui.label(format!("{} still has {} tracks.", previous.display(), count));
+                if ui.button("Move them here").clicked() {
+                    self.collections_move_prompt = None;
+                    self.move_collections_from(&previous);
+                }
+                if ui.button("Leave them").clicked() {
+                    self.collections_move_prompt = None;
+                }
+            });
+        }
+        if !self.collections_status.is_empty() {
+            ui.label(&self.collections_status);
+        }
+    }
+
+    /// Watch the collections folder for audio files being added, removed or renamed.
+    fn watch_collections(&mut self) {
+        let dirty_at = self.collections_dirty_at.clone();
+        let watcher = notify::recommended_watcher(move |result: notify::Result<notify::Event>| {
+            let Ok(event) = result else {
+                return;
+            };
+            let relevant = matches!(
+                event.kind,
+                notify::EventKind::Create(_)
+                    | notify::EventKind::Remove(_)
+                    | notify::EventKind::Modify(notify::event::ModifyKind::Name(_))
+            );
+            if relevant && event.paths.iter().any(|path| is_audio_file(path)) {
+                *dirty_at.lock().unwrap() = Some(Instant::now());
+            }
+        });
+        self.collections_watcher = watcher
+            .and_then(|mut watcher| {
+                watcher.watch(&self.collections_path, notify::RecursiveMode::NonRecursive)?;
+                Ok(watcher)
+            })
+            .map_err(|e| eprintln!("Collections watcher unavailable, polling instead: {}", e))
+            .ok();
+    }
+
+    /// Re-scan once watcher events have settled, or without a watcher, when
+    /// the folder's mtime moved (checked at most once per interval).
+    fn refresh_collections_if_changed(&mut self) {
+        if self.collections_watcher.is_some() {
+            let settled = {
+                let mut dirty_at = self.collections_dirty_at.lock().unwrap();
+                let settled = dirty_at.is_some_and(|t| t.elapsed() >= COLLECTIONS_DEBOUNCE);
+                if settled {
+                    *dirty_at = None;
+                }
+                settled
+            };
+            // The first call does the initial scan
+            if settled || self.collections_checked_at.is_none() {
+                self.refresh_collections();
+            }
+            return;
+        }
+        let due = self.collections_checked_at
+            .is_none_or(|t| t.elapsed() >= COLLECTIONS_POLL_INTERVAL);
+        if !due {
+            return;
+        }
+        self.collections_checked_at = Some(Instant::now());
+        let mtime = fs::metadata(&self.collections_path)
+            .and_then(|m| m.modified())
+            .ok();
+        if mtime.is_none() || mtime != self.collections_mtime {
+            self.refresh_collections();
+        }
+    }
+
+    /// Pick up durations measured by the background probe for the current track.
+    fn process_duration_results(&mut self) {
+        while let Ok((path, secs)) = self.duration_receiver.try_recv() {
+            let current = self.player.current_index
+                .and_then(|idx| self.player.queue.get(idx))
+                .filter(|item| item.file_path == path);
+            if let Some(item) = current {
+                self.total_duration = match item.cue {
+                    Some(cue) => cue.length(Some(secs)),
+                    None => Some(secs),
+                };
+            }
+        }
+    }
+
+    fn check_track_finished(&mut self) {
+        // A track prebuffered before "end of current track" was chosen must not start
+        let rolled_over = self.queued_next.is_some()
+            && self.player.sink.as_ref().is_some_and(|sink| sink.len() <= 1);
+        if self.sleep.at_track_end && rolled_over {
+            self.cancel_sleep_timer();
+            self.stop();
+            return;
+        }
+        self.advance_to_queued();
+        if self.player.finished() {
+            self.record_play();
+            self.finish_scrobble();
+            if self.sleep.at_track_end {
+                self.cancel_sleep_timer();
+                self.stop();
+                return;
+            }
+            match self.player.auto_advance_index().or_else(|| self.continue_from_library()) {
+                Some(next) => {
+                    self.player.play_index(next);
+                    self.play_current();
+                }
+                // Stop at the end of the queue; dropping the sink
+                // keeps this from firing again every frame
+                None => self.stop(),
+            }
+        }
+    }
+
+    /// Start the next track on a fresh sink `crossfade_secs` before the current
+    /// one ends, and ramp the old sink down while the new one fades in.
+    fn check_crossfade(&mut self) {
+        if self.crossfade_secs <= 0.0 || self.player.is_paused || self.fading_out.is_some() || self.sleep.at_track_end {
+            return;
+        }
+        let (Some(total), Some(_)) = (self.total_duration, &self.player.sink) else {
+            return;
+        };
+        // Track time runs `speed` times faster than the wall clock
+        let fade_secs = self.crossfade_secs.min(total / 2.0);
+        if total - self.current_position > fade_secs * self.speed {
+            return;
+        }
+        let Some(next) = self.player.auto_advance_index() else {
+            return;
+        };
+        // Tracks of one album run straight on; prebuffer_next queues them
+        if self.player.same_album_group(next) {
+            return;
+        }
+        let Some(old_sink) = self.player.sink.take() else {
+            return;
+        };
+        let fade = Duration::from_secs_f32(fade_secs);
+        self.fading_out = Some(FadingSink {
+            volume: old_sink.volume(),
+            sink: old_sink,
+            started: Instant::now(),
+            duration: fade,
+        });
+        self.record_play();
+        self.player.play_index(next);
+        self.total_duration = None;
+        self.clear_ab_loop();
+        self.fade_in_next = Some(fade);
+        self.start_current_at(0.0);
+    }
+
+    /// Lower the outgoing sink's volume across the crossfade and drop it at the end.
+    fn update_crossfade(&mut self) {
+        let Some(fading) = &self.fading_out else {
+            return;
+        };
+        let t = fading.started.elapsed().as_secs_f32() / fading.duration.as_secs_f32();
+        if t >= 1.0 || fading.sink.empty() {
+            self.finish_crossfade();
+        } else {
+            fading.sink.set_volume(fading.volume * (1.0 - t));
+        }
+    }
+
+    /// Cut off a crossfade in progress, e.g. on pause, stop or a manual track change.
+    fn finish_crossfade(&mut self) {
+        if let Some(fading) = self.fading_out.take() {
+            fading.sink.stop();
+        }
+    }
+
+    fn set_show_remaining_time(&mut self, show: bool) {
+        if show != self.show_remaining_time {
+            self.show_remaining_time = show;
+            self.config.show_remaining_time = show;
+            self.save_config();
+        }
+    }
+
+    /// Restart the current track at `new_time` seconds, keeping the pause state.
+    fn seek_to(&mut self, new_time: f32) {
+        // A stream is reopened at the new time, fetching from there with a range request
+        if let Some(stream) = &self.web_stream {
+            let (title, media_url) = (stream.title.clone(), stream.media_url.clone());
+            self.download_status = format!("Seeking {}...", title);
+            stream::reopen(title, media_url, new_time.max(0.0), self.stream_sender.clone());
+            return;
+        }
+        self.finish_crossfade();
+        self.start_current_at(new_time);
+    }
+
+    /// Follow the playing track for Last.fm: announce it once its artist is
+    /// known, count listening time, and scrobble it when another track starts.
+    fn update_scrobbling(&mut self, dt: f32) {
+        if !self.scrobbling_enabled || self.scrobbler.username.is_none() {
+            self.scrobble = None;
+            return;
+        }
+        let current = self.player.sink.as_ref()
+            .and(self.player.current_index)
+            .and_then(|idx| self.player.queue.get(idx))
+            .cloned();
+        // Cue tracks share a file, so the span tells them apart
+        let current_track = current.as_ref().map(|item| (&item.file_path, item.cue));
+        if self.scrobble.as_ref().map(|pending| (&pending.item.file_path, pending.item.cue)) != current_track {
+            self.finish_scrobble();
+        }
+        let Some(item) = current else {
+            return;
+        };
+        let pending = self.scrobble.get_or_insert_with(|| PendingScrobble {
+            item: item.clone(),
+            duration: None,
+            started_at: SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map_or(0, |since| since.as_secs()),
+            listened: 0.0,
+            announced: false,
+        });
+        // Tags and duration may arrive after playback starts
+        pending.item = item;
+        pending.duration = self.total_duration;
+        if !self.player.is_paused {
+            pending.listened += dt;
+        }
+        if !pending.announced && let Some(track) = scrobble_track(pending) {
+            self.scrobbler.now_playing(track);
+            pending.announced = true;
+        }
+    }
+
+    /// Submit the tracked play if enough of it was heard, and stop tracking it.
+    fn finish_scrobble(&mut self) {
+        let Some(pending) = self.scrobble.take() else {
+            return;
+        };
+        if scrobbler::should_scrobble(pending.listened, pending.duration) && let Some(track) = scrobble_track(&pending) {
+            self.scrobbler.scrobble(track);
+        }
+    }
+
+    fn process_scrobbler_events(&mut self) {
+        while let Ok(event) = self.scrobbler_events.try_recv() {
+            self.lastfm_status = match event {
+                scrobbler::ScrobblerEvent::LoggedIn(username) => {
+                    let status = format!("Logged in as {}", username);
+                    self.scrobbler.username = Some(username);
+                    status
+                }
+                scrobbler::ScrobblerEvent::LoginFailed(e) => format!("Login failed: {}", e),
+                scrobbler::ScrobblerEvent::Submitted(count) => format!("Scrobbled {} track(s)", count),
+                scrobbler::ScrobblerEvent::Failed(e) => format!("Last.fm unavailable, will retry: {}", e),
+            };
+        }
+    }
+
+    /// Opt-in toggle and login form for Last.fm scrobbling.
+    fn draw_lastfm_settings(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Last.fm");
+        if ui.checkbox(&mut self.scrobbling_enabled, "Scrobble plays to Last.fm")
+            .on_hover_text("Tracks count once half of them, or four minutes, has been heard")
+            .changed() {
+            self.config.lastfm_enabled = self.scrobbling_enabled;
+            self.save_config();
+        }
+        if let Some(username) = self.scrobbler.username.clone() {
+            ui.horizontal(|ui| {
+                ui.label(format!("Logged in as {}", username));
+                if ui.button("Log out").clicked() {
+                    self.scrobbler.logout();
+                    self.lastfm_status.clear();
+                }
+            });
+        } else {
+            egui::Grid::new("lastfm_login").num_columns(2).show(ui, |ui| {
+                ui.label("API key");
+                ui.text_edit_singleline(&mut self.lastfm_api_key);
+                ui.end_row();
+                ui.label("API secret");
+                ui.add(egui::TextEdit::singleline(&mut self.lastfm_api_secret).password(true));
+                ui.end_row();
+                ui.label("Username");
+                ui.text_edit_singleline(&mut self.lastfm_username);
+                ui.end_row();
+                ui.label("Password");
+                ui.add(egui::TextEdit::singleline(&mut self.lastfm_password).password(true));
+                ui.end_row();
+            });
+            let complete = [&self.lastfm_api_key, &self.lastfm_api_secret, &self.lastfm_username, &self.lastfm_password]
+                .iter()
+                .all(|field| !field.trim().is_empty());
+            if ui.add_enabled(complete, egui::Button::new("Log in"))
+                .on_hover_text("Get an API key at last.fm/api; only the resulting session key is saved")
+                .clicked() {
+                self.scrobbler.login(&self.lastfm_api_key, &self.lastfm_api_secret, &self.lastfm_username, &self.lastfm_password);
+                self.lastfm_password.clear();
+                self.lastfm_status = "Logging in…".to_string();
+            }
+        }
+        if !self.lastfm_status.is_empty() {
+            ui.label(&self.lastfm_status);
+        }
+    }
+
+    /// Write the config to disk, logging rather than failing on errors.
+    fn save_config(&self) {
+        if let Err(e) = self.config.save(&config::config_dir().join(config::CONFIG_FILE)) {
+            eprintln!("Failed to save config: {}", e);
+        }
+    }
+
+    /// Remember the collections filter for the next launch.
+    fn save_collections_search(&mut self) {
+        self.config.collections_search = self.collections_search.clone();
+        self.save_config();
+    }
+
+    fn toggle_favorite(&mut self, path: &Path) {
+        self.favorites.toggle(path);
+        self.save_favorites();
+    }
+
+    fn save_favorites(&self) {
+        if let Err(e) = self.favorites.save(&self.collections_path) {
+            eprintln!("Failed to save favorites: {}", e);
+        }
+    }
+
+    /// Play or enqueue a collection item according to `action`.
+    fn apply_collection_action(&mut self, item: MediaItem, action: CollectionClickAction) {
+        match action {
+            CollectionClickAction::PlayNow => {
+                let items = self.cue_tracks(item);
+                let added = self.player.insert(0, items);
+                self.player.group_album(added);
+                self.player.play_index(0);
+                self.play_current();
+            }
+            CollectionClickAction::PlayNext => match self.player.current_index {
+                Some(idx) if idx < self.player.queue.len() => {
+                    let items = self.cue_tracks(item);
+                    let added = self.player.insert(idx + 1, items);
+                    self.player.group_album(added);
+                }
+                _ => self.add_file(item),
+            },
+            CollectionClickAction::AddToQueue => self.add_file(item),
+        }
+    }
+
+    /// One collections entry; `matched` are search-matched char positions in its
+    /// label. Clicking runs the configured action, the context menu offers all of
+    /// them, and Ctrl/Shift-click adds it to the selection.
+    fn collection_item_row(&mut self, ui: &mut egui::Ui, item: &MediaItem, matched: &[usize]) {
+        self.collection_rows.push(item.file_path.clone());
+        if let Some((path, name)) = &mut self.renaming && *path == item.file_path {
+            let mut confirmed = false;
+            let mut cancelled = false;
+            ui.horizontal(|ui| {
+                let response = ui.text_edit_singleline(name);
+                response.request_focus();
+                confirmed = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                cancelled = ui.input(|i| i.key_pressed(egui::Key::Escape));
+                confirmed |= ui.button("Rename").clicked();
+                cancelled |= ui.button("Cancel").clicked();
+            });
+            if confirmed {
+                let (path, name) = self.renaming.take().unwrap();
+                self.rename_track_file(&path, &name);
+            } else if cancelled {
+                self.renaming = None;
+            }
+            return;
+        }
+        let background = ui.painter().add(egui::Shape::Noop);
+        let row = ui.horizontal(|ui| {
+            if widgets::favorite_button(ui, self.favorites.contains(&item.file_path), &self.theme) {
+                self.toggle_favorite(&item.file_path);
+            }
+            let label = highlighted_text(ui, &item.label(), matched, ui.visuals().selection.stroke.color);
+            let response = ui.label(label)
+                .on_hover_text(format!(
+                    "Click to {}, Ctrl/Shift-click to select",
+                    self.collection_click_action.label().to_lowercase(),
+                ));
+            if response.clicked() {
+                let modifiers = ui.input(|i| i.modifiers);
+                let selecting = select_row(
+                    &mut self.collection_selection,
+                    &mut self.collection_selection_anchor,
+                    &self.collection_rows_shown,
+                    &item.file_path,
+                    modifiers,
+                );
+                if !selecting {
+                    self.apply_collection_action(item.clone(), self.collection_click_action);
+                }
+            }
+            response.context_menu(|ui| {
+                for action in CollectionClickAction::ALL {
+                    if ui.button(action.label()).clicked() {
+                        self.apply_collection_action(item.clone(), action);
+                        ui.close_menu();
+                    }
+                }
+                ui.separator();
+                if ui.button("Rename").clicked() {
+                    let stem = item.file_path.file_stem().map(|s| s.to_string_lossy().into_owned());
+                    self.renaming = Some((item.file_path.clone(), stem.unwrap_or_default()));
+                    ui.close_menu();
+                }
+                if ui.button("Edit Tags…").clicked() {
+                    self.open_tag_editor(item);
+                    ui.close_menu();
+                }
+                if ui.button("Delete file…").clicked() {
+                    self.pending_delete = vec![item.file_path.clone()];
+                    ui.close_menu();
+                }
+            });
+            if self.collection_click_action != CollectionClickAction::AddToQueue
+                && ui.button("Add to Queue").clicked() {
+                self.add_file(item.clone());
+            }
+        });
+        if self.collection_selection.contains(&item.file_path) {
+            let fill = egui::Shape::rect_filled(row.response.rect, 2.0, ui.visuals().selection.bg_fill);
+            ui.painter().set(background, fill);
+        }
+    }
+
+    /// Whether `path` is open in the sink, as the current or prebuffered track.
+    fn is_loaded_in_sink(&self, path: &Path) -> bool {
+        let current = self.player.current_index
+            .and_then(|idx| self.player.queue.get(idx))
+            .is_some_and(|item| item.file_path == path);
+        (current && self.player.sink.is_some()) || self.queued_next.as_ref().is_some_and(|queued| queued.path == path)
+    }
+
+    /// Delete `path` from disk and drop it from the queue. Playback of the file
+    /// stops first, since on Windows the sink's open handle blocks the delete.
+    /// Returns false if the file couldn't be deleted.
+    fn delete_track_file(&mut self, path: &Path) -> bool {
+        let loaded = self.is_loaded_in_sink(path);
+        if loaded {
+            self.stop();
+        }
+        match fs::remove_file(path) {
+            // The audio thread lets go of a stopped source on its next
+            // callback, so try again shortly without blocking the UI
+            Err(e) if loaded && e.kind() == io::ErrorKind::PermissionDenied => {
+                let path = path.to_path_buf();
+                let tx = self.delete_sender.clone();
+                thread::spawn(move || {
+                    thread::sleep(Duration::from_millis(200));
+                    let result = fs::remove_file(&path);
+                    let _ = tx.send((path, result));
+                });
+                true
+            }
+            result => self.finish_delete(path, result),
+        }
+    }
+
+    /// Apply deletes that were retried on a worker thread.
+    fn process_delete_retries(&mut self) {
+        while let Ok((path, result)) = self.delete_receiver.try_recv() {
+            self.finish_delete(&path, result);
+        }
+    }
+
+    /// Drop a deleted file from the queue, caches and favorites and remove
+    /// its sidecar cover image. Returns false, with the reason in the
+    /// status, if the delete failed.
+    fn finish_delete(&mut self, path: &Path, result: io::Result<()>) -> bool {
+        let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        if let Err(e) = result {
+            self.collections_status = format!("Could not delete {}: {}", name, e);
+            return false;
+        }
+        for ext in ["jpg", "png", "webp"] {
+            let art = path.with_extension(ext);
+            if art.exists() {
+                let _ = fs::remove_file(&art);
+            }
+        }
+        while let Some(index) = self.player.queue.iter().rposition(|item| item.file_path == path) {
+            self.remove_from_queue(index);
+        }
+        self.tag_cache.remove(path);
+        if self.favorites.remove(path) {
+            self.save_favorites();
+        }
+        self.collections_status = format!("Deleted {}", name);
+        self.refresh_collections();
+        true
+    }
+
+    /// Rename `path` to `new_stem` plus its old extension, taking a sidecar
+    /// cover image along, and update queued entries to the new path.
+    fn rename_track_file(&mut self, path: &Path, new_stem: &str) {
+        let new_stem = new_stem.trim();
+        if new_stem.is_empty() || new_stem.contains(['/', '\\']) {
+            self.collections_status = "Enter a file name without slashes".to_string();
+            return;
+        }
+        let file_name = match path.extension() {
+            Some(ext) => format!("{}.{}", new_stem, ext.to_string_lossy()),
+            None => new_stem.to_string(),
+        };
+        let new_path = path.with_file_name(file_name);
+        if new_path == path {
+            return;
+        }
+        if new_path.exists() {
+            self.collections_status = format!("{} already exists", new_path.display());
+            return;
+        }
+        if let Err(e) = fs::rename(path, &new_path) {
+            let hint = if self.is_loaded_in_sink(path) { " (stop playback and try again)" } else { "" };
+            self.collections_status = format!("Could not rename: {}{}", e, hint);
+            return;
+        }
+        for ext in ["jpg", "png", "webp"] {
+            let art = path.with_extension(ext);
+            if art.exists() {
+                let _ = fs::rename(&art, new_path.with_extension(ext));
+            }
+        }
+        let renamed = MediaItem::from_path(new_path.clone());
+        for item in self.player.queue.iter_mut().filter(|item| item.file_path == path) {
+            item.file_path = new_path.clone();
+            item.display_name = renamed.display_name.clone();
+        }
+        if let Some(queued) = self.queued_next.as_mut().filter(|queued| queued.path == path) {
+            queued.path = new_path.clone();
+        }
+        if self.track_details_path.as_deref() == Some(path) {
+            self.track_details_path = Some(new_path.clone());
+        }
+        if self.favorites.rename(path, &new_path) {
+            self.save_favorites();
+        }
+        if self.track_gains.rename(path, &new_path) {
+            self.save_track_gains();
+        }
+        if self.track_eqs.rename(path, &new_path) {
+            self.save_track_eqs();
+        }
+        if self.history.rename(path, &new_path) && let Err(e) = self.history.save(&self.collections_path) {
+            eprintln!("Failed to save play history: {}", e);
+        }
+        if self.loudness_cache.rename(path, &new_path) && let Err(e) = self.loudness_cache.save(&self.collections_path) {
+            eprintln!("Failed to save loudness cache: {}", e);
+        }
+        if self.collection_selection.remove(path) {
+            self.collection_selection.insert(new_path.clone());
+        }
+        self.tag_cache.remove(path);
+        self.request_tags(vec![new_path]);
+        self.collections_status = format!("Renamed to {}", renamed.display_name);
+        self.refresh_collections();
+    }
+
+    /// Ask before deleting the files chosen from the collections panel.
+    fn draw_delete_confirmation(&mut self, ctx: &egui::Context) {
+        if self.pending_delete.is_empty() {
+            return;
+        }
+        let mut confirmed = false;
+        let mut cancelled = false;
+        egui::Window::new("Delete files?")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+            .show(ctx, |ui| {
+                match self.pending_delete.as_slice() {
+                    [path] => {
+                        ui.label(format!("Permanently delete \"{}\" from disk?", path.display()));
+                    }
+                    paths => {
+                        ui.label(format!("Permanently delete these {} files from disk?", paths.len()));
+                        egui::ScrollArea::vertical().max_height(150.0).show(ui, |ui| {
+                            for path in paths {
+                                ui.label(path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default());
+                            }
+                        });
+                    }
+                }
+                ui.horizontal(|ui| {
+                    confirmed = ui.button("Delete").clicked();
+                    cancelled = ui.button("Cancel").clicked();
+                });
+            });
+        if confirmed {
+            let paths = std::mem::take(&mut self.pending_delete);
+            let mut deleted = 0;
+            let mut last_error = None;
+            for path in &paths {
+                self.collection_selection.remove(path);
+                if self.delete_track_file(path) {
+                    deleted += 1;
+                } else {
+                    last_error = Some(self.collections_status.clone());
+                }
+            }
+            // Each delete overwrites the status, so sum up a batch
+            if paths.len() > 1 {
+                self.collections_status = match last_error {
+                    Some(error) => format!("Deleted {} of {} files. {}", deleted, paths.len(), error),
+                    None => format!("Deleted {} files", deleted),
+                };
+            }
+        } else if cancelled || ctx.input(|i| i.key_pressed(egui::Key::Escape)) {
+            self.pending_delete.clear();
+        }
+    }
+
+    /// Open the Edit Tags window for `item`'s file, filled in from its tags
+    /// or, where it has none, from what the list shows.
+    fn open_tag_editor(&mut self, item: &MediaItem) {
+        let tags = self.tag_cache.get(&item.file_path).cloned().flatten().unwrap_or_default();
+        // A cue track's title is the sheet's, not the file's
+        let shown_title = item.cue.is_none().then(|| item.display_name.clone());
+        self.tag_editor = Some(TagEditor {
+            path: item.file_path.clone(),
+            title: tags.title.or(shown_title).unwrap_or_default(),
+            artist: tags.artist.or_else(|| item.artist.clone()).unwrap_or_default(),
+            album: tags.album.or_else(|| item.album.clone()).unwrap_or_default(),
+            error: None,
+        });
+    }
+
+    fn draw_tag_editor(&mut self, ctx: &egui::Context) {
+        let Some(editor) = &mut self.tag_editor else {
+            return;
+        };
+        let mut save = false;
+        let mut cancelled = ctx.input(|i| i.key_pressed(egui::Key::Escape));
+        egui::Window::new("Edit Tags")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+            .show(ctx, |ui| {
+                ui.label(RichText::new(editor.path.display().to_string()).small().weak());
+                egui::Grid::new("tag_editor").num_columns(2).show(ui, |ui| {
+                    for (label, value) in [
+                        ("Title", &mut editor.title),
+                        ("Artist", &mut editor.artist),
+                        ("Album", &mut editor.album),
+                    ] {
+                        ui.label(label);
+                        ui.text_edit_singleline(value);
+                        ui.end_row();
+                    }
+                });
+                if let Some(error) = &editor.error {
+                    ui.colored_label(ui.visuals().error_fg_color, error);
+                }
+                ui.horizontal(|ui| {
+                    save = ui.button("Save").clicked();
+                    cancelled |= ui.button("Cancel").clicked();
+                });
+            });
+        if save {
+            self.save_tag_edit();
+        } else if cancelled {
+            self.tag_editor = None;
+        }
+    }
+
+    /// Write the edited tags to the file and show them everywhere it is
+    /// listed. Failures keep the window open with the reason.
+    fn save_tag_edit(&mut self) {
+        let Some(editor) = &self.tag_editor else {
+            return;
+        };
+        let path = editor.path.clone();
+        match metadata::write_tags(&path, &editor.title, &editor.artist, &editor.album) {
+            Ok(tags) => {
+                let items = self.player.queue.iter_mut().chain(self.collections.iter_mut());
+                for item in items.filter(|item| item.file_path == path) {
+                    // Start from the file name so a cleared title falls back to it
+                    if item.cue.is_none() {
+                        item.display_name = MediaItem::from_path(path.clone()).display_name;
+                    }
+                    item.apply_tags(&tags);
+                }
+                // Re-read the cover and stream info if this is the current track
+                if self.track_details_path.as_ref() == Some(&path) {
+                    self.track_details_path = None;
+                }
+                self.tag_cache.insert(path, Some(tags));
+                self.collections_order = None;
+                self.collection_groups = None;
+                self.history_rows.clear();
+                self.tag_editor = None;
+            }
+            Err(e) => {
+                // Windows won't write to a file that is open for playback
+                let hint = if self.is_loaded_in_sink(&path) { " (stop playback and try again)" } else { "" };
+                if let Some(editor) = &mut self.tag_editor {
+                    editor.error = Some(format!("Could not save tags: {}{}", e, hint));
+                }
+            }
+        }
+    }
+
+    /// Draw the collections as a grid of album tiles. Clicking a tile expands
+    /// its track list; double-clicking queues the whole album in order.
+    fn draw_album_grid(&mut self, ui: &mut egui::Ui, items: &[&MediaItem]) {
+        let mut albums: BTreeMap<String, Vec<MediaItem>> = BTreeMap::new();
+        for item in items {
+            let album = item.album.clone().unwrap_or_else(|| UNKNOWN_ALBUM.to_string());
+            albums.entry(album).or_default().push((*item).clone());
+        }
+        for tracks in albums.values_mut() {
+            tracks.sort_by(|a, b| self.album_track_order(a, b));
+        }
+        let albums: Vec<(String, Vec<MediaItem>)> = albums.into_iter().collect();
+
+        // Cloned since the tile loop below needs `self` mutably
+        let app_theme = self.theme.clone();
+        let tile_size = 110.0;
+        let columns = ((ui.available_width() + app_theme.widget_gap) / (tile_size + app_theme.widget_gap))
+            .floor()
+            .max(1.0) as usize;
+        for row in albums.chunks(columns) {
+            ui.horizontal(|ui| {
+                for (album, tracks) in row {
+                    let response = widgets::album_tile(ui, album, tracks.len(), tile_size, &app_theme)
+                        .on_hover_text("Click to expand, double-click to queue the album");
+                    if response.double_clicked() {
+                        for track in tracks {
+                            self.add_file(track.clone());
+                        }
+                    } else if response.clicked() {
+                        self.expanded_album = if self.expanded_album.as_ref() == Some(album) {
+                            None
+                        } else {
+                            Some(album.clone())
+                        };
+                    }
+                }
+            });
+            let expanded = row.iter().find(|(album, _)| self.expanded_album.as_ref() == Some(album));
+            if let Some((_, tracks)) = expanded {
+                ui.indent("expanded_album", |ui| {
+                    for track in tracks {
+                        self.collection_item_row(ui, track, &[]);
+                    }
+                });
+            }
+        }
+    }
+
+    /// Draw the collections under collapsible album or artist headings.
+    /// Clicking a heading's name queues its tracks in album order.
+    fn draw_collection_groups(
+        &mut self,
+        ui: &mut egui::Ui,
+        items: &[&MediaItem],
+        highlights: &HashMap<PathBuf, Vec<usize>>,
+        by_artist: bool,
+    ) {
+        let mut hasher = std::hash::DefaultHasher::new();
+        by_artist.hash(&mut hasher);
+        for item in items {
+            (&item.file_path, item.cue.map(|cue| cue.track)).hash(&mut hasher);
+        }
+        let key = hasher.finish();
+        let mut cached = self.collection_groups.take().filter(|(cached, _)| *cached == key);
+        let (_, groups) = cached.get_or_insert_with(|| (key, self.group_collection_items(items, by_artist)));
+
+        // Search results get their own open state so matches show expanded
+        let searching = !self.collections_search.is_empty();
+        let mut clicked = None;
+        for (index, (name, tracks)) in groups.iter().enumerate() {
+            let id = ui.make_persistent_id(("collection_group", by_artist, searching, name));
+            let state = egui::collapsing_header::CollapsingState::load_with_default_open(ui.ctx(), id, searching);
+            let header = state.show_header(ui, |ui| {
+                let heading = RichText::new(format!("{} ({})", name, tracks.len())).strong();
+                ui.add(egui::Label::new(heading).sense(egui::Sense::click()))
+                    .on_hover_text(if by_artist { "Click to queue the artist's tracks" } else { "Click to queue the album" })
+            });
+            let (_, heading, _) = header.body(|ui| {
+                for track in tracks {
+                    let matched = highlights.get(&track.file_path).map_or(&[][..], Vec::as_slice);
+                    self.collection_item_row(ui, track, matched);
+                }
+            });
+            if heading.inner.clicked() {
+                clicked = Some(index);
+            }
+        }
+        let tracks = clicked.map(|index| groups[index].1.clone());
+        // Keep the grouping unless a row changed the collections meanwhile
+        if self.collections_order.is_some() {
+            self.collection_groups = cached;
+        }
+        if let Some(tracks) = tracks {
+            self.add_files(tracks);
+        }
+    }
+
+    /// `items` grouped by artist or album name, with each group's tracks in
+    /// album order.
+    fn group_collection_items(&self, items: &[&MediaItem], by_artist: bool) -> CollectionGroups {
+        let mut groups: BTreeMap<String, Vec<MediaItem>> = BTreeMap::new();
+        for item in items {
+            let name = if by_artist {
+                item.artist.clone().unwrap_or_else(|| UNKNOWN_ARTIST.to_string())
+            } else {
+                item.album.clone().unwrap_or_else(|| UNKNOWN_ALBUM.to_string())
+            };
+            groups.entry(name).or_default().push((*item).clone());
+        }
+        for tracks in groups.values_mut() {
+            tracks.sort_by(|a, b| a.album.cmp(&b.album).then_with(|| self.album_track_order(a, b)));
+        }
+        groups.into_iter().collect()
+    }
+
+    /// Update the equalizer settings and apply them in real-time
+    fn update_equalizer_settings(&mut self) {
+        // Update the shared state so audio processing can access the changes
+        let mut shared = self.player.chain.equalizer.lock().unwrap();
+        *shared = self.equalizer.clone();
+        // Signal playing sources to rebuild their filters
+        self.player.chain.equalizer_generation.fetch_add(1, Ordering::Release);
+    }
+
+    /// With per-track EQ on, switch to the current track's remembered curve,
+    /// or back to the global one if it has none.
+    fn apply_track_eq(&mut self) {
+        if !self.per_track_eq {
+            return;
+        }
+        let saved = self.player.current()
+            .and_then(|item| self.track_eqs.get(&item.file_path))
+            .cloned();
+        let Some(saved) = saved else {
+            self.restore_global_eq();
+            return;
+        };
+        self.global_eq.get_or_insert_with(|| self.equalizer.clone());
+        self.equalizer.apply_saved(&saved);
+        self.active_saved_preset = None;
+        self.update_equalizer_settings();
+    }
+
+    /// Put back the global curve set aside by `apply_track_eq`. Other EQ
+    /// settings changed in the meantime, such as the limiter, are kept.
+    fn restore_global_eq(&mut self) {
+        let Some(global) = self.global_eq.take() else {
+            return;
+        };
+        self.equalizer.preset = global.preset;
+        self.equalizer.bands = global.bands;
+        self.equalizer.preamp_db = global.preamp_db;
+        self.equalizer.bass_db = global.bass_db;
+        self.equalizer.treble_db = global.treble_db;
+        self.update_equalizer_settings();
+    }
+
+    /// Remember the current curve for the playing track.
+    fn save_track_eq(&mut self) {
+        let Some(path) = self.player.current().map(|item| item.file_path.clone()) else {
+            return;
+        };
+        let eq = eq_presets::SavedPreset {
+            name: path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default(),
+            bands: self.equalizer.bands.clone(),
+            preamp_db: self.equalizer.preamp_db,
+            bass_db: self.equalizer.bass_db,
+            treble_db: self.equalizer.treble_db,
+        };
+        self.track_eqs.set(path, eq);
+        self.save_track_eqs();
+        self.eq_status = "Saved EQ for this track".to_string();
+    }
+
+    /// Forget the playing track's curve and go back to the global one.
+    fn clear_track_eq(&mut self) {
+        let Some(path) = self.player.current().map(|item| item.file_path.clone()) else {
+            return;
+        };
+        if self.track_eqs.remove(&path) {
+            self.save_track_eqs();
+        }
+        self.restore_global_eq();
+        self.eq_status = "Cleared this track's EQ".to_string();
+    }
+
+    fn save_track_eqs(&mut self) {
+        if let Err(e) = self.track_eqs.save(&self.collections_path) {
+            self.eq_status = format!("Failed to save track EQ: {}", e);
+        }
+    }
+
+    fn draw_track_eq(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            if ui.checkbox(&mut self.per_track_eq, "Per-track EQ")
+                .on_hover_text("Remember a curve for single tracks and switch to it when they play")
+                .changed() {
+                self.config.per_track_eq = self.per_track_eq;
+                self.save_config();
+                if self.per_track_eq {
+                    self.apply_track_eq();
+                } else {
+                    self.restore_global_eq();
+                }
+            }
+            if !self.per_track_eq {
+                return;
+            }
+            let Some(path) = self.player.current().map(|item| item.file_path.clone()) else {
+                ui.label(RichText::new("No track playing").weak());
+                return;
+            };
+            if self.track_eqs.get(&path).is_some() {
+                ui.label(RichText::new("● Track EQ").color(self.theme.accent_color))
+                    .on_hover_text(path.display().to_string());
+                if ui.button("Update track EQ").clicked() {
+                    self.save_track_eq();
+                }
+                if ui.button("Clear track EQ").clicked() {
+                    self.clear_track_eq();
+                }
+            } else if ui.button("Save for this track").clicked() {
+                self.save_track_eq();
+            }
+        });
+    }
+
+    /// Save the current bands as a named preset and refresh the preset list.
+    fn save_preset(&mut self, name: String) {
+        let preset = eq_presets::SavedPreset {
+            name: name.clone(),
+            bands: self.equalizer.bands.clone(),
+            preamp_db: self.equalizer.preamp_db,
+            bass_db: self.equalizer.bass_db,
+            treble_db: self.equalizer.treble_db,
+        };
+        let dir = eq_presets::presets_dir(&self.collections_path);
+        match eq_presets::save(&dir, &preset) {
+            Ok(()) => {
+                self.saved_presets = eq_presets::load_all(&dir);
+                self.equalizer.preset = EqualizerPreset::Custom;
+                self.active_saved_preset = Some(name.clone());
+                self.preset_name_input.clear();
+                self.eq_status = format!("Saved preset \"{}\"", name);
+            }
+            Err(e) => {
+                self.eq_status = format!("Failed to save preset: {}", e);
+            }
+        }
+    }
+
+    /// Let the user pick an EQ curve file and load it as a Custom preset.
+    fn import_eq_curve(&mut self) {
+        let Some(path) = FileDialog::new()
+            .add_filter("EQ curve", &["txt", "csv"])
+            .pick_file()
+        else {
+            return;
+        };
+        match fs::read_to_string(&path) {
+            Ok(text) => {
+                let curve = parse_eq_curve(&text);
+                if curve.is_empty() {
+                    self.eq_status = "No frequency/gain pairs found in file".to_string();
+                    return;
+                }
+                self.equalizer.load_curve(&curve);
+                self.active_saved_preset = None;
+                self.update_equalizer_settings();
+                self.eq_status = format!("Imported {} points from {}", curve.len(), path.display());
+            }
+            Err(e) => {
+                self.eq_status = format!("Failed to read EQ curve: {}", e);
+            }
+        }
+    }
+
+    /// Draw the Equalizer tab UI.
+    /// Now updates in real-time without restarting playback.
+    fn draw_equalizer_tab(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.heading("Audio Equalizer");
+            let label = if self.equalizer.enabled { "EQ: On" } else { "EQ: Off" };
+            if ui.add(egui::Button::new(RichText::new(label).strong()).selected(self.equalizer.enabled))
+                .on_hover_text("Bypass the equalizer to compare; band settings are kept")
+                .clicked() {
+                self.equalizer.enabled = !self.equalizer.enabled;
+                self.update_equalizer_settings();
+                self.config.eq_enabled = self.equalizer.enabled;
+                self.save_config();
+            }
+        });
+        self.draw_eq_curve(ui);
+        self.draw_track_eq(ui);
+
+        ui.horizontal(|ui| {
+            let mut changed = false;
+            for (label, gain, tooltip) in [
+                ("Bass", &mut self.equalizer.bass_db, "Low shelf below 100 Hz, on top of the band curve"),
+                ("Treble", &mut self.equalizer.treble_db, "High shelf above 8 kHz, on top of the band curve"),
+            ] {
+                ui.label(format!("{}:", label));
+                changed |= ui.add(egui::Slider::new(gain, -12.0..=12.0).step_by(0.5).suffix(" dB"))
+                    .on_hover_text(tooltip)
+                    .changed();
+            }
+            if ui.add_enabled(self.equalizer.bass_db != 0.0 || self.equalizer.treble_db != 0.0, egui::Button::new("Reset"))
+                .clicked() {
+                self.equalizer.bass_db = 0.0;
+                self.equalizer.treble_db = 0.0;
+                changed = true;
+            }
+            if changed {
+                self.update_equalizer_settings();
+            }
+        });
+        ui.separator();
+
+        let mut preset_changed = false;
+        let mut load_saved = None;
+        
+        let selected_text = match &self.active_saved_preset {
+            Some(name) if self.equalizer.preset == EqualizerPreset::Custom => name.clone(),
+            _ => format!("{:?}", self.equalizer.preset),
+        };
+        egui::ComboBox::from_label("Preset")
+            .selected_text(selected_text)
+            .show_ui(ui, |ui| {
+                preset_changed |= ui.selectable_value(&mut self.equalizer.preset, EqualizerPreset::Flat, "Flat").clicked();
+                preset_changed |= ui.selectable_value(&mut self.equalizer.preset, EqualizerPreset::Classical, "Classical").clicked();
+                preset_changed |= ui.selectable_value(&mut self.equalizer.preset, EqualizerPreset::HipHop, "Hip Hop").clicked();
+                preset_changed |= ui.selectable_value(&mut self.equalizer.preset, EqualizerPreset::Pop, "Pop").clicked();
+                preset_changed |= ui.selectable_value(&mut self.equalizer.preset, EqualizerPreset::Rock, "Rock").clicked();
+                preset_changed |= ui.selectable_value(&mut self.equalizer.preset, EqualizerPreset::HeavyMetal, "Heavy Metal").clicked();
+                preset_changed |= ui.selectable_value(&mut self.equalizer.preset, EqualizerPreset::Folk, "Folk").clicked();
+                preset_changed |= ui.selectable_value(&mut self.equalizer.preset, EqualizerPreset::Custom, "Custom").clicked();
+                if !self.saved_presets.is_empty() {
+                    ui.separator();
+                    for saved in &self.saved_presets {
+                        let selected = self.active_saved_preset.as_deref() == Some(saved.name.as_str());
+                        if ui.selectable_label(selected, &saved.name).clicked() {
+                            load_saved = Some(saved.clone());
+                        }
+                    }
+                }
+            });
+
+        let band_count = self.equalizer.bands.len();
+        egui::ComboBox::from_label("Bands")
+            .selected_text(band_count.to_string())
+            .show_ui(ui, |ui| {
+                for count in EQ_BAND_COUNTS {
+                    if ui.selectable_label(band_count == count, count.to_string()).clicked() && count != band_count {
+                        self.equalizer.set_band_count(count);
+                        self.update_equalizer_settings();
+                    }
+                }
+            });
+
+        if let Some(saved) = load_saved {
+            self.equalizer.apply_saved(&saved);
+            self.active_saved_preset = Some(saved.name);
+            self.update_equalizer_settings();
+        } else if preset_changed || ui.button("Apply Preset").clicked() {
+            self.active_saved_preset = None;
+            self.equalizer.apply_preset();
+            self.update_equalizer_settings();
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("Save Preset As:");
+            let response = ui.text_edit_singleline(&mut self.preset_name_input);
+            if ui.button("Save").clicked()
+                || (response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter))) {
+                let name = self.preset_name_input.trim().to_string();
+                if name.is_empty() {
+                    self.eq_status = "Enter a name for the preset".to_string();
+                } else if self.saved_presets.iter().any(|p| p.name == name) {
+                    self.pending_overwrite = Some(name);
+                } else {
+                    self.save_preset(name);
+                }
+            }
+        });
+        if let Some(name) = self.pending_overwrite.clone() {
+            ui.horizontal(|ui| {
+                ui.label(format!("Preset \"{}\" already exists. Overwrite?", name));
+                if ui.button("Overwrite").clicked() {
+                    self.pending_overwrite = None;
+                    self.save_preset(name);
+                }
+                if ui.button("Cancel").clicked() {
+                    self.pending_overwrite = None;
+                }
             });
+        }
 
-        if preset_changed || ui.button("Apply Preset").clicked() {
-            self.equalizer.apply_preset();
-            self.update_equalizer_settings();
+        ui.horizontal(|ui| {
+            if ui.button("Reset").on_hover_text("Zero all bands").clicked() {
+                self.equalizer.reset();
+                self.update_equalizer_settings();
+            }
+            if ui.button("Invert").on_hover_text("Negate every band gain").clicked() {
+                self.equalizer.invert();
+                self.active_saved_preset = None;
+                self.update_equalizer_settings();
+            }
+            if ui.button("Import EQ curve").on_hover_text("Load a \"Freq Gain\" or AutoEQ file").clicked() {
+                self.import_eq_curve();
+            }
+        });
+        if !self.eq_status.is_empty() {
+            ui.label(&self.eq_status);
         }
 
+        ui.horizontal(|ui| {
+            ui.label("Preamp:");
+            if ui.add(egui::Slider::new(&mut self.equalizer.preamp_db, -12.0..=12.0).step_by(0.5).suffix(" dB"))
+                .on_hover_text("Lower this when boosting bands to avoid clipping")
+                .changed() {
+                self.update_equalizer_settings();
+            }
+        });
+
         // For custom settings, update on slider change.
         if self.equalizer.preset == EqualizerPreset::Custom {
             ui.separator();
             ui.label("Custom adjustments:");
             let mut update_needed = false;
-            for (i, band) in self.equalizer.bands.iter_mut().enumerate() {
-                ui.horizontal(|ui| {
-                    ui.label(format!("Band {}:", i + 1));
-                    if ui.add(egui::Slider::new(band, -10.0..=10.0).text("dB")).changed() {
-                        update_needed = true;
-                    }
-                });
-            }
+            // dB scale header lined up with the slider rails below
+            ui.horizontal(|ui| {
+                ui.add_sized([EQ_LABEL_WIDTH, 18.0], egui::Label::new(""));
+                let rail = eq_slider_rail(ui);
+                ui.allocate_space(rail.size());
+                draw_db_scale(ui, rail, true);
+            });
+            let center_frequencies = eq_center_frequencies(self.equalizer.bands.len());
+            egui::ScrollArea::vertical().max_height(320.0).show(ui, |ui| {
+                for (band, &freq) in self.equalizer.bands.iter_mut().zip(&center_frequencies) {
+                    ui.horizontal(|ui| {
+                        ui.add_sized(
+                            [EQ_LABEL_WIDTH, 18.0],
+                            egui::Label::new(format_frequency(freq)),
+                        );
+                        draw_db_scale(ui, eq_slider_rail(ui), false);
+                        if ui.add(egui::Slider::new(band, -10.0..=10.0).text("dB")).changed() {
+                            update_needed = true;
+                        }
+                    });
+                }
+            });
             if update_needed {
                 self.update_equalizer_settings();
             }
         }
+
+        ui.separator();
+        self.draw_limiter_controls(ui);
     }
-}
 
-impl eframe::App for AudioPlayerApp {
-    fn update(&mut self, ctx: &eframe::egui::Context, _frame: &mut eframe::Frame) {
-        self.check_track_finished();
-        self.process_youtube_result();
-        self.process_key_commands();
+    /// Combined magnitude response of the preamp, shelves and bands from 20 Hz
+    /// to 20 kHz on a log frequency axis. Drawn dimmed while the EQ is bypassed.
+    fn draw_eq_curve(&self, ui: &mut egui::Ui) {
+        let (rect, _) = ui.allocate_exact_size(egui::vec2(ui.available_width(), EQ_CURVE_HEIGHT), egui::Sense::hover());
+        let painter = ui.painter_at(rect);
+        let theme = &self.theme;
+        painter.rect_filled(rect, theme.corner_radius, theme.panel_color);
 
-        if !self.is_paused {
-            self.current_position += ctx.input(|i| i.unstable_dt);
-            if self.current_position >= self.total_duration {
-                self.current_position = self.total_duration;
-            }
+        let (low, high) = (20.0_f32.log10(), 20000.0_f32.log10());
+        let x_for = |hz: f32| egui::lerp(rect.x_range(), (hz.log10() - low) / (high - low));
+        let y_for = |db: f32| {
+            let t = (db.clamp(-EQ_CURVE_RANGE_DB, EQ_CURVE_RANGE_DB) + EQ_CURVE_RANGE_DB) / (2.0 * EQ_CURVE_RANGE_DB);
+            egui::lerp(rect.bottom()..=rect.top(), t)
+        };
+        let label_font = egui::FontId::proportional(10.0);
+        for hz in EQ_CURVE_GRID_HZ {
+            let x = x_for(hz);
+            painter.vline(x, rect.y_range(), egui::Stroke::new(1.0, theme.inactive_color.gamma_multiply(0.4)));
+            painter.text(
+                egui::pos2(x + 2.0, rect.bottom() - 2.0),
+                egui::Align2::LEFT_BOTTOM,
+                format_frequency(hz),
+                label_font.clone(),
+                theme.dim_text_color,
+            );
+        }
+        for db in EQ_GRID_DB {
+            let stroke = if db == 0.0 {
+                egui::Stroke::new(1.0, theme.inactive_color)
+            } else {
+                egui::Stroke::new(1.0, theme.inactive_color.gamma_multiply(0.4))
+            };
+            let y = y_for(db);
+            painter.hline(rect.x_range(), y, stroke);
+            painter.text(
+                egui::pos2(rect.left() + 2.0, y - 1.0),
+                egui::Align2::LEFT_BOTTOM,
+                format!("{:+}", db),
+                label_font.clone(),
+                theme.dim_text_color,
+            );
+        }
+
+        let coefficients = eq_filter_coefficients(&self.equalizer, EQ_CURVE_SAMPLE_RATE);
+        let points: Vec<egui::Pos2> = (0..EQ_CURVE_POINTS)
+            .map(|i| {
+                let t = i as f32 / (EQ_CURVE_POINTS - 1) as f32;
+                let hz = 10.0_f32.powf(low + t * (high - low));
+                let db = self.equalizer.preamp_db
+                    + coefficients
+                        .iter()
+                        .map(|coef| biquad_response_db(coef, hz, EQ_CURVE_SAMPLE_RATE))
+                        .sum::<f32>();
+                egui::pos2(x_for(hz), y_for(db))
+            })
+            .collect();
+        let color = if self.equalizer.enabled {
+            theme.accent_color
+        } else {
+            theme.accent_color.gamma_multiply(0.35)
+        };
+        painter.add(egui::Shape::line(points, egui::Stroke::new(2.0, color)));
+        painter.rect_stroke(
+            rect,
+            theme.corner_radius,
+            egui::Stroke::new(1.0, theme.inactive_color),
+            egui::StrokeKind::Inside,
+        );
+    }
+
+    /// Look-ahead limiter toggle and timing, rebuilt into the EQ chain on change.
+    fn draw_limiter_controls(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Limiter");
+        let limiter = &mut self.equalizer.limiter;
+        let mut changed = ui.checkbox(&mut limiter.enabled, "Enable limiter")
+            .on_hover_text("Smoothly turn down peaks the EQ pushes past 0 dBFS instead of clipping")
+            .changed();
+        ui.add_enabled_ui(limiter.enabled, |ui| {
+            ui.horizontal(|ui| {
+                changed |= ui.add(egui::Slider::new(&mut limiter.attack_ms, 1.0..=20.0).suffix(" ms").text("Attack"))
+                    .on_hover_text("Also the look-ahead, which delays the audio by this much")
+                    .changed();
+                changed |= ui.add(egui::Slider::new(&mut limiter.release_ms, 50.0..=1000.0).suffix(" ms").text("Release"))
+                    .on_hover_text("Shorter recovers faster but can pump")
+                    .changed();
+            });
+        });
+        if changed {
+            let limiter = &self.equalizer.limiter;
+            self.config.limiter_enabled = limiter.enabled;
+            self.config.limiter_attack_ms = limiter.attack_ms;
+            self.config.limiter_release_ms = limiter.release_ms;
+            self.update_equalizer_settings();
+            self.save_config();
+        }
+    }
+
+    /// Crossfeed toggle and strength, applied live by the audio thread.
+    fn draw_crossfeed_controls(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Headphone Crossfeed");
+        let mut crossfeed = self.player.chain.crossfeed.lock().unwrap().clone();
+        let toggle = ui.checkbox(&mut crossfeed.enabled, "Enable crossfeed")
+            .on_hover_text("Blend a little of each channel into the other to narrow hard-panned mixes");
+        let strength = ui.add_enabled_ui(crossfeed.enabled, |ui| {
+            ui.add(egui::Slider::new(&mut crossfeed.intensity, 0.0..=1.0).text("Strength"))
+                .on_hover_text("Only applies to stereo tracks")
+        }).inner;
+        if toggle.changed() || strength.changed() {
+            self.config.crossfeed_enabled = crossfeed.enabled;
+            self.config.crossfeed_intensity = crossfeed.intensity;
+            *self.player.chain.crossfeed.lock().unwrap() = crossfeed;
         }
+        if toggle.changed() || edit_finished(&strength) {
+            self.save_config();
+        }
+    }
 
-        ctx.set_visuals(if self.show_collections {
-            egui::Visuals::dark()
+    /// Switch between the mini player and the full layout, resizing the window to match.
+    fn set_compact(&mut self, ctx: &egui::Context, compact: bool) {
+        if compact == self.compact {
+            return;
+        }
+        let size = if compact {
+            self.expanded_size = ctx.input(|i| i.viewport().inner_rect).map(|rect| rect.size());
+            COMPACT_WINDOW_SIZE.into()
         } else {
-            egui::Visuals::light()
+            self.expanded_size.take().unwrap_or(FULL_WINDOW_SIZE.into())
+        };
+        ctx.send_viewport_cmd(egui::ViewportCommand::InnerSize(size));
+        self.compact = compact;
+        self.config.compact_mode = compact;
+        self.save_config();
+    }
+
+    /// Album art, title, transport controls and progress of the current track.
+    fn draw_compact_player(&mut self, ctx: &egui::Context) {
+        let theme = self.theme.clone();
+        let current = self.player.current_index.and_then(|idx| self.player.queue.get(idx)).cloned();
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                widgets::album_art(
+                    ui,
+                    &mut self.album_art_cache,
+                    self.track_details_path.as_deref(),
+                    self.album_art.as_deref(),
+                    96.0,
+                    &theme,
+                );
+                ui.vertical(|ui| {
+                    ui.horizontal(|ui| {
+                        let title = current.as_ref().map_or("No track playing", |item| item.display_name.as_str());
+                        ui.add(egui::Label::new(RichText::new(title).strong()).truncate());
+                        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                            if ui.button(egui_phosphor::regular::ARROWS_OUT_SIMPLE)
+                                .on_hover_text("Expand to the full player")
+                                .clicked() {
+                                self.set_compact(ctx, false);
+                            }
+                        });
+                    });
+                    ui.add_enabled_ui(self.player.stream_handle.is_some(), |ui| ui.horizontal(|ui| {
+                        if widgets::prev_button(ui, &theme).on_hover_text("Previous track (P)").clicked() {
+                            self.prev_track();
+                        }
+                        let playing = self.player.sink.is_some() && !self.player.is_paused;
+                        if widgets::play_button(ui, playing, &theme).on_hover_text("Play/Pause (Space)").clicked() {
+                            if playing {
+                                self.pause();
+                            } else if self.player.sink.is_some() {
+                                self.resume();
+                            } else if self.player.current_index.is_some() {
+                                self.play_current();
+                            }
+                        }
+                        if widgets::next_button(ui, &theme).on_hover_text("Next track (N)").clicked() {
+                            self.next_track();
+                        }
+                    }));
+                    if let (Some(_), Some(total)) = (&current, self.total_duration) {
+                        let mut show_remaining = self.show_remaining_time;
+                        if let Some(position) = widgets::progress_bar(
+                            ui,
+                            self.current_position,
+                            total,
+                            (self.loop_a, self.loop_b),
+                            self.current_overview(),
+                            &mut show_remaining,
+                            &theme,
+                        ) {
+                            self.seek_to(position);
+                        }
+                        self.set_show_remaining_time(show_remaining);
+                    }
+                });
+            });
         });
+    }
+
+    /// Tab bar, tabs and collections panel of the regular window.
+    fn draw_full_layout(&mut self, ctx: &egui::Context) {
+        if self.player.stream_handle.is_none() {
+            egui::TopBottomPanel::top("no_device_banner").show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label(RichText::new("⚠ No audio output device found. Playback is disabled until one is connected.")
+                        .color(egui::Color32::YELLOW));
+                });
+            });
+        }
 
         egui::TopBottomPanel::top("tab_bar").show(ctx, |ui| {
             ui.horizontal(|ui| {
@@ -682,9 +5514,34 @@ impl eframe::App for AudioPlayerApp {
                 if ui.selectable_label(self.current_tab == AppTab::Equalizer, "Equalizer").clicked() {
                     self.current_tab = AppTab::Equalizer;
                 }
+                if ui.selectable_label(self.current_tab == AppTab::Visualizer, "Visualizer").clicked() {
+                    self.current_tab = AppTab::Visualizer;
+                }
+                if ui.selectable_label(self.current_tab == AppTab::Settings, "Settings").clicked() {
+                    self.current_tab = AppTab::Settings;
+                }
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    let theme_icon = if self.theme_settings.dark { "☀" } else { "🌙" };
+                    if ui.button(theme_icon).on_hover_text("Toggle light/dark theme").clicked() {
+                        self.theme_settings.dark = !self.theme_settings.dark;
+                        self.apply_theme_settings(ctx);
+                        self.save_theme_settings();
+                    }
+                    if ui.button("Mini player").on_hover_text("Shrink to album art and transport controls").clicked() {
+                        self.set_compact(ctx, true);
+                    }
+                    ui.toggle_value(&mut self.show_collections, "Collections")
+                        .on_hover_text("Show or hide the collections panel");
+                });
             });
         });
 
+        if !self.tasks.is_empty() {
+            egui::TopBottomPanel::bottom("task_bar").show(ctx, |ui| {
+                self.tasks.draw(ui);
+            });
+        }
+
         egui::CentralPanel::default().show(ctx, |ui| {
             match self.current_tab {
                 AppTab::Player => {
@@ -693,110 +5550,436 @@ impl eframe::App for AudioPlayerApp {
                     ui.separator();
                     ui.group(|ui| {
                         ui.horizontal(|ui| {
-                            if ui.button("Open File").clicked() {
-                                if let Some(path) = FileDialog::new().pick_file() {
+                            if ui.button("Open File").clicked() && let Some(path) = FileDialog::new().pick_file() {
+                                if playlists::is_playlist_file(&path) {
+                                    self.add_playlist_file(&path);
+                                } else {
                                     let display_name = clean_title(&path.file_stem().unwrap().to_string_lossy());
                                     self.add_file(MediaItem {
                                         file_path: path,
                                         display_name,
                                         artist: None,
+                                        album: None,
+                                        cue: None,
+                                        album_group: None,
+                                        from_library: false,
                                     });
                                 }
                             }
-                            if ui.button("Open Folder").clicked() {
-                                if let Some(folder) = FileDialog::new().pick_folder() {
-                                    self.add_folder(folder);
-                                }
+                            if ui.button("Open Folder").clicked() && let Some(folder) = FileDialog::new().pick_folder() {
+                                self.add_folder(folder);
                             }
                         });
                         ui.separator();
                         ui.heading(RichText::new("YouTube Playback").size(20.0));
-                        ui.horizontal(|ui| {
-                            ui.label("YouTube URL:");
-                            let response = ui.text_edit_singleline(&mut self.youtube_url);
-                            if ui.button("Add to Collection").clicked() ||
-                               (response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter))) {
-                                self.add_youtube_audio(self.youtube_url.clone());
-                                self.youtube_url.clear();
-                            }
-                        });
+                        ui.add_enabled_ui(self.yt_dlp_available, |ui| {
+                            ui.horizontal(|ui| {
+                                ui.label("YouTube URL:");
+                                let response = ui.text_edit_singleline(&mut self.youtube_url);
+                                if ui.button("Add to Collection").clicked() ||
+                                   (response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter))) {
+                                    self.add_youtube_audio(self.youtube_url.clone());
+                                    self.youtube_url.clear();
+                                }
+                                if ui.button("Stream")
+                                    .on_hover_text("Play right away without saving; Add to Collection keeps a copy")
+                                    .clicked() {
+                                    self.start_stream(self.youtube_url.clone());
+                                    self.youtube_url.clear();
+                                }
+                            });
+                        }).response.on_disabled_hover_text(YT_DLP_MISSING);
                         if !self.download_status.is_empty() {
                             ui.label(&self.download_status);
                         }
+                        if self.downloads.has_finished() {
+                            egui::CollapsingHeader::new(format!("Downloads: {}", self.downloads.summary()))
+                                .id_salt("downloads")
+                                .default_open(true)
+                                .show(ui, |ui| self.downloads.draw(ui));
+                        }
                     });
                     ui.add_space(10.0);
                     ui.group(|ui| {
                         ui.heading(RichText::new("Now Playing").underline());
-                        if let Some(idx) = self.current_index {
-                            if let Some(item) = self.queue.get(idx) {
-                                ui.label(format!("{}", item.display_name));
-                                let mut progress = self.current_position;
-                                if ui.add(egui::Slider::new(&mut progress, 0.0..=self.total_duration)
-                                    .text(format!("{:.0} / {:.0} sec", self.current_position, self.total_duration))).changed() {
-                                    self.seek_to(progress);
+                        if let Some(idx) = self.player.current_index {
+                            if let Some(item) = self.player.queue.get(idx).cloned() {
+                                ui.horizontal(|ui| {
+                                    widgets::album_art(
+                                        ui,
+                                        &mut self.album_art_cache,
+                                        self.track_details_path.as_deref(),
+                                        self.album_art.as_deref(),
+                                        200.0,
+                                        &self.theme,
+                                    );
+                                    ui.vertical(|ui| {
+                                        ui.horizontal(|ui| {
+                                            ui.label(RichText::new(&item.display_name).size(20.0));
+                                            let favorite = self.favorites.contains(&item.file_path);
+                                            if widgets::favorite_button(ui, favorite, &self.theme) {
+                                                self.toggle_favorite(&item.file_path);
+                                            }
+                                            if ui.small_button("Show in Queue")
+                                                .on_hover_text("Scroll the queue to this track")
+                                                .clicked() {
+                                                self.queue_jump_requested = true;
+                                            }
+                                        });
+                                        if let Some(artist) = &item.artist {
+                                            ui.label(artist);
+                                        }
+                                        if let Some(album) = &item.album {
+                                            ui.label(RichText::new(album).italics());
+                                        }
+                                        if let Some(info) = &self.track_info {
+                                            ui.label(RichText::new(info.to_string()).small().weak());
+                                        }
+                                        if let Some(error) = self.failed_tracks.get(&item.file_path) {
+                                            ui.colored_label(ui.visuals().error_fg_color, format!("⚠ Can't play this file: {}", error));
+                                        }
+                                    });
+                                });
+                                if let Some(total) = self.total_duration {
+                                    ui.add_space(12.0); // room for the loop marker labels
+                                    let mut show_remaining = self.show_remaining_time;
+                                    if let Some(position) = widgets::progress_bar(
+                                        ui,
+                                        self.current_position,
+                                        total,
+                                        (self.loop_a, self.loop_b),
+                                        self.current_overview(),
+                                        &mut show_remaining,
+                                        &self.theme,
+                                    ) {
+                                        self.seek_to(position);
+                                    }
+                                    self.set_show_remaining_time(show_remaining);
+                                    ui.add_space(24.0); // time labels are drawn below the bar
+                                } else {
+                                    // Unknown length: show elapsed time only, no seek handle
+                                    ui.label(format!("{:.0} sec", self.current_position));
+                                }
+                                self.draw_ab_loop_controls(ui);
+                                self.track_trim_slider(ui, &item.file_path);
+                                let (rect, _) = ui.allocate_exact_size(
+                                    egui::vec2(ui.available_width(), 80.0),
+                                    egui::Sense::hover(),
+                                );
+                                let mut visualizer = self.visualizer.lock().unwrap();
+                                visualizer.analyze();
+                                visualizer.draw_spectrum(ui, rect, &self.theme);
+                            }
+                        } else if let Some(stream) = &self.web_stream {
+                            ui.label(RichText::new(&stream.title).size(20.0));
+                            let state = if stream.stalled { "Streaming (buffering)" } else { "Streaming" };
+                            ui.label(RichText::new(state).italics());
+                            match self.total_duration {
+                                // Seeking reopens the stream at the chosen time
+                                Some(total) => {
+                                    ui.add_space(12.0);
+                                    let mut show_remaining = self.show_remaining_time;
+                                    if let Some(position) = widgets::progress_bar(
+                                        ui,
+                                        self.current_position,
+                                        total,
+                                        (None, None),
+                                        None,
+                                        &mut show_remaining,
+                                        &self.theme,
+                                    ) {
+                                        self.seek_to(position);
+                                    }
+                                    self.set_show_remaining_time(show_remaining);
+                                    ui.add_space(24.0);
+                                }
+                                None => {
+                                    ui.label(format!("{:.0} sec", self.current_position));
                                 }
                             }
                         } else {
                             ui.label("No track playing.");
                         }
-                        ui.horizontal(|ui| {
-                            if ui.button("Prev").clicked() {
+                        let has_device = self.player.stream_handle.is_some();
+                        ui.add_enabled_ui(has_device, |ui| ui.horizontal(|ui| {
+                            if widgets::prev_button(ui, &self.theme).on_hover_text("Previous track (P)").clicked() {
                                 self.prev_track();
                             }
-                            if self.is_paused {
-                                if ui.button("Resume").clicked() {
-                                    self.resume();
-                                }
-                            } else {
-                                if ui.button("Pause").clicked() {
+                            let playing = self.player.sink.is_some() && !self.player.is_paused;
+                            if widgets::play_button(ui, playing, &self.theme).on_hover_text("Play/Pause (Space)").clicked() {
+                                if playing {
                                     self.pause();
+                                } else if self.player.sink.is_some() {
+                                    self.resume();
+                                } else if self.player.current_index.is_some() {
+                                    self.play_current();
                                 }
                             }
-                            if ui.button("Next").clicked() {
+                            if widgets::next_button(ui, &self.theme).on_hover_text("Next track (N)").clicked() {
                                 self.next_track();
                             }
-                        });
+                            if widgets::shuffle_button(ui, self.player.shuffle, &self.theme)
+                                .on_hover_text("Play tracks in random order (S)")
+                                .clicked() {
+                                self.player.toggle_shuffle();
+                            }
+                            if ui.button("Stop").clicked() {
+                                self.stop();
+                            }
+                            if ui.button(self.player.repeat_mode.label())
+                                .on_hover_text("Cycle repeat mode")
+                                .clicked() {
+                                self.player.repeat_mode = self.player.repeat_mode.next();
+                            }
+                            let mute_label = if self.is_muted { "Unmute" } else { "Mute" };
+                            if ui.selectable_label(self.is_muted, mute_label).on_hover_text("M").clicked() {
+                                self.toggle_mute();
+                            }
+                        })).response.on_disabled_hover_text("No audio output device");
                         ui.horizontal(|ui| {
-                            ui.label("Volume:");
-                            let volume_slider = ui.add(egui::Slider::new(&mut self.volume, 0.0..=1.0));
-                            if volume_slider.changed() {
-                                self.set_volume(self.volume);
+                            // The slider paints its speaker icon in front of itself
+                            ui.add_space(30.0);
+                            if widgets::volume_slider(ui, &mut self.volume, self.is_muted, &self.theme) {
+                                self.change_volume(self.volume);
                             }
+                            ui.label(format!("{:.0}%", self.volume * 100.0));
                         });
                         ui.horizontal(|ui| {
-                            if ui.checkbox(&mut self.shuffle, "Shuffle")
-                                .on_hover_text("Play tracks in random order")
+                            ui.label("Speed:");
+                            let mut changed = ui.add(egui::Slider::new(&mut self.speed, 0.5..=2.0)
+                                .step_by(0.05)
+                                .suffix("x"))
+                                .changed();
+                            if ui.add_enabled(self.speed != 1.0, egui::Button::new("1x")).clicked() {
+                                self.speed = 1.0;
+                                changed = true;
+                            }
+                            if ui.checkbox(&mut self.preserve_pitch, "Preserve pitch")
+                                .on_hover_text("Time-stretch instead of resampling so voices keep their pitch")
                                 .changed() {
-                                // Optionally handle shuffle changes.
+                                self.config.preserve_pitch = self.preserve_pitch;
+                                self.save_config();
+                                changed = true;
+                            }
+                            if changed {
+                                self.apply_speed();
                             }
                         });
+                        self.draw_sleep_timer(ui);
                     });
                     ui.add_space(10.0);
                     ui.group(|ui| {
-                        ui.heading(RichText::new("Queue").underline());
-                        egui::ScrollArea::vertical().max_height(150.0).show(ui, |ui| {
-                            for i in 0..self.queue.len() {
-                                let item = self.queue[i].clone();
-                                ui.horizontal(|ui| {
-                                    let is_current = Some(i) == self.current_index;
-                                    let text = if is_current {
-                                        RichText::new(format!("> {}", item.display_name)).strong()
-                                    } else {
-                                        RichText::new(format!("  {}", item.display_name))
-                                    };
-                                    ui.label(text);
-                                    if ui.interact(ui.min_rect(), egui::Id::new(format!("track_{}", i)), egui::Sense::click()).clicked() {
-                                        self.current_index = Some(i);
-                                        self.play_current();
+                        ui.horizontal(|ui| {
+                            ui.heading(RichText::new("Queue").underline());
+                            if ui.checkbox(&mut self.queue_auto_follow, "Auto-follow")
+                                .on_hover_text("Scroll to each new track as it starts playing")
+                                .changed() {
+                                self.config.queue_auto_follow = self.queue_auto_follow;
+                                self.save_config();
+                            }
+                            if ui.add_enabled(!self.player.queue.is_empty(), egui::Button::new("Clear Queue")).clicked() {
+                                self.clear_queue();
+                            }
+                            ui.add_enabled_ui(self.player.queue.len() > 1, |ui| {
+                                ui.menu_button("Sort", |ui| {
+                                    for order in SortOrder::ALL {
+                                        if ui.button(order.label()).clicked() {
+                                            self.sort_queue(order);
+                                            ui.close_menu();
+                                        }
                                     }
                                 });
+                            });
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("Filter:");
+                            ui.text_edit_singleline(&mut self.queue_filter)
+                                .on_hover_text("Show only tracks whose title or artist contains this");
+                            if ui.add_enabled(!self.queue_filter.is_empty(), egui::Button::new("Clear")).clicked() {
+                                self.queue_filter.clear();
+                            }
+                        });
+                        // Selected indices would point at other tracks once the queue changed
+                        let fingerprint = self.player.queue_fingerprint();
+                        if fingerprint != self.queue_selection_fingerprint {
+                            self.queue_selection.clear();
+                            self.queue_selection_anchor = None;
+                            self.queue_selection_fingerprint = fingerprint;
+                        }
+                        if !self.queue_selection.is_empty() {
+                            ui.horizontal(|ui| {
+                                ui.label(format!("{} selected", self.queue_selection.len()));
+                                let mut indices: Vec<usize> = self.queue_selection.iter().copied().collect();
+                                indices.sort_unstable();
+                                let selected: Vec<MediaItem> = indices.iter().map(|&i| self.player.queue[i].clone()).collect();
+                                if ui.button("Remove").clicked() {
+                                    self.remove_selected_from_queue();
+                                }
+                                self.add_to_playlist_menu(ui, &selected);
+                                if ui.button("Select None").clicked() {
+                                    self.queue_selection.clear();
+                                }
+                            });
+                        }
+                        let jump = std::mem::take(&mut self.queue_jump_requested);
+                        let mut filter = self.queue_filter.trim().to_lowercase();
+                        // Jumping to a track the filter hides clears the filter
+                        if jump && self.player.current().is_some_and(|item| !item.label().to_lowercase().contains(&filter)) {
+                            self.queue_filter.clear();
+                            filter.clear();
+                        }
+                        let user_scrolling = self.queue_user_scrolled_at
+                            .is_some_and(|t| t.elapsed() < QUEUE_FOLLOW_PAUSE);
+                        let follow = jump || (self.queue_auto_follow
+                            && !user_scrolling
+                            && self.queue_followed_index != self.player.current_index);
+                        // Row edits are applied after the loop so indices stay valid while drawing
+                        let mut remove = None;
+                        let mut swap = None;
+                        // Where a dragged row would land, as an index to insert before
+                        let mut drop_before = None;
+                        let scroll_output = egui::ScrollArea::vertical().max_height(150.0).show(ui, |ui| {
+                            let queue_len = self.player.queue.len();
+                            let rows: Vec<usize> = (0..queue_len)
+                                .filter(|&i| filter.is_empty() || self.player.queue[i].label().to_lowercase().contains(&filter))
+                                .collect();
+                            let (_, dropped) = ui.dnd_drop_zone::<usize, ()>(egui::Frame::default(), |ui| {
+                                for &i in &rows {
+                                    let item = self.player.queue[i].clone();
+                                    if item.from_library && !(i > 0 && self.player.queue[i - 1].from_library) {
+                                        ui.vertical_centered(|ui| {
+                                            ui.label(RichText::new("— from library —").italics().color(self.theme.dim_text_color));
+                                        });
+                                    }
+                                    let background = ui.painter().add(egui::Shape::Noop);
+                                    let row = ui.horizontal(|ui| {
+                                        // Neighbours may be hidden while filtering, so reordering waits
+                                        if filter.is_empty() {
+                                            ui.dnd_drag_source(egui::Id::new(("queue_drag", i)), i, |ui| {
+                                                ui.label(RichText::new("☰").color(self.theme.dim_text_color));
+                                            })
+                                            .response
+                                            .on_hover_cursor(egui::CursorIcon::Grab)
+                                            .on_hover_text("Drag to reorder");
+                                        }
+                                        let is_current = Some(i) == self.player.current_index;
+                                        let failed = self.failed_tracks.get(&item.file_path);
+                                        let mut text = match (is_current, failed) {
+                                            (_, Some(_)) => RichText::new(format!("⚠ {}", item.label())),
+                                            (true, None) => RichText::new(format!("> {}", item.label())).strong(),
+                                            (false, None) => RichText::new(format!("  {}", item.label())),
+                                        };
+                                        if failed.is_some() {
+                                            text = text.color(ui.visuals().error_fg_color);
+                                        }
+                                        // The label alone, so grabbing the handle doesn't also play the track
+                                        let mut label = ui.label(text);
+                                        if let Some(error) = failed {
+                                            label = label.on_hover_text(error);
+                                        }
+                                        let response = ui.interact(label.rect, egui::Id::new(format!("track_{}", i)), egui::Sense::click());
+                                        if response.clicked() {
+                                            let modifiers = ui.input(|i| i.modifiers);
+                                            let selecting = select_row(
+                                                &mut self.queue_selection,
+                                                &mut self.queue_selection_anchor,
+                                                &rows,
+                                                &i,
+                                                modifiers,
+                                            );
+                                            if !selecting {
+                                                self.player.play_index(i);
+                                                self.play_current();
+                                            }
+                                        }
+                                        response.context_menu(|ui| {
+                                            self.track_trim_slider(ui, &item.file_path);
+                                            if ui.button("Edit Tags…").clicked() {
+                                                self.open_tag_editor(&item);
+                                                ui.close_menu();
+                                            }
+                                        });
+                                        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                                            if ui.small_button("✕").on_hover_text("Remove from queue").clicked() {
+                                                remove = Some(i);
+                                            }
+                                            if ui.add_enabled(filter.is_empty() && i + 1 < queue_len, egui::Button::new("⏷").small())
+                                                .on_hover_text("Move down")
+                                                .clicked() {
+                                                swap = Some((i, i + 1));
+                                            }
+                                            if ui.add_enabled(filter.is_empty() && i > 0, egui::Button::new("⏶").small())
+                                                .on_hover_text("Move up")
+                                                .clicked() {
+                                                swap = Some((i, i - 1));
+                                            }
+                                        });
+                                    });
+                                    if self.queue_selection.contains(&i) {
+                                        let fill = egui::Shape::rect_filled(row.response.rect, 2.0, ui.visuals().selection.bg_fill);
+                                        ui.painter().set(background, fill);
+                                    }
+                                    if follow && Some(i) == self.player.current_index {
+                                        row.response.scroll_to_me(Some(egui::Align::Center));
+                                    }
+                                    if let (Some(_), Some(pointer)) = (row.response.dnd_hover_payload::<usize>(), ui.ctx().pointer_interact_pos()) {
+                                        let rect = row.response.rect;
+                                        let (before, y) = if pointer.y < rect.center().y {
+                                            (i, rect.top())
+                                        } else {
+                                            (i + 1, rect.bottom())
+                                        };
+                                        drop_before = Some(before);
+                                        ui.painter().hline(rect.x_range(), y, egui::Stroke::new(2.0, self.theme.accent_color));
+                                    }
+                                }
+                            });
+                            if let (Some(from), Some(to)) = (dropped, drop_before) {
+                                self.player.move_track(*from, to);
+                                self.queue_selection.clear();
+                            }
+                            if rows.is_empty() && !filter.is_empty() {
+                                ui.label(RichText::new(format!("No queued tracks match \"{}\"", self.queue_filter.trim()))
+                                    .italics()
+                                    .color(self.theme.dim_text_color));
                             }
                         });
+                        // Selected indices would point at other tracks after a reorder
+                        if let Some((a, b)) = swap {
+                            self.player.swap(a, b);
+                            self.queue_selection.clear();
+                        }
+                        if let Some(index) = remove {
+                            self.remove_from_queue(index);
+                            self.queue_selection.clear();
+                        }
+                        if follow {
+                            self.queue_followed_index = self.player.current_index;
+                        }
+                        // Back off while the user is scrolling the queue themselves
+                        if ui.rect_contains_pointer(scroll_output.inner_rect)
+                            && ui.input(|i| i.raw_scroll_delta.y != 0.0) {
+                            self.queue_user_scrolled_at = Some(Instant::now());
+                        }
+                    });
+                    ui.add_space(10.0);
+                    ui.group(|ui| {
+                        self.draw_playlists(ui);
+                    });
+                    ui.add_space(10.0);
+                    ui.group(|ui| {
+                        self.draw_history(ui);
                     });
                 }
                 AppTab::Equalizer => {
                     self.draw_equalizer_tab(ui);
                 }
+                AppTab::Visualizer => {
+                    self.draw_visualizer_tab(ui);
+                }
+                AppTab::Settings => {
+                    self.draw_settings_tab(ui);
+                }
             }
         });
 
@@ -811,37 +5994,129 @@ impl eframe::App for AudioPlayerApp {
                     });
                     ui.horizontal(|ui| {
                         ui.label("Search:");
-                        ui.text_edit_singleline(&mut self.collections_search);
+                        let search = ui.text_edit_singleline(&mut self.collections_search);
+                        if search.changed() {
+                            self.config.collections_search = self.collections_search.clone();
+                        }
+                        // Written once typing is done rather than on every keystroke
+                        if search.lost_focus() {
+                            self.save_config();
+                        }
                         if ui.button("Clear").clicked() {
                             self.collections_search.clear();
                             self.show_youtube_input = false;
+                            self.save_collections_search();
+                        }
+                        if ui.button("Refresh").on_hover_text("Re-scan the collections folder").clicked() {
+                            self.refresh_collections();
+                        }
+                        if ui.button("Import…").on_hover_text("Copy local files into the collection without playing them").clicked() && let Some(paths) = FileDialog::new().add_filter("Audio", &AUDIO_EXTENSIONS).pick_files() {
+                            self.import_to_collection(paths);
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.selectable_value(&mut self.collections_view, CollectionsView::List, "List");
+                        ui.selectable_value(&mut self.collections_view, CollectionsView::Albums, "Albums");
+                        ui.selectable_value(&mut self.collections_view, CollectionsView::ByAlbum, "By Album");
+                        ui.selectable_value(&mut self.collections_view, CollectionsView::ByArtist, "By Artist");
+                        ui.separator();
+                        let previous_action = self.collection_click_action;
+                        egui::ComboBox::from_label("on click")
+                            .selected_text(self.collection_click_action.label())
+                            .show_ui(ui, |ui| {
+                                for action in CollectionClickAction::ALL {
+                                    ui.selectable_value(&mut self.collection_click_action, action, action.label());
+                                }
+                            });
+                        if self.collection_click_action != previous_action {
+                            self.config.collection_click = self.collection_click_action;
+                            self.save_config();
+                        }
+                        let previous_sort = self.collections_sort;
+                        egui::ComboBox::from_label("sort")
+                            .selected_text(self.collections_sort.label())
+                            .show_ui(ui, |ui| {
+                                for order in SortOrder::ALL {
+                                    ui.selectable_value(&mut self.collections_sort, order, order.label());
+                                }
+                            });
+                        if self.collections_sort != previous_sort {
+                            self.collections_order = None;
+                            self.collection_groups = None;
+                            self.config.collections_sort = self.collections_sort;
+                            self.save_config();
+                        }
+                        if ui.checkbox(&mut self.favorites_only, "Show favorites only").changed() {
+                            self.config.favorites_only = self.favorites_only;
+                            self.save_config();
                         }
                     });
+                    if !self.collections_status.is_empty() {
+                        ui.label(&self.collections_status);
+                    }
+                    if !self.collection_selection.is_empty() {
+                        ui.horizontal(|ui| {
+                            ui.label(format!("{} selected", self.collection_selection.len()));
+                            let selected = self.selected_collection_items();
+                            if ui.button("Add to Queue").clicked() {
+                                for item in selected.iter().cloned() {
+                                    self.add_file(item);
+                                }
+                                self.collection_selection.clear();
+                            }
+                            self.add_to_playlist_menu(ui, &selected);
+                            if ui.button("Delete…").clicked() {
+                                let mut paths: Vec<PathBuf> = self.collection_selection.iter().cloned().collect();
+                                paths.sort();
+                                self.pending_delete = paths;
+                            }
+                            if ui.button("Select None").clicked() {
+                                self.collection_selection.clear();
+                            }
+                        });
+                    }
                     ui.separator();
-                    let items = self.load_collections();
+                    self.refresh_collections_if_changed();
+                    self.collection_rows_shown = std::mem::take(&mut self.collection_rows);
+                    // View-only: the folder and the cached list keep their order
+                    self.sort_collections_if_needed();
+                    let items: Vec<MediaItem> = self.collections_order.iter().flatten()
+                        .map(|&i| &self.collections[i])
+                        .filter(|item| !self.favorites_only || self.favorites.contains(&item.file_path))
+                        .cloned()
+                        .collect();
+                    // Matched label positions per search result, for highlighting
+                    let mut highlights: HashMap<PathBuf, Vec<usize>> = HashMap::new();
                     let filtered_items: Vec<&MediaItem> = if self.collections_search.is_empty() {
                         items.iter().collect()
                     } else {
-                        let search_term = self.collections_search.to_lowercase();
-                        items.iter()
-                            .filter(|item| item.display_name.to_lowercase().contains(&search_term))
-                            .collect()
+                        // Best matches first, so typos and out-of-order words still rank
+                        let matcher = SkimMatcherV2::default().ignore_case();
+                        let mut scored: Vec<(i64, &MediaItem)> = items.iter()
+                            .filter_map(|item| {
+                                let (score, indices) = matcher.fuzzy_indices(&item.label(), &self.collections_search)?;
+                                if score < FUZZY_MIN_SCORE {
+                                    return None;
+                                }
+                                highlights.insert(item.file_path.clone(), indices);
+                                Some((score, item))
+                            })
+                            .collect();
+                        scored.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+                        scored.into_iter().map(|(_, item)| item).collect()
                     };
                     egui::ScrollArea::vertical().show(ui, |ui| {
                         ui.spacing_mut().item_spacing.y = 6.0;
-                        for item in filtered_items.iter() {
-                            ui.horizontal(|ui| {
-                                if ui.label(RichText::new(&item.display_name).strong())
-                                    .on_hover_text("Click to play now")
-                                    .clicked() {
-                                    self.queue.insert(0, (*item).clone());
-                                    self.current_index = Some(0);
-                                    self.play_current();
-                                }
-                                if ui.button("Add to Queue").clicked() {
-                                    self.add_file((*item).clone());
+                        match self.collections_view {
+                            CollectionsView::Albums => self.draw_album_grid(ui, &filtered_items),
+                            CollectionsView::ByAlbum => self.draw_collection_groups(ui, &filtered_items, &highlights, false),
+                            CollectionsView::ByArtist => self.draw_collection_groups(ui, &filtered_items, &highlights, true),
+                            CollectionsView::List => {
+                                for item in filtered_items.iter() {
+                                    let matched = highlights.get(&item.file_path).map_or(&[][..], Vec::as_slice);
+                                    self.collection_item_row(ui, item, matched);
                                 }
-                            });
+                            }
                         }
                         if filtered_items.is_empty() && !self.collections_search.is_empty() {
                             ui.add_space(10.0);
@@ -849,7 +6124,9 @@ impl eframe::App for AudioPlayerApp {
                                 ui.label(RichText::new(format!("\"{}\" not found", self.collections_search))
                                     .color(egui::Color32::GRAY));
                                 if !self.show_youtube_input {
-                                    if ui.button("Add from YouTube").clicked() {
+                                    if ui.add_enabled(self.yt_dlp_available, egui::Button::new("Add from YouTube"))
+                                        .on_disabled_hover_text(YT_DLP_MISSING)
+                                        .clicked() {
                                         self.show_youtube_input = true;
                                         self.youtube_search_url = String::new();
                                     }
@@ -858,13 +6135,12 @@ impl eframe::App for AudioPlayerApp {
                                     ui.label("Enter YouTube URL:");
                                     let response = ui.text_edit_singleline(&mut self.youtube_search_url);
                                     ui.horizontal(|ui| {
-                                        if ui.button("Add").clicked() ||
-                                           (response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter))) {
-                                            if !self.youtube_search_url.is_empty() {
-                                                self.add_youtube_audio(self.youtube_search_url.clone());
-                                                self.youtube_search_url.clear();
-                                                self.show_youtube_input = false;
-                                            }
+                                        if (ui.button("Add").clicked() ||
+                                            (response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter))))
+                                            && !self.youtube_search_url.is_empty() {
+                                            self.add_youtube_audio(self.youtube_search_url.clone());
+                                            self.youtube_search_url.clear();
+                                            self.show_youtube_input = false;
                                         }
                                         if ui.button("Cancel").clicked() {
                                             self.show_youtube_input = false;
@@ -872,6 +6148,13 @@ impl eframe::App for AudioPlayerApp {
                                     });
                                 }
                             });
+                        } else if items.is_empty() && self.favorites_only && !self.collections.is_empty() {
+                            ui.vertical_centered(|ui| {
+                                ui.add_space(20.0);
+                                ui.label(RichText::new("No favorites yet — click a track's star to add it")
+                                    .color(egui::Color32::GRAY)
+                                    .italics());
+                            });
                         } else if items.is_empty() {
                             ui.vertical_centered(|ui| {
                                 ui.add_space(20.0);
@@ -887,26 +6170,237 @@ impl eframe::App for AudioPlayerApp {
                     });
                 });
         }
+    }
+}
+
+impl eframe::App for AudioPlayerApp {
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        if let Err(e) = session::save(&self.collections_path, &self.session()) {
+            eprintln!("Failed to save session: {}", e);
+        }
+        self.save_config();
+        self.save_track_gains();
+        if self.theme_unsaved {
+            self.save_theme_settings();
+        }
+    }
+
+    fn update(&mut self, ctx: &eframe::egui::Context, _frame: &mut eframe::Frame) {
+        self.check_crossfade();
+        self.update_crossfade();
+        self.update_volume_ramp();
+        self.check_sleep_timer();
+        self.prebuffer_next();
+        self.check_track_finished();
+        if std::mem::take(&mut self.skip_failed_track) {
+            self.next_track();
+        }
+        self.process_youtube_result();
+        self.process_delete_retries();
+        self.process_collections_move();
+        self.process_imports();
+        self.process_key_commands(ctx);
+        self.handle_shortcuts(ctx);
+        #[cfg(target_os = "linux")]
+        self.process_media_commands();
+        self.process_duration_results();
+        self.process_tag_results();
+        self.request_track_details();
+        self.process_track_details();
+        self.process_loudness_results();
+        self.request_missing_loudness();
+        self.process_waveform_results();
+        self.request_waveform_overview();
+        self.process_stream_events();
+        self.check_stream_buffer();
+        self.check_output_device();
+        self.handle_dropped_files(ctx);
+        self.tasks.poll();
+        self.downloads.poll(&mut self.tasks);
+
+        if let Some(position) = self.player.position() {
+            self.current_position = position;
+            if let Some(total) = self.total_duration {
+                self.current_position = self.current_position.min(total);
+            }
+        }
+        self.check_ab_loop();
+        self.update_scrobbling(ctx.input(|i| i.unstable_dt));
+        self.process_scrobbler_events();
+
+        if self.compact {
+            self.draw_compact_player(ctx);
+        } else {
+            self.draw_full_layout(ctx);
+        }
 
-        ctx.request_repaint();
+        #[cfg(target_os = "linux")]
+        self.publish_media_state();
+        #[cfg(any(target_os = "windows", target_os = "linux"))]
+        self.update_tray_tooltip();
+        #[cfg(any(target_os = "windows", target_os = "linux"))]
+        self.update_notifications(ctx);
+        self.draw_delete_confirmation(ctx);
+        self.draw_tag_editor(ctx);
+        self.draw_toast(ctx);
+        self.draw_drop_overlay(ctx);
+        // The progress bar and visualizer only move while audio is playing or fading
+        let animating = (self.player.sink.is_some() && !self.player.is_paused)
+            || self.fading_out.is_some()
+            || self.volume_ramp.is_some();
+        if animating {
+            ctx.request_repaint();
+        } else {
+            ctx.request_repaint_after(IDLE_REPAINT_INTERVAL);
+        }
     }
 }
 
 fn main() {
+    let launch_args = parse_args(std::env::args().skip(1));
+    let working_dir = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+    let config_dir = config::config_dir();
+    // Older versions kept config.toml and theme.json in the working directory
+    let config_path = config::user_file(&config_dir, &working_dir, config::CONFIG_FILE);
+    let theme_path = config::user_file(&config_dir, &working_dir, theme::THEME_FILE);
+    let legacy_path = working_dir.join(settings::SETTINGS_FILE);
+    // Older versions kept untyped settings in audio_player.conf; carry them over once
+    let legacy = (!config_path.exists() && legacy_path.exists()).then(|| settings::Settings::load(legacy_path));
+    let config = match &legacy {
+        Some(legacy) => {
+            let config = config::Config::from_legacy(legacy);
+            if let Err(e) = config.save(&config_path) {
+                eprintln!("Failed to save config: {}", e);
+            }
+            config
+        }
+        None => config::Config::load(&config_path),
+    };
+    if launch_args.no_gui {
+        if let Err(e) = headless::run(launch_args, &config) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+    // Reopen in the layout that was last used
+    let window_size = if config.compact_mode {
+        COMPACT_WINDOW_SIZE
+    } else {
+        FULL_WINDOW_SIZE
+    };
     let options = eframe::NativeOptions {
-        viewport: ViewportBuilder::default().with_inner_size([1200.0, 600.0]),
+        viewport: ViewportBuilder::default().with_inner_size(window_size),
         ..Default::default()
     };
+    let mut theme_settings = theme::ThemeSettings::load(&theme_path);
+    // Older versions kept only a dark_mode setting; carry it over into theme.json
+    if !theme_path.exists() && let Some(dark_mode) = legacy.as_ref().and_then(|legacy| legacy.get("dark_mode")) {
+        theme_settings.dark = dark_mode != "false";
+        if let Err(e) = theme_settings.save(&theme_path) {
+            eprintln!("Failed to save theme: {}", e);
+        }
+    }
     
-    eframe::run_native(
+    if let Err(e) = eframe::run_native(
         "Rust Audio Player",
         options,
         Box::new(|cc| {
-            // Create the theme and get the context from CreationContext
-            let app_theme = theme::Theme::dark();
-            app_theme.apply_to_ctx(&cc.egui_ctx); // Use cc.egui_ctx instead of ctx
-            
-            Ok(Box::new(AudioPlayerApp::new()))
+            let mut app = AudioPlayerApp::new(config, theme_settings, &cc.egui_ctx);
+            app.apply_launch_args(launch_args);
+            Ok(Box::new(app))
         }),
-    );
+    ) {
+        eprintln!("Failed to start the player: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    const BANDS: usize = 31;
+
+    /// Band whose center is nearest 1 kHz, and that center.
+    fn band_near_1khz() -> (usize, f32) {
+        eq_center_frequencies(BANDS)
+            .into_iter()
+            .enumerate()
+            .min_by(|a, b| (a.1 - 1000.0).abs().total_cmp(&(b.1 - 1000.0).abs()))
+            .unwrap()
+    }
+
+    /// Level in dB of one second of a sine at `frequency`, recorded at
+    /// `file_rate`, after the EQ with `band` set to `gain_db`.
+    fn level_after_eq(file_rate: u32, frequency: f32, band: usize, gain_db: f32) -> f32 {
+        let sine: Vec<f32> = (0..file_rate)
+            .map(|n| 0.05 * (std::f32::consts::TAU * frequency * n as f32 / file_rate as f32).sin())
+            .collect();
+        let mut settings = EqualizerSettings::new();
+        settings.bands = vec![0.0; BANDS];
+        settings.bands[band] = gain_db;
+        settings.limiter.enabled = false;
+        let source = EqualizedSource::new(
+            SamplesBuffer::new(1, file_rate, sine),
+            Arc::new(Mutex::new(settings)),
+            Arc::new(AtomicUsize::new(0)),
+        );
+        assert_eq!(source.sample_rate(), file_rate);
+        let output: Vec<f32> = source.collect();
+        // Skip the first half while the filters settle
+        let settled = &output[output.len() / 2..];
+        let rms = (settled.iter().map(|s| s * s).sum::<f32>() / settled.len() as f32).sqrt();
+        20.0 * rms.log10()
+    }
+
+    #[test]
+    fn eq_boost_lands_on_its_band_at_any_file_rate() {
+        let (band, center) = band_near_1khz();
+        for file_rate in [44100, 48000] {
+            let boost = level_after_eq(file_rate, center, band, 12.0) - level_after_eq(file_rate, center, band, 0.0);
+            assert!((boost - 12.0).abs() < 0.25, "{} Hz file: {:.2} dB boost", file_rate, boost);
+        }
+    }
+
+    #[test]
+    fn eq_boost_leaves_distant_frequencies_alone() {
+        let (band, _) = band_near_1khz();
+        for file_rate in [44100, 48000] {
+            let boost = level_after_eq(file_rate, 8000.0, band, 12.0) - level_after_eq(file_rate, 8000.0, band, 0.0);
+            assert!(boost.abs() < 0.25, "{} Hz file: {:.2} dB at 8 kHz", file_rate, boost);
+        }
+    }
+
+    /// A sleep timer whose deadline passed and whose fade began `elapsed` ago.
+    fn sleep_timer_fading_for(elapsed: Duration) -> SleepTimer {
+        let started = Instant::now() - elapsed;
+        SleepTimer {
+            deadline: Some(started),
+            fade_started: Some(started),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn finished_sleep_fade_leaves_the_sink_paused() {
+        let (sink, _output) = Sink::new_idle();
+        let mut sleep = sleep_timer_fading_for(SLEEP_FADE * 2);
+        assert!(sleep.fade(&sink, 1.0));
+        assert!(sink.is_paused());
+        // Nothing is left to restore, so clearing the timer can't turn the
+        // silent sink back up
+        assert!(!sleep.cancel());
+        assert!(sleep.deadline.is_none());
+    }
+
+    #[test]
+    fn cancelled_sleep_fade_asks_for_the_volume_back() {
+        let (sink, _output) = Sink::new_idle();
+        let mut sleep = sleep_timer_fading_for(SLEEP_FADE / 2);
+        assert!(!sleep.fade(&sink, 1.0));
+        assert!(!sink.is_paused());
+        assert!(sink.volume() < 0.6);
+        assert!(sleep.cancel());
+    }
 }
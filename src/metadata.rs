@@ -0,0 +1,198 @@
+use std::fmt;
+use std::path::Path;
+
+use lofty::config::{ParseOptions, WriteOptions};
+use lofty::error::LoftyError;
+use lofty::file::FileType;
+use lofty::mp4::{Mp4Codec, Mp4File};
+use lofty::picture::PictureType;
+use lofty::prelude::*;
+use lofty::tag::Tag;
+
+/// Image files checked next to a track when it has no embedded cover.
+const SIDECAR_ART_EXTENSIONS: [&str; 3] = ["jpg", "png", "webp"];
+
+/// Tag fields read from a track's embedded metadata.
+#[derive(Clone, Default)]
+pub struct TrackTags {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub track_number: Option<u32>,
+    pub duration: Option<f32>, // seconds, from the stream properties
+}
+
+/// Read ID3/Vorbis/MP4 tags and the duration from `path`. Returns `None` if
+/// the file can't be parsed; an untagged file yields only its duration.
+/// This touches the disk, so call it off the UI thread.
+pub fn read_tags(path: &Path) -> Option<TrackTags> {
+    let tagged_file = lofty::read_from_path(path).ok()?;
+    let duration = tagged_file.properties().duration().as_secs_f32();
+    let duration = (duration > 0.0).then_some(duration);
+    let Some(tag) = tagged_file
+        .primary_tag()
+        .or_else(|| tagged_file.first_tag()) else {
+        return Some(TrackTags { duration, ..TrackTags::default() });
+    };
+    Some(TrackTags {
+        title: tag.title().and_then(|v| non_empty(&v)),
+        artist: tag.artist().and_then(|v| non_empty(&v)),
+        album: tag.album().and_then(|v| non_empty(&v)),
+        track_number: tag.track(),
+        duration,
+    })
+}
+
+/// `value` trimmed, or None if that leaves nothing.
+fn non_empty(value: &str) -> Option<String> {
+    let value = value.trim();
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+/// Cover image bytes for `path`: the embedded front cover (or first picture),
+/// else a same-named image beside the file such as yt-dlp's thumbnail.
+/// This touches the disk, so call it off the UI thread.
+pub fn read_cover_art(path: &Path) -> Option<Vec<u8>> {
+    let embedded = lofty::read_from_path(path).ok().and_then(|tagged_file| {
+        let tag = tagged_file
+            .primary_tag()
+            .or_else(|| tagged_file.first_tag())?;
+        let pictures = tag.pictures();
+        pictures
+            .iter()
+            .find(|picture| picture.pic_type() == PictureType::CoverFront)
+            .or_else(|| pictures.first())
+            .map(|picture| picture.data().to_vec())
+    });
+    embedded.or_else(|| {
+        SIDECAR_ART_EXTENSIONS
+            .iter()
+            .find_map(|ext| std::fs::read(path.with_extension(ext)).ok())
+    })
+}
+
+/// Replace the title in the file's primary tag. Files without a tag are left alone.
+pub fn write_title(path: &Path, title: &str) -> Result<(), LoftyError> {
+    let mut tagged_file = lofty::read_from_path(path)?;
+    let Some(tag) = tagged_file.primary_tag_mut() else {
+        return Ok(());
+    };
+    tag.set_title(title.to_string());
+    tag.save_to_path(path, WriteOptions::default())
+}
+
+/// Set the title, artist and album in the file's primary tag, adding a tag
+/// if it has none; blank values remove the field. The file is read back
+/// afterwards, and the tags it now has are returned only if the edit stuck.
+pub fn write_tags(path: &Path, title: &str, artist: &str, album: &str) -> Result<TrackTags, String> {
+    let mut tagged_file = lofty::read_from_path(path).map_err(|e| e.to_string())?;
+    if tagged_file.primary_tag().is_none() {
+        let tag_type = tagged_file.primary_tag_type();
+        tagged_file.insert_tag(Tag::new(tag_type));
+    }
+    let Some(tag) = tagged_file.primary_tag_mut() else {
+        return Err("this file type can't hold tags".to_string());
+    };
+    let wanted = [non_empty(title), non_empty(artist), non_empty(album)];
+    match &wanted[0] {
+        Some(title) => tag.set_title(title.clone()),
+        None => tag.remove_title(),
+    }
+    match &wanted[1] {
+        Some(artist) => tag.set_artist(artist.clone()),
+        None => tag.remove_artist(),
+    }
+    match &wanted[2] {
+        Some(album) => tag.set_album(album.clone()),
+        None => tag.remove_album(),
+    }
+    tag.save_to_path(path, WriteOptions::default()).map_err(|e| e.to_string())?;
+
+    let written = read_tags(path).ok_or("the file can't be read back")?;
+    if [written.title.clone(), written.artist.clone(), written.album.clone()] != wanted {
+        return Err("the file kept its old tags".to_string());
+    }
+    Ok(written)
+}
+
+/// Technical details of a track's audio stream.
+#[derive(Clone)]
+pub struct AudioInfo {
+    pub codec: String,
+    pub sample_rate: Option<u32>,
+    pub bit_depth: Option<u8>,
+    pub channels: Option<u8>,
+    pub bitrate_kbps: Option<u32>,
+}
+
+/// "FLAC · 44.1 kHz · 16-bit · stereo · 981 kbps", leaving out unknown fields.
+impl fmt::Display for AudioInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = vec![self.codec.clone()];
+        if let Some(rate) = self.sample_rate {
+            parts.push(format!("{} kHz", rate as f32 / 1000.0));
+        }
+        if let Some(depth) = self.bit_depth {
+            parts.push(format!("{}-bit", depth));
+        }
+        if let Some(channels) = self.channels {
+            parts.push(match channels {
+                1 => "mono".to_string(),
+                2 => "stereo".to_string(),
+                n => format!("{} channels", n),
+            });
+        }
+        if let Some(kbps) = self.bitrate_kbps {
+            parts.push(format!("{} kbps", kbps));
+        }
+        write!(f, "{}", parts.join(" · "))
+    }
+}
+
+/// Codec inside an MP4 container, which holds ALAC as well as AAC.
+fn mp4_codec(path: &Path) -> Option<String> {
+    let mut file = std::fs::File::open(path).ok()?;
+    let mp4 = Mp4File::read_from(&mut file, ParseOptions::new().read_tags(false)).ok()?;
+    let codec = match mp4.properties().codec() {
+        Mp4Codec::AAC => "AAC",
+        Mp4Codec::ALAC => "ALAC",
+        Mp4Codec::MP3 => "MP3",
+        Mp4Codec::FLAC => "FLAC",
+        _ => return None,
+    };
+    Some(codec.to_string())
+}
+
+/// Codec and stream properties of `path`. When the container doesn't report a
+/// bitrate it is estimated from the file size and duration.
+/// This touches the disk, so call it off the UI thread.
+pub fn read_audio_info(path: &Path) -> Option<AudioInfo> {
+    let tagged_file = lofty::read_from_path(path).ok()?;
+    let properties = tagged_file.properties();
+    let codec = match tagged_file.file_type() {
+        FileType::Mpeg => "MP3".to_string(),
+        FileType::Flac => "FLAC".to_string(),
+        FileType::Mp4 => mp4_codec(path).unwrap_or_else(|| "MP4".to_string()),
+        FileType::Opus => "Opus".to_string(),
+        FileType::Vorbis => "Vorbis".to_string(),
+        FileType::Wav => "WAV".to_string(),
+        FileType::Aiff => "AIFF".to_string(),
+        other => format!("{:?}", other).to_uppercase(),
+    };
+    let bitrate_kbps = properties
+        .audio_bitrate()
+        .or_else(|| properties.overall_bitrate())
+        .filter(|&kbps| kbps > 0)
+        .or_else(|| {
+            let secs = properties.duration().as_secs_f64();
+            let bytes = std::fs::metadata(path).ok()?.len() as f64;
+            (secs > 0.0).then(|| (bytes * 8.0 / secs / 1000.0).round() as u32)
+        });
+    Some(AudioInfo {
+        codec,
+        sample_rate: properties.sample_rate(),
+        bit_depth: properties.bit_depth(),
+        channels: properties.channels(),
+        bitrate_kbps,
+    })
+}
@@ -0,0 +1,126 @@
+use std::path::Path;
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::thread;
+use std::time::Duration;
+
+use mpris_server::{Metadata, PlaybackStatus, Player};
+
+/// D-Bus name suffix; the full bus name is `org.mpris.MediaPlayer2.<BUS_NAME>`.
+const BUS_NAME: &str = "rust_audio_player";
+
+/// How often the server thread picks up state pushed from the UI.
+const UPDATE_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Transport requests from desktop media controls and headset buttons.
+pub enum MediaCommand {
+    PlayPause,
+    Play,
+    Pause,
+    Stop,
+    Next,
+    Previous,
+}
+
+/// What the desktop should show for the player.
+#[derive(Clone, Default, PartialEq)]
+pub struct MediaState {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub art_url: Option<String>,
+    pub playing: bool,
+    pub stopped: bool,
+}
+
+/// `file://` URL of a cover image next to `track`: a same-named image such as a
+/// downloaded thumbnail, or the folder's cover.
+pub fn find_cover_art(track: &Path) -> Option<String> {
+    let dir = track.parent()?;
+    ["jpg", "png"]
+        .iter()
+        .map(|ext| track.with_extension(ext))
+        .chain(["cover.jpg", "cover.png", "folder.jpg", "folder.png"].iter().map(|name| dir.join(name)))
+        .find(|path| path.exists())
+        .and_then(|path| path.canonicalize().ok())
+        .map(|path| format!("file://{}", path.display()))
+}
+
+/// Start the MPRIS server on its own thread. Incoming calls are sent on
+/// `commands`; push state changes through the returned sender.
+pub fn spawn(commands: Sender<MediaCommand>) -> Sender<MediaState> {
+    let (state_tx, state_rx) = channel::<MediaState>();
+    thread::spawn(move || {
+        if let Err(e) = async_io::block_on(serve(commands, state_rx)) {
+            eprintln!("MPRIS server stopped: {}", e);
+        }
+    });
+    state_tx
+}
+
+async fn serve(commands: Sender<MediaCommand>, states: Receiver<MediaState>) -> mpris_server::zbus::Result<()> {
+    let player = Player::builder(BUS_NAME)
+        .identity("Rust Audio Player")
+        .can_play(true)
+        .can_pause(true)
+        .can_go_next(true)
+        .can_go_previous(true)
+        .can_control(true)
+        .build()
+        .await?;
+
+    let connect = |command: fn() -> MediaCommand| {
+        let commands = commands.clone();
+        move |_: &Player| {
+            let _ = commands.send(command());
+        }
+    };
+    player.connect_play_pause(connect(|| MediaCommand::PlayPause));
+    player.connect_play(connect(|| MediaCommand::Play));
+    player.connect_pause(connect(|| MediaCommand::Pause));
+    player.connect_stop(connect(|| MediaCommand::Stop));
+    player.connect_next(connect(|| MediaCommand::Next));
+    player.connect_previous(connect(|| MediaCommand::Previous));
+
+    let updates = async {
+        loop {
+            async_io::Timer::after(UPDATE_INTERVAL).await;
+            // Only the newest state matters
+            let mut latest = None;
+            loop {
+                match states.try_recv() {
+                    Ok(state) => latest = Some(state),
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => return Ok::<(), mpris_server::zbus::Error>(()),
+                }
+            }
+            if let Some(state) = latest {
+                apply_state(&player, state).await?;
+            }
+        }
+    };
+    futures_lite::future::or(async {
+        player.run().await;
+        Ok(())
+    }, updates).await
+}
+
+async fn apply_state(player: &Player, state: MediaState) -> mpris_server::zbus::Result<()> {
+    let mut metadata = Metadata::builder();
+    if let Some(title) = state.title {
+        metadata = metadata.title(title);
+    }
+    if let Some(artist) = state.artist {
+        metadata = metadata.artist([artist]);
+    }
+    if let Some(art_url) = state.art_url {
+        metadata = metadata.art_url(art_url);
+    }
+    player.set_metadata(metadata.build()).await?;
+    let status = if state.stopped {
+        PlaybackStatus::Stopped
+    } else if state.playing {
+        PlaybackStatus::Playing
+    } else {
+        PlaybackStatus::Paused
+    };
+    player.set_playback_status(status).await
+}
@@ -0,0 +1,116 @@
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::Duration;
+
+use eframe::egui;
+use notify_rust::Notification;
+
+use crate::{metadata, KeyCommand};
+
+/// Quiet period after a track change before it is announced, so skipping
+/// through several tracks only shows the one that stays.
+const DEBOUNCE: Duration = Duration::from_millis(800);
+
+/// Side length of the cover thumbnail shown in the notification, in pixels.
+const THUMBNAIL_SIZE: u32 = 128;
+
+/// A track that started playing.
+pub struct NowPlaying {
+    pub title: String,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub path: PathBuf, // to read the cover from
+}
+
+/// Start the notification thread and return the sender tracks are announced
+/// through. Showing a notification can stall on the notification daemon, so
+/// it never happens on the UI thread. Clicking one sends
+/// `KeyCommand::ShowWindow` and wakes `ctx` where the platform supports it.
+pub fn spawn(commands: Sender<KeyCommand>, ctx: egui::Context) -> Sender<NowPlaying> {
+    let (tracks_tx, tracks_rx) = channel::<NowPlaying>();
+    thread::spawn(move || run(tracks_rx, commands, ctx));
+    tracks_tx
+}
+
+#[cfg_attr(target_os = "windows", allow(unused_variables))]
+fn run(tracks: Receiver<NowPlaying>, commands: Sender<KeyCommand>, ctx: egui::Context) {
+    // Replace our last notification rather than stacking a new one per track
+    #[cfg(target_os = "linux")]
+    let mut previous_id = None;
+    // Thread waiting for a click on the notification with this id. Replacing
+    // the notification keeps its id, so one waiter serves every replacement.
+    #[cfg(target_os = "linux")]
+    let mut waiter: Option<(u32, thread::JoinHandle<()>)> = None;
+    while let Ok(mut track) = tracks.recv() {
+        loop {
+            match tracks.recv_timeout(DEBOUNCE) {
+                Ok(newer) => track = newer,
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+        }
+        let mut notification = Notification::new();
+        notification
+            .appname("Rust Audio Player")
+            .summary(&track.title)
+            .body(&body(&track));
+        if let Some(thumbnail) = write_thumbnail(&track.path) {
+            notification.image_path(&thumbnail.to_string_lossy());
+        }
+
+        #[cfg(target_os = "linux")]
+        {
+            notification.action("default", "Show player");
+            if let Some(id) = previous_id {
+                notification.id(id);
+            }
+            match notification.show() {
+                Ok(handle) => {
+                    let id = handle.id();
+                    previous_id = Some(id);
+                    let waiting = waiter.as_ref().is_some_and(|(waiting_for, thread)| *waiting_for == id && !thread.is_finished());
+                    if !waiting {
+                        let commands = commands.clone();
+                        let ctx = ctx.clone();
+                        // Blocks until the notification is clicked or closed
+                        let thread = thread::spawn(move || {
+                            handle.wait_for_action(|action| {
+                                if action == "default" {
+                                    let _ = commands.send(KeyCommand::ShowWindow);
+                                    ctx.request_repaint();
+                                }
+                            });
+                        });
+                        waiter = Some((id, thread));
+                    }
+                }
+                Err(e) => eprintln!("Couldn't show notification: {}", e),
+            }
+        }
+        #[cfg(target_os = "windows")]
+        if let Err(e) = notification.show() {
+            eprintln!("Couldn't show notification: {}", e);
+        }
+    }
+}
+
+/// "Artist — Album", whichever parts are known.
+fn body(track: &NowPlaying) -> String {
+    [&track.artist, &track.album]
+        .into_iter()
+        .flatten()
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .join(" — ")
+}
+
+/// Save a small copy of the track's cover to the temp folder for the
+/// notification to show. None when the track has no readable cover.
+fn write_thumbnail(track: &Path) -> Option<PathBuf> {
+    let bytes = metadata::read_cover_art(track)?;
+    let thumbnail = image::load_from_memory(&bytes).ok()?.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE);
+    let path = std::env::temp_dir().join("rust-audio-player-cover.png");
+    thumbnail.save(&path).ok()?;
+    Some(path)
+}
@@ -0,0 +1,94 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+/// On-disk layout shared by every store: `{"entries": {path: value}}`.
+#[derive(Serialize, Deserialize)]
+struct Stored<E> {
+    entries: E,
+}
+
+/// Values keyed by file path, persisted as one JSON file in the collections folder.
+pub struct PathStore<T> {
+    file: &'static str,
+    entries: HashMap<PathBuf, T>,
+}
+
+impl<T: Serialize + DeserializeOwned> PathStore<T> {
+    /// Load `file` from `collections_path`; a missing or corrupt file yields an empty store.
+    pub fn load(collections_path: &Path, file: &'static str) -> Self {
+        let entries = fs::read_to_string(collections_path.join(file))
+            .ok()
+            .and_then(|text| serde_json::from_str::<Stored<_>>(&text).ok())
+            .map(|stored| stored.entries)
+            .unwrap_or_default();
+        Self { file, entries }
+    }
+
+    pub fn save(&self, collections_path: &Path) -> io::Result<()> {
+        let json = serde_json::to_string(&Stored { entries: &self.entries })
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        fs::write(collections_path.join(self.file), json)
+    }
+}
+
+impl<T> PathStore<T> {
+    pub fn get(&self, path: &Path) -> Option<&T> {
+        self.entries.get(path)
+    }
+
+    pub fn set(&mut self, path: PathBuf, value: T) {
+        self.entries.insert(path, value);
+    }
+
+    /// Move the value to a moved file. Returns whether `from` had one.
+    pub fn rename(&mut self, from: &Path, to: &Path) -> bool {
+        match self.entries.remove(from) {
+            Some(value) => {
+                self.entries.insert(to.to_path_buf(), value);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Forget `path`'s value; false if it had none.
+    pub fn remove(&mut self, path: &Path) -> bool {
+        self.entries.remove(path).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_dir;
+
+    #[test]
+    fn entries_survive_a_save_rename_and_reload() {
+        let dir = temp_dir("path_store", "round_trip");
+        let mut store = PathStore::load(&dir, "store.json");
+        store.set(PathBuf::from("/music/a.flac"), 1.5f32);
+        store.set(PathBuf::from("/music/b.flac"), -3.0);
+        assert!(store.rename(Path::new("/music/a.flac"), Path::new("/music/c.flac")));
+        assert!(!store.rename(Path::new("/music/a.flac"), Path::new("/music/d.flac")));
+        assert!(store.remove(Path::new("/music/b.flac")));
+        store.save(&dir).unwrap();
+
+        let store = PathStore::<f32>::load(&dir, "store.json");
+        assert_eq!(store.get(Path::new("/music/c.flac")), Some(&1.5));
+        assert_eq!(store.get(Path::new("/music/a.flac")), None);
+        assert_eq!(store.get(Path::new("/music/b.flac")), None);
+    }
+
+    #[test]
+    fn a_corrupt_file_loads_empty() {
+        let dir = temp_dir("path_store", "corrupt");
+        fs::write(dir.join("store.json"), "{not json").unwrap();
+        let store = PathStore::<f32>::load(&dir, "store.json");
+        assert_eq!(store.get(Path::new("/music/a.flac")), None);
+    }
+}
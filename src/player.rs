@@ -0,0 +1,679 @@
+use std::collections::{HashMap, HashSet};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use rand::seq::SliceRandom;
+use rodio::source::Amplify;
+use rodio::{OutputStream, OutputStreamHandle, Sink, Source};
+
+use crate::crossfeed::{CrossfeedSettings, CrossfeedSource};
+use crate::decoder::TrackDecoder;
+use crate::loudness::LoudnessCache;
+use crate::position::{self, PlaybackPosition, PositionCounter};
+use crate::timestretch::TimeStretchSource;
+use crate::{
+    db_to_gain, item_duration, item_samples, EqualizedSource, EqualizerSettings, MediaItem, RepeatMode, SourceChain,
+};
+
+/// Tracks remembered for stepping back with Previous.
+const PREVIOUS_TRACKS_LEN: usize = 200;
+
+/// A queue entry's file and cue track number, so the tracks of one file
+/// split by a cue sheet are told apart.
+type TrackKey = (PathBuf, Option<u32>);
+
+fn track_key(item: &MediaItem) -> TrackKey {
+    (item.file_path.clone(), item.cue.map(|cue| cue.track))
+}
+
+/// A queue entry as it leaves the source chain.
+pub type TrackSource =
+    CrossfeedSource<EqualizedSource<TimeStretchSource<PositionCounter<Amplify<Box<dyn Source<Item = f32> + Send>>>>>>;
+
+/// A queue entry decoded and ready for the sink.
+pub struct DecodedTrack {
+    pub source: TrackSource,
+    pub duration: Option<f32>, // None when the length can't be determined
+    pub position: PlaybackPosition,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PlaybackState {
+    Stopped,
+    Playing,
+    Paused,
+}
+
+/// A snapshot of what the player is doing.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlayerStatus {
+    pub state: PlaybackState,
+    pub index: Option<usize>,
+    pub position: f32, // seconds into the current track
+    pub volume: f32,
+}
+
+/// The playback engine: the play queue with shuffle, repeat and the history
+/// Previous steps back through, and the output stream, sink and source chain
+/// the current track plays through. Without an output stream it still runs
+/// the queue, so it can be tested without a window or an audio device. Fades,
+/// crossfades and device selection are built on top by the app.
+pub struct Player {
+    pub queue: Vec<MediaItem>,
+    pub current_index: Option<usize>,
+    pub shuffle: bool,
+    pub repeat_mode: RepeatMode,
+    // Tracks already drawn from the current shuffle bag
+    shuffle_played: HashSet<TrackKey>,
+    // Tracks left by moving forward, most recent last
+    previous_tracks: Vec<TrackKey>,
+    // The sink plays through `stream_handle` and falls silent once `stream` is dropped
+    pub stream: Option<OutputStream>,
+    pub stream_handle: Option<OutputStreamHandle>,
+    pub sink: Option<Sink>,
+    // Time-stretch, equalizer and crossfeed every track is played through
+    pub chain: SourceChain,
+    pub is_paused: bool,
+    pub volume: f32, // sink volume; new sinks start at it
+    // Samples the output has taken from the current track
+    pub playback_position: Option<PlaybackPosition>,
+}
+
+impl Default for Player {
+    fn default() -> Self {
+        Self::new(None, SourceChain::new(EqualizerSettings::new(), CrossfeedSettings::default()))
+    }
+}
+
+impl Player {
+    /// An empty player sending `chain`'s output to `output`, if any.
+    pub fn new(output: Option<(OutputStream, OutputStreamHandle)>, chain: SourceChain) -> Self {
+        let (stream, stream_handle) = output.unzip();
+        Self {
+            queue: Vec::new(),
+            current_index: None,
+            shuffle: false,
+            repeat_mode: RepeatMode::All,
+            shuffle_played: HashSet::new(),
+            previous_tracks: Vec::new(),
+            stream,
+            stream_handle,
+            sink: None,
+            chain,
+            is_paused: false,
+            volume: 1.0,
+            playback_position: None,
+        }
+    }
+
+    /// Play through `output` from now on, or through nothing. The sink is
+    /// stopped first, since it can't outlive the stream it plays through.
+    pub fn set_output(&mut self, output: Option<(OutputStream, OutputStreamHandle)>) {
+        self.stop();
+        let (stream, handle) = output.unzip();
+        self.stream_handle = handle;
+        self.stream = stream;
+    }
+
+    /// Decode queue entry `index` from `position` seconds in, scaled by `gain`,
+    /// and run it through the chain.
+    pub fn decode(&self, index: usize, position: f32, gain: f32) -> Result<DecodedTrack, String> {
+        let item = self.queue.get(index).ok_or("no such queue entry")?;
+        let decoder = TrackDecoder::open(&item.file_path)?;
+        let duration = item_duration(item.cue, &decoder);
+        let samples = item_samples(decoder, item.cue, position).amplify(gain);
+        let (samples, position) = position::counted(samples, position.max(0.0));
+        Ok(DecodedTrack { source: self.chain.build(samples), duration, position })
+    }
+
+    /// Start `source` on a fresh sink in place of whatever was playing,
+    /// paused if `is_paused`, reading its position back through `position`.
+    pub fn play_source<S>(&mut self, source: S, position: PlaybackPosition) -> Result<(), String>
+    where
+        S: Source<Item = f32> + Send + 'static,
+    {
+        if let Some(sink) = self.sink.take() {
+            sink.stop();
+        }
+        let handle = self.stream_handle.as_ref().ok_or("No audio output device")?;
+        let sink = Sink::try_new(handle).map_err(|e| format!("Couldn't start playback: {}", e))?;
+        if self.is_paused {
+            sink.pause();
+        }
+        sink.append(source);
+        sink.set_volume(self.volume);
+        self.sink = Some(sink);
+        self.playback_position = Some(position);
+        Ok(())
+    }
+
+    /// Decode queue entry `index` and play it from `position` seconds in.
+    /// Returns the track's length if known.
+    pub fn play_track(&mut self, index: usize, position: f32, gain: f32) -> Result<Option<f32>, String> {
+        let track = self.decode(index, position, gain)?;
+        self.play_source(track.source, track.position)?;
+        Ok(track.duration)
+    }
+
+    /// Queue `source` behind the current one on the same sink, so they play
+    /// back to back. Returns false when nothing is playing to follow.
+    pub fn append_source<S>(&self, source: S) -> bool
+    where
+        S: Source<Item = f32> + Send + 'static,
+    {
+        let Some(ref sink) = self.sink else {
+            return false;
+        };
+        sink.append(source);
+        true
+    }
+
+    /// Halt playback and drop the sink, leaving the queue as it is.
+    pub fn stop(&mut self) {
+        if let Some(sink) = self.sink.take() {
+            sink.stop();
+        }
+        self.playback_position = None;
+        self.is_paused = false;
+    }
+
+    pub fn pause(&mut self) {
+        self.is_paused = true;
+        if let Some(ref sink) = self.sink {
+            sink.pause();
+        }
+    }
+
+    pub fn resume(&mut self) {
+        self.is_paused = false;
+        if let Some(ref sink) = self.sink {
+            sink.play();
+        }
+    }
+
+    /// Set the sink volume at once, and the one new sinks start at.
+    pub fn set_volume(&mut self, volume: f32) {
+        self.volume = volume;
+        if let Some(ref sink) = self.sink {
+            sink.set_volume(volume);
+        }
+    }
+
+    /// Seconds into the current track the output has got, if anything is playing.
+    pub fn position(&self) -> Option<f32> {
+        self.playback_position.as_ref().map(PlaybackPosition::seconds)
+    }
+
+    /// Whether the current track has played out while not paused.
+    pub fn finished(&self) -> bool {
+        !self.is_paused && self.sink.as_ref().is_some_and(Sink::empty)
+    }
+
+    pub fn status(&self) -> PlayerStatus {
+        let state = match self.sink {
+            None => PlaybackState::Stopped,
+            Some(_) if self.is_paused => PlaybackState::Paused,
+            Some(_) => PlaybackState::Playing,
+        };
+        PlayerStatus {
+            state,
+            index: self.current_index,
+            position: self.position().unwrap_or(0.0),
+            volume: self.volume,
+        }
+    }
+
+    /// The selected track, playing or not.
+    pub fn current(&self) -> Option<&MediaItem> {
+        self.current_index.and_then(|idx| self.queue.get(idx))
+    }
+
+    /// Make `index` the current track, remembering the one it replaces for Previous.
+    pub fn play_index(&mut self, index: usize) {
+        self.remember_current(index);
+        self.current_index = Some(index);
+    }
+
+    /// Insert `items` before queue entry `at`, keeping the current track
+    /// current, and return where they landed.
+    pub fn insert(&mut self, at: usize, items: Vec<MediaItem>) -> Range<usize> {
+        let count = items.len();
+        self.queue.splice(at..at, items);
+        if let Some(current) = self.current_index.filter(|&current| current >= at) {
+            self.current_index = Some(current + count);
+        }
+        at..at + count
+    }
+
+    /// Hash of the queued tracks in order. Any edit that adds, removes or
+    /// moves entries changes it, so anything kept by queue index can tell
+    /// when it went stale.
+    pub fn queue_fingerprint(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        for item in &self.queue {
+            (&item.file_path, item.cue.map(|cue| cue.track)).hash(&mut hasher);
+        }
+        hasher.finish()
+    }
+
+    /// Move on to the track Next goes to and return it, or None when the
+    /// queue is empty.
+    pub fn next(&mut self) -> Option<usize> {
+        let next = self.following_index()?;
+        self.play_index(next);
+        Some(next)
+    }
+
+    /// Go back to the track that played before this one and return it. With
+    /// no history left, shuffle picks a random track and sequential play
+    /// steps back one, wrapping to the end.
+    pub fn prev(&mut self) -> Option<usize> {
+        if self.queue.is_empty() {
+            return None;
+        }
+        if let Some(prev) = self.pop_previous() {
+            self.current_index = Some(prev);
+        } else if self.shuffle {
+            let mut indices: Vec<usize> = (0..self.queue.len()).collect();
+            if let Some(current) = self.current_index {
+                indices.retain(|&i| i != current);
+            }
+            if let Some(&prev) = indices.choose(&mut rand::thread_rng()) {
+                self.current_index = Some(prev);
+            }
+        } else {
+            self.current_index = Some(match self.current_index {
+                Some(i) if i > 0 => i - 1,
+                _ => self.queue.len() - 1,
+            });
+        }
+        self.current_index
+    }
+
+    /// Track `next` moves to: when shuffling, a random track not yet
+    /// drawn from the shuffle bag, otherwise the following one, wrapping at the end.
+    pub fn following_index(&self) -> Option<usize> {
+        if self.queue.is_empty() {
+            return None;
+        }
+        if self.shuffle {
+            let others: Vec<usize> = (0..self.queue.len())
+                .filter(|&i| Some(i) != self.current_index)
+                .collect();
+            let unplayed: Vec<usize> = others
+                .iter()
+                .copied()
+                .filter(|&i| !self.shuffle_played.contains(&track_key(&self.queue[i])))
+                .collect();
+            // A dry bag means the round is over; any other track starts the next one
+            let candidates = if unplayed.is_empty() { &others } else { &unplayed };
+            Some(candidates.choose(&mut rand::thread_rng()).copied().or(self.current_index).unwrap_or(0))
+        } else {
+            Some(match self.current_index {
+                Some(i) if i + 1 < self.queue.len() => i + 1,
+                _ => 0,
+            })
+        }
+    }
+
+    /// Track to play when the current one ends under the repeat mode, or
+    /// `None` when playback should stop.
+    pub fn auto_advance_index(&self) -> Option<usize> {
+        match self.repeat_mode {
+            RepeatMode::One => self.current_index,
+            RepeatMode::All => self.following_index(),
+            RepeatMode::Off => {
+                // Shuffled, the end is reached once the bag has run dry
+                let at_end = if self.shuffle {
+                    self.queue.iter().enumerate().all(|(i, item)| {
+                        Some(i) == self.current_index || self.shuffle_played.contains(&track_key(item))
+                    })
+                } else {
+                    self.current_index.is_none_or(|i| i + 1 >= self.queue.len())
+                };
+                if at_end {
+                    None
+                } else {
+                    self.following_index()
+                }
+            }
+        }
+    }
+
+    /// Whether the current track and `next` belong to the same album group,
+    /// so the change should be gapless rather than crossfaded. Shuffled
+    /// playback leaves album order anyway, so groups only count without it.
+    pub fn same_album_group(&self, next: usize) -> bool {
+        let group = |index: Option<usize>| index.and_then(|i| self.queue.get(i)).and_then(|item| item.album_group);
+        !self.shuffle && group(self.current_index).is_some_and(|current| group(Some(next)) == Some(current))
+    }
+
+    /// Linear gain bringing `item` to the reference loudness, or 1.0 when the
+    /// track hasn't been analyzed yet. Tracks of an album group share the
+    /// average gain of its analyzed tracks, so quiet movements stay quiet
+    /// relative to loud ones.
+    pub fn normalization_gain(&self, item: &MediaItem, loudness: &LoudnessCache) -> f32 {
+        let Some(group) = item.album_group else {
+            return loudness.get(&item.file_path).map_or(1.0, db_to_gain);
+        };
+        let paths: HashSet<&Path> = self.queue
+            .iter()
+            .filter(|other| other.album_group == Some(group))
+            .map(|other| other.file_path.as_path())
+            .collect();
+        let gains: Vec<f32> = paths.into_iter().filter_map(|path| loudness.get(path)).collect();
+        if gains.is_empty() {
+            return 1.0;
+        }
+        db_to_gain(gains.iter().sum::<f32>() / gains.len() as f32)
+    }
+
+    /// Remove the queue entries at `indices`, keeping `current_index` on the
+    /// same track. When the current track goes the one after it takes its
+    /// place, or none at the end of the queue; returns true in that case.
+    pub fn remove(&mut self, indices: &HashSet<usize>) -> bool {
+        let mut index = 0;
+        self.queue.retain(|_| {
+            index += 1;
+            !indices.contains(&(index - 1))
+        });
+        let Some(current) = self.current_index else {
+            return false;
+        };
+        // The track after the current one moves up when it was removed
+        let next = current - indices.iter().filter(|&&i| i < current).count();
+        self.current_index = (next < self.queue.len()).then_some(next);
+        indices.contains(&current)
+    }
+
+    /// Swap queue entries `a` and `b`, keeping `current_index` on the same track.
+    pub fn swap(&mut self, a: usize, b: usize) {
+        if a >= self.queue.len() || b >= self.queue.len() {
+            return;
+        }
+        self.queue.swap(a, b);
+        if self.current_index == Some(a) {
+            self.current_index = Some(b);
+        } else if self.current_index == Some(b) {
+            self.current_index = Some(a);
+        }
+    }
+
+    /// Move the track at `from` to just before the one at `to`, or to the
+    /// end when `to` is the queue length. The current track stays current.
+    pub fn move_track(&mut self, from: usize, to: usize) {
+        let len = self.queue.len();
+        // Dropping a track next to itself leaves it where it was
+        if from >= len || to > len || to == from || to == from + 1 {
+            return;
+        }
+        let item = self.queue.remove(from);
+        let dest = if to > from { to - 1 } else { to };
+        self.queue.insert(dest, item);
+        self.current_index = self.current_index.map(|current| {
+            if current == from {
+                dest
+            } else if from < current && current <= dest {
+                current - 1
+            } else if dest <= current && current < from {
+                current + 1
+            } else {
+                current
+            }
+        });
+    }
+
+    /// Push the current track onto the Previous stack before moving to `next`.
+    fn remember_current(&mut self, next: usize) {
+        if self.current_index == Some(next) {
+            return;
+        }
+        let Some(item) = self.current_index.and_then(|idx| self.queue.get(idx)) else {
+            return;
+        };
+        self.previous_tracks.push(track_key(item));
+        if self.previous_tracks.len() > PREVIOUS_TRACKS_LEN {
+            self.previous_tracks.remove(0);
+        }
+    }
+
+    /// Pop the Previous stack down to the most recent track still in the queue.
+    fn pop_previous(&mut self) -> Option<usize> {
+        while let Some(key) = self.previous_tracks.pop() {
+            if let Some(index) = self.queue.iter().position(|item| track_key(item) == key) {
+                return Some(index);
+            }
+        }
+        None
+    }
+
+    /// Take the current track out of the shuffle bag. A track started after
+    /// every queued track has been drawn begins a fresh bag, so repeat Off can
+    /// still see that the last round finished.
+    pub fn mark_shuffle_played(&mut self) {
+        if !self.shuffle {
+            return;
+        }
+        let Some(item) = self.current_index.and_then(|idx| self.queue.get(idx)) else {
+            return;
+        };
+        let key = track_key(item);
+        if self.queue.iter().all(|item| self.shuffle_played.contains(&track_key(item))) {
+            self.shuffle_played.clear();
+        }
+        self.shuffle_played.insert(key);
+    }
+
+    /// Switch shuffle on or off. Each time it is turned on a fresh bag starts.
+    pub fn toggle_shuffle(&mut self) {
+        self.shuffle = !self.shuffle;
+        self.shuffle_played.clear();
+        self.mark_shuffle_played();
+    }
+
+    /// Mark queue entries `range`, just added together from one folder or
+    /// cue sheet, as an album group when there is more than one track and the
+    /// album tags known so far agree.
+    pub fn group_album(&mut self, range: Range<usize>) {
+        let items = &self.queue[range.clone()];
+        let albums: HashSet<&str> = items.iter().filter_map(|item| item.album.as_deref()).collect();
+        if items.len() < 2 || albums.len() > 1 {
+            return;
+        }
+        let group = self.queue.iter().filter_map(|item| item.album_group).max().map_or(0, |group| group + 1);
+        for item in &mut self.queue[range] {
+            item.album_group = Some(group);
+        }
+    }
+
+    /// Break up album groups whose tracks turned out to carry different album tags.
+    pub fn split_mixed_album_groups(&mut self) {
+        let mut albums: HashMap<u32, HashSet<&str>> = HashMap::new();
+        for item in &self.queue {
+            if let (Some(group), Some(album)) = (item.album_group, item.album.as_deref()) {
+                albums.entry(group).or_default().insert(album);
+            }
+        }
+        let mixed: HashSet<u32> = albums
+            .into_iter()
+            .filter(|(_, names)| names.len() > 1)
+            .map(|(group, _)| group)
+            .collect();
+        for item in &mut self.queue {
+            if item.album_group.is_some_and(|group| mixed.contains(&group)) {
+                item.album_group = None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CueSpan;
+
+    fn player(len: usize) -> Player {
+        Player {
+            queue: (0..len).map(|i| MediaItem::from_path(PathBuf::from(format!("/music/{}.flac", i)))).collect(),
+            current_index: Some(0),
+            ..Default::default()
+        }
+    }
+
+    fn names(player: &Player) -> Vec<String> {
+        player.queue.iter().map(|item| item.display_name.clone()).collect()
+    }
+
+    #[test]
+    fn next_and_prev_wrap_around() {
+        let mut player = player(3);
+        assert_eq!(player.prev(), Some(2));
+        assert_eq!(player.next(), Some(0));
+        player.play_index(2);
+        assert_eq!(player.next(), Some(0));
+        assert!(Player::default().next().is_none());
+    }
+
+    #[test]
+    fn prev_returns_to_the_track_played_before() {
+        let mut player = player(5);
+        player.play_index(3);
+        player.play_index(1);
+        assert_eq!(player.prev(), Some(3));
+        assert_eq!(player.prev(), Some(0));
+    }
+
+    #[test]
+    fn prev_tells_cue_tracks_of_one_file_apart() {
+        let mut player = player(0);
+        player.queue = (1..=3)
+            .map(|track| MediaItem {
+                cue: Some(CueSpan { track, start: track as f32 * 60.0, end: None }),
+                ..MediaItem::from_path(PathBuf::from("/music/album.flac"))
+            })
+            .collect();
+        player.current_index = Some(0);
+        player.play_index(2);
+        player.play_index(1);
+        assert_eq!(player.prev(), Some(2));
+    }
+
+    #[test]
+    fn shuffle_bag_draws_every_track_before_repeating() {
+        let mut player = player(6);
+        player.toggle_shuffle();
+        let mut drawn = HashSet::from([0]);
+        for _ in 1..6 {
+            let next = player.next().unwrap();
+            player.mark_shuffle_played();
+            assert!(drawn.insert(next), "track {} drawn twice", next);
+        }
+        // The next round still leaves out the track just played
+        let last = player.current_index;
+        player.next();
+        assert_ne!(player.current_index, last);
+    }
+
+    #[test]
+    fn auto_advance_follows_the_repeat_mode() {
+        let mut player = player(3);
+        player.current_index = Some(1);
+        player.repeat_mode = RepeatMode::One;
+        assert_eq!(player.auto_advance_index(), Some(1));
+        player.repeat_mode = RepeatMode::Off;
+        assert_eq!(player.auto_advance_index(), Some(2));
+        player.repeat_mode = RepeatMode::All;
+        assert_eq!(player.auto_advance_index(), Some(2));
+
+        player.current_index = Some(2);
+        player.repeat_mode = RepeatMode::Off;
+        assert_eq!(player.auto_advance_index(), None);
+        player.repeat_mode = RepeatMode::All;
+        assert_eq!(player.auto_advance_index(), Some(0));
+        player.repeat_mode = RepeatMode::One;
+        assert_eq!(player.auto_advance_index(), Some(2));
+
+        // Shuffled, Off stops once every track has been drawn
+        player.repeat_mode = RepeatMode::Off;
+        player.toggle_shuffle();
+        for _ in 1..3 {
+            assert!(player.auto_advance_index().is_some());
+            player.next();
+            player.mark_shuffle_played();
+        }
+        assert_eq!(player.auto_advance_index(), None);
+        player.repeat_mode = RepeatMode::All;
+        let next = player.auto_advance_index();
+        assert!(next.is_some() && next != player.current_index);
+    }
+
+    #[test]
+    fn remove_keeps_the_current_track_current() {
+        let mut player = player(5);
+        player.current_index = Some(2);
+        assert!(!player.remove(&HashSet::from([0, 4])));
+        assert_eq!(names(&player), ["1", "2", "3"]);
+        assert_eq!(player.current_index, Some(1));
+        // The track after a removed current one takes its place
+        assert!(player.remove(&HashSet::from([1])));
+        assert_eq!(player.current_index, Some(1));
+        assert_eq!(names(&player), ["1", "3"]);
+        // Removing the last track leaves nothing current
+        assert!(player.remove(&HashSet::from([1])));
+        assert_eq!(player.current_index, None);
+    }
+
+    #[test]
+    fn swap_keeps_the_current_track_current() {
+        let mut player = player(3);
+        player.swap(0, 2);
+        assert_eq!(names(&player), ["2", "1", "0"]);
+        assert_eq!(player.current_index, Some(2));
+        player.swap(1, 3);
+        assert_eq!(names(&player), ["2", "1", "0"]);
+    }
+
+    #[test]
+    fn status_follows_pause_and_volume() {
+        let mut player = player(2);
+        assert_eq!(player.status().state, PlaybackState::Stopped);
+        // Nothing to play through without an output device
+        assert!(player.play_track(0, 0.0, 1.0).is_err());
+
+        player.sink = Some(Sink::new_idle().0);
+        player.set_volume(0.25);
+        player.pause();
+        let status = player.status();
+        assert_eq!(status.state, PlaybackState::Paused);
+        assert_eq!(status.index, Some(0));
+        assert_eq!(status.volume, 0.25);
+        assert_eq!(player.sink.as_ref().unwrap().volume(), 0.25);
+        assert!(player.sink.as_ref().unwrap().is_paused());
+        player.resume();
+        assert_eq!(player.status().state, PlaybackState::Playing);
+        player.stop();
+        assert_eq!(player.status().state, PlaybackState::Stopped);
+    }
+
+    #[test]
+    fn move_track_keeps_the_current_track_current() {
+        let mut player = player(4);
+        player.current_index = Some(1);
+        player.move_track(1, 4);
+        assert_eq!(names(&player), ["0", "2", "3", "1"]);
+        assert_eq!(player.current_index, Some(3));
+
+        player.move_track(0, 2);
+        assert_eq!(names(&player), ["2", "0", "3", "1"]);
+        assert_eq!(player.current_index, Some(3));
+
+        player.move_track(3, 0);
+        assert_eq!(names(&player), ["1", "2", "0", "3"]);
+        assert_eq!(player.current_index, Some(0));
+
+        // Dropping a track next to itself changes nothing
+        player.move_track(1, 2);
+        assert_eq!(names(&player), ["1", "2", "0", "3"]);
+    }
+}
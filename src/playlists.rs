@@ -0,0 +1,315 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::eq_presets::safe_file_stem;
+
+/// Playlist formats that can be opened or dropped onto the player.
+pub const PLAYLIST_EXTENSIONS: [&str; 3] = ["m3u", "m3u8", "pls"];
+
+/// One playlist line: a file plus whatever `#EXTINF` said about it.
+pub struct PlaylistEntry {
+    pub path: PathBuf,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+}
+
+/// Directory holding saved playlists as `.m3u8` files.
+pub fn playlists_dir(collections_path: &Path) -> PathBuf {
+    collections_path.join("playlists")
+}
+
+/// File a playlist named `name` is stored in, with unsafe characters replaced.
+fn playlist_path(dir: &Path, name: &str) -> PathBuf {
+    dir.join(format!("{}.m3u8", safe_file_stem(name)))
+}
+
+/// Names of the saved playlists in `dir`, sorted case-insensitively.
+pub fn list(dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|entry| entry.path())
+                .filter(|path| path.extension().is_some_and(|ext| ext == "m3u8" || ext == "m3u"))
+                .filter_map(|path| path.file_stem().map(|s| s.to_string_lossy().into_owned()))
+                .collect()
+        })
+        .unwrap_or_default();
+    names.sort_by_key(|name| name.to_lowercase());
+    names
+}
+
+/// Write `entries` as an extended M3U playlist named `name`, replacing any
+/// playlist of the same name. Paths are written as given (absolute for the queue).
+pub fn save(dir: &Path, name: &str, entries: &[PlaylistEntry]) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    let mut text = String::from("#EXTM3U\n");
+    for entry in entries {
+        let title = entry.title.as_deref().unwrap_or_default();
+        match &entry.artist {
+            Some(artist) => {
+                text.push_str(&format!("#EXTINF:-1,{} - {}\n", artist, title));
+                text.push_str(&format!("#EXTART:{}\n", artist));
+            }
+            None => text.push_str(&format!("#EXTINF:-1,{}\n", title)),
+        }
+        text.push_str(&format!("{}\n", entry.path.display()));
+    }
+    fs::write(playlist_path(dir, name), text)
+}
+
+/// Read the saved playlist named `name`.
+pub fn load(dir: &Path, name: &str) -> io::Result<Vec<PlaylistEntry>> {
+    let path = playlist_path(dir, name);
+    let path = if path.exists() { path } else { path.with_extension("m3u") };
+    read(&path)
+}
+
+/// Whether `path` has one of the `PLAYLIST_EXTENSIONS`.
+pub fn is_playlist_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|ext| PLAYLIST_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
+/// Read an M3U or PLS playlist from anywhere, such as one exported by
+/// another player. Relative paths are resolved against the playlist's
+/// folder; URLs other than `file://` and entries whose files no longer
+/// exist are skipped.
+pub fn read(path: &Path) -> io::Result<Vec<PlaylistEntry>> {
+    let bytes = fs::read(path)?;
+    // Plain .m3u and .pls files are often Latin-1 rather than UTF-8
+    let text = match String::from_utf8(bytes) {
+        Ok(text) => text,
+        Err(e) => e.into_bytes().iter().map(|&b| b as char).collect(),
+    };
+    let text = text.trim_start_matches('\u{feff}');
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let is_pls = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("pls"));
+    Ok(if is_pls { parse_pls(text, dir) } else { parse_m3u(text, dir) })
+}
+
+/// The local file a playlist line points to, if it exists. `file://` URIs
+/// are decoded, other URLs skipped, and backslash separators from
+/// playlists written on Windows are accepted.
+fn resolve(dir: &Path, location: &str) -> Option<PathBuf> {
+    let location = match location.get(..7).filter(|scheme| scheme.eq_ignore_ascii_case("file://")) {
+        Some(_) => file_uri_path(&location[7..])?,
+        None if location.contains("://") => return None,
+        None => location.to_string(),
+    };
+    let location = if cfg!(windows) { location } else { location.replace('\\', "/") };
+    let file = dir.join(location); // join keeps absolute paths as they are
+    file.exists().then_some(file)
+}
+
+/// The path of a `file://` URI after the scheme: `/music/a%20b.mp3`,
+/// `localhost/music/...` or `/C:/Music/...`. None when it names another host.
+fn file_uri_path(rest: &str) -> Option<String> {
+    let path = rest.strip_prefix("localhost").unwrap_or(rest);
+    if !path.starts_with('/') {
+        return None;
+    }
+    let path = percent_decode(path);
+    // Drive letters follow the slash: /C:/Music
+    let bytes = path.as_bytes();
+    let drive = bytes.len() >= 3 && bytes[1].is_ascii_alphabetic() && bytes[2] == b':';
+    Some(if drive { path[1..].to_string() } else { path })
+}
+
+/// `%XX` escapes replaced by their bytes, read as UTF-8.
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match (bytes[i], escaped) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn parse_m3u(text: &str, dir: &Path) -> Vec<PlaylistEntry> {
+    let mut entries = Vec::new();
+    let mut display: Option<String> = None;
+    let mut known_artist: Option<String> = None;
+    for line in text.lines().map(str::trim) {
+        if let Some(rest) = line.strip_prefix("#EXTINF:") {
+            display = Some(extinf_display(rest).to_string());
+        } else if let Some(artist) = line.strip_prefix("#EXTART:") {
+            known_artist = non_empty(artist);
+        } else if line.is_empty() || line.starts_with('#') {
+            continue;
+        } else {
+            let (title, artist) = split_display(display.take().as_deref(), known_artist.take());
+            if let Some(file) = resolve(dir, line) {
+                entries.push(PlaylistEntry { path: file, title, artist });
+            }
+        }
+    }
+    entries
+}
+
+/// The display name of `#EXTINF:<seconds> [key="value" ...],<display>`,
+/// after the duration comma. Commas inside quoted attributes don't count.
+fn extinf_display(rest: &str) -> &str {
+    let mut quoted = false;
+    for (i, c) in rest.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ',' if !quoted => return rest[i + 1..].trim(),
+            _ => {}
+        }
+    }
+    ""
+}
+
+/// Title and artist from an `#EXTINF` display name. With the artist known
+/// from `#EXTART`, only a leading "<Artist> - " is dropped; otherwise the
+/// artist is whatever comes before the last " - ".
+fn split_display(display: Option<&str>, artist: Option<String>) -> (Option<String>, Option<String>) {
+    let Some(display) = display else {
+        return (None, artist);
+    };
+    match artist {
+        Some(artist) => {
+            let title = display
+                .strip_prefix(artist.as_str())
+                .and_then(|rest| rest.strip_prefix(" - "))
+                .unwrap_or(display);
+            (non_empty(title), Some(artist))
+        }
+        None => match display.rsplit_once(" - ") {
+            Some((artist, title)) => (non_empty(title), non_empty(artist)),
+            None => (non_empty(display), None),
+        },
+    }
+}
+
+/// PLS: an INI-style `[playlist]` section of numbered `FileN=` and
+/// `TitleN=` keys, which may come in any order.
+fn parse_pls(text: &str, dir: &Path) -> Vec<PlaylistEntry> {
+    let mut files: BTreeMap<u32, String> = BTreeMap::new();
+    let mut titles: BTreeMap<u32, String> = BTreeMap::new();
+    for line in text.lines().map(str::trim) {
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let key = key.trim().to_lowercase();
+        let numbered = |prefix: &str| key.strip_prefix(prefix).and_then(|n| n.parse::<u32>().ok());
+        if let Some(n) = numbered("file") {
+            files.insert(n, value.trim().to_string());
+        } else if let Some(n) = numbered("title") {
+            titles.insert(n, value.trim().to_string());
+        }
+    }
+    files
+        .into_iter()
+        .filter_map(|(n, location)| {
+            let path = resolve(dir, &location)?;
+            let title = titles.get(&n).and_then(|title| non_empty(title));
+            Some(PlaylistEntry { path, title, artist: None })
+        })
+        .collect()
+}
+
+fn non_empty(value: &str) -> Option<String> {
+    let value = value.trim();
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_dir;
+
+    #[test]
+    fn extinf_display_follows_the_duration_comma() {
+        assert_eq!(extinf_display("123,Artist - Title"), "Artist - Title");
+        assert_eq!(extinf_display("-1 tvg-name=\"A, B\",Hello, World"), "Hello, World");
+        assert_eq!(extinf_display("-1"), "");
+    }
+
+    #[test]
+    fn known_artist_keeps_dashes_in_the_title() {
+        let (title, artist) = split_display(Some("Band - Song - Live"), Some("Band".to_string()));
+        assert_eq!(title.as_deref(), Some("Song - Live"));
+        assert_eq!(artist.as_deref(), Some("Band"));
+
+        let (title, _) = split_display(Some("Song - Live"), Some("Band".to_string()));
+        assert_eq!(title.as_deref(), Some("Song - Live"));
+    }
+
+    #[test]
+    fn unknown_artist_splits_on_the_last_dash() {
+        let (title, artist) = split_display(Some("Jay - Z - Song"), None);
+        assert_eq!(title.as_deref(), Some("Song"));
+        assert_eq!(artist.as_deref(), Some("Jay - Z"));
+
+        let (title, artist) = split_display(Some("Just a title"), None);
+        assert_eq!(title.as_deref(), Some("Just a title"));
+        assert_eq!(artist, None);
+    }
+
+    fn paths(entries: &[PlaylistEntry]) -> Vec<&Path> {
+        entries.iter().map(|entry| entry.path.as_path()).collect()
+    }
+
+    #[test]
+    fn m3u_lines_resolve_relative_absolute_and_file_uris() {
+        let dir = temp_dir("playlists", "m3u");
+        fs::create_dir_all(dir.join("Disc 1")).unwrap();
+        for name in ["a.mp3", "Disc 1/b é.flac", "c.ogg"] {
+            fs::write(dir.join(name), b"").unwrap();
+        }
+        let uri = format!("file://{}/c.ogg", dir.display()).replace(' ', "%20");
+        let text = format!(
+            "#EXTM3U\n#EXTINF:10,Band - First\na.mp3\n#EXTINF:-1,Second\n#EXTART:Other\nDisc 1\\b%20é.flac\nDisc 1\\b é.flac\n\
+             http://example.com/stream.mp3\nmissing.mp3\n{}\n",
+            uri
+        );
+
+        let entries = parse_m3u(&text, &dir);
+        assert_eq!(paths(&entries), [dir.join("a.mp3"), dir.join("Disc 1/b é.flac"), dir.join("c.ogg")]);
+        assert_eq!((entries[0].title.as_deref(), entries[0].artist.as_deref()), (Some("First"), Some("Band")));
+        // The EXTINF of a skipped line doesn't carry over to the next
+        assert_eq!((entries[1].title.as_deref(), entries[2].title.as_deref()), (None, None));
+    }
+
+    #[test]
+    fn file_uris_are_percent_decoded() {
+        assert_eq!(file_uri_path("/music/A%20B%C3%A9.mp3").as_deref(), Some("/music/A Bé.mp3"));
+        assert_eq!(file_uri_path("localhost/music/a.mp3").as_deref(), Some("/music/a.mp3"));
+        assert_eq!(file_uri_path("/C:/Music/a.mp3").as_deref(), Some("C:/Music/a.mp3"));
+        assert_eq!(file_uri_path("server/share/a.mp3"), None);
+        assert_eq!(percent_decode("100%_sure%2"), "100%_sure%2");
+    }
+
+    #[test]
+    fn pls_entries_follow_their_numbers() {
+        let dir = temp_dir("playlists", "pls");
+        for name in ["one.mp3", "two.mp3"] {
+            fs::write(dir.join(name), b"").unwrap();
+        }
+        let text = "[playlist]\nTitle2=Second\nFile2=two.mp3\nfile1=one.mp3\nFile3=http://radio.example/live\n\
+                    Title1=  \nNumberOfEntries=3\n";
+
+        let entries = parse_pls(text, &dir);
+        assert_eq!(paths(&entries), [dir.join("one.mp3"), dir.join("two.mp3")]);
+        assert_eq!((entries[0].title.as_deref(), entries[1].title.as_deref()), (None, Some("Second")));
+    }
+}
@@ -0,0 +1,74 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use rodio::Source;
+
+/// Passes `inner` through unchanged while counting the samples the output
+/// pulls from it, so the track position follows the audio actually played
+/// rather than the wall clock.
+pub struct PositionCounter<S>
+where
+    S: Source<Item = f32>,
+{
+    inner: S,
+    played: Arc<AtomicU64>,
+}
+
+/// The UI's view of a `PositionCounter`: how far into the track the output
+/// has got.
+#[derive(Clone)]
+pub struct PlaybackPosition {
+    played: Arc<AtomicU64>,
+    samples_per_sec: f32,
+    start: f32, // seconds into the track where counting began
+}
+
+impl PlaybackPosition {
+    pub fn seconds(&self) -> f32 {
+        self.start + self.played.load(Ordering::Relaxed) as f32 / self.samples_per_sec
+    }
+}
+
+/// Wrap `inner`, which begins `start` seconds into its track, in a counter
+/// and return it with a handle for reading the position back.
+pub fn counted<S>(inner: S, start: f32) -> (PositionCounter<S>, PlaybackPosition)
+where
+    S: Source<Item = f32>,
+{
+    let played = Arc::new(AtomicU64::new(0));
+    let samples_per_sec = (inner.sample_rate() as f32 * inner.channels() as f32).max(1.0);
+    let position = PlaybackPosition { played: played.clone(), samples_per_sec, start };
+    (PositionCounter { inner, played }, position)
+}
+
+impl<S> Iterator for PositionCounter<S>
+where
+    S: Source<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let sample = self.inner.next()?;
+        self.played.fetch_add(1, Ordering::Relaxed);
+        Some(sample)
+    }
+}
+
+impl<S> Source for PositionCounter<S>
+where
+    S: Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+}
@@ -0,0 +1,390 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::settings::Settings;
+
+/// Last.fm API credentials and session key, kept out of the main settings
+/// file, in `config::config_dir`.
+pub const LASTFM_FILE: &str = "lastfm.conf";
+/// Scrobbles Last.fm hasn't accepted yet, so offline plays are sent later;
+/// kept in `config::data_dir`.
+pub const QUEUE_FILE: &str = "scrobble_queue.json";
+
+const API_URL: &str = "https://ws.audioscrobbler.com/2.0/";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+/// How often queued scrobbles are retried while submission keeps failing.
+const RETRY_INTERVAL: Duration = Duration::from_secs(60);
+/// Most scrobbles Last.fm accepts in one request.
+const BATCH_SIZE: usize = 50;
+
+/// Last.fm error codes worth retrying: the service being down or busy, rate
+/// limits, and session or key problems that logging in again fixes. Any other
+/// code means the scrobbles themselves were rejected.
+const RETRYABLE_ERRORS: [u64; 9] = [4, 8, 9, 10, 11, 13, 16, 26, 29];
+
+/// Tracks shorter than this are never scrobbled.
+const MIN_TRACK_SECS: f32 = 30.0;
+/// Listening time after which a long track scrobbles without reaching its halfway point.
+const SCROBBLE_AFTER_SECS: f32 = 240.0;
+
+/// A play as Last.fm sees it.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Track {
+    pub artist: String,
+    pub title: String,
+    pub album: Option<String>,
+    pub duration: Option<f32>,
+    pub timestamp: u64, // when playback started, seconds since the Unix epoch
+}
+
+/// Whether `listened` seconds of a track count as a play under Last.fm's
+/// rules: half the track or four minutes, whichever comes first.
+pub fn should_scrobble(listened: f32, duration: Option<f32>) -> bool {
+    match duration {
+        Some(total) if total < MIN_TRACK_SECS => false,
+        Some(total) => listened >= (total / 2.0).min(SCROBBLE_AFTER_SECS),
+        None => listened >= SCROBBLE_AFTER_SECS,
+    }
+}
+
+/// Why an API call failed.
+struct CallError {
+    code: Option<u64>, // Last.fm's error code, if the response had one
+    message: String,
+}
+
+impl CallError {
+    /// Whether sending the same request later can succeed. Failures without
+    /// a Last.fm code are network or server trouble.
+    fn is_retryable(&self) -> bool {
+        self.code.is_none_or(|code| RETRYABLE_ERRORS.contains(&code))
+    }
+}
+
+/// Results reported back to the UI.
+pub enum ScrobblerEvent {
+    LoggedIn(String),
+    LoginFailed(String),
+    Submitted(usize),
+    Failed(String),
+}
+
+enum Request {
+    Login {
+        api_key: String,
+        api_secret: String,
+        username: String,
+        password: String,
+    },
+    Logout,
+    NowPlaying(Track),
+    Scrobble(Track),
+}
+
+/// Handle to the scrobbling thread. All network calls happen there.
+pub struct Scrobbler {
+    requests: Sender<Request>,
+    /// Last.fm account the stored session belongs to, if logged in.
+    pub username: Option<String>,
+}
+
+impl Scrobbler {
+    /// Start the worker with credentials from `config_path` and queued
+    /// scrobbles from `queue_path`.
+    pub fn spawn(config_path: PathBuf, queue_path: PathBuf, events: Sender<ScrobblerEvent>) -> Self {
+        let config = Settings::load(config_path);
+        let username = config
+            .get("session_key")
+            .and(config.get("username"))
+            .map(str::to_string);
+        let (requests, request_rx) = channel::<Request>();
+        thread::spawn(move || {
+            let mut worker = Worker {
+                queue: load_queue(&queue_path),
+                queue_path,
+                config,
+                events,
+            };
+            worker.flush();
+            loop {
+                match request_rx.recv_timeout(RETRY_INTERVAL) {
+                    Ok(request) => worker.handle(request),
+                    Err(RecvTimeoutError::Timeout) => worker.flush(),
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+            }
+        });
+        Self { requests, username }
+    }
+
+    /// Exchange the account password for a session key. Only the key is stored.
+    pub fn login(&self, api_key: &str, api_secret: &str, username: &str, password: &str) {
+        let _ = self.requests.send(Request::Login {
+            api_key: api_key.trim().to_string(),
+            api_secret: api_secret.trim().to_string(),
+            username: username.trim().to_string(),
+            password: password.to_string(),
+        });
+    }
+
+    pub fn logout(&mut self) {
+        self.username = None;
+        let _ = self.requests.send(Request::Logout);
+    }
+
+    pub fn now_playing(&self, track: Track) {
+        let _ = self.requests.send(Request::NowPlaying(track));
+    }
+
+    pub fn scrobble(&self, track: Track) {
+        let _ = self.requests.send(Request::Scrobble(track));
+    }
+}
+
+struct Worker {
+    queue: Vec<Track>,
+    queue_path: PathBuf,
+    config: Settings,
+    events: Sender<ScrobblerEvent>,
+}
+
+impl Worker {
+    fn handle(&mut self, request: Request) {
+        match request {
+            Request::Login { api_key, api_secret, username, password } => {
+                let params = vec![
+                    ("username".to_string(), username.clone()),
+                    ("password".to_string(), password),
+                ];
+                let result = call("auth.getMobileSession", params, &api_key, &api_secret, None)
+                    .map_err(|e| e.message)
+                    .and_then(|response| {
+                        response["session"]["key"]
+                            .as_str()
+                            .map(str::to_string)
+                            .ok_or_else(|| "Last.fm returned no session key".to_string())
+                    });
+                match result {
+                    Ok(session_key) => {
+                        self.config.set("api_key", api_key);
+                        self.config.set("api_secret", api_secret);
+                        self.config.set("username", &username);
+                        self.config.set("session_key", session_key);
+                        self.save_config();
+                        let _ = self.events.send(ScrobblerEvent::LoggedIn(username));
+                        self.flush();
+                    }
+                    Err(e) => {
+                        let _ = self.events.send(ScrobblerEvent::LoginFailed(e));
+                    }
+                }
+            }
+            Request::Logout => {
+                self.config.remove("session_key");
+                self.config.remove("username");
+                self.save_config();
+            }
+            Request::NowPlaying(track) => {
+                // Only meaningful right now, so failures are not retried
+                if let Some((api_key, api_secret, session_key)) = self.credentials() {
+                    let mut params = track_params(&track, None);
+                    params.retain(|(key, _)| key != "timestamp");
+                    if let Err(e) = call("track.updateNowPlaying", params, &api_key, &api_secret, Some(&session_key)) {
+                        let _ = self.events.send(ScrobblerEvent::Failed(e.message));
+                    }
+                }
+            }
+            Request::Scrobble(track) => {
+                self.queue.push(track);
+                self.save_queue();
+                self.flush();
+            }
+        }
+    }
+
+    fn credentials(&self) -> Option<(String, String, String)> {
+        Some((
+            self.config.get("api_key")?.to_string(),
+            self.config.get("api_secret")?.to_string(),
+            self.config.get("session_key")?.to_string(),
+        ))
+    }
+
+    /// Submit queued scrobbles in batches, oldest first. A batch that fails
+    /// for a passing reason stays queued for the next retry. One Last.fm
+    /// rejects is resent a track at a time, and the tracks it still rejects
+    /// are dropped so they can't hold up the rest of the queue.
+    fn flush(&mut self) {
+        let Some((api_key, api_secret, session_key)) = self.credentials() else {
+            return;
+        };
+        let mut batch_size = BATCH_SIZE;
+        while !self.queue.is_empty() {
+            let count = self.queue.len().min(batch_size);
+            let params = self.queue[..count]
+                .iter()
+                .enumerate()
+                .flat_map(|(i, track)| track_params(track, Some(i)))
+                .collect();
+            match call("track.scrobble", params, &api_key, &api_secret, Some(&session_key)) {
+                Ok(_) => {
+                    self.queue.drain(..count);
+                    self.save_queue();
+                    let _ = self.events.send(ScrobblerEvent::Submitted(count));
+                }
+                Err(e) if e.is_retryable() => {
+                    let _ = self.events.send(ScrobblerEvent::Failed(e.message));
+                    return;
+                }
+                // Find out which tracks are at fault
+                Err(_) if count > 1 => batch_size = 1,
+                Err(e) => {
+                    let track = self.queue.remove(0);
+                    self.save_queue();
+                    let message = format!("Dropped scrobble of {} - {}: {}", track.artist, track.title, e.message);
+                    let _ = self.events.send(ScrobblerEvent::Failed(message));
+                }
+            }
+        }
+    }
+
+    fn save_config(&self) {
+        if let Err(e) = self.config.save() {
+            eprintln!("Failed to save Last.fm settings: {}", e);
+        }
+    }
+
+    fn save_queue(&self) {
+        let result = serde_json::to_string(&self.queue)
+            .map_err(|e| e.to_string())
+            .and_then(|json| fs::write(&self.queue_path, json).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            eprintln!("Failed to save scrobble queue: {}", e);
+        }
+    }
+}
+
+fn load_queue(path: &Path) -> Vec<Track> {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default()
+}
+
+/// Request parameters for `track`; batched scrobbles index each name, e.g. `artist[3]`.
+fn track_params(track: &Track, index: Option<usize>) -> Vec<(String, String)> {
+    let name = |key: &str| match index {
+        Some(i) => format!("{}[{}]", key, i),
+        None => key.to_string(),
+    };
+    let mut params = vec![
+        (name("artist"), track.artist.clone()),
+        (name("track"), track.title.clone()),
+        (name("timestamp"), track.timestamp.to_string()),
+    ];
+    if let Some(album) = &track.album {
+        params.push((name("album"), album.clone()));
+    }
+    if let Some(duration) = track.duration {
+        params.push((name("duration"), (duration.round() as u64).to_string()));
+    }
+    params
+}
+
+/// Make a signed API call and return the JSON response.
+fn call(
+    method: &str,
+    mut params: Vec<(String, String)>,
+    api_key: &str,
+    api_secret: &str,
+    session_key: Option<&str>,
+) -> Result<Value, CallError> {
+    params.push(("method".to_string(), method.to_string()));
+    params.push(("api_key".to_string(), api_key.to_string()));
+    if let Some(session_key) = session_key {
+        params.push(("sk".to_string(), session_key.to_string()));
+    }
+    // The signature covers every parameter sorted by name, then the secret
+    params.sort();
+    let signed: String = params.iter().map(|(key, value)| format!("{}{}", key, value)).collect();
+    let signature = format!("{:x}", md5::compute(signed + api_secret));
+    params.push(("api_sig".to_string(), signature));
+    params.push(("format".to_string(), "json".to_string()));
+
+    let form: Vec<(&str, &str)> = params.iter().map(|(key, value)| (key.as_str(), value.as_str())).collect();
+    let network_error = |message: String| CallError { code: None, message };
+    let response = match ureq::post(API_URL).timeout(REQUEST_TIMEOUT).send_form(&form) {
+        Ok(response) => response,
+        // API errors come back as 4xx responses with a JSON message
+        Err(ureq::Error::Status(code, response)) => {
+            return Err(response
+                .into_json::<Value>()
+                .ok()
+                .and_then(|body| api_error(&body))
+                .unwrap_or_else(|| network_error(format!("HTTP {}", code))));
+        }
+        Err(e) => return Err(network_error(e.to_string())),
+    };
+    let body: Value = response.into_json().map_err(|e| network_error(e.to_string()))?;
+    match api_error(&body) {
+        Some(error) => Err(error),
+        None => Ok(body),
+    }
+}
+
+/// The error in a Last.fm `{"error": <code>, "message": ...}` response.
+fn api_error(body: &Value) -> Option<CallError> {
+    let code = body.get("error")?.as_u64();
+    let message = body["message"].as_str().unwrap_or("Last.fm returned an error").to_string();
+    Some(CallError { code, message })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_tracks_never_scrobble() {
+        assert!(!should_scrobble(29.0, Some(29.0)));
+        assert!(should_scrobble(15.0, Some(30.0)));
+    }
+
+    #[test]
+    fn half_the_track_or_four_minutes() {
+        assert!(!should_scrobble(89.0, Some(180.0)));
+        assert!(should_scrobble(90.0, Some(180.0)));
+        // A long mix scrobbles at four minutes, well before its halfway point
+        assert!(!should_scrobble(239.0, Some(3600.0)));
+        assert!(should_scrobble(240.0, Some(3600.0)));
+    }
+
+    #[test]
+    fn unknown_length_waits_four_minutes() {
+        assert!(!should_scrobble(239.0, None));
+        assert!(should_scrobble(240.0, None));
+    }
+
+    #[test]
+    fn only_passing_failures_are_retried() {
+        let error = |code| CallError { code, message: String::new() };
+        assert!(error(None).is_retryable());
+        assert!(error(Some(11)).is_retryable());
+        assert!(error(Some(29)).is_retryable());
+        assert!(!error(Some(6)).is_retryable());
+    }
+
+    #[test]
+    fn api_errors_carry_their_code() {
+        let body = serde_json::json!({"error": 6, "message": "Invalid parameters"});
+        let error = api_error(&body).unwrap();
+        assert_eq!(error.code, Some(6));
+        assert_eq!(error.message, "Invalid parameters");
+        assert!(api_error(&serde_json::json!({"scrobbles": {}})).is_none());
+    }
+}
@@ -0,0 +1,61 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{EqualizerPreset, RepeatMode};
+
+/// File the session is stored in, inside the collections folder.
+pub const SESSION_FILE: &str = "session.json";
+
+/// Playback state saved on exit and restored on the next launch.
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct Session {
+    pub queue: Vec<PathBuf>,
+    pub cue_tracks: Vec<Option<u32>>, // cue sheet track of each queue entry
+    pub album_groups: Vec<Option<u32>>, // album group of each queue entry
+    pub current_index: Option<usize>,
+    pub position: f32,
+    pub volume: f32,
+    pub shuffle: bool,
+    pub repeat_mode: RepeatMode,
+    pub eq_preset: EqualizerPreset,
+    pub eq_bands: Vec<f32>,
+    pub eq_preamp_db: f32,
+    pub eq_bass_db: f32,
+    pub eq_treble_db: f32,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self {
+            queue: Vec::new(),
+            cue_tracks: Vec::new(),
+            album_groups: Vec::new(),
+            current_index: None,
+            position: 0.0,
+            volume: 0.5,
+            shuffle: false,
+            repeat_mode: RepeatMode::All,
+            eq_preset: EqualizerPreset::Flat,
+            eq_bands: Vec::new(),
+            eq_preamp_db: 0.0,
+            eq_bass_db: 0.0,
+            eq_treble_db: 0.0,
+        }
+    }
+}
+
+/// Load the saved session, if there is a readable one.
+pub fn load(collections_path: &Path) -> Option<Session> {
+    let text = fs::read_to_string(collections_path.join(SESSION_FILE)).ok()?;
+    serde_json::from_str(&text).ok()
+}
+
+pub fn save(collections_path: &Path, session: &Session) -> io::Result<()> {
+    let json = serde_json::to_string_pretty(session)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    fs::write(collections_path.join(SESSION_FILE), json)
+}
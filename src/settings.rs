@@ -0,0 +1,132 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+/// Settings file older versions kept in the working directory, before
+/// `config.toml`; read once to carry its values over.
+pub const SETTINGS_FILE: &str = "audio_player.conf";
+
+/// First line of every saved file.
+const HEADER: &str = "# Remove a line to restore its default.";
+
+/// Simple `key = value` store for app state that should survive restarts.
+/// Values are parsed where they are read, and anything missing or
+/// unparseable falls back to that setting's default.
+pub struct Settings {
+    path: PathBuf,
+    values: BTreeMap<String, String>,
+}
+
+impl Settings {
+    /// Load settings from `path`. A missing or unreadable file yields empty
+    /// settings. The file is decoded lossily, so stray non-UTF-8 bytes only
+    /// affect the values they're in. A file with malformed lines is copied to
+    /// `<path>.bak` and rewritten with whatever entries could be read, so one
+    /// bad line doesn't lose the rest.
+    pub fn load(path: PathBuf) -> Self {
+        let text = match fs::read(&path) {
+            Ok(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+            Err(e) => {
+                if e.kind() != io::ErrorKind::NotFound {
+                    eprintln!("Cannot read {}: {}; starting from defaults", path.display(), e);
+                }
+                String::new()
+            }
+        };
+        let mut values = BTreeMap::new();
+        let mut malformed = false;
+        for (number, line) in text.lines().enumerate() {
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+            // Prefer the " = " we write so keys may contain '='
+            match line.split_once(" = ").or_else(|| line.split_once('=')) {
+                Some((key, value)) if !key.trim().is_empty() => {
+                    values.insert(key.trim().to_string(), value.trim().to_string());
+                }
+                _ => {
+                    eprintln!("Ignoring malformed line {} in {}", number + 1, path.display());
+                    malformed = true;
+                }
+            }
+        }
+        let settings = Self { path, values };
+        if malformed {
+            settings.repair();
+        }
+        settings
+    }
+
+    /// Keep a copy of the damaged file, then overwrite it with the values read.
+    fn repair(&self) {
+        let mut backup = self.path.clone().into_os_string();
+        backup.push(".bak");
+        let result = fs::copy(&self.path, &backup).and_then(|_| self.save());
+        if let Err(e) = result {
+            eprintln!("Failed to repair {}: {}", self.path.display(), e);
+        }
+    }
+
+    /// Every stored `(key, value)`, in key order.
+    pub fn entries(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.values.iter().map(|(key, value)| (key.as_str(), value.as_str()))
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.values.get(key).map(|v| v.as_str())
+    }
+
+    /// Store a value. Newlines are flattened since each entry is one line.
+    pub fn set(&mut self, key: &str, value: impl ToString) {
+        let value = value.to_string().replace(['\n', '\r'], " ");
+        self.values.insert(key.to_string(), value);
+    }
+
+    pub fn remove(&mut self, key: &str) {
+        self.values.remove(key);
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let mut text = format!("{}\n", HEADER);
+        for (key, value) in &self.values {
+            text.push_str(&format!("{} = {}\n", key, value));
+        }
+        fs::write(&self.path, text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_dir;
+
+    #[test]
+    fn malformed_lines_are_dropped_and_the_rest_kept() {
+        let dir = temp_dir("settings", "repair");
+        let path = dir.join("lastfm.conf");
+        let damaged = "username = someone\nnot a setting\nsession_key = abc=123\n";
+        fs::write(&path, damaged).unwrap();
+
+        let settings = Settings::load(path.clone());
+        assert_eq!(settings.get("username"), Some("someone"));
+        assert_eq!(settings.get("session_key"), Some("abc=123"));
+        assert_eq!(fs::read_to_string(dir.join("lastfm.conf.bak")).unwrap(), damaged);
+        assert!(!fs::read_to_string(&path).unwrap().contains("not a setting"));
+    }
+
+    #[test]
+    fn non_utf8_bytes_only_affect_their_value() {
+        let dir = temp_dir("settings", "latin1");
+        let path = dir.join("lastfm.conf");
+        fs::write(&path, b"username = Bj\xf6rk\nsession_key = abc\n").unwrap();
+
+        let settings = Settings::load(path.clone());
+        assert_eq!(settings.get("username"), Some("Bj\u{fffd}rk"));
+        assert_eq!(settings.get("session_key"), Some("abc"));
+        // Nothing was malformed, so the file is left alone
+        assert!(!dir.join("lastfm.conf.bak").exists());
+        assert!(fs::read(&path).unwrap().contains(&0xf6));
+    }
+}
@@ -0,0 +1,461 @@
+use std::io::{self, Read, Seek, SeekFrom};
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+
+use rodio::Source;
+use symphonia::core::io::MediaSource;
+
+use crate::decoder::TrackDecoder;
+use crate::YT_DLP_MISSING;
+
+/// Formats symphonia can decode, best first. Symphonia has no Opus codec, so
+/// YouTube's default WebM/Opus audio is only a last resort.
+const STREAM_FORMAT: &str = "bestaudio[ext=m4a]/bestaudio[acodec=mp3]/bestaudio";
+/// Bytes fetched per network read.
+const CHUNK_BYTES: usize = 64 * 1024;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(15);
+/// How long the server may go quiet, and how long opening the stream waits
+/// for data, before the stream is given up.
+const STALL_TIMEOUT: Duration = Duration::from_secs(30);
+/// The download pauses once this far ahead of the decoder.
+const MAX_AHEAD_BYTES: u64 = 8 * 1024 * 1024;
+/// Data kept behind the decoder, for the small backward seeks demuxers make.
+const KEEP_BEHIND_BYTES: u64 = 1024 * 1024;
+/// A forward seek at most this far past the downloaded data waits for the
+/// download to get there; anything further starts a new range request.
+const SEEK_GAP_BYTES: u64 = 512 * 1024;
+/// Buffered data the decoder needs before it may decode another packet
+/// without running into the end of what has arrived.
+const DECODE_AHEAD_BYTES: u64 = 64 * 1024;
+/// Data buffered ahead of the decoder before a stalled stream resumes.
+pub const RESUME_BYTES: u64 = 256 * 1024;
+
+/// Result of opening a stream, reported back to the UI.
+pub enum StreamEvent {
+    Ready {
+        title: String,
+        media_url: String,
+        source: StreamSource,
+        position: f32,
+        status: StreamStatus,
+    },
+    Failed(String),
+}
+
+/// Resolve `url` with yt-dlp, connect and start decoding on a worker thread.
+pub fn open(url: String, events: Sender<StreamEvent>) {
+    thread::spawn(move || {
+        let event = resolve(&url)
+            .and_then(|(title, media_url)| connect(title, media_url, 0.0))
+            .unwrap_or_else(StreamEvent::Failed);
+        let _ = events.send(event);
+    });
+}
+
+/// Reopen an already resolved stream at `position` seconds, on a worker thread.
+pub fn reopen(title: String, media_url: String, position: f32, events: Sender<StreamEvent>) {
+    thread::spawn(move || {
+        let event = connect(title, media_url, position).unwrap_or_else(StreamEvent::Failed);
+        let _ = events.send(event);
+    });
+}
+
+fn connect(title: String, media_url: String, position: f32) -> Result<StreamEvent, String> {
+    let (stream, status) = HttpStream::open(&media_url)?;
+    // Opening and seeking may wait for data, which is fine on this thread
+    let mut decoder = TrackDecoder::new(Box::new(stream), None).map_err(|e| format!("Cannot decode stream: {}", e))?;
+    if position > 0.0 {
+        decoder.seek(position).map_err(|e| format!("Cannot seek stream: {}", e))?;
+    }
+    status.shared.blocking.store(false, Ordering::Relaxed);
+    let source = StreamSource { decoder, status: status.clone(), silence: 0 };
+    Ok(StreamEvent::Ready { title, media_url, source, position, status })
+}
+
+/// Title and direct media URL of the audio behind a page URL.
+fn resolve(url: &str) -> Result<(String, String), String> {
+    let output = Command::new("yt-dlp")
+        .args(["--no-playlist", "-f", STREAM_FORMAT, "--print", "title", "--print", "urls", url])
+        .output()
+        .map_err(|_| YT_DLP_MISSING.to_string())?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(stderr.lines().last().unwrap_or("yt-dlp failed").to_string());
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut lines = stdout.lines();
+    match (lines.next(), lines.next()) {
+        (Some(title), Some(media_url)) => Ok((title.to_string(), media_url.to_string())),
+        _ => Err("yt-dlp returned no stream URL".to_string()),
+    }
+}
+
+/// A window of the stream around the decoder's read position.
+struct Buffer {
+    start: u64, // stream offset of `data[0]`
+    data: Vec<u8>,
+    length: Option<u64>, // Content-Length of the whole stream, if the server sent one
+    finished: bool,      // the window reaches the end of the stream, or the download failed
+    error: Option<String>,
+    read_pos: u64,       // where the decoder is reading
+    // Bumped when a seek moves the window; the downloader then re-requests from `start`
+    generation: u64,
+}
+
+impl Buffer {
+    fn end(&self) -> u64 {
+        self.start + self.data.len() as u64
+    }
+
+    /// Point the decoder at `pos`, moving the window there when it is too
+    /// far from the downloaded data to wait for.
+    fn set_read_pos(&mut self, pos: u64) {
+        self.read_pos = pos;
+        if pos < self.start || pos > self.end() + SEEK_GAP_BYTES {
+            self.start = pos;
+            self.data.clear();
+            self.finished = false;
+            self.error = None;
+            self.generation += 1;
+        }
+        // Let go of data the decoder has moved well past
+        let behind = pos.saturating_sub(self.start);
+        if behind > 2 * KEEP_BEHIND_BYTES {
+            let drop = ((behind - KEEP_BEHIND_BYTES) as usize).min(self.data.len());
+            self.data.drain(..drop);
+            self.start += drop as u64;
+        }
+    }
+}
+
+struct Shared {
+    buffer: Mutex<Buffer>,
+    changed: Condvar,
+    cancelled: AtomicBool,
+    // Reads wait for data while the stream is being opened on a worker
+    // thread, and never once it plays on the audio thread
+    blocking: AtomicBool,
+}
+
+/// `Read + Seek` over an HTTP download that a background thread fills in.
+/// Only a window around the read position is kept in memory; seeks far
+/// outside it restart the download there with a range request.
+pub struct HttpStream {
+    shared: Arc<Shared>,
+    pos: u64,
+}
+
+impl HttpStream {
+    fn open(url: &str) -> Result<(Self, StreamStatus), String> {
+        let agent = ureq::AgentBuilder::new()
+            .timeout_connect(CONNECT_TIMEOUT)
+            .timeout_read(STALL_TIMEOUT)
+            .build();
+        let response = agent.get(url).call().map_err(|e| e.to_string())?;
+        let length = response.header("Content-Length").and_then(|v| v.parse().ok());
+        let shared = Arc::new(Shared {
+            buffer: Mutex::new(Buffer {
+                start: 0,
+                data: Vec::new(),
+                length,
+                finished: false,
+                error: None,
+                read_pos: 0,
+                generation: 0,
+            }),
+            changed: Condvar::new(),
+            cancelled: AtomicBool::new(false),
+            blocking: AtomicBool::new(true),
+        });
+        let writer = shared.clone();
+        let url = url.to_string();
+        thread::spawn(move || download(&agent, &url, response, &writer));
+        let status = StreamStatus { shared: shared.clone() };
+        Ok((Self { shared, pos: 0 }, status))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Buffer> {
+        self.shared.buffer.lock().unwrap()
+    }
+}
+
+/// Fill the shared window from `response`, pausing while far enough ahead
+/// of the decoder and starting a range request whenever a seek moves the window.
+fn download(agent: &ureq::Agent, url: &str, response: ureq::Response, shared: &Shared) {
+    let mut reader: Option<Box<dyn Read + Send>> = Some(Box::new(response.into_reader()));
+    let mut generation = 0;
+    let mut chunk = vec![0; CHUNK_BYTES];
+    loop {
+        let offset = {
+            let mut buffer = shared.buffer.lock().unwrap();
+            loop {
+                if shared.cancelled.load(Ordering::Relaxed) {
+                    return;
+                }
+                if buffer.generation != generation {
+                    generation = buffer.generation;
+                    reader = None;
+                }
+                let ahead = buffer.end().saturating_sub(buffer.read_pos);
+                if !buffer.finished && ahead < MAX_AHEAD_BYTES {
+                    break buffer.end();
+                }
+                buffer = shared.changed.wait(buffer).unwrap();
+            }
+        };
+        if reader.is_none() {
+            match request_from(agent, url, offset) {
+                Ok(from) => reader = Some(from),
+                Err(e) => {
+                    finish(shared, generation, Some(e));
+                    continue;
+                }
+            }
+        }
+        let Some(current) = reader.as_mut() else {
+            continue;
+        };
+        match current.read(&mut chunk) {
+            Ok(0) => finish(shared, generation, None),
+            Ok(n) => {
+                let mut buffer = shared.buffer.lock().unwrap();
+                // A seek may have moved the window while we were reading
+                if buffer.generation == generation {
+                    buffer.data.extend_from_slice(&chunk[..n]);
+                }
+                drop(buffer);
+                shared.changed.notify_all();
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => finish(shared, generation, Some(e.to_string())),
+        }
+    }
+}
+
+/// Reader for the stream from `offset` on. A server that ignores the range
+/// sends it from the start, and the bytes before `offset` are skipped.
+fn request_from(agent: &ureq::Agent, url: &str, offset: u64) -> Result<Box<dyn Read + Send>, String> {
+    let response = agent
+        .get(url)
+        .set("Range", &format!("bytes={}-", offset))
+        .call()
+        .map_err(|e| e.to_string())?;
+    let ranged = response.status() == 206;
+    let mut reader = response.into_reader();
+    if !ranged {
+        io::copy(&mut (&mut reader).take(offset), &mut io::sink()).map_err(|e| e.to_string())?;
+    }
+    Ok(Box::new(reader))
+}
+
+/// Mark the download of `generation` as ended, unless a seek has since moved the window.
+fn finish(shared: &Shared, generation: u64, error: Option<String>) {
+    let mut buffer = shared.buffer.lock().unwrap();
+    if buffer.generation == generation {
+        buffer.finished = true;
+        buffer.error = error;
+    }
+    drop(buffer);
+    shared.changed.notify_all();
+}
+
+impl Read for HttpStream {
+    /// Copy out what has arrived at the read position. While playing, a
+    /// read past the downloaded data fails with `WouldBlock` instead of
+    /// waiting; `StreamSource` only decodes once enough is buffered.
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let shared = self.shared.clone();
+        let mut buffer = shared.buffer.lock().unwrap();
+        buffer.set_read_pos(self.pos);
+        shared.changed.notify_all();
+        while buffer.end() <= self.pos {
+            if buffer.finished {
+                return match &buffer.error {
+                    Some(e) => Err(io::Error::other(e.clone())),
+                    None => Ok(0),
+                };
+            }
+            if !shared.blocking.load(Ordering::Relaxed) {
+                return Err(io::Error::new(io::ErrorKind::WouldBlock, "stream data not downloaded yet"));
+            }
+            let (next, wait) = shared.changed.wait_timeout(buffer, STALL_TIMEOUT).unwrap();
+            buffer = next;
+            if wait.timed_out() && buffer.end() <= self.pos && !buffer.finished {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "stream stalled"));
+            }
+        }
+        let start = (self.pos - buffer.start) as usize;
+        let count = (buffer.data.len() - start).min(out.len());
+        out[..count].copy_from_slice(&buffer.data[start..start + count]);
+        self.pos += count as u64;
+        buffer.read_pos = self.pos;
+        Ok(count)
+    }
+}
+
+impl Seek for HttpStream {
+    fn seek(&mut self, to: SeekFrom) -> io::Result<u64> {
+        let shared = self.shared.clone();
+        let mut buffer = shared.buffer.lock().unwrap();
+        let target = match to {
+            SeekFrom::Start(offset) => offset as i64,
+            SeekFrom::Current(delta) => self.pos as i64 + delta,
+            SeekFrom::End(delta) => {
+                let end = buffer.length
+                    .or((buffer.finished && buffer.error.is_none()).then_some(buffer.end()))
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::Unsupported, "stream length unknown"))?;
+                end as i64 + delta
+            }
+        };
+        if target < 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "seek before start of stream"));
+        }
+        self.pos = target as u64;
+        buffer.set_read_pos(self.pos);
+        drop(buffer);
+        shared.changed.notify_all();
+        Ok(self.pos)
+    }
+}
+
+impl MediaSource for HttpStream {
+    fn is_seekable(&self) -> bool {
+        self.lock().length.is_some()
+    }
+
+    fn byte_len(&self) -> Option<u64> {
+        self.lock().length
+    }
+}
+
+impl Drop for HttpStream {
+    fn drop(&mut self) {
+        // Stop downloading once playback lets go of the stream
+        self.shared.cancelled.store(true, Ordering::Relaxed);
+        self.shared.changed.notify_all();
+    }
+}
+
+/// A stream's decoder as played by the sink. While the download has too
+/// little buffered for the next packet, it plays silence instead of letting
+/// the decoder wait for the network on the audio thread.
+pub struct StreamSource {
+    decoder: TrackDecoder,
+    status: StreamStatus,
+    silence: usize, // samples of silence left to play
+}
+
+impl Iterator for StreamSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.silence > 0 {
+            self.silence -= 1;
+            return Some(0.0);
+        }
+        // The decoder reads its next packet after the last frame of this
+        // one, so whole frames of silence go in before that frame
+        let channels = self.decoder.channels().max(1) as usize;
+        if self.decoder.remaining_in_packet() == channels && !self.status.can_decode() {
+            self.silence = channels - 1;
+            return Some(0.0);
+        }
+        self.decoder.next()
+    }
+}
+
+impl Source for StreamSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        Some(self.silence + self.decoder.remaining_in_packet())
+    }
+
+    fn channels(&self) -> u16 {
+        self.decoder.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.decoder.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.decoder.total_duration()
+    }
+}
+
+/// Handle the UI polls to notice a stream running dry.
+#[derive(Clone)]
+pub struct StreamStatus {
+    shared: Arc<Shared>,
+}
+
+impl StreamStatus {
+    /// Bytes downloaded beyond the decoder's read position.
+    pub fn buffered_ahead(&self) -> u64 {
+        let buffer = self.shared.buffer.lock().unwrap();
+        buffer.end().saturating_sub(buffer.read_pos)
+    }
+
+    /// Whether the download has ended, completely or with an error.
+    pub fn is_finished(&self) -> bool {
+        self.shared.buffer.lock().unwrap().finished
+    }
+
+    pub fn error(&self) -> Option<String> {
+        self.shared.buffer.lock().unwrap().error.clone()
+    }
+
+    /// Whether the decoder can read its next packet without waiting.
+    fn can_decode(&self) -> bool {
+        let buffer = self.shared.buffer.lock().unwrap();
+        buffer.finished || buffer.end().saturating_sub(buffer.read_pos) >= DECODE_AHEAD_BYTES
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIB: u64 = 1024 * 1024;
+
+    /// A window holding `len` bytes from offset `start`.
+    fn window(start: u64, len: u64) -> Buffer {
+        Buffer {
+            start,
+            data: vec![0; len as usize],
+            length: Some(100 * MIB),
+            finished: false,
+            error: None,
+            read_pos: start,
+            generation: 0,
+        }
+    }
+
+    #[test]
+    fn reads_just_past_the_download_wait_for_it() {
+        let mut buffer = window(0, MIB);
+        buffer.set_read_pos(MIB + SEEK_GAP_BYTES);
+        assert_eq!((buffer.generation, buffer.start, buffer.end()), (0, 0, MIB));
+    }
+
+    #[test]
+    fn far_seeks_restart_the_download_there() {
+        let mut buffer = window(0, MIB);
+        buffer.set_read_pos(50 * MIB);
+        assert_eq!((buffer.generation, buffer.start, buffer.end()), (1, 50 * MIB, 50 * MIB));
+
+        buffer.set_read_pos(10 * MIB);
+        assert_eq!((buffer.generation, buffer.start), (2, 10 * MIB));
+    }
+
+    #[test]
+    fn data_well_behind_the_reader_is_dropped() {
+        let mut buffer = window(0, 4 * MIB);
+        buffer.set_read_pos(3 * MIB);
+        assert_eq!((buffer.start, buffer.end()), (2 * MIB, 4 * MIB));
+        assert_eq!(buffer.generation, 0);
+    }
+}
@@ -0,0 +1,122 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+
+/// Progress messages sent by worker threads.
+enum TaskUpdate {
+    Progress { id: u64, fraction: f32 },
+    Finished { id: u64 },
+}
+
+/// Worker-side handle for reporting progress on a background task.
+/// The task is marked finished when the handle is dropped.
+pub struct TaskHandle {
+    id: u64,
+    sender: Sender<TaskUpdate>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl TaskHandle {
+    /// Report progress as a fraction in 0.0..=1.0.
+    pub fn progress(&self, fraction: f32) {
+        let _ = self.sender.send(TaskUpdate::Progress {
+            id: self.id,
+            fraction: fraction.clamp(0.0, 1.0),
+        });
+    }
+
+    /// Whether the user pressed cancel. Workers should stop soon after.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for TaskHandle {
+    fn drop(&mut self) {
+        let _ = self.sender.send(TaskUpdate::Finished { id: self.id });
+    }
+}
+
+/// A running task as shown in the status bar.
+struct TaskEntry {
+    id: u64,
+    name: String,
+    fraction: Option<f32>, // None until the worker reports progress
+    cancelled: Arc<AtomicBool>,
+}
+
+/// Tracks background tasks and renders them as a shared status area.
+pub struct TaskManager {
+    sender: Sender<TaskUpdate>,
+    receiver: Receiver<TaskUpdate>,
+    tasks: Vec<TaskEntry>,
+    next_id: u64,
+}
+
+impl TaskManager {
+    pub fn new() -> Self {
+        let (sender, receiver) = channel();
+        Self {
+            sender,
+            receiver,
+            tasks: Vec::new(),
+            next_id: 0,
+        }
+    }
+
+    /// Register a new task and return the handle to move into the worker thread.
+    pub fn start(&mut self, name: impl Into<String>) -> TaskHandle {
+        let id = self.next_id;
+        self.next_id += 1;
+        let cancelled = Arc::new(AtomicBool::new(false));
+        self.tasks.push(TaskEntry {
+            id,
+            name: name.into(),
+            fraction: None,
+            cancelled: cancelled.clone(),
+        });
+        TaskHandle {
+            id,
+            sender: self.sender.clone(),
+            cancelled,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+
+    /// Apply pending updates from worker threads.
+    pub fn poll(&mut self) {
+        while let Ok(update) = self.receiver.try_recv() {
+            match update {
+                TaskUpdate::Progress { id, fraction } => {
+                    if let Some(task) = self.tasks.iter_mut().find(|t| t.id == id) {
+                        task.fraction = Some(fraction);
+                    }
+                }
+                TaskUpdate::Finished { id } => {
+                    self.tasks.retain(|t| t.id != id);
+                }
+            }
+        }
+    }
+
+    /// Draw one row per running task with a progress bar and cancel button.
+    pub fn draw(&self, ui: &mut egui::Ui) {
+        for task in &self.tasks {
+            ui.horizontal(|ui| {
+                ui.label(&task.name);
+                let bar = match task.fraction {
+                    Some(fraction) => egui::ProgressBar::new(fraction).show_percentage(),
+                    None => egui::ProgressBar::new(0.0).animate(true),
+                };
+                ui.add(bar.desired_width(200.0));
+                let cancelled = task.cancelled.load(Ordering::Relaxed);
+                if ui.add_enabled(!cancelled, egui::Button::new("Cancel")).clicked() {
+                    task.cancelled.store(true, Ordering::Relaxed);
+                }
+            });
+        }
+    }
+}
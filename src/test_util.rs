@@ -0,0 +1,11 @@
+use std::fs;
+use std::path::PathBuf;
+
+/// A fresh directory under the system temp dir, emptied first. `module`
+/// keeps tests in different files from sharing a directory.
+pub fn temp_dir(module: &str, name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("audio_player_{}_{}", module, name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
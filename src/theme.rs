@@ -1,7 +1,17 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
 use egui::{Color32, FontFamily, FontId, RichText, Vec2, Visuals};
 use egui::epaint::CornerRadius;
+use serde::{Deserialize, Serialize};
+
+/// Name of the saved theme file, kept in `config::config_dir`.
+pub const THEME_FILE: &str = "theme.json";
 
+#[derive(Clone)]
 pub struct Theme {
+    pub is_dark: bool,
     pub accent_color: Color32,
     pub background_color: Color32,
     pub panel_color: Color32,
@@ -28,6 +38,7 @@ impl Default for Theme {
 impl Theme {
     pub fn dark() -> Self {
         Self {
+            is_dark: true,
             accent_color: Color32::from_rgb(94, 129, 172),
             background_color: Color32::from_rgb(46, 52, 64),
             panel_color: Color32::from_rgb(59, 66, 82),
@@ -48,6 +59,7 @@ impl Theme {
 
     pub fn light() -> Self {
         Self {
+            is_dark: false,
             accent_color: Color32::from_rgb(94, 129, 172),
             background_color: Color32::from_rgb(236, 239, 244),
             panel_color: Color32::from_rgb(229, 233, 240),
@@ -73,7 +85,7 @@ impl Theme {
         style.spacing.window_margin = self.widget_padding.into(); // Convert Vec2 to Margin
         style.spacing.button_padding = self.widget_padding;
         
-        let mut visuals = Visuals::dark();
+        let mut visuals = if self.is_dark { Visuals::dark() } else { Visuals::light() };
         visuals.widgets.noninteractive.bg_fill = self.panel_color;
         visuals.widgets.inactive.bg_fill = self.inactive_color;
         visuals.widgets.active.bg_fill = self.active_color;
@@ -86,17 +98,21 @@ impl Theme {
         
         visuals.window_corner_radius = self.corner_radius;
         visuals.window_fill = self.panel_color;
+        visuals.panel_fill = self.background_color;
+        visuals.selection.bg_fill = self.accent_color;
         
         style.visuals = visuals;
         ctx.set_style(style);
     }
 
+    #[allow(dead_code)]
     pub fn title_text(&self, text: &str) -> RichText {
         RichText::new(text)
             .font(self.heading_font.clone())
             .color(self.header_text_color)
     }
 
+    #[allow(dead_code)]
     pub fn heading_text(&self, text: &str) -> RichText {
         RichText::new(text)
             .font(self.body_font.clone())
@@ -104,21 +120,73 @@ impl Theme {
             .strong()
     }
 
+    #[allow(dead_code)]
     pub fn body_text(&self, text: &str) -> RichText {
         RichText::new(text)
             .font(self.body_font.clone())
             .color(self.text_color)
     }
     
+    #[allow(dead_code)]
     pub fn secondary_text(&self, text: &str) -> RichText {
         RichText::new(text)
             .font(self.small_font.clone())
             .color(self.dim_text_color)
     }
 
+    #[allow(dead_code)]
     pub fn tiny_text(&self, text: &str) -> RichText {
         RichText::new(text)
             .font(self.tiny_font.clone())
             .color(self.dim_text_color)
     }
 }
+
+/// The user's theme choices, saved to `THEME_FILE`. Fields missing from the
+/// file fall back to the defaults.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ThemeSettings {
+    pub dark: bool,
+    pub accent_color: [u8; 3],
+    // None keeps the dark or light theme's own background
+    pub background_color: Option<[u8; 3]>,
+}
+
+impl Default for ThemeSettings {
+    fn default() -> Self {
+        let [r, g, b, _] = Theme::dark().accent_color.to_array();
+        Self {
+            dark: true,
+            accent_color: [r, g, b],
+            background_color: None,
+        }
+    }
+}
+
+impl ThemeSettings {
+    /// Load theme choices from `path`; a missing or malformed file gives the defaults.
+    pub fn load(path: &Path) -> Self {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        fs::write(path, json)
+    }
+
+    /// Build the theme these choices describe.
+    pub fn to_theme(&self) -> Theme {
+        let mut theme = if self.dark { Theme::dark() } else { Theme::light() };
+        let [r, g, b] = self.accent_color;
+        theme.accent_color = Color32::from_rgb(r, g, b);
+        if let Some([r, g, b]) = self.background_color {
+            theme.background_color = Color32::from_rgb(r, g, b);
+        }
+        theme
+    }
+}
@@ -0,0 +1,232 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use rodio::Source;
+
+/// Length of each overlap-add grain, in seconds.
+pub const GRAIN_SECS: f32 = 0.04;
+/// How far either side of the nominal read position to look for the grain
+/// that best continues the previous one, in seconds.
+pub const SEARCH_SECS: f32 = 0.01;
+/// Correlation is measured on every Nth frame to keep the search cheap.
+const CORRELATION_STRIDE: usize = 4;
+
+/// WSOLA time-stretcher: plays `inner` at `ratio` times its speed without
+/// changing pitch. Grains of `GRAIN_SECS` are overlap-added with a Hann window,
+/// each taken from wherever within `SEARCH_SECS` of its nominal position lines
+/// up best with the previous grain, which avoids the phasing of plain OLA.
+pub struct TimeStretchSource<S>
+where
+    S: Source<Item = f32>,
+{
+    inner: S,
+    ratio: Arc<Mutex<f32>>,
+    channels: usize,
+    hop: usize,       // frames output per grain, half the grain length
+    tolerance: usize, // search radius in frames
+    window: Vec<f32>,
+    // Interleaved input frames; `input[0]` is absolute frame `base`
+    input: Vec<f32>,
+    base: usize,
+    nominal: f64,
+    previous: Option<usize>, // absolute start of the last grain used
+    // Windowed second half of the last grain, to overlap with the next one
+    tail: Vec<f32>,
+    output: VecDeque<f32>,
+    inner_done: bool,
+    drained: bool,
+}
+
+impl<S> TimeStretchSource<S>
+where
+    S: Source<Item = f32>,
+{
+    pub fn new(inner: S, ratio: Arc<Mutex<f32>>) -> Self {
+        let channels = inner.channels().max(1) as usize;
+        let sample_rate = inner.sample_rate() as f32;
+        let hop = ((sample_rate * GRAIN_SECS / 2.0) as usize).max(64);
+        let tolerance = (sample_rate * SEARCH_SECS) as usize;
+        // Periodic Hann: the halves of overlapping grains sum to exactly one
+        let window = (0..2 * hop)
+            .map(|k| 0.5 - 0.5 * (std::f32::consts::PI * k as f32 / hop as f32).cos())
+            .collect();
+        Self {
+            inner,
+            ratio,
+            channels,
+            hop,
+            tolerance,
+            window,
+            input: Vec::new(),
+            base: 0,
+            nominal: 0.0,
+            previous: None,
+            tail: Vec::new(),
+            output: VecDeque::new(),
+            inner_done: false,
+            drained: false,
+        }
+    }
+
+    fn end_frame(&self) -> usize {
+        self.base + self.input.len() / self.channels
+    }
+
+    /// Pull from the inner source until absolute frame `until` is buffered.
+    fn fill(&mut self, until: usize) {
+        while !self.inner_done && self.end_frame() < until {
+            let start = self.input.len();
+            for _ in 0..self.channels {
+                match self.inner.next() {
+                    Some(sample) => self.input.push(sample),
+                    None => {
+                        // Drop a partial trailing frame
+                        self.input.truncate(start);
+                        self.inner_done = true;
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    fn sample(&self, frame: usize, channel: usize) -> f32 {
+        self.input[(frame - self.base) * self.channels + channel]
+    }
+
+    fn mono(&self, frame: usize) -> f32 {
+        (0..self.channels).map(|c| self.sample(frame, c)).sum()
+    }
+
+    /// Start in `lo..=hi` whose first half best matches the frames at `natural`.
+    fn best_match(&self, lo: usize, hi: usize, natural: usize) -> usize {
+        let mut best = lo;
+        let mut best_score = f32::NEG_INFINITY;
+        for candidate in (lo..=hi).step_by(2) {
+            let score: f32 = (0..self.hop)
+                .step_by(CORRELATION_STRIDE)
+                .map(|k| self.mono(candidate + k) * self.mono(natural + k))
+                .sum();
+            if score > best_score {
+                best_score = score;
+                best = candidate;
+            }
+        }
+        best
+    }
+
+    /// Queue one hop of output, or whatever is left at the end of the input.
+    fn step(&mut self) {
+        let ratio = self.ratio.lock().unwrap().clamp(0.25, 4.0) as f64;
+        let hop = self.hop;
+
+        let Some(previous) = self.previous else {
+            // First grain: play its first half as-is so playback doesn't fade in
+            self.fill(self.base + 2 * hop);
+            if self.end_frame() < self.base + 2 * hop {
+                self.output.extend(self.input.drain(..));
+                self.drained = true;
+                return;
+            }
+            let start = self.base;
+            self.output.extend(self.input[..hop * self.channels].iter().copied());
+            self.tail = (0..hop)
+                .flat_map(|k| (0..self.channels).map(move |c| (k, c)))
+                .map(|(k, c)| self.sample(start + hop + k, c) * self.window[hop + k])
+                .collect();
+            self.previous = Some(start);
+            self.nominal = start as f64 + hop as f64 * ratio;
+            return;
+        };
+
+        let natural = previous + hop;
+        let (lo, hi) = if (ratio - 1.0).abs() < 1e-3 {
+            // Normal speed: continue seamlessly and skip the search
+            self.nominal = natural as f64;
+            (natural, natural)
+        } else {
+            let nominal = self.nominal.round() as usize;
+            (nominal.saturating_sub(self.tolerance).max(self.base), nominal + self.tolerance)
+        };
+        self.fill((hi + 2 * hop).max(natural + hop));
+
+        if self.end_frame() < hi + 2 * hop {
+            // Out of input: finish the overlap with the natural continuation
+            // and play the remainder unstretched
+            let end = self.end_frame();
+            for k in 0..hop.min(end.saturating_sub(natural)) {
+                for c in 0..self.channels {
+                    let sample = self.tail[k * self.channels + c] + self.sample(natural + k, c) * self.window[k];
+                    self.output.push_back(sample);
+                }
+            }
+            let rest = (natural + hop).saturating_sub(self.base) * self.channels;
+            if rest < self.input.len() {
+                self.output.extend(self.input[rest..].iter().copied());
+            }
+            self.input.clear();
+            self.drained = true;
+            return;
+        }
+
+        let start = self.best_match(lo, hi, natural);
+        for k in 0..hop {
+            for c in 0..self.channels {
+                let sample = self.tail[k * self.channels + c] + self.sample(start + k, c) * self.window[k];
+                self.output.push_back(sample);
+            }
+        }
+        for k in 0..hop {
+            for c in 0..self.channels {
+                self.tail[k * self.channels + c] = self.sample(start + hop + k, c) * self.window[hop + k];
+            }
+        }
+        self.previous = Some(start);
+        self.nominal += hop as f64 * ratio;
+
+        // Drop input no later grain can reach
+        let keep_from = (start + hop).min((self.nominal as usize).saturating_sub(self.tolerance));
+        if keep_from > self.base {
+            self.input.drain(..(keep_from - self.base) * self.channels);
+            self.base = keep_from;
+        }
+    }
+}
+
+impl<S> Iterator for TimeStretchSource<S>
+where
+    S: Source<Item = f32>,
+{
+    type Item = f32;
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(sample) = self.output.pop_front() {
+                return Some(sample);
+            }
+            if self.drained {
+                return None;
+            }
+            self.step();
+        }
+    }
+}
+
+impl<S> Source for TimeStretchSource<S>
+where
+    S: Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        // Output no longer lines up with the inner source's frames
+        None
+    }
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
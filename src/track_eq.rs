@@ -0,0 +1,8 @@
+use crate::eq_presets::SavedPreset;
+use crate::path_store::PathStore;
+
+/// EQ curves remembered for single tracks, inside the collections folder.
+pub const TRACK_EQ_FILE: &str = "track_eq.json";
+
+/// Per-track EQ curves keyed by file path, persisted in `TRACK_EQ_FILE`.
+pub type TrackEqs = PathStore<SavedPreset>;
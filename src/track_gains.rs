@@ -0,0 +1,66 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::path_store::PathStore;
+
+/// Manual gain trims, inside the collections folder.
+pub const TRACK_GAINS_FILE: &str = "track_gains.json";
+/// Where older versions kept trims, as `path = dB` lines in the working directory.
+pub const LEGACY_TRACK_GAINS_FILE: &str = "track_gains.conf";
+
+/// Per-track gain trims in dB keyed by file path, persisted in `TRACK_GAINS_FILE`.
+pub struct TrackGains(PathStore<f32>);
+
+impl TrackGains {
+    /// Load the store from `collections_path`; a missing or corrupt file yields an empty store.
+    pub fn load(collections_path: &Path) -> Self {
+        Self(PathStore::load(collections_path, TRACK_GAINS_FILE))
+    }
+
+    pub fn save(&self, collections_path: &Path) -> io::Result<()> {
+        self.0.save(collections_path)
+    }
+
+    /// Carry over trims from an old `track_gains.conf` the store doesn't
+    /// already have. The dB value is split off the last " = ", so paths that
+    /// contain one survive. Returns whether anything was added.
+    pub fn import_legacy(&mut self, legacy_path: &Path) -> bool {
+        let Ok(text) = fs::read_to_string(legacy_path) else {
+            return false;
+        };
+        let mut added = false;
+        for line in text.lines().filter(|line| !line.trim_start().starts_with('#')) {
+            let Some((path, db)) = line.rsplit_once(" = ") else {
+                continue;
+            };
+            if let Ok(db) = db.trim().parse() {
+                let path = PathBuf::from(path.trim());
+                if self.0.get(&path).is_none() {
+                    self.0.set(path, db);
+                }
+                added = true;
+            }
+        }
+        added
+    }
+
+    /// Trim for `path`, 0.0 if none.
+    pub fn get(&self, path: &Path) -> f32 {
+        self.0.get(path).copied().unwrap_or(0.0)
+    }
+
+    /// Store a trim; 0.0 removes the entry.
+    pub fn set(&mut self, path: &Path, db: f32) {
+        if db == 0.0 {
+            self.0.remove(path);
+        } else {
+            self.0.set(path.to_path_buf(), db);
+        }
+    }
+
+    /// Move the trim to a renamed file. Returns whether `from` had one.
+    pub fn rename(&mut self, from: &Path, to: &Path) -> bool {
+        self.0.rename(from, to)
+    }
+}
@@ -0,0 +1,191 @@
+use std::sync::mpsc::Sender;
+
+use eframe::egui;
+#[cfg(target_os = "linux")]
+use ksni::blocking::TrayMethods;
+#[cfg(target_os = "windows")]
+use tray_icon::menu::{Menu, MenuEvent, MenuItem, PredefinedMenuItem};
+#[cfg(target_os = "windows")]
+use tray_icon::{Icon, MouseButton, MouseButtonState, TrayIcon, TrayIconBuilder, TrayIconEvent};
+
+use crate::KeyCommand;
+
+/// Side length of the generated tray icon, in pixels.
+const ICON_SIZE: u32 = 32;
+
+/// System tray icon with transport controls. Menu actions are sent as
+/// `KeyCommand`s so they take the same path as the global hotkeys.
+pub struct Tray {
+    #[cfg(target_os = "windows")]
+    icon: TrayIcon,
+    // The desktop draws the Linux icon; this updates what it shows
+    #[cfg(target_os = "linux")]
+    handle: ksni::blocking::Handle<LinuxTray>,
+    tooltip: String,
+}
+
+impl Tray {
+    /// Create the tray icon, or None when the desktop has no tray to show it
+    /// in. `ctx` is woken on every action so commands are handled even while
+    /// the window is hidden and idle.
+    pub fn new(commands: Sender<KeyCommand>, ctx: egui::Context) -> Option<Self> {
+        #[cfg(target_os = "windows")]
+        {
+            let menu_commands = commands.clone();
+            let menu_ctx = ctx.clone();
+            MenuEvent::set_event_handler(Some(move |event: MenuEvent| {
+                let command = match event.id.0.as_str() {
+                    "play_pause" => KeyCommand::TogglePause,
+                    "next" => KeyCommand::NextTrack,
+                    "previous" => KeyCommand::PreviousTrack,
+                    "show_hide" => KeyCommand::ToggleWindow,
+                    "quit" => KeyCommand::Quit,
+                    _ => return,
+                };
+                let _ = menu_commands.send(command);
+                menu_ctx.request_repaint();
+            }));
+            TrayIconEvent::set_event_handler(Some(move |event: TrayIconEvent| {
+                if let TrayIconEvent::Click { button: MouseButton::Left, button_state: MouseButtonState::Up, .. } = event {
+                    let _ = commands.send(KeyCommand::ToggleWindow);
+                    ctx.request_repaint();
+                }
+            }));
+            match build_icon() {
+                Ok(icon) => Some(Self { icon, tooltip: String::new() }),
+                Err(e) => {
+                    eprintln!("System tray unavailable: {}", e);
+                    None
+                }
+            }
+        }
+
+        // A StatusNotifierItem over D-Bus, so no GTK loop has to run alongside eframe
+        #[cfg(target_os = "linux")]
+        {
+            let tray = LinuxTray { commands, ctx, tooltip: String::new() };
+            match tray.spawn() {
+                Ok(handle) => Some(Self { handle, tooltip: String::new() }),
+                Err(e) => {
+                    eprintln!("System tray unavailable: {}", e);
+                    None
+                }
+            }
+        }
+    }
+
+    /// Show `text` when hovering the icon. Unchanged text is not resent.
+    pub fn set_tooltip(&mut self, text: &str) {
+        if self.tooltip == text {
+            return;
+        }
+        self.tooltip = text.to_string();
+        #[cfg(target_os = "windows")]
+        let _ = self.icon.set_tooltip(Some(text));
+        #[cfg(target_os = "linux")]
+        {
+            let text = text.to_string();
+            self.handle.update(move |tray| tray.tooltip = text);
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn build_icon() -> Result<TrayIcon, Box<dyn std::error::Error>> {
+    let menu = Menu::new();
+    menu.append_items(&[
+        &MenuItem::with_id("play_pause", "Play/Pause", true, None),
+        &MenuItem::with_id("next", "Next", true, None),
+        &MenuItem::with_id("previous", "Previous", true, None),
+        &PredefinedMenuItem::separator(),
+        &MenuItem::with_id("show_hide", "Show/Hide", true, None),
+        &MenuItem::with_id("quit", "Quit", true, None),
+    ])?;
+    let icon = TrayIconBuilder::new()
+        .with_menu(Box::new(menu))
+        .with_tooltip("Rust Audio Player")
+        .with_icon(Icon::from_rgba(icon_rgba(), ICON_SIZE, ICON_SIZE)?)
+        .build()?;
+    Ok(icon)
+}
+
+/// The Linux tray's state, owned by ksni's service thread.
+#[cfg(target_os = "linux")]
+struct LinuxTray {
+    commands: Sender<KeyCommand>,
+    ctx: egui::Context,
+    tooltip: String,
+}
+
+#[cfg(target_os = "linux")]
+impl LinuxTray {
+    fn send(&self, command: KeyCommand) {
+        let _ = self.commands.send(command);
+        self.ctx.request_repaint();
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl ksni::Tray for LinuxTray {
+    fn id(&self) -> String {
+        "rust-audio-player".to_string()
+    }
+
+    fn title(&self) -> String {
+        "Rust Audio Player".to_string()
+    }
+
+    fn icon_pixmap(&self) -> Vec<ksni::Icon> {
+        let mut data = icon_rgba();
+        // StatusNotifierItem wants ARGB
+        for pixel in data.chunks_exact_mut(4) {
+            pixel.rotate_right(1);
+        }
+        vec![ksni::Icon { width: ICON_SIZE as i32, height: ICON_SIZE as i32, data }]
+    }
+
+    fn tool_tip(&self) -> ksni::ToolTip {
+        ksni::ToolTip { title: self.tooltip.clone(), ..Default::default() }
+    }
+
+    fn activate(&mut self, _x: i32, _y: i32) {
+        self.send(KeyCommand::ToggleWindow);
+    }
+
+    fn menu(&self) -> Vec<ksni::MenuItem<Self>> {
+        vec![
+            menu_item("Play/Pause", || KeyCommand::TogglePause),
+            menu_item("Next", || KeyCommand::NextTrack),
+            menu_item("Previous", || KeyCommand::PreviousTrack),
+            ksni::MenuItem::Separator,
+            menu_item("Show/Hide", || KeyCommand::ToggleWindow),
+            menu_item("Quit", || KeyCommand::Quit),
+        ]
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn menu_item(label: &str, command: fn() -> KeyCommand) -> ksni::MenuItem<LinuxTray> {
+    ksni::menu::StandardItem {
+        label: label.to_string(),
+        activate: Box::new(move |tray: &mut LinuxTray| tray.send(command())),
+        ..Default::default()
+    }
+    .into()
+}
+
+/// A filled disc in the default accent colour, as RGBA, so no image asset is needed.
+fn icon_rgba() -> Vec<u8> {
+    let center = (ICON_SIZE as f32 - 1.0) / 2.0;
+    let radius = ICON_SIZE as f32 / 2.0;
+    let mut rgba = Vec::with_capacity((ICON_SIZE * ICON_SIZE * 4) as usize);
+    for y in 0..ICON_SIZE {
+        for x in 0..ICON_SIZE {
+            let distance = ((x as f32 - center).powi(2) + (y as f32 - center).powi(2)).sqrt();
+            // One pixel of anti-aliasing at the edge
+            let alpha = (radius - distance).clamp(0.0, 1.0);
+            rgba.extend_from_slice(&[94, 129, 172, (alpha * 255.0) as u8]);
+        }
+    }
+    rgba
+}
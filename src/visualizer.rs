@@ -1,14 +1,105 @@
 use egui::{Color32, Pos2, Rect, Vec2, Stroke};
 use rustfft::{FftPlanner, num_complex::Complex};
 use std::collections::VecDeque;
-use egui::epaint::{CornerRadius, StrokeKind}; // <-- new import
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use egui::epaint::StrokeKind;
+use rodio::Source;
+use serde::{Deserialize, Serialize};
 
 // Constants for visualization
-pub const SPECTRUM_BUFFER_SIZE: usize = 4096;  // Must be power of 2 for FFT
-pub const SPECTRUM_BANDS: usize = 64;          // Number of frequency bands to display
-pub const WAVEFORM_POINTS: usize = 1024;       // Number of points to display in waveform
+pub const DEFAULT_FFT_SIZE: usize = 4096;      // Must be power of 2 for FFT
+pub const DEFAULT_BANDS: usize = 64;           // Number of frequency bands to display
+pub const DEFAULT_WAVEFORM_POINTS: usize = 1024; // Number of points to display in waveform
+pub const FFT_SIZES: [usize; 4] = [1024, 2048, 4096, 8192];
+pub const BAND_COUNTS: [usize; 4] = [16, 32, 64, 128];
+pub const TAP_MIN_BLOCK: usize = 256;          // Smallest batch pushed to the visualizer
+pub const TAP_MAX_BLOCK: usize = 2048;         // Largest batch, keeps spectrum close to audible output
+pub const VU_FLOOR_DB: f32 = -60.0;            // Level shown as an empty VU bar
+const MIN_FREQUENCY: f32 = 20.0;               // Lower edge of the first spectrum band, in Hz
+const SPECTRUM_FLOOR_DB: f32 = -80.0;          // Bin level shown as an empty spectrum bar
+const VU_DECAY: f32 = 0.92;                     // Per-frame falloff of the VU bars
+const SCOPE_WINDOW: usize = 1024;               // Samples across the oscilloscope trace
+const LISSAJOUS_POINTS: usize = 1024;           // Most recent frames plotted on the goniometer
+pub const DEFAULT_SPECTROGRAM_HISTORY: usize = 256; // FFT frames shown across the spectrogram
+pub const SPECTROGRAM_HISTORIES: [usize; 3] = [128, 256, 512];
+
+/// What the visualizer draws.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VisualizerMode {
+    Spectrum,
+    Waveform,
+    VuMeter,
+    Oscilloscope,
+    Lissajous,
+    Spectrogram,
+}
+
+impl VisualizerMode {
+    pub const ALL: [VisualizerMode; 6] = [
+        VisualizerMode::Spectrum,
+        VisualizerMode::Waveform,
+        VisualizerMode::VuMeter,
+        VisualizerMode::Oscilloscope,
+        VisualizerMode::Lissajous,
+        VisualizerMode::Spectrogram,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            VisualizerMode::Spectrum => "Spectrum",
+            VisualizerMode::Waveform => "Waveform",
+            VisualizerMode::VuMeter => "VU Meter",
+            VisualizerMode::Oscilloscope => "Oscilloscope",
+            VisualizerMode::Lissajous => "Lissajous",
+            VisualizerMode::Spectrogram => "Spectrogram",
+        }
+    }
+}
+
+/// Colour map for the spectrogram heatmap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpectrogramColors {
+    Heat,
+    Rainbow,
+    Grayscale,
+}
+
+impl SpectrogramColors {
+    pub const ALL: [SpectrogramColors; 3] = [
+        SpectrogramColors::Heat,
+        SpectrogramColors::Rainbow,
+        SpectrogramColors::Grayscale,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            SpectrogramColors::Heat => "Heat",
+            SpectrogramColors::Rainbow => "Rainbow",
+            SpectrogramColors::Grayscale => "Grayscale",
+        }
+    }
+
+    /// Colour of a cell at `level`, 0.0 (silent) to 1.0 (loud).
+    fn color(self, level: f32) -> Color32 {
+        let level = level.clamp(0.0, 1.0);
+        let (r, g, b) = match self {
+            // Black through red and orange to pale yellow
+            SpectrogramColors::Heat => hsv_to_rgb(level * 60.0, 1.0 - level * 0.6, level.sqrt()),
+            // Blue for quiet through to red for loud
+            SpectrogramColors::Rainbow => hsv_to_rgb(240.0 - level * 240.0, 0.9, 0.15 + level * 0.85),
+            SpectrogramColors::Grayscale => hsv_to_rgb(0.0, 0.0, level),
+        };
+        Color32::from_rgb(r, g, b)
+    }
+}
 
 pub struct AudioVisualizer {
+    pub fft_size: usize,
+    pub band_count: usize,
+    pub waveform_points: usize,
     pub sample_buffer: VecDeque<f32>,
     pub spectrum_data: Vec<f32>,
     pub waveform_data: Vec<f32>,
@@ -17,98 +108,174 @@ pub struct AudioVisualizer {
     pub fft_planner: FftPlanner<f32>,
     pub update_needed: bool,
     pub peak_hold_frames: Vec<u8>,  // For peak falloff
+    // Per-channel analysis for the stereo spectrum view
+    pub stereo: bool,
+    pub left_buffer: VecDeque<f32>,
+    pub right_buffer: VecDeque<f32>,
+    pub spectrum_left: Vec<f32>,
+    pub spectrum_right: Vec<f32>,
+    // Stereo VU levels (linear), left then right; mono plays on both
+    pub channel_peak: [f32; 2],
+    pub channel_rms: [f32; 2],
+    pub vu_peak_hold: [f32; 2],
+    pub vu_hold_frames: [u8; 2],
+    pending_peak: [f32; 2],
+    pending_rms: [f32; 2],
+    // Recent spectra for the spectrogram, oldest first
+    pub spectrogram: VecDeque<Vec<f32>>,
+    pub spectrogram_history: usize,
+    pub spectrogram_colors: SpectrogramColors,
+    spectrogram_texture: Option<egui::TextureHandle>,
 }
 
 impl AudioVisualizer {
     pub fn new(sample_rate: u32) -> Self {
-        Self {
-            sample_buffer: VecDeque::with_capacity(SPECTRUM_BUFFER_SIZE),
-            spectrum_data: vec![0.0; SPECTRUM_BANDS],
-            waveform_data: vec![0.0; WAVEFORM_POINTS],
-            peak_levels: vec![0.0; SPECTRUM_BANDS],
+        Self::with_resolution(sample_rate, DEFAULT_FFT_SIZE, DEFAULT_BANDS)
+            .expect("default visualizer resolution is valid")
+    }
+
+    /// Visualizer with `fft_size` samples per analysis and `band_count` bars.
+    pub fn with_resolution(sample_rate: u32, fft_size: usize, band_count: usize) -> Result<Self, String> {
+        validate_resolution(fft_size, band_count)?;
+        Ok(Self {
+            fft_size,
+            band_count,
+            waveform_points: DEFAULT_WAVEFORM_POINTS,
+            sample_buffer: VecDeque::with_capacity(fft_size),
+            spectrum_data: vec![0.0; band_count],
+            waveform_data: vec![0.0; DEFAULT_WAVEFORM_POINTS],
+            peak_levels: vec![0.0; band_count],
             sample_rate,
             fft_planner: FftPlanner::new(),
             update_needed: true,
-            peak_hold_frames: vec![0; SPECTRUM_BANDS],
+            peak_hold_frames: vec![0; band_count],
+            stereo: false,
+            left_buffer: VecDeque::with_capacity(fft_size),
+            right_buffer: VecDeque::with_capacity(fft_size),
+            spectrum_left: vec![0.0; band_count],
+            spectrum_right: vec![0.0; band_count],
+            channel_peak: [0.0; 2],
+            channel_rms: [0.0; 2],
+            vu_peak_hold: [0.0; 2],
+            vu_hold_frames: [0; 2],
+            pending_peak: [0.0; 2],
+            pending_rms: [0.0; 2],
+            spectrogram: VecDeque::with_capacity(DEFAULT_SPECTROGRAM_HISTORY),
+            spectrogram_history: DEFAULT_SPECTROGRAM_HISTORY,
+            spectrogram_colors: SpectrogramColors::Heat,
+            spectrogram_texture: None,
+        })
+    }
+
+    /// Change the FFT size and band count, resizing the analysis buffers.
+    /// The FFT planner is kept; it caches plans for each size it has seen.
+    pub fn set_resolution(&mut self, fft_size: usize, band_count: usize) -> Result<(), String> {
+        validate_resolution(fft_size, band_count)?;
+        self.fft_size = fft_size;
+        self.band_count = band_count;
+        for buffer in [&mut self.sample_buffer, &mut self.left_buffer, &mut self.right_buffer] {
+            while buffer.len() > fft_size {
+                buffer.pop_front();
+            }
+        }
+        self.spectrum_data = vec![0.0; band_count];
+        self.spectrum_left = vec![0.0; band_count];
+        self.spectrum_right = vec![0.0; band_count];
+        self.peak_levels = vec![0.0; band_count];
+        self.peak_hold_frames = vec![0; band_count];
+        // Old columns have the wrong height for the new band count
+        self.spectrogram.clear();
+        self.update_needed = true;
+        Ok(())
+    }
+
+    /// Keep the last `frames` spectra for the spectrogram.
+    pub fn set_spectrogram_history(&mut self, frames: usize) {
+        self.spectrogram_history = frames.max(1);
+        while self.spectrogram.len() > self.spectrogram_history {
+            self.spectrogram.pop_front();
+        }
+    }
+
+    /// Record per-channel peak and RMS measured over one block of audio.
+    pub fn add_levels(&mut self, peak: [f32; 2], rms: [f32; 2]) {
+        for ch in 0..2 {
+            self.pending_peak[ch] = self.pending_peak[ch].max(peak[ch]);
+            self.pending_rms[ch] = self.pending_rms[ch].max(rms[ch]);
+        }
+    }
+
+    /// Fold in levels received since the last frame, letting the bars fall
+    /// back smoothly and holding peaks like the spectrum does.
+    fn update_levels(&mut self) {
+        for ch in 0..2 {
+            self.channel_peak[ch] = self.pending_peak[ch].max(self.channel_peak[ch] * VU_DECAY);
+            self.channel_rms[ch] = self.pending_rms[ch].max(self.channel_rms[ch] * VU_DECAY);
+            self.pending_peak[ch] = 0.0;
+            self.pending_rms[ch] = 0.0;
+
+            if self.channel_peak[ch] > self.vu_peak_hold[ch] {
+                self.vu_peak_hold[ch] = self.channel_peak[ch];
+                self.vu_hold_frames[ch] = 30; // Hold peak for 30 frames
+            } else if self.vu_hold_frames[ch] > 0 {
+                self.vu_hold_frames[ch] -= 1;
+            } else {
+                self.vu_peak_hold[ch] = (self.vu_peak_hold[ch] * VU_DECAY).max(self.channel_peak[ch]);
+            }
         }
     }
 
     pub fn add_sample(&mut self, sample: f32) {
-        if self.sample_buffer.len() >= SPECTRUM_BUFFER_SIZE {
+        if self.sample_buffer.len() >= self.fft_size {
             self.sample_buffer.pop_front();
         }
         self.sample_buffer.push_back(sample);
         self.update_needed = true;
         
         // Update waveform display (downsampled)
-        let waveform_idx = (self.sample_buffer.len() * WAVEFORM_POINTS / SPECTRUM_BUFFER_SIZE) % WAVEFORM_POINTS;
+        let waveform_idx = (self.sample_buffer.len() * self.waveform_points / self.fft_size) % self.waveform_points;
         if waveform_idx < self.waveform_data.len() {
             self.waveform_data[waveform_idx] = sample;
         }
     }
 
-    pub fn analyze(&mut self) {
-        if !self.update_needed || self.sample_buffer.len() < SPECTRUM_BUFFER_SIZE {
-            return;
+    pub fn add_samples(&mut self, samples: &[f32]) {
+        for &sample in samples {
+            self.add_sample(sample);
         }
+    }
 
-        // Prepare FFT input
-        let fft_input: Vec<Complex<f32>> = self.sample_buffer.iter()
-            .enumerate()
-            .map(|(i, &sample)| {
-                // Apply Hann window
-                let window = 0.5 * (1.0 - (2.0 * std::f32::consts::PI * i as f32 / SPECTRUM_BUFFER_SIZE as f32).cos());
-                Complex { re: sample * window, im: 0.0 }
-            })
-            .collect();
-
-        // Create output buffer (in-place FFT)
-        let mut fft_output = fft_input.clone();
-
-        // Perform FFT
-        let fft = self.fft_planner.plan_fft_forward(SPECTRUM_BUFFER_SIZE);
-        fft.process(&mut fft_output);
-
-        // Process FFT results
-        let nyquist = self.sample_rate as f32 / 2.0;
-        let bin_size = nyquist / (SPECTRUM_BUFFER_SIZE as f32 / 2.0);
-        
-        // Temporary buffer for new values
-        let mut new_spectrum = vec![0.0; SPECTRUM_BANDS];
-
-        // Process frequency bands
-        for i in 0..SPECTRUM_BUFFER_SIZE/2 {
-            let re = fft_output[i].re;
-            let im = fft_output[i].im;
-            let magnitude = (re * re + im * im).sqrt() as f32;
-            
-            // Convert to decibels (range approximately -80 to 0)
-            let db = 20.0 * magnitude.log10().max(-80.0);
-            // Normalize to 0.0-1.0 range
-            let normalized = (db + 80.0) / 80.0;
-
-            // Map to logarithmic frequency band
-            let freq = i as f32 * bin_size;
-            let band_index = if freq > 0.0 {
-                let log_freq = freq.log10();
-                let log_min = 20.0_f32.log10(); // 20 Hz
-                let log_max = nyquist.log10();
-                
-                let normalized_log = (log_freq - log_min) / (log_max - log_min);
-                (normalized_log * (SPECTRUM_BANDS as f32 - 1.0)) as usize
-            } else {
-                0
-            };
-            
-            if band_index < SPECTRUM_BANDS {
-                new_spectrum[band_index] = f32::max(new_spectrum[band_index], normalized);
+    /// Feed left/right frames for the stereo spectrum. Mono sources send the
+    /// same sample for both channels.
+    pub fn add_stereo_frames(&mut self, frames: &[[f32; 2]]) {
+        for &[left, right] in frames {
+            if self.left_buffer.len() >= self.fft_size {
+                self.left_buffer.pop_front();
+                self.right_buffer.pop_front();
             }
+            self.left_buffer.push_back(left);
+            self.right_buffer.push_back(right);
         }
+    }
+
+    pub fn analyze(&mut self) {
+        self.update_levels();
+        if !self.update_needed || self.sample_buffer.len() < self.fft_size {
+            return;
+        }
+
+        let new_spectrum = band_levels(
+            &mut self.fft_planner,
+            &self.sample_buffer,
+            self.fft_size,
+            self.band_count,
+            self.sample_rate,
+        );
 
         // Update spectrum with smoother transitions
-        for i in 0..SPECTRUM_BANDS {
+        for (i, &target) in new_spectrum.iter().enumerate().take(self.band_count) {
             // Smooth the transition to new values (70% old, 30% new)
-            self.spectrum_data[i] = self.spectrum_data[i] * 0.7 + new_spectrum[i] * 0.3;
+            self.spectrum_data[i] = self.spectrum_data[i] * 0.7 + target * 0.3;
             
             // Handle peak levels with falloff
             if self.spectrum_data[i] > self.peak_levels[i] {
@@ -122,12 +289,45 @@ impl AudioVisualizer {
             }
         }
 
+        if self.spectrogram.len() >= self.spectrogram_history {
+            self.spectrogram.pop_front();
+        }
+        self.spectrogram.push_back(self.spectrum_data.clone());
+
+        // Per-channel spectra are only computed while the stereo view is shown
+        if self.stereo && self.left_buffer.len() >= self.fft_size && self.right_buffer.len() >= self.fft_size {
+            let channels = [
+                (&self.left_buffer, &mut self.spectrum_left),
+                (&self.right_buffer, &mut self.spectrum_right),
+            ];
+            for (buffer, spectrum) in channels {
+                let levels = band_levels(&mut self.fft_planner, buffer, self.fft_size, self.band_count, self.sample_rate);
+                for (value, new_value) in spectrum.iter_mut().zip(levels) {
+                    *value = *value * 0.7 + new_value * 0.3;
+                }
+            }
+        }
+
         self.update_needed = false;
     }
 
     pub fn draw_spectrum(&self, ui: &egui::Ui, rect: Rect, theme: &super::theme::Theme) {
         let painter = ui.painter();
         
+        // Draw the frame first so the bars paint over its fill
+        painter.rect(
+            rect, 
+            theme.corner_radius, 
+            theme.panel_color, 
+            Stroke::new(1.0, theme.inactive_color),
+            StrokeKind::Middle   // explicitly supply a variant
+        );
+
+        if self.stereo {
+            self.draw_stereo_bars(ui, rect, theme);
+            return;
+        }
+        
         let bar_count = self.spectrum_data.len();
         let bar_width = rect.width() / (bar_count as f32);
         let bar_spacing = bar_width * 0.1;
@@ -168,19 +368,56 @@ impl AudioVisualizer {
                 Stroke::new(2.0, Color32::WHITE)
             );
         }
+    }
+
+    /// Left channel bars rise from the center line, right channel bars hang below it.
+    fn draw_stereo_bars(&self, ui: &egui::Ui, rect: Rect, theme: &super::theme::Theme) {
+        let painter = ui.painter();
+        let center_y = rect.center().y;
+        let half_height = rect.height() / 2.0;
+        let bar_count = self.spectrum_left.len();
+        let bar_width = rect.width() / (bar_count as f32);
+        let bar_spacing = bar_width * 0.1;
+        let effective_bar_width = bar_width - bar_spacing;
+
+        for i in 0..bar_count {
+            let x = rect.left() + (i as f32 * bar_width) + bar_spacing * 0.5;
+            let hue = 210.0 - (i as f32 / bar_count as f32) * 210.0;
+            for (value, upward) in [(self.spectrum_left[i], true), (self.spectrum_right[i], false)] {
+                let (r, g, b) = hsv_to_rgb(hue, 0.8, 0.7 + 0.3 * (0.2 + value * 0.8));
+                let bar_height = value * half_height;
+                let top = if upward { center_y - bar_height } else { center_y };
+                let bar_rect = Rect::from_min_size(
+                    Pos2::new(x, top),
+                    Vec2::new(effective_bar_width, bar_height),
+                );
+                painter.rect_filled(bar_rect, theme.corner_radius, Color32::from_rgb(r, g, b));
+            }
+        }
+
+        painter.line_segment(
+            [Pos2::new(rect.left(), center_y), Pos2::new(rect.right(), center_y)],
+            Stroke::new(1.0, theme.inactive_color),
+        );
+        for (label, y, align) in [
+            ("L", rect.top() + 4.0, egui::Align2::LEFT_TOP),
+            ("R", rect.bottom() - 4.0, egui::Align2::LEFT_BOTTOM),
+        ] {
+            painter.text(Pos2::new(rect.left() + 4.0, y), align, label, theme.tiny_font.clone(), theme.dim_text_color);
+        }
+    }
+
+    pub fn draw_waveform(&self, ui: &egui::Ui, rect: Rect, theme: &super::theme::Theme) {
+        let painter = ui.painter();
         
-        // Draw the frame:
+        // Draw the frame first so the waveform paints over its fill
         painter.rect(
             rect, 
             theme.corner_radius, 
             theme.panel_color, 
             Stroke::new(1.0, theme.inactive_color),
-            StrokeKind::Middle   // explicitly supply a variant
+            StrokeKind::Middle  // explicitly supply a variant
         );
-    }
-
-    pub fn draw_waveform(&self, ui: &egui::Ui, rect: Rect, theme: &super::theme::Theme) {
-        let painter = ui.painter();
         
         let point_count = self.waveform_data.len();
         let point_width = rect.width() / (point_count as f32);
@@ -213,16 +450,381 @@ impl AudioVisualizer {
                 painter.line_segment([start, end], Stroke::new(2.0, color));
             }
         }
-        
-        // Draw the frame:
+    }
+
+    /// Raw waveform of the most recent samples, triggered on a rising zero
+    /// crossing so periodic signals hold still instead of scrolling.
+    pub fn draw_oscilloscope(&self, ui: &egui::Ui, rect: Rect, theme: &super::theme::Theme) {
+        let painter = ui.painter();
         painter.rect(
-            rect, 
-            theme.corner_radius, 
-            theme.panel_color, 
+            rect,
+            theme.corner_radius,
+            theme.panel_color,
             Stroke::new(1.0, theme.inactive_color),
-            StrokeKind::Middle  // explicitly supply a variant
+            StrokeKind::Middle,
         );
+        painter.hline(rect.x_range(), rect.center().y, Stroke::new(1.0, theme.inactive_color));
+
+        let samples: Vec<f32> = self.sample_buffer.iter().copied().collect();
+        let window = SCOPE_WINDOW.min(samples.len());
+        if window < 2 {
+            return;
+        }
+        let start = trigger_start(&samples, window);
+        let points: Vec<Pos2> = samples[start..start + window]
+            .iter()
+            .enumerate()
+            .map(|(i, &sample)| {
+                let x = rect.left() + rect.width() * i as f32 / (window - 1) as f32;
+                let y = rect.center().y - sample.clamp(-1.0, 1.0) * rect.height() * 0.45;
+                Pos2::new(x, y)
+            })
+            .collect();
+        painter.add(egui::Shape::line(points, Stroke::new(1.5, theme.accent_color)));
     }
+
+    /// Goniometer: each left/right frame is plotted rotated 45 degrees, so
+    /// mono content is a vertical line and wide stereo spreads sideways.
+    pub fn draw_lissajous(&self, ui: &egui::Ui, rect: Rect, theme: &super::theme::Theme) {
+        let painter = ui.painter();
+        let side = rect.width().min(rect.height());
+        let square = Rect::from_center_size(rect.center(), Vec2::splat(side));
+        painter.rect(
+            square,
+            theme.corner_radius,
+            theme.panel_color,
+            Stroke::new(1.0, theme.inactive_color),
+            StrokeKind::Middle,
+        );
+        let axis = Stroke::new(1.0, theme.inactive_color);
+        painter.vline(square.center().x, square.y_range(), axis);
+        painter.hline(square.x_range(), square.center().y, axis);
+        // L and R axes along the diagonals
+        painter.line_segment([square.left_bottom(), square.right_top()], axis);
+        painter.line_segment([square.left_top(), square.right_bottom()], axis);
+        painter.text(square.left_top() + Vec2::new(6.0, 4.0), egui::Align2::LEFT_TOP, "L", egui::FontId::proportional(12.0), theme.dim_text_color);
+        painter.text(square.right_top() + Vec2::new(-6.0, 4.0), egui::Align2::RIGHT_TOP, "R", egui::FontId::proportional(12.0), theme.dim_text_color);
+
+        let count = LISSAJOUS_POINTS.min(self.left_buffer.len());
+        let skip = self.left_buffer.len() - count;
+        let scale = side * 0.45 / std::f32::consts::SQRT_2;
+        let points: Vec<Pos2> = self.left_buffer
+            .iter()
+            .zip(self.right_buffer.iter())
+            .skip(skip)
+            .map(|(&left, &right)| {
+                let (left, right) = (left.clamp(-1.0, 1.0), right.clamp(-1.0, 1.0));
+                // Side on the x axis, mid on the y axis
+                Pos2::new(
+                    square.center().x + (right - left) * scale,
+                    square.center().y - (left + right) * scale,
+                )
+            })
+            .collect();
+        if points.len() >= 2 {
+            painter.add(egui::Shape::line(points, Stroke::new(1.0, theme.accent_color.gamma_multiply(0.7))));
+        }
+    }
+
+    /// Scrolling heatmap of recent spectra: time runs left to right with the
+    /// newest frame at the right edge, low frequencies at the bottom. The
+    /// whole history is uploaded as one texture per frame.
+    pub fn draw_spectrogram(&mut self, ui: &egui::Ui, rect: Rect, theme: &super::theme::Theme) {
+        let width = self.spectrogram_history;
+        let height = self.band_count;
+        let background = self.spectrogram_colors.color(0.0);
+        let mut image = egui::ColorImage::new([width, height], background);
+        // Fewer frames than the history so far: start partway across
+        let offset = width - self.spectrogram.len().min(width);
+        for (column, spectrum) in self.spectrogram.iter().enumerate() {
+            for (band, &level) in spectrum.iter().take(height).enumerate() {
+                image[(offset + column, height - 1 - band)] = self.spectrogram_colors.color(level);
+            }
+        }
+
+        if let Some(texture) = &mut self.spectrogram_texture {
+            texture.set(image, egui::TextureOptions::LINEAR);
+        } else {
+            self.spectrogram_texture = Some(ui.ctx().load_texture("spectrogram", image, egui::TextureOptions::LINEAR));
+        }
+        let Some(texture) = &self.spectrogram_texture else {
+            return;
+        };
+
+        let painter = ui.painter();
+        painter.image(
+            texture.id(),
+            rect,
+            Rect::from_min_max(Pos2::ZERO, Pos2::new(1.0, 1.0)),
+            Color32::WHITE,
+        );
+        painter.rect_stroke(
+            rect,
+            theme.corner_radius,
+            Stroke::new(1.0, theme.inactive_color),
+            StrokeKind::Middle,
+        );
+    }
+
+    /// Two horizontal bars, left over right: RMS as the solid bar, the
+    /// instantaneous peak as a lighter extension and a held peak marker.
+    pub fn draw_vu_meter(&self, ui: &egui::Ui, rect: Rect, theme: &super::theme::Theme) {
+        let painter = ui.painter();
+        painter.rect(
+            rect,
+            theme.corner_radius,
+            theme.panel_color,
+            Stroke::new(1.0, theme.inactive_color),
+            StrokeKind::Middle,
+        );
+
+        let label_width = 20.0;
+        let row_height = rect.height() / 2.0;
+        for (ch, label) in ["L", "R"].into_iter().enumerate() {
+            let top = rect.top() + ch as f32 * row_height;
+            painter.text(
+                Pos2::new(rect.left() + label_width / 2.0, top + row_height / 2.0),
+                egui::Align2::CENTER_CENTER,
+                label,
+                theme.small_font.clone(),
+                theme.text_color,
+            );
+            let track = Rect::from_min_max(
+                Pos2::new(rect.left() + label_width, top + row_height * 0.2),
+                Pos2::new(rect.right() - 6.0, top + row_height * 0.8),
+            );
+            painter.rect_filled(track, theme.corner_radius, theme.inactive_color);
+
+            let width_at = |level: f32| track.width() * vu_fraction(level);
+            // Green up to -12 dB, amber to -3 dB, red above
+            let rms = vu_fraction(self.channel_rms[ch]);
+            let color = if rms > 0.95 {
+                Color32::from_rgb(220, 70, 60)
+            } else if rms > 0.8 {
+                Color32::from_rgb(230, 180, 60)
+            } else {
+                Color32::from_rgb(90, 190, 110)
+            };
+            let peak_rect = Rect::from_min_size(track.left_top(), Vec2::new(width_at(self.channel_peak[ch]), track.height()));
+            painter.rect_filled(peak_rect, theme.corner_radius, color.gamma_multiply(0.4));
+            let rms_rect = Rect::from_min_size(track.left_top(), Vec2::new(width_at(self.channel_rms[ch]), track.height()));
+            painter.rect_filled(rms_rect, theme.corner_radius, color);
+
+            let hold_x = track.left() + width_at(self.vu_peak_hold[ch]);
+            painter.line_segment(
+                [Pos2::new(hold_x, track.top()), Pos2::new(hold_x, track.bottom())],
+                Stroke::new(2.0, Color32::WHITE),
+            );
+        }
+    }
+}
+
+/// Pass-through source that copies a mono mix of the audio into the visualizer.
+/// Samples are batched per decoder frame so the mutex is taken once per block.
+pub struct VisualizerTap<S>
+where
+    S: Source<Item = f32>,
+{
+    inner: S,
+    visualizer: Arc<Mutex<AudioVisualizer>>,
+    block: Vec<f32>,
+    // Left/right of each frame in `block`, for the stereo spectrum
+    stereo_block: Vec<[f32; 2]>,
+    frame: [f32; 2],
+    block_len: usize,
+    frame_sum: f32,
+    frame_pos: u16,
+    // Per-channel peak and sum of squares over the current block
+    block_peak: [f32; 2],
+    block_square_sum: [f32; 2],
+}
+
+impl<S> VisualizerTap<S>
+where
+    S: Source<Item = f32>,
+{
+    pub fn new(inner: S, visualizer: Arc<Mutex<AudioVisualizer>>) -> Self {
+        visualizer.lock().unwrap().sample_rate = inner.sample_rate();
+        Self {
+            inner,
+            visualizer,
+            block: Vec::with_capacity(TAP_MAX_BLOCK),
+            stereo_block: Vec::with_capacity(TAP_MAX_BLOCK),
+            frame: [0.0; 2],
+            block_len: TAP_MIN_BLOCK,
+            frame_sum: 0.0,
+            frame_pos: 0,
+            block_peak: [0.0; 2],
+            block_square_sum: [0.0; 2],
+        }
+    }
+
+    fn flush(&mut self) {
+        if self.block.is_empty() {
+            return;
+        }
+        let frames = self.block.len() as f32;
+        let rms = self.block_square_sum.map(|sum| (sum / frames).sqrt());
+        if let Ok(mut visualizer) = self.visualizer.try_lock() {
+            visualizer.add_samples(&self.block);
+            visualizer.add_stereo_frames(&self.stereo_block);
+            visualizer.add_levels(self.block_peak, rms);
+        }
+        // If the UI holds the lock, drop this block rather than stall audio
+        self.block.clear();
+        self.stereo_block.clear();
+        self.block_peak = [0.0; 2];
+        self.block_square_sum = [0.0; 2];
+    }
+}
+
+impl<S> Iterator for VisualizerTap<S>
+where
+    S: Source<Item = f32>,
+{
+    type Item = f32;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.block.is_empty() && self.frame_pos == 0 {
+            // Size the next batch to the rest of the decoder frame
+            let channels = self.inner.channels().max(1) as usize;
+            self.block_len = self.inner.current_frame_len()
+                .map_or(TAP_MAX_BLOCK, |len| len / channels)
+                .clamp(TAP_MIN_BLOCK, TAP_MAX_BLOCK);
+        }
+        let Some(sample) = self.inner.next() else {
+            self.flush();
+            return None;
+        };
+        let channels = self.inner.channels().max(1);
+        // Channel 0 is left, 1 is right, and mono feeds both meters
+        let meters = match (channels, self.frame_pos) {
+            (1, _) => 0..2,
+            (_, 0) => 0..1,
+            (_, 1) => 1..2,
+            _ => 0..0,
+        };
+        for ch in meters {
+            self.frame[ch] = sample;
+            self.block_peak[ch] = self.block_peak[ch].max(sample.abs());
+            self.block_square_sum[ch] += sample * sample;
+        }
+        self.frame_sum += sample;
+        self.frame_pos += 1;
+        if self.frame_pos >= channels {
+            self.block.push(self.frame_sum / channels as f32);
+            self.stereo_block.push(self.frame);
+            self.frame_sum = 0.0;
+            self.frame_pos = 0;
+            if self.block.len() >= self.block_len {
+                self.flush();
+            }
+        }
+        Some(sample)
+    }
+}
+
+impl<S> Source for VisualizerTap<S>
+where
+    S: Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+}
+
+/// Start of a `window`-long slice of `samples` centred on the rising zero
+/// crossing nearest the middle of the buffer. Falls back to the latest
+/// samples when there is no crossing, e.g. in silence.
+fn trigger_start(samples: &[f32], window: usize) -> usize {
+    let latest = samples.len() - window;
+    let half = window / 2;
+    let center = samples.len() / 2;
+    (half..=latest + half)
+        .filter(|&i| samples[i - 1] < 0.0 && samples[i] >= 0.0)
+        .min_by_key(|&i| i.abs_diff(center))
+        .map_or(latest, |i| i - half)
+}
+
+/// Windowed FFT of the last `fft_size` samples, mapped onto `band_count`
+/// log-spaced bands from 20 Hz to Nyquist. See `normalized_db` for the levels.
+fn band_levels(
+    planner: &mut FftPlanner<f32>,
+    samples: &VecDeque<f32>,
+    fft_size: usize,
+    band_count: usize,
+    sample_rate: u32,
+) -> Vec<f32> {
+    // Prepare FFT input
+    let mut fft_buffer: Vec<Complex<f32>> = samples.iter()
+        .skip(samples.len().saturating_sub(fft_size))
+        .enumerate()
+        .map(|(i, &sample)| {
+            // Apply Hann window
+            let window = 0.5 * (1.0 - (2.0 * std::f32::consts::PI * i as f32 / fft_size as f32).cos());
+            Complex { re: sample * window, im: 0.0 }
+        })
+        .collect();
+    fft_buffer.resize(fft_size, Complex { re: 0.0, im: 0.0 });
+
+    // Perform FFT (in place)
+    let fft = planner.plan_fft_forward(fft_size);
+    fft.process(&mut fft_buffer);
+
+    let mut levels = vec![0.0; band_count];
+    for (i, bin) in fft_buffer.iter().take(fft_size / 2).enumerate() {
+        let magnitude = (bin.re * bin.re + bin.im * bin.im).sqrt();
+        let band = band_index(i, fft_size, band_count, sample_rate);
+        levels[band] = f32::max(levels[band], normalized_db(magnitude));
+    }
+    levels
+}
+
+/// Band that FFT bin `bin` falls in when `band_count` bands are spaced
+/// logarithmically from `MIN_FREQUENCY` to Nyquist. DC and anything below
+/// `MIN_FREQUENCY` go to the first band.
+fn band_index(bin: usize, fft_size: usize, band_count: usize, sample_rate: u32) -> usize {
+    let nyquist = sample_rate as f32 / 2.0;
+    let freq = bin as f32 * sample_rate as f32 / fft_size as f32;
+    if freq <= MIN_FREQUENCY {
+        return 0;
+    }
+    let position = (freq.log10() - MIN_FREQUENCY.log10()) / (nyquist.log10() - MIN_FREQUENCY.log10());
+    // Scaling by the band count rather than one less gives every band an
+    // equal share, so the top one isn't left for Nyquist alone
+    ((position * band_count as f32) as usize).min(band_count - 1)
+}
+
+/// Level of an FFT bin magnitude, 0.0 at `SPECTRUM_FLOOR_DB` rising to 1.0
+/// at 0 dB. Silence maps to 0.0.
+fn normalized_db(magnitude: f32) -> f32 {
+    let db = (20.0 * magnitude.log10()).max(SPECTRUM_FLOOR_DB);
+    (db - SPECTRUM_FLOOR_DB) / -SPECTRUM_FLOOR_DB
+}
+
+/// FFT sizes must be powers of two; both values must be non-zero.
+fn validate_resolution(fft_size: usize, band_count: usize) -> Result<(), String> {
+    if !fft_size.is_power_of_two() {
+        return Err(format!("FFT size {} is not a power of two", fft_size));
+    }
+    if band_count == 0 {
+        return Err("band count must be at least 1".to_string());
+    }
+    Ok(())
+}
+
+/// Position of a linear level on the VU scale, 0.0 at `VU_FLOOR_DB` up to 1.0 at 0 dBFS.
+fn vu_fraction(level: f32) -> f32 {
+    let db = 20.0 * level.max(1e-6).log10();
+    ((db - VU_FLOOR_DB) / -VU_FLOOR_DB).clamp(0.0, 1.0)
 }
 
 // Helper function to convert HSV to RGB
@@ -249,3 +851,57 @@ fn hsv_to_rgb(h: f32, s: f32, v: f32) -> (u8, u8, u8) {
         (b * 255.0) as u8,
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 48000;
+
+    fn levels_of(samples: &VecDeque<f32>) -> Vec<f32> {
+        band_levels(&mut FftPlanner::new(), samples, DEFAULT_FFT_SIZE, DEFAULT_BANDS, SAMPLE_RATE)
+    }
+
+    #[test]
+    fn band_index_covers_the_whole_range() {
+        let band = |bin| band_index(bin, DEFAULT_FFT_SIZE, DEFAULT_BANDS, SAMPLE_RATE);
+        // DC and the 11.7 Hz bin are below the first band's edge
+        assert_eq!(band(0), 0);
+        assert_eq!(band(1), 0);
+        assert_eq!(band(2), 1);
+        assert_eq!(band(DEFAULT_FFT_SIZE / 2 - 1), DEFAULT_BANDS - 1);
+        let bands: Vec<usize> = (0..DEFAULT_FFT_SIZE / 2).map(band).collect();
+        assert!(bands.windows(2).all(|pair| pair[0] <= pair[1]));
+    }
+
+    #[test]
+    fn sine_lights_up_its_band() {
+        let sine: VecDeque<f32> = (0..DEFAULT_FFT_SIZE)
+            .map(|n| (std::f32::consts::TAU * 1000.0 * n as f32 / SAMPLE_RATE as f32).sin())
+            .collect();
+        let levels = levels_of(&sine);
+        let loudest = (0..levels.len())
+            .max_by(|&a, &b| levels[a].total_cmp(&levels[b]))
+            .unwrap();
+        // 1 kHz falls between bins 85 and 86
+        assert_eq!(loudest, band_index(85, DEFAULT_FFT_SIZE, DEFAULT_BANDS, SAMPLE_RATE));
+        assert_eq!(loudest, 35);
+        // Window leakage keeps distant bands well down
+        assert!(levels[10] < 0.5);
+        assert!(levels[60] < 0.5);
+    }
+
+    #[test]
+    fn silence_gives_an_empty_spectrum() {
+        let silence = VecDeque::from(vec![0.0; DEFAULT_FFT_SIZE]);
+        assert!(levels_of(&silence).iter().all(|&level| level < 0.01));
+    }
+
+    #[test]
+    fn normalized_db_spans_floor_to_full_scale() {
+        assert_eq!(normalized_db(0.0), 0.0);
+        assert_eq!(normalized_db(1e-6), 0.0);
+        assert!((normalized_db(0.01) - 0.5).abs() < 1e-6);
+        assert!((normalized_db(1.0) - 1.0).abs() < 1e-6);
+    }
+}
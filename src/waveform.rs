@@ -0,0 +1,43 @@
+use std::fs;
+use std::io::BufReader;
+use std::path::Path;
+
+use rodio::{Decoder, Source};
+
+/// Columns in a track overview; the progress bar stretches them to its width.
+pub const OVERVIEW_COLUMNS: usize = 600;
+/// Samples folded into one min/max pair while decoding, before the pairs are
+/// merged down to `OVERVIEW_COLUMNS`. Keeps memory flat for long tracks.
+const BLOCK_SAMPLES: usize = 1024;
+
+/// Amplitude outline of a whole track: the lowest and highest sample in each
+/// column, with all channels folded together.
+pub type Overview = Vec<(f32, f32)>;
+
+/// Decode `path` and build its overview.
+/// This decodes the whole file, so call it off the UI thread.
+pub fn compute(path: &Path) -> Option<Overview> {
+    let file = fs::File::open(path).ok()?;
+    let decoder = Decoder::new(BufReader::new(file)).ok()?;
+    let mut samples = decoder.convert_samples::<f32>().peekable();
+    let mut blocks = Vec::new();
+    while samples.peek().is_some() {
+        blocks.push(min_max(samples.by_ref().take(BLOCK_SAMPLES).map(|s| (s, s))));
+    }
+    if blocks.is_empty() {
+        return None;
+    }
+    let columns = OVERVIEW_COLUMNS.min(blocks.len());
+    let overview = (0..columns)
+        .map(|column| {
+            let start = column * blocks.len() / columns;
+            let end = ((column + 1) * blocks.len() / columns).max(start + 1);
+            min_max(blocks[start..end].iter().copied())
+        })
+        .collect();
+    Some(overview)
+}
+
+fn min_max(pairs: impl Iterator<Item = (f32, f32)>) -> (f32, f32) {
+    pairs.fold((0.0, 0.0), |(low, high), (min, max)| (low.min(min), high.max(max)))
+}
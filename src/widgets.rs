@@ -1,10 +1,12 @@
-use egui::{pos2, Pos2, Rect, Vec2};
+use std::path::{Path, PathBuf};
+
+use egui::{pos2, Pos2, Rect, Response, Vec2};
 use egui::epaint::CornerRadius;
 use egui_phosphor::regular::*;
 use crate::theme::Theme;
 
 // Custom playback control buttons
-pub fn play_button(ui: &mut egui::Ui, is_playing: bool, theme: &Theme) -> bool {
+pub fn play_button(ui: &mut egui::Ui, is_playing: bool, theme: &Theme) -> Response {
     let (rect, response) = ui.allocate_exact_size(
         Vec2::new(42.0, 42.0),
         egui::Sense::click(),
@@ -70,31 +72,29 @@ pub fn play_button(ui: &mut egui::Ui, is_playing: bool, theme: &Theme) -> bool {
         }
     }
     
-    response.clicked()
+    response
 }
 
-pub fn prev_button(ui: &mut egui::Ui, theme: &Theme) -> bool {
-    control_button(ui, SKIP_BACK, "Previous Track", theme)
+pub fn prev_button(ui: &mut egui::Ui, theme: &Theme) -> Response {
+    control_button(ui, SKIP_BACK, theme)
 }
 
-pub fn next_button(ui: &mut egui::Ui, theme: &Theme) -> bool {
-    control_button(ui, SKIP_FORWARD, "Next Track", theme)
+pub fn next_button(ui: &mut egui::Ui, theme: &Theme) -> Response {
+    control_button(ui, SKIP_FORWARD, theme)
 }
 
-pub fn shuffle_button(ui: &mut egui::Ui, is_active: bool, theme: &Theme) -> bool {
-    toggle_button(ui, SHUFFLE, "Shuffle", is_active, theme)
+pub fn shuffle_button(ui: &mut egui::Ui, is_active: bool, theme: &Theme) -> Response {
+    toggle_button(ui, SHUFFLE, is_active, theme)
 }
 
-fn control_button(ui: &mut egui::Ui, icon: &str, tooltip: &str, theme: &Theme) -> bool {
+// The transport buttons return their response so callers can add tooltips naming the shortcut
+fn control_button(ui: &mut egui::Ui, icon: &str, theme: &Theme) -> Response {
     let size = Vec2::new(36.0, 36.0);
     let (rect, response) = ui.allocate_exact_size(size, egui::Sense::click());
     
-    let response_clone = response.clone();
-    response.on_hover_text(tooltip);
-    
     if ui.is_rect_visible(rect) {
         let painter = ui.painter();
-        let is_hovered = response_clone.hovered();
+        let is_hovered = response.hovered();
         
         let bg_color = if is_hovered {
             theme.inactive_color
@@ -120,19 +120,16 @@ fn control_button(ui: &mut egui::Ui, icon: &str, tooltip: &str, theme: &Theme) -
         );
     }
     
-    response_clone.clicked()
+    response
 }
 
-fn toggle_button(ui: &mut egui::Ui, icon: &str, tooltip: &str, is_active: bool, theme: &Theme) -> bool {
+fn toggle_button(ui: &mut egui::Ui, icon: &str, is_active: bool, theme: &Theme) -> Response {
     let size = Vec2::new(30.0, 30.0);
     let (rect, response) = ui.allocate_exact_size(size, egui::Sense::click());
     
-    let response_clone = response.clone();
-    response.on_hover_text(tooltip);
-    
     if ui.is_rect_visible(rect) {
         let painter = ui.painter();
-        let is_hovered = response_clone.hovered();
+        let is_hovered = response.hovered();
         
         let bg_color = if is_active {
             theme.accent_color
@@ -160,11 +157,39 @@ fn toggle_button(ui: &mut egui::Ui, icon: &str, tooltip: &str, is_active: bool,
         );
     }
     
-    response_clone.clicked()
+    response
 }
 
-// Custom volume slider
-pub fn volume_slider(ui: &mut egui::Ui, volume: &mut f32, theme: &Theme) -> bool {
+// Star toggle for marking a track as a favorite; filled with the accent color when set
+pub fn favorite_button(ui: &mut egui::Ui, is_favorite: bool, theme: &Theme) -> bool {
+    let size = Vec2::new(20.0, 20.0);
+    let (rect, response) = ui.allocate_exact_size(size, egui::Sense::click());
+    let tooltip = if is_favorite { "Remove from favorites" } else { "Add to favorites" };
+    let response = response.on_hover_text(tooltip);
+    
+    if ui.is_rect_visible(rect) {
+        let icon_color = if is_favorite {
+            theme.accent_color
+        } else if response.hovered() {
+            theme.text_color
+        } else {
+            theme.dim_text_color
+        };
+        
+        ui.painter().text(
+            rect.center(),
+            egui::Align2::CENTER_CENTER,
+            STAR,
+            egui::FontId::proportional(16.0),
+            icon_color,
+        );
+    }
+    
+    response.clicked()
+}
+
+// Custom volume slider. While `muted` the fill is dimmed but the stored level is kept.
+pub fn volume_slider(ui: &mut egui::Ui, volume: &mut f32, muted: bool, theme: &Theme) -> bool {
     let desired_size = Vec2::new(120.0, 24.0);
     let (rect, response) = ui.allocate_exact_size(desired_size, egui::Sense::click_and_drag());
     
@@ -197,10 +222,15 @@ pub fn volume_slider(ui: &mut egui::Ui, volume: &mut f32, theme: &Theme) -> bool
                 rect.left_top(),
                 Vec2::new(filled_width, rect.height()),
             );
+            let fill = if muted {
+                theme.accent_color.gamma_multiply(0.35)
+            } else {
+                theme.accent_color
+            };
             painter.rect_filled(
                 filled_rect,
                 theme.corner_radius,
-                theme.accent_color,
+                fill,
             );
         }
         
@@ -216,12 +246,12 @@ pub fn volume_slider(ui: &mut egui::Ui, volume: &mut f32, theme: &Theme) -> bool
         );
         
         // Draw volume icon
-        let icon = if *volume < 0.01 {
+        let icon = if muted {
+            SPEAKER_SLASH
+        } else if *volume < 0.01 {
             SPEAKER_NONE
         } else if *volume < 0.3 {
             SPEAKER_LOW
-        } else if *volume < 0.7 {
-            SPEAKER_HIGH
         } else {
             SPEAKER_HIGH
         };
@@ -238,8 +268,19 @@ pub fn volume_slider(ui: &mut egui::Ui, volume: &mut f32, theme: &Theme) -> bool
     value_changed
 }
 
-// Custom progress bar for playback
-pub fn progress_bar(ui: &mut egui::Ui, current: f32, total: f32, theme: &Theme) -> Option<f32> {
+// Custom progress bar for playback. `loop_points` are optional A-B loop
+// markers, in seconds, drawn over the track. With an `overview` the track's
+// amplitude outline replaces the plain fill, coloured up to the playhead.
+// Clicking the elapsed time flips `show_remaining`, which counts down instead.
+pub fn progress_bar(
+    ui: &mut egui::Ui,
+    current: f32,
+    total: f32,
+    loop_points: (Option<f32>, Option<f32>),
+    overview: Option<&[(f32, f32)]>,
+    show_remaining: &mut bool,
+    theme: &Theme,
+) -> Option<f32> {
     let desired_size = Vec2::new(ui.available_width(), 24.0);
     let (rect, response) = ui.allocate_exact_size(desired_size, egui::Sense::click_and_drag());
     
@@ -266,7 +307,26 @@ pub fn progress_bar(ui: &mut egui::Ui, current: f32, total: f32, theme: &Theme)
         let progress_ratio = if total > 0.0 { current / total } else { 0.0 };
         let filled_width = rect.width() * progress_ratio;
         
-        if filled_width > 0.0 {
+        if let Some(overview) = overview.filter(|overview| !overview.is_empty()) {
+            let columns = rect.width().max(1.0) as usize;
+            let half_height = rect.height() / 2.0 - 1.0;
+            for column in 0..columns {
+                let (low, high) = overview[column * overview.len() / columns];
+                let x = rect.left() + column as f32 + 0.5;
+                let color = if x <= rect.left() + filled_width {
+                    theme.accent_color
+                } else {
+                    theme.dim_text_color
+                };
+                painter.line_segment(
+                    [
+                        pos2(x, rect.center().y - high.clamp(-1.0, 1.0) * half_height),
+                        pos2(x, rect.center().y - low.clamp(-1.0, 1.0) * half_height),
+                    ],
+                    egui::Stroke::new(1.0, color),
+                );
+            }
+        } else if filled_width > 0.0 {
             let filled_rect = Rect::from_min_size(
                 rect.left_top(),
                 Vec2::new(filled_width, rect.height()),
@@ -278,6 +338,32 @@ pub fn progress_bar(ui: &mut egui::Ui, current: f32, total: f32, theme: &Theme)
             );
         }
         
+        // Draw A-B loop region and markers
+        let marker_x = |seconds: f32| {
+            let ratio = if total > 0.0 { (seconds / total).clamp(0.0, 1.0) } else { 0.0 };
+            rect.left() + rect.width() * ratio
+        };
+        if let (Some(a), Some(b)) = loop_points {
+            let loop_rect = Rect::from_x_y_ranges(marker_x(a)..=marker_x(b), rect.y_range());
+            painter.rect_filled(loop_rect, 0.0, theme.active_color.gamma_multiply(0.35));
+        }
+        for (point, label) in [(loop_points.0, "A"), (loop_points.1, "B")] {
+            if let Some(seconds) = point {
+                let x = marker_x(seconds);
+                painter.line_segment(
+                    [pos2(x, rect.top()), pos2(x, rect.bottom())],
+                    egui::Stroke::new(2.0, theme.active_color),
+                );
+                painter.text(
+                    pos2(x, rect.top() - 2.0),
+                    egui::Align2::CENTER_BOTTOM,
+                    label,
+                    theme.tiny_font.clone(),
+                    theme.active_color,
+                );
+            }
+        }
+
         // Draw handle
         let handle_radius = 8.0;
         let handle_x = rect.left() + rect.width() * progress_ratio;
@@ -290,16 +376,25 @@ pub fn progress_bar(ui: &mut egui::Ui, current: f32, total: f32, theme: &Theme)
         );
         
         // Draw time indicators
-        let current_time = format_time(current);
+        let current_time = if *show_remaining {
+            format_time(current - total)
+        } else {
+            format_time(current)
+        };
         let total_time = format_time(total);
         
-        ui.painter().text(
+        let current_rect = ui.painter().text(
             pos2(rect.left(), rect.bottom() + 8.0),
             egui::Align2::LEFT_TOP,
             current_time,
             theme.small_font.clone(),
             theme.dim_text_color,
         );
+        if ui.interact(current_rect, response.id.with("time"), egui::Sense::click())
+            .on_hover_text("Click to switch between elapsed and remaining time")
+            .clicked() {
+            *show_remaining = !*show_remaining;
+        }
         
         ui.painter().text(
             pos2(rect.right(), rect.bottom() + 8.0),
@@ -313,32 +408,62 @@ pub fn progress_bar(ui: &mut egui::Ui, current: f32, total: f32, theme: &Theme)
     seek_pos
 }
 
-fn format_time(seconds: f32) -> String {
+/// `mm:ss` for a time in seconds, or `-mm:ss` for a negative one such as
+/// the time left in a track.
+pub fn format_time(seconds: f32) -> String {
+    let sign = if seconds <= -1.0 { "-" } else { "" };
+    let seconds = seconds.abs();
     let minutes = (seconds / 60.0) as i32;
     let secs = (seconds % 60.0) as i32;
-    format!("{:02}:{:02}", minutes, secs)
+    format!("{}{:02}:{:02}", sign, minutes, secs)
 }
 
-// Album artwork display 
-pub fn album_art(ui: &mut egui::Ui, image_data: Option<&[u8]>, theme: &Theme) {
-    let size = Vec2::new(200.0, 200.0);
-    let (rect, _response) = ui.allocate_exact_size(size, egui::Sense::hover());
-    
-    if ui.is_rect_visible(rect) {
-        if let Some(data) = image_data {
-            // Try to load image data
-            if let Ok(img) = image::load_from_memory(data) {
+/// Decoded cover texture for the track it was made from, so the image is
+/// decoded once per track instead of on every repaint.
+#[derive(Default)]
+pub struct AlbumArtCache {
+    path: Option<PathBuf>,
+    texture: Option<egui::TextureHandle>, // None when the image failed to decode
+}
+
+impl AlbumArtCache {
+    fn texture(&mut self, ctx: &egui::Context, path: &Path, data: &[u8]) -> Option<&egui::TextureHandle> {
+        if self.path.as_deref() != Some(path) {
+            self.path = Some(path.to_path_buf());
+            self.texture = image::load_from_memory(data).ok().map(|img| {
                 let img_size = [img.width() as usize, img.height() as usize];
                 let pixels = img.to_rgba8().into_vec();
                 let color_image = egui::ColorImage::from_rgba_unmultiplied(img_size, &pixels);
-                let texture = ui.ctx().load_texture(
-                    "album_art", 
+                ctx.load_texture(
+                    format!("album_art:{}", path.display()),
                     color_image,
-                    egui::TextureOptions::default()
-                );
-                
-                // Pass a reference to the texture directly.
-                ui.image(&texture);
+                    egui::TextureOptions::default(),
+                )
+            });
+        }
+        self.texture.as_ref()
+    }
+}
+
+// Album artwork display
+pub fn album_art(
+    ui: &mut egui::Ui,
+    cache: &mut AlbumArtCache,
+    path: Option<&Path>,
+    image_data: Option<&[u8]>,
+    size: f32,
+    theme: &Theme,
+) {
+    let (rect, _response) = ui.allocate_exact_size(Vec2::splat(size), egui::Sense::hover());
+    
+    if ui.is_rect_visible(rect) {
+        if let (Some(path), Some(data)) = (path, image_data) {
+            if let Some(texture) = cache.texture(ui.ctx(), path, data) {
+                // Scale into the reserved square rather than the image's own size
+                egui::Image::new(texture)
+                    .maintain_aspect_ratio(true)
+                    .corner_radius(theme.corner_radius)
+                    .paint_at(ui, rect);
             }
         } else {
             // Draw placeholder
@@ -355,7 +480,7 @@ pub fn album_art(ui: &mut egui::Ui, image_data: Option<&[u8]>, theme: &Theme) {
                 rect.center(),
                 egui::Align2::CENTER_CENTER,
                 MUSIC_NOTES,
-                egui::FontId::proportional(48.0),
+                egui::FontId::proportional(size * 0.24),
                 theme.dim_text_color,
             );
         }
@@ -363,6 +488,7 @@ pub fn album_art(ui: &mut egui::Ui, image_data: Option<&[u8]>, theme: &Theme) {
 }
 
 // Track entry in a playlist
+#[allow(dead_code)]
 pub fn track_entry(
     ui: &mut egui::Ui,
     title: &str,
@@ -469,3 +595,76 @@ pub fn track_entry(
     
     response
 }
+
+// Album tile for the collections grid, with a generated placeholder cover
+pub fn album_tile(
+    ui: &mut egui::Ui,
+    album: &str,
+    track_count: usize,
+    size: f32,
+    theme: &Theme,
+) -> egui::Response {
+    let caption_height = 36.0;
+    let (rect, response) = ui.allocate_exact_size(
+        Vec2::new(size, size + caption_height),
+        egui::Sense::click(),
+    );
+    
+    if ui.is_rect_visible(rect) {
+        let painter = ui.painter();
+        let cover_rect = Rect::from_min_size(rect.left_top(), Vec2::new(size, size));
+        
+        // Stable color derived from the album name
+        let hash = album.bytes().fold(2166136261u32, |h, b| (h ^ b as u32).wrapping_mul(16777619));
+        let hue = (hash % 360) as f32 / 360.0;
+        let cover_color: egui::Color32 = egui::ecolor::Hsva::new(hue, 0.45, 0.55, 1.0).into();
+        
+        painter.rect_filled(
+            cover_rect,
+            theme.corner_radius,
+            cover_color,
+        );
+        
+        if response.hovered() {
+            painter.rect_stroke(
+                cover_rect,
+                theme.corner_radius,
+                egui::Stroke::new(2.0, theme.accent_color),
+                egui::epaint::StrokeKind::Inside,
+            );
+        }
+        
+        // First letter of the album as the placeholder art
+        let initial = album.chars().next().unwrap_or('?').to_uppercase().to_string();
+        painter.text(
+            cover_rect.center(),
+            egui::Align2::CENTER_CENTER,
+            initial,
+            egui::FontId::proportional(size * 0.4),
+            theme.text_color,
+        );
+        
+        // Album name (clipped to the tile) and track count
+        let name_galley = painter.layout_no_wrap(
+            album.to_string(),
+            theme.small_font.clone(),
+            theme.text_color,
+        );
+        let name_height = name_galley.rect.height();
+        painter.with_clip_rect(rect).galley(
+            pos2(rect.left(), cover_rect.bottom() + 2.0),
+            name_galley,
+            theme.text_color,
+        );
+        
+        painter.text(
+            pos2(rect.left(), cover_rect.bottom() + 2.0 + name_height),
+            egui::Align2::LEFT_TOP,
+            format!("{} tracks", track_count),
+            theme.tiny_font.clone(),
+            theme.dim_text_color,
+        );
+    }
+    
+    response
+}